@@ -1 +0,0 @@
-RUSTFLAGS="--emit asm" cargo build --release
@@ -50,6 +50,16 @@ impl BBox
 			self.max.z >= other.max.z
 	}
 
+	pub fn intersects(&self, other: &BBox) -> bool
+	{
+		self.min.x <= other.max.x &&
+			self.max.x >= other.min.x &&
+			self.min.y <= other.max.y &&
+			self.max.y >= other.min.y &&
+			self.min.z <= other.max.z &&
+			self.max.z >= other.min.z
+	}
+
 	pub fn get_center(&self) -> Vec3f
 	{
 		(self.min + self.max) * 0.5
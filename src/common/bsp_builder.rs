@@ -30,6 +30,8 @@ pub struct BSPLeaf
 {
 	pub polygons: Vec<Polygon>,
 	pub portals: Vec<LeafsPortalWeakPtr>,
+	// Some if this leaf is inside liquid volume.
+	pub liquid: Option<material::LiquidType>,
 }
 
 pub type BSPLeafPtr = rc::Rc<cell::RefCell<BSPLeaf>>;
@@ -98,6 +100,9 @@ pub fn build_leaf_bsp_tree(map_entities: &[map_polygonizer::Entity], materials:
 	// But anyway try to remove removed portals from list of portals for each leaf.
 	remove_expired_portals_from_leafs_r(&mut tree_root);
 
+	// Mark leafs inside liquid brushes.
+	set_leafs_liquid_types(&reachable_leafs, map_entities, materials);
+
 	BSPTree {
 		root: tree_root,
 		portals,
@@ -113,6 +118,7 @@ fn build_leaf_bsp_tree_r(mut in_polygons: Vec<Polygon>) -> BSPNodeChild
 		return BSPNodeChild::LeafChild(rc::Rc::new(cell::RefCell::new(BSPLeaf {
 			polygons: in_polygons,
 			portals: Vec::new(),
+			liquid: None,
 		})));
 	}
 	let splitter_plane = splitter_plane_opt.unwrap();
@@ -153,6 +159,7 @@ fn build_leaf_bsp_tree_r(mut in_polygons: Vec<Polygon>) -> BSPNodeChild
 		return BSPNodeChild::LeafChild(rc::Rc::new(cell::RefCell::new(BSPLeaf {
 			polygons: polygons_back,
 			portals: Vec::new(),
+			liquid: None,
 		})));
 	}
 	if polygons_back.is_empty()
@@ -160,6 +167,7 @@ fn build_leaf_bsp_tree_r(mut in_polygons: Vec<Polygon>) -> BSPNodeChild
 		return BSPNodeChild::LeafChild(rc::Rc::new(cell::RefCell::new(BSPLeaf {
 			polygons: polygons_front,
 			portals: Vec::new(),
+			liquid: None,
 		})));
 	}
 
@@ -1304,6 +1312,105 @@ fn remove_expired_portals_from_leafs_r(node_child: &mut BSPNodeChild)
 	}
 }
 
+fn set_leafs_liquid_types(
+	leafs: &ReachableLeafsMap,
+	map_entities: &[map_polygonizer::Entity],
+	materials: &material::MaterialsMap,
+)
+{
+	// Liquid type of brush is determined by material of any of its polygons.
+	// Use brushes of all entities, since liquid volumes may be submodels.
+	let mut liquid_brushes = Vec::new();
+	for entity in map_entities
+	{
+		for brush in &entity.brushes
+		{
+			let polygons = &entity.polygons[brush.clone()];
+			let liquid = polygons.iter().find_map(|polygon| {
+				materials
+					.get(&polygon.texture_info.texture)
+					.and_then(|material| material.liquid)
+			});
+			if let Some(liquid) = liquid
+			{
+				liquid_brushes.push((polygons, liquid));
+			}
+		}
+	}
+
+	if liquid_brushes.is_empty()
+	{
+		return;
+	}
+
+	let mut num_liquid_leafs = 0;
+	let mut sample_points = Vec::new();
+	for leaf_ptr in leafs.values()
+	{
+		// Liquid brush sides are not necessarily BSP splitters (submodel brushes, invisible sides).
+		// So, classify leaf by sample points inside it - select brush containing most of them.
+		sample_points.clear();
+		get_leaf_sample_points(&leaf_ptr.borrow(), &mut sample_points);
+
+		let mut best_liquid = None;
+		let mut best_num_points_inside = sample_points.len() / 2;
+		for (polygons, liquid) in &liquid_brushes
+		{
+			let num_points_inside = sample_points
+				.iter()
+				.filter(|point| {
+					polygons
+						.iter()
+						.all(|polygon| polygon.plane.vec.dot(**point) < polygon.plane.dist)
+				})
+				.count();
+			if num_points_inside > best_num_points_inside
+			{
+				best_num_points_inside = num_points_inside;
+				best_liquid = Some(*liquid);
+			}
+		}
+
+		if best_liquid.is_some()
+		{
+			leaf_ptr.borrow_mut().liquid = best_liquid;
+			num_liquid_leafs += 1;
+		}
+	}
+
+	println!("Liquid leafs: {}", num_liquid_leafs);
+}
+
+// Get leaf center and points between center and each vertex of leaf polygons and portals.
+// All these points are strictly inside leaf, since leaf is convex.
+fn get_leaf_sample_points(leaf: &BSPLeaf, out_points: &mut Vec<Vec3f>)
+{
+	let mut vertices = Vec::new();
+	for polygon in &leaf.polygons
+	{
+		vertices.extend_from_slice(&polygon.vertices);
+	}
+	for portal_weak_ptr in &leaf.portals
+	{
+		if let Some(portal_ptr) = portal_weak_ptr.upgrade()
+		{
+			vertices.extend_from_slice(&portal_ptr.borrow().vertices);
+		}
+	}
+
+	if vertices.is_empty()
+	{
+		return;
+	}
+
+	let center = vertices.iter().fold(Vec3f::zero(), |sum, v| sum + *v) / (vertices.len() as f32);
+	out_points.push(center);
+	for v in &vertices
+	{
+		out_points.push((center + *v) * 0.5);
+	}
+}
+
 pub fn split_long_polygons(polygons: &[Polygon], materials: &material::MaterialsMap) -> Vec<Polygon>
 {
 	let mut result = Vec::new();
@@ -53,8 +53,15 @@ pub struct BSPLeaf
 	pub num_polygons: u32,
 	pub first_leaf_portal: u32,
 	pub num_leaf_portals: u32,
+	// One of CONTENTS_* constants.
+	pub contents: u32,
 }
 
+pub const CONTENTS_EMPTY: u32 = 0;
+pub const CONTENTS_WATER: u32 = 1;
+pub const CONTENTS_SLIME: u32 = 2;
+pub const CONTENTS_LAVA: u32 = 3;
+
 #[repr(C)]
 #[derive(Clone, Copy)]
 pub struct Polygon
@@ -205,6 +212,19 @@ pub fn get_map_string(s: StringRef, map: &BSPMap) -> &str
 	std::str::from_utf8(&map.strings_data[(s.offset as usize) .. ((s.offset + s.size) as usize)]).unwrap_or("")
 }
 
+pub fn get_entity_key_value<'a>(map: &'a BSPMap, entity: &Entity, key: &str) -> Option<&'a str>
+{
+	for key_value_pair in &map.key_value_pairs[(entity.first_key_value_pair as usize) ..
+		((entity.first_key_value_pair + entity.num_key_value_pairs) as usize)]
+	{
+		if get_map_string(key_value_pair.key, map) == key
+		{
+			return Some(get_map_string(key_value_pair.value, map));
+		}
+	}
+	None
+}
+
 pub fn get_texture_string(texture_name: &Texture) -> &str
 {
 	let null_pos = texture_name
@@ -275,6 +295,18 @@ pub fn get_leaf_for_point(map: &BSPMap, point: &Vec3f) -> u32
 	}
 }
 
+// Get contents of leaf containing given point.
+// Use it in order to check if camera or some object is inside liquid.
+pub fn get_point_contents(map: &BSPMap, point: &Vec3f) -> u32
+{
+	map.leafs[get_leaf_for_point(map, point) as usize].contents
+}
+
+pub fn is_liquid_contents(contents: u32) -> bool
+{
+	contents == CONTENTS_WATER || contents == CONTENTS_SLIME || contents == CONTENTS_LAVA
+}
+
 pub fn get_root_node_index(map: &BSPMap) -> u32
 {
 	(map.nodes.len() - 1) as u32
@@ -190,6 +190,13 @@ fn convert_leaf_to_compact_format(
 		num_polygons: polygons_splitted.len() as u32,
 		first_leaf_portal,
 		num_leaf_portals: leaf.portals.len() as u32,
+		contents: match leaf.liquid
+		{
+			None => CONTENTS_EMPTY,
+			Some(LiquidType::Water) => CONTENTS_WATER,
+			Some(LiquidType::Slime) => CONTENTS_SLIME,
+			Some(LiquidType::Lava) => CONTENTS_LAVA,
+		},
 	}
 }
 
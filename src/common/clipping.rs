@@ -188,8 +188,10 @@ pub fn get_line_plane_intersection(v0: &Vec3f, v1: &Vec3f, plane: &Plane) -> Vec
 pub struct ModelVertex2d
 {
 	pub pos: Vec2f,
+	pub inv_z: f32,
 	pub tc: Vec2f,
 	pub light: [f32; 3],
+	pub tangent_space_light: ModelVertexTangentSpaceLight,
 }
 
 #[derive(Copy, Clone)]
@@ -198,6 +200,31 @@ pub struct ModelVertex3d
 	pub pos: Vec3f,
 	pub tc: Vec2f,
 	pub light: [f32; 3],
+	pub tangent_space_light: ModelVertexTangentSpaceLight,
+}
+
+// Light data for models with normal maps. All vectors are in tangent space.
+// Zero for models without normal maps.
+#[derive(Copy, Clone)]
+pub struct ModelVertexTangentSpaceLight
+{
+	// Direction to dominant light, scaled by light intensity.
+	pub light_vector_scaled: Vec3f,
+	pub light_color: [f32; 3],
+	// Used for specular.
+	pub vec_to_camera: Vec3f,
+}
+
+impl ModelVertexTangentSpaceLight
+{
+	pub fn zero() -> Self
+	{
+		Self {
+			light_vector_scaled: Vec3f::zero(),
+			light_color: [0.0; 3],
+			vec_to_camera: Vec3f::zero(),
+		}
+	}
 }
 
 pub fn clip_2d_model_polygon(
@@ -321,12 +348,19 @@ fn get_model_line_line_intersection(v0: &ModelVertex2d, v1: &ModelVertex2d, line
 	let k1 = dist1 / dist_sum;
 	ModelVertex2d {
 		pos: v0.pos * k1 - v1.pos * k0,
+		inv_z: v0.inv_z * k1 - v1.inv_z * k0,
 		tc: v0.tc * k1 - v1.tc * k0,
 		light: [
 			v0.light[0] * k1 - v1.light[0] * k0,
 			v0.light[1] * k1 - v1.light[1] * k0,
 			v0.light[2] * k1 - v1.light[2] * k0,
 		],
+		tangent_space_light: get_tangent_space_light_intersection(
+			&v0.tangent_space_light,
+			&v1.tangent_space_light,
+			k0,
+			k1,
+		),
 	}
 }
 
@@ -345,5 +379,29 @@ fn get_model_line_plane_intersection(v0: &ModelVertex3d, v1: &ModelVertex3d, pla
 			v0.light[1] * k1 - v1.light[1] * k0,
 			v0.light[2] * k1 - v1.light[2] * k0,
 		],
+		tangent_space_light: get_tangent_space_light_intersection(
+			&v0.tangent_space_light,
+			&v1.tangent_space_light,
+			k0,
+			k1,
+		),
+	}
+}
+
+fn get_tangent_space_light_intersection(
+	l0: &ModelVertexTangentSpaceLight,
+	l1: &ModelVertexTangentSpaceLight,
+	k0: f32,
+	k1: f32,
+) -> ModelVertexTangentSpaceLight
+{
+	ModelVertexTangentSpaceLight {
+		light_vector_scaled: l0.light_vector_scaled * k1 - l1.light_vector_scaled * k0,
+		light_color: [
+			l0.light_color[0] * k1 - l1.light_color[0] * k0,
+			l0.light_color[1] * k1 - l1.light_color[1] * k0,
+			l0.light_color[2] * k1 - l1.light_color[2] * k0,
+		],
+		vec_to_camera: l0.vec_to_camera * k1 - l1.vec_to_camera * k0,
 	}
 }
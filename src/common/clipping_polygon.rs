@@ -137,6 +137,26 @@ impl ClippingPolygon
 		self.x_minus_y.increase(delta);
 	}
 
+	// Flip relative to vertical line x = viewport_width / 2.
+	pub fn flip_x(&mut self, viewport_width: f32)
+	{
+		let x = self.x;
+		let x_plus_y = self.x_plus_y;
+		let x_minus_y = self.x_minus_y;
+		self.x = ClipAxis {
+			min: viewport_width - x.max,
+			max: viewport_width - x.min,
+		};
+		self.x_plus_y = ClipAxis {
+			min: viewport_width - x_minus_y.max,
+			max: viewport_width - x_minus_y.min,
+		};
+		self.x_minus_y = ClipAxis {
+			min: viewport_width - x_plus_y.max,
+			max: viewport_width - x_plus_y.min,
+		};
+	}
+
 	pub fn get_clip_planes(&self) -> ClippingPolygonPlanes
 	{
 		[
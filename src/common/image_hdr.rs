@@ -0,0 +1,304 @@
+// Loader and saver for Radiance HDR (.hdr) images with RGBE pixels.
+
+#[derive(Default, Clone)]
+pub struct ImageHDR
+{
+	pub size: [u32; 2],
+	// Linear RGB values, 1.0 - maximum of regular (LDR) images.
+	pub pixels: Vec<[f32; 3]>,
+}
+
+pub fn is_hdr_file_name(file_name: &str) -> bool
+{
+	file_name.to_lowercase().ends_with(".hdr")
+}
+
+// Maximum width or height of loaded image.
+pub const MAX_IMAGE_SIZE: u32 = 1 << 14;
+
+// Returns error message in case of failure.
+pub fn load(file_path: &std::path::Path) -> Result<ImageHDR, String>
+{
+	let data = std::fs::read(file_path).map_err(|e| format!("Failed to read HDR image {:?}: {}", file_path, e))?;
+	parse(&data).map_err(|e| format!("Failed to parse HDR image {:?}: {}", file_path, e))
+}
+
+// Returns error message in case of failure.
+pub fn save(image: &ImageHDR, file_path: &std::path::Path) -> Result<(), String>
+{
+	let width = image.size[0] as usize;
+	let height = image.size[1] as usize;
+	let num_pixels = width
+		.checked_mul(height)
+		.ok_or_else(|| format!("Invalid HDR image size: {:?}", image.size))?;
+	if image.pixels.len() < num_pixels
+	{
+		return Err(format!("Not enough pixels for HDR image of size {:?}", image.size));
+	}
+
+	let mut data = format!(
+		"#?RADIANCE\nFORMAT=32-bit_rle_rgbe\n\n-Y {} +X {}\n",
+		image.size[1], image.size[0]
+	)
+	.into_bytes();
+	data.reserve(num_pixels * 4);
+
+	let mut scanline = vec![[0u8; 4]; width];
+	for y in 0 .. height
+	{
+		for (dst, src) in scanline.iter_mut().zip(&image.pixels[y * width .. (y + 1) * width])
+		{
+			*dst = rgb_to_rgbe(src);
+		}
+		write_scanline(&scanline, &mut data);
+	}
+
+	std::fs::write(file_path, data).map_err(|e| format!("Failed to save HDR image {:?}: {}", file_path, e))
+}
+
+fn parse(data: &[u8]) -> Result<ImageHDR, String>
+{
+	let mut pos = 0;
+
+	let signature = read_line(data, &mut pos).ok_or_else(unexpected_end_of_data)?;
+	if !signature.starts_with("#?")
+	{
+		return Err("Invalid signature".to_string());
+	}
+
+	// Read header lines until empty line.
+	loop
+	{
+		let line = read_line(data, &mut pos).ok_or_else(unexpected_end_of_data)?;
+		if line.is_empty()
+		{
+			break;
+		}
+		if let Some(format) = line.strip_prefix("FORMAT=")
+		{
+			if format != "32-bit_rle_rgbe"
+			{
+				return Err(format!("Unsupported format: {}", format));
+			}
+		}
+	}
+
+	// Support only standard orientation - rows from top to bottom, pixels from left to right.
+	let resolution_line = read_line(data, &mut pos).ok_or_else(unexpected_end_of_data)?;
+	let resolution_parts = resolution_line.split_whitespace().collect::<Vec<_>>();
+	if resolution_parts.len() != 4 || resolution_parts[0] != "-Y" || resolution_parts[2] != "+X"
+	{
+		return Err(format!("Unsupported orientation: {}", resolution_line));
+	}
+	let height = resolution_parts[1]
+		.parse::<u32>()
+		.map_err(|_| format!("Invalid height: {}", resolution_parts[1]))?;
+	let width = resolution_parts[3]
+		.parse::<u32>()
+		.map_err(|_| format!("Invalid width: {}", resolution_parts[3]))?;
+	if width == 0 || height == 0 || width > MAX_IMAGE_SIZE || height > MAX_IMAGE_SIZE
+	{
+		return Err(format!("Invalid size: {}x{}", width, height));
+	}
+	let num_pixels = (width as usize)
+		.checked_mul(height as usize)
+		.ok_or_else(|| format!("Invalid size: {}x{}", width, height))?;
+
+	let mut result = ImageHDR {
+		size: [width, height],
+		pixels: Vec::with_capacity(num_pixels),
+	};
+
+	let mut scanline = vec![[0u8; 4]; width as usize];
+	for y in 0 .. height
+	{
+		read_scanline(data, &mut pos, &mut scanline).ok_or_else(|| format!("Invalid scanline {}", y))?;
+		result.pixels.extend(scanline.iter().map(rgbe_to_rgb));
+	}
+
+	Ok(result)
+}
+
+fn unexpected_end_of_data() -> String
+{
+	"Unexpected end of data".to_string()
+}
+
+fn read_line(data: &[u8], pos: &mut usize) -> Option<String>
+{
+	let start = *pos;
+	while *pos < data.len() && data[*pos] != b'\n'
+	{
+		*pos += 1;
+	}
+	if *pos >= data.len()
+	{
+		return None;
+	}
+	let line = String::from_utf8_lossy(&data[start .. *pos]).trim().to_string();
+	*pos += 1;
+	Some(line)
+}
+
+fn read_byte(data: &[u8], pos: &mut usize) -> Option<u8>
+{
+	let b = *data.get(*pos)?;
+	*pos += 1;
+	Some(b)
+}
+
+fn read_scanline(data: &[u8], pos: &mut usize, scanline: &mut [[u8; 4]]) -> Option<()>
+{
+	let width = scanline.len();
+	let header = data.get(*pos .. *pos + 4)?;
+	if width < 8 || width > 0x7FFF || header[0] != 2 || header[1] != 2 || (header[2] & 0x80) != 0
+	{
+		return read_scanline_old_format(data, pos, scanline);
+	}
+
+	if ((header[2] as usize) << 8 | (header[3] as usize)) != width
+	{
+		return None;
+	}
+	*pos += 4;
+
+	// New run-length encoding - each component is stored separately.
+	for component in 0 .. 4
+	{
+		let mut x = 0;
+		while x < width
+		{
+			let count = read_byte(data, pos)? as usize;
+			if count > 128
+			{
+				let count = count - 128;
+				if x + count > width
+				{
+					return None;
+				}
+				let value = read_byte(data, pos)?;
+				for pixel in &mut scanline[x .. x + count]
+				{
+					pixel[component] = value;
+				}
+				x += count;
+			}
+			else
+			{
+				if count == 0 || x + count > width
+				{
+					return None;
+				}
+				for pixel in &mut scanline[x .. x + count]
+				{
+					pixel[component] = read_byte(data, pos)?;
+				}
+				x += count;
+			}
+		}
+	}
+
+	Some(())
+}
+
+// Read flat pixels, possibly with old-style run-length encoding.
+fn read_scanline_old_format(data: &[u8], pos: &mut usize, scanline: &mut [[u8; 4]]) -> Option<()>
+{
+	let width = scanline.len();
+	let mut x = 0;
+	let mut repeat_shift = 0;
+	while x < width
+	{
+		let bytes = data.get(*pos .. *pos + 4)?;
+		*pos += 4;
+		let pixel = [bytes[0], bytes[1], bytes[2], bytes[3]];
+
+		if pixel[0] == 1 && pixel[1] == 1 && pixel[2] == 1
+		{
+			// Repeat previous pixel.
+			if x == 0
+			{
+				return None;
+			}
+			// Reject too many consecutive repeat records, which cause shift overflow.
+			let count = (pixel[3] as usize).checked_shl(repeat_shift)?;
+			if count > width - x
+			{
+				return None;
+			}
+			let prev_pixel = scanline[x - 1];
+			for dst in &mut scanline[x .. x + count]
+			{
+				*dst = prev_pixel;
+			}
+			x += count;
+			repeat_shift += 8;
+		}
+		else
+		{
+			scanline[x] = pixel;
+			x += 1;
+			repeat_shift = 0;
+		}
+	}
+
+	Some(())
+}
+
+fn write_scanline(scanline: &[[u8; 4]], data: &mut Vec<u8>)
+{
+	let width = scanline.len();
+	if width < 8 || width > 0x7FFF
+	{
+		// Use flat format for too short and too long lines.
+		for pixel in scanline
+		{
+			data.extend_from_slice(pixel);
+		}
+		return;
+	}
+
+	// Use new run-length encoding format, but write only non-repeated runs.
+	data.extend_from_slice(&[2, 2, (width >> 8) as u8, (width & 0xFF) as u8]);
+	for component in 0 .. 4
+	{
+		for chunk in scanline.chunks(128)
+		{
+			data.push(chunk.len() as u8);
+			data.extend(chunk.iter().map(|pixel| pixel[component]));
+		}
+	}
+}
+
+fn rgb_to_rgbe(rgb: &[f32; 3]) -> [u8; 4]
+{
+	let max = rgb[0].max(rgb[1]).max(rgb[2]);
+	if max <= 1.0e-32
+	{
+		return [0, 0, 0, 0];
+	}
+
+	let exponent = max.log2().floor() as i32 + 1;
+	let scale = 2.0f32.powi(8 - exponent);
+	[
+		(rgb[0].max(0.0) * scale).min(255.0) as u8,
+		(rgb[1].max(0.0) * scale).min(255.0) as u8,
+		(rgb[2].max(0.0) * scale).min(255.0) as u8,
+		(exponent + 128).max(0).min(255) as u8,
+	]
+}
+
+fn rgbe_to_rgb(rgbe: &[u8; 4]) -> [f32; 3]
+{
+	if rgbe[3] == 0
+	{
+		return [0.0, 0.0, 0.0];
+	}
+
+	let scale = 2.0f32.powi(rgbe[3] as i32 - (128 + 8));
+	[
+		(rgbe[0] as f32 + 0.5) * scale,
+		(rgbe[1] as f32 + 0.5) * scale,
+		(rgbe[2] as f32 + 0.5) * scale,
+	]
+}
@@ -19,6 +19,8 @@ pub struct Polygon
 pub struct Entity
 {
 	pub polygons: Vec<Polygon>,
+	// Range of polygons for each brush. Polygons of each brush form convex volume.
+	pub brushes: Vec<std::ops::Range<usize>>,
 	pub keys: std::collections::HashMap<String, String>,
 }
 
@@ -46,13 +48,17 @@ pub fn polygonize_map_q4<TextureSizeGetter: FnMut(&str) -> [u32; 2]>(
 fn polygonize_entity(input_entity: &map_file_q1::Entity) -> Entity
 {
 	let mut polygons = Vec::new();
+	let mut brushes = Vec::with_capacity(input_entity.brushes.len());
 	for brush in &input_entity.brushes
 	{
+		let first_polygon = polygons.len();
 		polygons.append(&mut polygonize_brush(brush));
+		brushes.push(first_polygon .. polygons.len());
 	}
 
 	Entity {
 		polygons,
+		brushes,
 		keys: input_entity.keys.clone(),
 	}
 }
@@ -60,13 +66,17 @@ fn polygonize_entity(input_entity: &map_file_q1::Entity) -> Entity
 fn polygonize_entity_q4(input_entity: &map_file_q4::Entity) -> Entity
 {
 	let mut polygons = Vec::new();
+	let mut brushes = Vec::with_capacity(input_entity.brushes.len());
 	for brush in &input_entity.brushes
 	{
+		let first_polygon = polygons.len();
 		polygons.append(&mut polygonize_brush_q4(brush));
+		brushes.push(first_polygon .. polygons.len());
 	}
 
 	Entity {
 		polygons,
+		brushes,
 		keys: input_entity.keys.clone(),
 	}
 }
@@ -0,0 +1,225 @@
+use super::{bbox::*, bsp_map_compact, clipping::*, math_types::*};
+
+#[derive(Copy, Clone)]
+pub struct TraceResult
+{
+	pub polygon_index: u32,
+	pub pos: Vec3f,
+}
+
+// Find intersection of segment with polygons of map BSP tree, nearest to segment start.
+// Only polygons for which given filter returns true are checked.
+// Submodels polygons are not checked.
+pub fn trace_segment<PolygonFilter: Fn(&bsp_map_compact::Polygon) -> bool>(
+	map: &bsp_map_compact::BSPMap,
+	from: &Vec3f,
+	to: &Vec3f,
+	polygon_filter: &PolygonFilter,
+) -> Option<TraceResult>
+{
+	let root_node = bsp_map_compact::get_root_node_index(map);
+	trace_segment_r(from, to, root_node, map, polygon_filter)
+}
+
+// Find intersection of segment with polygons of given submodel, nearest to segment start.
+// Segment should be in submodel space.
+pub fn trace_segment_submodel<PolygonFilter: Fn(&bsp_map_compact::Polygon) -> bool>(
+	map: &bsp_map_compact::BSPMap,
+	submodel_index: u32,
+	from: &Vec3f,
+	to: &Vec3f,
+	polygon_filter: &PolygonFilter,
+) -> Option<TraceResult>
+{
+	let submodel = &map.submodels[submodel_index as usize];
+	get_nearest_polygon_intersection(
+		from,
+		to,
+		submodel.first_polygon .. submodel.first_polygon + submodel.num_polygons,
+		map,
+		polygon_filter,
+	)
+}
+
+// Returns fraction of segment, where segment enters given box, or None, if there is no intersection.
+pub fn get_segment_bbox_entry_fraction(start: &Vec3f, end: &Vec3f, bbox: &BBox) -> Option<f32>
+{
+	let mut t_min = 0.0f32;
+	let mut t_max = 1.0f32;
+	let dir = end - start;
+	for i in 0 .. 3
+	{
+		if dir[i] == 0.0
+		{
+			if start[i] < bbox.min[i] || start[i] > bbox.max[i]
+			{
+				return None;
+			}
+			continue;
+		}
+
+		let inv_dir = 1.0 / dir[i];
+		let t0 = (bbox.min[i] - start[i]) * inv_dir;
+		let t1 = (bbox.max[i] - start[i]) * inv_dir;
+		t_min = t_min.max(t0.min(t1));
+		t_max = t_max.min(t0.max(t1));
+	}
+
+	if t_min <= t_max
+	{
+		Some(t_min)
+	}
+	else
+	{
+		None
+	}
+}
+
+// Speed-up intersection calculation - recursively determine location of segment within BSP tree.
+// Than check only leafs where segment is actually located.
+fn trace_segment_r<PolygonFilter: Fn(&bsp_map_compact::Polygon) -> bool>(
+	from: &Vec3f,
+	to: &Vec3f,
+	current_index: u32,
+	map: &bsp_map_compact::BSPMap,
+	polygon_filter: &PolygonFilter,
+) -> Option<TraceResult>
+{
+	if current_index >= bsp_map_compact::FIRST_LEAF_INDEX
+	{
+		let leaf_index = current_index - bsp_map_compact::FIRST_LEAF_INDEX;
+		let leaf = &map.leafs[leaf_index as usize];
+		return get_nearest_polygon_intersection(
+			from,
+			to,
+			leaf.first_polygon .. leaf.first_polygon + leaf.num_polygons,
+			map,
+			polygon_filter,
+		);
+	}
+
+	let node = &map.nodes[current_index as usize];
+	let dist_from = from.dot(node.plane.vec) - node.plane.dist;
+	let dist_to = to.dot(node.plane.vec) - node.plane.dist;
+	if dist_from >= 0.0 && dist_to >= 0.0
+	{
+		return trace_segment_r(from, to, node.children[0], map, polygon_filter);
+	}
+	if dist_from <= 0.0 && dist_to <= 0.0
+	{
+		return trace_segment_r(from, to, node.children[1], map, polygon_filter);
+	}
+
+	// Split segment using BSP node plane.
+
+	let dist_sum = dist_to - dist_from;
+	if dist_sum.abs() < MIN_POSITIVE_VALUE
+	{
+		// Segment is almost on node plane.
+		return None;
+	}
+	let k_from = dist_from / dist_sum;
+	let k_to = dist_to / dist_sum;
+	let intersection_pos = from * k_to - to * k_from;
+
+	let (child_from, child_to) = if dist_from > 0.0 { (0, 1) } else { (1, 0) };
+
+	// HACK!
+	// There is some problems with intersection detection if intersection polygon plane is same as BSP plane.
+	// So, extend segment a little bit behind splitter plane.
+	let eps = 1.0 / 1024.0;
+	let intersection_pos_from = intersection_pos * (1.0 - eps) + from * eps;
+	let intersection_pos_to = intersection_pos * (1.0 - eps) + to * eps;
+
+	// Trace using from -> to order in order to find nearest point.
+	let result_from = trace_segment_r(
+		from,
+		&intersection_pos_to,
+		node.children[child_from],
+		map,
+		polygon_filter,
+	);
+	if result_from.is_some()
+	{
+		return result_from;
+	}
+
+	trace_segment_r(&intersection_pos_from, to, node.children[child_to], map, polygon_filter)
+}
+
+fn get_nearest_polygon_intersection<PolygonFilter: Fn(&bsp_map_compact::Polygon) -> bool>(
+	from: &Vec3f,
+	to: &Vec3f,
+	polygons_range: std::ops::Range<u32>,
+	map: &bsp_map_compact::BSPMap,
+	polygon_filter: &PolygonFilter,
+) -> Option<TraceResult>
+{
+	let mut result: Option<TraceResult> = None;
+	for polygon_index in polygons_range
+	{
+		let polygon = &map.polygons[polygon_index as usize];
+		if !polygon_filter(polygon) || !edge_intersects_with_polygon(from, to, polygon, map)
+		{
+			continue;
+		}
+
+		let pos = get_line_plane_intersection(from, to, &polygon.plane);
+		let closer = match &result
+		{
+			Some(prev) => (from - pos).magnitude2() < (from - prev.pos).magnitude2(),
+			None => true,
+		};
+		if closer
+		{
+			result = Some(TraceResult { polygon_index, pos });
+		}
+	}
+	result
+}
+
+pub fn edge_intersects_with_polygon(
+	v0: &Vec3f,
+	v1: &Vec3f,
+	polygon: &bsp_map_compact::Polygon,
+	map: &bsp_map_compact::BSPMap,
+) -> bool
+{
+	let plane = &polygon.plane;
+
+	let dist0 = v0.dot(plane.vec) - plane.dist;
+	let dist1 = v1.dot(plane.vec) - plane.dist;
+	if dist0.signum() == dist1.signum()
+	{
+		// Edge is located at one side of polygon plane.
+		return false;
+	}
+	let dist_sum = dist1 - dist0;
+	if dist_sum.abs() < MIN_POSITIVE_VALUE
+	{
+		// Edge is almost on polygon plane.
+		return false;
+	}
+	let k0 = dist0 / dist_sum;
+	let k1 = dist1 / dist_sum;
+	let intersection_pos = v0 * k1 - v1 * k0;
+
+	// TODO - extend polygon just a bit, to prevent light leakage between adjusted polygons.
+	for i in 0 .. polygon.num_vertices
+	{
+		let v = map.vertices[(polygon.first_vertex + i) as usize];
+		let next_v = map.vertices[(polygon.first_vertex + (i + 1) % polygon.num_vertices) as usize];
+		let edge_vec = next_v - v;
+		let vec_to_instersection_pos = intersection_pos - v;
+		let cross = vec_to_instersection_pos.cross(edge_vec);
+		let normal_dot = cross.dot(plane.vec);
+		if normal_dot < 0.0
+		{
+			return false;
+		}
+	}
+
+	true
+}
+
+pub const MIN_POSITIVE_VALUE: f32 = 1.0 / ((1 << 30) as f32);
@@ -56,6 +56,13 @@ pub struct Material
 	#[serde(default)]
 	pub emissive_light: [f32; 3],
 
+	/// Emissive texture path. Emissive texels are added after lighting, so they are bright even in darkness.
+	pub emissive_texture: Option<String>,
+
+	/// Emissive texture is modulated by this value. Use values greater than 1 in order to produce bloom in HDR mode.
+	#[serde(default = "default_one")]
+	pub emissive_texture_brightness: f32,
+
 	/// If some - polygons with such materials are semitransparent.
 	#[serde(default)]
 	pub blending_mode: BlendingMode,
@@ -67,6 +74,25 @@ pub struct Material
 	/// If some - this is a skybox.
 	#[serde(default)]
 	pub skybox: Option<SkyboxParams>,
+
+	/// If some - polygons with this material are planar mirrors.
+	#[serde(default)]
+	pub mirror: Option<MirrorParams>,
+
+	/// If some - texture of this material is replaced with image of camera view with given name.
+	/// Use it for security monitors, etc.
+	#[serde(default)]
+	pub camera_view: Option<String>,
+
+	/// If some - brushes with this material form liquid volumes.
+	/// BSP leafs inside such brushes have corresponding contents.
+	/// Normally such materials should not block view.
+	#[serde(default)]
+	pub liquid: Option<LiquidType>,
+
+	/// If some - diffuse texture is animated (flipbook).
+	#[serde(default)]
+	pub animation: Option<TextureAnimationParams>,
 }
 
 #[derive(Serialize, Deserialize, Debug, Copy, Clone, PartialEq)]
@@ -87,6 +113,14 @@ impl Default for BlendingMode
 	}
 }
 
+#[derive(Serialize, Deserialize, Debug, Copy, Clone, PartialEq)]
+pub enum LiquidType
+{
+	Water,
+	Slime,
+	Lava,
+}
+
 #[derive(Serialize, Deserialize, Debug, Copy, Clone)]
 pub struct TurbParams
 {
@@ -104,15 +138,130 @@ pub struct TurbParams
 	pub scroll_speed: [f32; 2],
 }
 
+#[derive(Serialize, Deserialize, Debug, Copy, Clone)]
+pub struct MirrorParams
+{
+	/// If true - draw texture of this material over reflection, using blending mode of this material.
+	/// Use it for reflective water, dirty mirrors, etc.
+	#[serde(default)]
+	pub draw_texture: bool,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct TextureAnimationParams
+{
+	/// Diffuse images of animation frames. Other textures (normal map, roughness map, emissive) are shared.
+	#[serde(default)]
+	pub frames: Vec<String>,
+
+	/// If some - frames are extracted from single image, consisting of frames placed vertically.
+	/// Used only if "frames" list is empty.
+	#[serde(default)]
+	pub frame_strip: Option<FrameStripParams>,
+
+	/// Animation speed. If zero - frames are switched only via game code.
+	#[serde(default)]
+	pub frames_per_second: f32,
+
+	#[serde(default)]
+	pub loop_mode: AnimationLoopMode,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct FrameStripParams
+{
+	pub image: String,
+	pub num_frames: u32,
+}
+
+#[derive(Serialize, Deserialize, Debug, Copy, Clone, PartialEq)]
+pub enum AnimationLoopMode
+{
+	/// Start from first frame after reaching last frame.
+	Loop,
+	/// Stop at last frame.
+	Once,
+	/// Play forward, than backward.
+	PingPong,
+}
+
+impl Default for AnimationLoopMode
+{
+	fn default() -> Self
+	{
+		AnimationLoopMode::Loop
+	}
+}
+
 #[derive(Serialize, Deserialize, Debug, Clone)]
 pub struct SkyboxParams
 {
 	/// Side images in order -X, +X, -Y, +Y, -Z, +Z. If empty - side is not drawn.
+	/// Radiance HDR images (.hdr) are supported, they preserve full dynamic range in HDR rendering mode.
+	#[serde(default)]
 	pub side_images: [String; 6],
 
 	// Side images are modulated by this value.
 	#[serde(default = "default_one")]
 	pub brightness: f32,
+
+	/// If some - side images are ignored and sky is generated procedurally.
+	#[serde(default)]
+	pub procedural: Option<ProceduralSkyParams>,
+}
+
+/// Colors are linear, 1.0 - regular brightness.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(default)]
+pub struct ProceduralSkyParams
+{
+	pub zenith_color: [f32; 3],
+	pub horizon_color: [f32; 3],
+	pub ground_color: [f32; 3],
+	/// Color of horizon near sun, when sun is low.
+	pub sunset_color: [f32; 3],
+
+	/// Direction towards sun. Used if frame has no sun parameters.
+	pub sun_direction: [f32; 3],
+	/// Used if frame has no sun parameters.
+	pub sun_color: [f32; 3],
+	/// In degrees.
+	pub sun_angular_size: f32,
+	/// Sun disc color is sun color multiplied by this value. Use large values to produce bloom in HDR mode.
+	pub sun_disc_brightness: f32,
+
+	/// Fraction of sky, covered by clouds. 0 - no clouds.
+	pub cloud_coverage: f32,
+	/// Scale of clouds noise.
+	pub cloud_scale: f32,
+	/// Clouds scrolling speed.
+	pub cloud_speed: [f32; 2],
+	pub cloud_color: [f32; 3],
+
+	/// Size of each generated sky side texture.
+	pub texture_size: u32,
+}
+
+impl Default for ProceduralSkyParams
+{
+	fn default() -> Self
+	{
+		Self {
+			zenith_color: [0.15, 0.35, 0.8],
+			horizon_color: [0.6, 0.75, 0.9],
+			ground_color: [0.25, 0.22, 0.2],
+			sunset_color: [1.0, 0.45, 0.15],
+			sun_direction: [0.3, 0.2, 0.9],
+			sun_color: [1.0, 1.0, 1.0],
+			sun_angular_size: 1.5,
+			sun_disc_brightness: 32.0,
+			cloud_coverage: 0.0,
+			cloud_scale: 1.0,
+			cloud_speed: [0.02, 0.01],
+			cloud_color: [1.0, 1.0, 1.0],
+			texture_size: 128,
+		}
+	}
 }
 
 impl Default for Material
@@ -132,9 +281,15 @@ impl Default for Material
 			light: true,
 			decals: true,
 			emissive_light: [0.0, 0.0, 0.0],
+			emissive_texture: None,
+			emissive_texture_brightness: 1.0,
 			blending_mode: BlendingMode::None,
 			turb: None,
 			skybox: None,
+			mirror: None,
+			camera_view: None,
+			liquid: None,
+			animation: None,
 		}
 	}
 }
@@ -1,4 +1,4 @@
-use super::math_types::*;
+use super::{math_types::*, plane::*};
 
 #[derive(Copy, Clone)]
 pub struct CameraMatrices
@@ -89,6 +89,86 @@ pub fn complete_view_matrix(
 	}
 }
 
+// Build camera matrices for view reflected relative to given plane.
+// Result image is also flipped horizontally in order to preserve vertices order of reflected polygons.
+pub fn build_reflected_camera_matrices(
+	camera_matrices: &CameraMatrices,
+	plane: &Plane,
+	viewport_width: f32,
+	viewport_height: f32,
+) -> CameraMatrices
+{
+	let normal_length = plane.vec.magnitude();
+	let n = plane.vec / normal_length;
+	let dist = plane.dist / normal_length;
+
+	// p' = p - 2 * (dot(n, p) - dist) * n
+	let mut reflection = Mat4f::identity();
+	for i in 0 .. 3
+	{
+		for j in 0 .. 3
+		{
+			reflection[j][i] -= 2.0 * n[i] * n[j];
+		}
+		reflection.w[i] = 2.0 * dist * n[i];
+	}
+
+	// Flip screen x relative to viewport center.
+	let mut flip_x = Mat4f::identity();
+	flip_x.x.x = -1.0;
+
+	let shift_to_viewport_center =
+		Mat4f::from_translation(Vec3f::new(viewport_width * 0.5, viewport_height * 0.5, 0.0));
+	let shift_from_viewport_center =
+		Mat4f::from_translation(Vec3f::new(-viewport_width * 0.5, -viewport_height * 0.5, 0.0));
+
+	let mut planes_shift_to_viewport_center = Mat4f::identity();
+	planes_shift_to_viewport_center.x.z = -viewport_width * 0.5;
+	planes_shift_to_viewport_center.y.z = -viewport_height * 0.5;
+	let planes_shift_from_viewport_center = planes_shift_to_viewport_center.invert().unwrap();
+
+	CameraMatrices {
+		position: (reflection * camera_matrices.position.extend(1.0)).truncate(),
+		view_matrix: shift_to_viewport_center *
+			flip_x * shift_from_viewport_center *
+			camera_matrices.view_matrix *
+			reflection,
+		// Reflection matrix is inverse to itself, so, use just transposed matrix.
+		planes_matrix: planes_shift_to_viewport_center *
+			flip_x * planes_shift_from_viewport_center *
+			camera_matrices.planes_matrix *
+			reflection.transpose(),
+	}
+}
+
+// Build camera matrices for viewport with size scaled relative to size of viewport of source matrices.
+pub fn build_scaled_viewport_camera_matrices(
+	camera_matrices: &CameraMatrices,
+	scale_x: f32,
+	scale_y: f32,
+) -> CameraMatrices
+{
+	// Screen-space planes are transformed using inverse scale.
+	// Shift to viewport center is scaled too, so, there is no need to correct it.
+	CameraMatrices {
+		position: camera_matrices.position,
+		view_matrix: Mat4f::from_nonuniform_scale(scale_x, scale_y, 1.0) * camera_matrices.view_matrix,
+		planes_matrix: Mat4f::from_nonuniform_scale(1.0 / scale_x, 1.0 / scale_y, 1.0) * camera_matrices.planes_matrix,
+	}
+}
+
+// Build camera matrices for view, where all world is transformed using given matrix.
+// Transform matrix should preserve orientation (has no reflection).
+pub fn build_transformed_camera_matrices(camera_matrices: &CameraMatrices, transform_matrix: &Mat4f) -> CameraMatrices
+{
+	let transform_matrix_inverse = transform_matrix.invert().unwrap_or_else(Mat4f::identity);
+	CameraMatrices {
+		position: (transform_matrix * camera_matrices.position.extend(1.0)).truncate(),
+		view_matrix: camera_matrices.view_matrix * transform_matrix_inverse,
+		planes_matrix: camera_matrices.planes_matrix * transform_matrix.transpose(),
+	}
+}
+
 pub fn get_object_matrix(position: Vec3f, rotation: QuaternionF) -> Mat4f
 {
 	let rotate = Mat4f::from(rotation);
@@ -96,3 +176,17 @@ pub fn get_object_matrix(position: Vec3f, rotation: QuaternionF) -> Mat4f
 	let translate = Mat4f::from_translation(position);
 	translate * rotate
 }
+
+// Reconstruct world position of point with given screen coordinates and depth (distance along view direction).
+pub fn get_screen_point_world_position(camera_matrices: &CameraMatrices, x: f32, y: f32, z: f32) -> Option<Vec3f>
+{
+	// Solve projection equations (for x, y and w components).
+	let m = &camera_matrices.view_matrix;
+	let mat = Mat3f::from_cols(
+		Vec3f::new(m.x.x, m.x.y, m.x.w),
+		Vec3f::new(m.y.x, m.y.y, m.y.w),
+		Vec3f::new(m.z.x, m.z.y, m.z.w),
+	);
+	let projected = Vec3f::new(x * z - m.w.x, y * z - m.w.y, z - m.w.w);
+	Some(mat.invert()? * projected)
+}
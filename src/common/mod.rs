@@ -10,6 +10,7 @@ pub mod clipping_polygon;
 pub mod color;
 pub mod fixed_math;
 pub mod image;
+pub mod image_hdr;
 pub mod light_cube;
 pub mod light_hemisphere;
 pub mod light_trace;
@@ -20,6 +21,7 @@ pub mod map_file_q1;
 pub mod map_file_q4;
 pub mod map_lights;
 pub mod map_polygonizer;
+pub mod map_trace;
 pub mod material;
 pub mod math_types;
 pub mod matrix;
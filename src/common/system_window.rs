@@ -7,7 +7,7 @@ pub struct SystemWindow
 	sdl2_mouse: sdl2::mouse::MouseUtil,
 }
 
-#[derive(PartialEq)]
+#[derive(Copy, Clone, PartialEq)]
 pub struct SurfaceInfo
 {
 	pub width: usize,
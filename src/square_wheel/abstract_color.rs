@@ -6,8 +6,13 @@ pub trait AbstractColor: Default + Copy + Send + Sync + From<ColorVec> + From<Co
 	fn average(a: Self, b: Self) -> Self;
 	fn saturated_sum(a: Self, b: Self) -> Self;
 	fn get_alpha(self) -> i32;
-	fn alpha_blend(dst: Self, src: Self) -> Self;
+	fn alpha_blend<const SIMD_LEVEL: u32>(dst: Self, src: Self) -> Self;
 	fn test_alpha(self) -> bool;
+
+	// Conversions for code, specialized for SIMD level.
+	fn from_color_vec<const SIMD_LEVEL: u32>(v: SimdColorVec<SIMD_LEVEL>) -> Self;
+	fn from_color_vec_i<const SIMD_LEVEL: u32>(v: SimdColorVecI<SIMD_LEVEL>) -> Self;
+	fn into_color_vec_i<const SIMD_LEVEL: u32>(self) -> SimdColorVecI<SIMD_LEVEL>;
 }
 
 impl AbstractColor for Color32
@@ -27,16 +32,16 @@ impl AbstractColor for Color32
 		(self.get_raw() >> 24) as i32
 	}
 
-	fn alpha_blend(dst: Self, src: Self) -> Self
+	fn alpha_blend<const SIMD_LEVEL: u32>(dst: Self, src: Self) -> Self
 	{
 		let alpha = src.get_alpha();
-		let dst_vec: ColorVecI = dst.into();
-		let src_vec: ColorVecI = src.into();
-		ColorVecI::shift_right::<8>(&ColorVecI::add(
-			&ColorVecI::mul_scalar(&dst_vec, 255 - alpha),
-			&ColorVecI::mul_scalar(&src_vec, alpha),
+		let dst_vec = SimdColorVecI::<SIMD_LEVEL>::from_color32(dst);
+		let src_vec = SimdColorVecI::<SIMD_LEVEL>::from_color32(src);
+		SimdColorVecI::shift_right::<8>(&SimdColorVecI::add(
+			&SimdColorVecI::mul_scalar(&dst_vec, 255 - alpha),
+			&SimdColorVecI::mul_scalar(&src_vec, alpha),
 		))
-		.into()
+		.into_color32()
 	}
 
 	fn test_alpha(self) -> bool
@@ -44,6 +49,21 @@ impl AbstractColor for Color32
 		// TODO - speed-up this?
 		self.get_raw() & 0xFF000000 > 0x7F000000
 	}
+
+	fn from_color_vec<const SIMD_LEVEL: u32>(v: SimdColorVec<SIMD_LEVEL>) -> Self
+	{
+		v.into_color32()
+	}
+
+	fn from_color_vec_i<const SIMD_LEVEL: u32>(v: SimdColorVecI<SIMD_LEVEL>) -> Self
+	{
+		v.into_color32()
+	}
+
+	fn into_color_vec_i<const SIMD_LEVEL: u32>(self) -> SimdColorVecI<SIMD_LEVEL>
+	{
+		SimdColorVecI::from_color32(self)
+	}
 }
 
 impl From<ColorVec> for Color32
@@ -87,16 +107,16 @@ impl AbstractColor for Color64
 		(self.get_raw() >> 48) as i32
 	}
 
-	fn alpha_blend(dst: Self, src: Self) -> Self
+	fn alpha_blend<const SIMD_LEVEL: u32>(dst: Self, src: Self) -> Self
 	{
 		let alpha = src.get_alpha();
-		let dst_vec: ColorVecI = dst.into();
-		let src_vec: ColorVecI = src.into();
-		ColorVecI::shift_right::<8>(&ColorVecI::add(
-			&ColorVecI::mul_scalar(&dst_vec, 255 - alpha),
-			&ColorVecI::mul_scalar(&src_vec, alpha),
+		let dst_vec = SimdColorVecI::<SIMD_LEVEL>::from_color64(dst);
+		let src_vec = SimdColorVecI::<SIMD_LEVEL>::from_color64(src);
+		SimdColorVecI::shift_right::<8>(&SimdColorVecI::add(
+			&SimdColorVecI::mul_scalar(&dst_vec, 255 - alpha),
+			&SimdColorVecI::mul_scalar(&src_vec, alpha),
 		))
-		.into()
+		.into_color64()
 	}
 
 	fn test_alpha(self) -> bool
@@ -104,6 +124,21 @@ impl AbstractColor for Color64
 		// TODO - speed-up this?
 		self.get_raw() & 0xFFFF000000000000 > 0x007F000000000000
 	}
+
+	fn from_color_vec<const SIMD_LEVEL: u32>(v: SimdColorVec<SIMD_LEVEL>) -> Self
+	{
+		v.into_color64()
+	}
+
+	fn from_color_vec_i<const SIMD_LEVEL: u32>(v: SimdColorVecI<SIMD_LEVEL>) -> Self
+	{
+		v.into_color64()
+	}
+
+	fn into_color_vec_i<const SIMD_LEVEL: u32>(self) -> SimdColorVecI<SIMD_LEVEL>
+	{
+		SimdColorVecI::from_color64(self)
+	}
 }
 
 impl From<ColorVec> for Color64
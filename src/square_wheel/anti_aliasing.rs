@@ -0,0 +1,277 @@
+use crate::common::{color::*, system_window};
+use rayon::prelude::*;
+
+#[derive(Copy, Clone, PartialEq)]
+pub enum AntiAliasingMode
+{
+	None,
+	// Post-process edges smoothing, based on luminance.
+	Fxaa,
+	// Render image with doubled resolution and downsample it.
+	Supersampling2x,
+}
+
+impl AntiAliasingMode
+{
+	pub fn from_config_value(value: u32) -> Self
+	{
+		match value
+		{
+			0 => AntiAliasingMode::None,
+			1 => AntiAliasingMode::Fxaa,
+			_ => AntiAliasingMode::Supersampling2x,
+		}
+	}
+}
+
+// Get surface info for supersampled image.
+pub fn get_supersampling_surface_info(surface_info: &system_window::SurfaceInfo) -> system_window::SurfaceInfo
+{
+	system_window::SurfaceInfo {
+		width: surface_info.width * 2,
+		height: surface_info.height * 2,
+		pitch: surface_info.width * 2,
+	}
+}
+
+// Downsample image with 2x size by averaging each 2x2 block.
+pub fn perform_supersampling_downscale(
+	src: &[Color32],
+	src_surface_info: &system_window::SurfaceInfo,
+	dst: &mut [Color32],
+	dst_surface_info: &system_window::SurfaceInfo,
+)
+{
+	let width = dst_surface_info.width.min(src_surface_info.width / 2);
+	let height = dst_surface_info.height.min(src_surface_info.height / 2);
+
+	let func = |(y, dst_line): (usize, &mut [Color32])| {
+		let src_line0 = &src[(y * 2) * src_surface_info.pitch ..];
+		let src_line1 = &src[(y * 2 + 1) * src_surface_info.pitch ..];
+		for (x, dst) in dst_line[.. width].iter_mut().enumerate()
+		{
+			*dst = Color32::get_average_4([
+				src_line0[x * 2],
+				src_line0[x * 2 + 1],
+				src_line1[x * 2],
+				src_line1[x * 2 + 1],
+			]);
+		}
+	};
+
+	if rayon::current_num_threads() == 1
+	{
+		dst.chunks_mut(dst_surface_info.pitch)
+			.take(height)
+			.enumerate()
+			.for_each(func);
+	}
+	else
+	{
+		dst.par_chunks_mut(dst_surface_info.pitch)
+			.take(height)
+			.enumerate()
+			.for_each(func);
+	}
+}
+
+// Smooth edges of given image.
+// Temp buffers are used to store copy of source image and its luminance.
+pub fn perform_fxaa(
+	pixels: &mut [Color32],
+	surface_info: &system_window::SurfaceInfo,
+	src_buffer: &mut Vec<Color32>,
+	luma_buffer: &mut Vec<u8>,
+)
+{
+	let width = surface_info.width;
+	let height = surface_info.height;
+	if width < 3 || height < 3
+	{
+		return;
+	}
+
+	src_buffer.clear();
+	luma_buffer.clear();
+	for line in pixels.chunks(surface_info.pitch).take(height)
+	{
+		src_buffer.extend_from_slice(&line[.. width]);
+		luma_buffer.extend(line[.. width].iter().map(|c| get_luma(*c)));
+	}
+
+	let src = &src_buffer[..];
+	let luma = &luma_buffer[..];
+	let func = |(y, dst_line): (usize, &mut [Color32])| {
+		// Leave border pixels as is.
+		if y == 0 || y + 1 >= height
+		{
+			return;
+		}
+		for x in 1 .. width - 1
+		{
+			if let Some(c) = fxaa_pixel(src, luma, width, height, x, y)
+			{
+				dst_line[x] = c;
+			}
+		}
+	};
+
+	if rayon::current_num_threads() == 1
+	{
+		pixels
+			.chunks_mut(surface_info.pitch)
+			.take(height)
+			.enumerate()
+			.for_each(func);
+	}
+	else
+	{
+		pixels
+			.par_chunks_mut(surface_info.pitch)
+			.take(height)
+			.enumerate()
+			.for_each(func);
+	}
+}
+
+// Returns None if pixel should not be changed.
+fn fxaa_pixel(src: &[Color32], luma: &[u8], width: usize, height: usize, x: usize, y: usize) -> Option<Color32>
+{
+	// Minimum contrast (absolute and relative) of processed edges.
+	const EDGE_THRESHOLD_MIN: i32 = 12;
+	const EDGE_THRESHOLD_SHIFT: i32 = 3;
+	// Maximum distance of edge end search.
+	const MAX_SEARCH_STEPS: usize = 8;
+
+	let offset = x + y * width;
+	let l = |dx: isize, dy: isize| luma[((offset as isize) + dx + dy * (width as isize)) as usize] as i32;
+
+	let c = l(0, 0);
+	let n = l(0, -1);
+	let s = l(0, 1);
+	let w = l(-1, 0);
+	let e = l(1, 0);
+
+	let range_min = c.min(n).min(s).min(w).min(e);
+	let range_max = c.max(n).max(s).max(w).max(e);
+	let range = range_max - range_min;
+	if range < EDGE_THRESHOLD_MIN.max(range_max >> EDGE_THRESHOLD_SHIFT)
+	{
+		return None;
+	}
+
+	let nw = l(-1, -1);
+	let ne = l(1, -1);
+	let sw = l(-1, 1);
+	let se = l(1, 1);
+
+	// Determine edge orientation.
+	let edge_horizontal = (nw - 2 * w + sw).abs() + 2 * (n - 2 * c + s).abs() + (ne - 2 * e + se).abs();
+	let edge_vertical = (nw - 2 * n + ne).abs() + 2 * (w - 2 * c + e).abs() + (sw - 2 * s + se).abs();
+	let is_horizontal = edge_horizontal >= edge_vertical;
+
+	// Choose side of edge with larger gradient.
+	let (luma_neg, luma_pos) = if is_horizontal { (n, s) } else { (w, e) };
+	let gradient_neg = (luma_neg - c).abs();
+	let gradient_pos = (luma_pos - c).abs();
+	let (step_across, luma_across, gradient) = if gradient_neg >= gradient_pos
+	{
+		(-1, luma_neg, gradient_neg)
+	}
+	else
+	{
+		(1, luma_pos, gradient_pos)
+	};
+
+	// Search for edge ends in both directions along the edge.
+	let edge_luma = (c + luma_across) >> 1;
+	let gradient_threshold = gradient >> 2;
+	let (along_dx, along_dy, across_dx, across_dy) = if is_horizontal
+	{
+		(1, 0, 0, step_across)
+	}
+	else
+	{
+		(0, 1, step_across, 0)
+	};
+	let max_steps_neg = if is_horizontal { x } else { y }.min(MAX_SEARCH_STEPS);
+	let max_steps_pos = if is_horizontal { width - 1 - x } else { height - 1 - y }.min(MAX_SEARCH_STEPS);
+	let across_valid = |px: isize, py: isize| {
+		px + across_dx >= 0 &&
+			py + across_dy >= 0 &&
+			px + across_dx < width as isize &&
+			py + across_dy < height as isize
+	};
+
+	let search = |sign: isize, max_steps: usize| -> (usize, i32) {
+		for step in 1 ..= max_steps
+		{
+			let px = (x as isize) + along_dx * sign * (step as isize);
+			let py = (y as isize) + along_dy * sign * (step as isize);
+			if !across_valid(px, py)
+			{
+				return (step, 0);
+			}
+			let pair_luma = (l(px - x as isize, py - y as isize) +
+				l(px + across_dx - x as isize, py + across_dy - y as isize)) >>
+				1;
+			let delta = pair_luma - edge_luma;
+			if delta.abs() >= gradient_threshold
+			{
+				return (step, delta);
+			}
+		}
+		(max_steps + 1, 0)
+	};
+	let (distance_neg, delta_neg) = search(-1, max_steps_neg);
+	let (distance_pos, delta_pos) = search(1, max_steps_pos);
+
+	// Blend only if luminance at nearest edge end changes in direction, opposite to current pixel.
+	let (distance_min, delta_min) = if distance_neg < distance_pos
+	{
+		(distance_neg, delta_neg)
+	}
+	else
+	{
+		(distance_pos, delta_pos)
+	};
+	let center_is_darker = c < edge_luma;
+	let edge_blend = if delta_min != 0 && (delta_min < 0) != center_is_darker
+	{
+		0.5 - (distance_min as f32) / ((distance_neg + distance_pos) as f32)
+	}
+	else
+	{
+		0.0
+	};
+
+	// Blend also isolated pixels, using local average luminance.
+	let luma_average = (2 * (n + s + w + e) + nw + ne + sw + se) as f32 / 12.0;
+	let subpixel = (((luma_average - (c as f32)).abs() / (range as f32)).min(1.0)).powi(2) * 0.75;
+
+	let blend = edge_blend.max(subpixel);
+	if blend <= 0.0
+	{
+		return None;
+	}
+
+	let blend_scaled = (blend * 256.0) as u32;
+	let neighbor_offset = ((offset as isize) + across_dx + across_dy * (width as isize)) as usize;
+	Some(blend_colors(src[offset], src[neighbor_offset], blend_scaled))
+}
+
+// Factor is scaled by 256.
+fn blend_colors(a: Color32, b: Color32, factor: u32) -> Color32
+{
+	let a_rgb = a.get_rgb();
+	let b_rgb = b.get_rgb();
+	let inv_factor = 256 - factor;
+	let mix = |i: usize| (((a_rgb[i] as u32) * inv_factor + (b_rgb[i] as u32) * factor) >> 8) as u8;
+	Color32::from_rgb(mix(0), mix(1), mix(2))
+}
+
+fn get_luma(c: Color32) -> u8
+{
+	let rgb = c.get_rgb();
+	(((rgb[0] as u32) * 77 + (rgb[1] as u32) * 150 + (rgb[2] as u32) * 29) >> 8) as u8
+}
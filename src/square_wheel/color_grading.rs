@@ -0,0 +1,215 @@
+use super::{console, fast_math::*, postprocessor::TonemappingOperator, resources_manager::*};
+use crate::common::{bsp_map_compact, image, map_file_common};
+
+// 3D color lookup table, used for color grading.
+pub struct ColorGradingLut
+{
+	size: usize,
+	// Colors in range [0; 255]. Red index changes fastest, blue index changes slowest.
+	data: Vec<[f32; 3]>,
+}
+
+impl ColorGradingLut
+{
+	// Create LUT from horizontal strip of N slices with size N x N.
+	// Blue is constant for each slice, red changes along X axis, green changes along Y axis.
+	pub fn from_image(image: &image::Image) -> Option<Self>
+	{
+		let size = image.size[1] as usize;
+		if size < 2 || image.size[0] as usize != size * size
+		{
+			return None;
+		}
+
+		let mut data = Vec::with_capacity(size * size * size);
+		for b in 0 .. size
+		{
+			for g in 0 .. size
+			{
+				for r in 0 .. size
+				{
+					let rgb = image.pixels[b * size + r + g * size * size].get_rgb();
+					data.push([rgb[0] as f32, rgb[1] as f32, rgb[2] as f32]);
+				}
+			}
+		}
+
+		Some(Self { size, data })
+	}
+
+	// Input and output colors are in range [0; 255].
+	pub fn apply(&self, c: &[f32; 3]) -> [f32; 3]
+	{
+		let max_coord = (self.size - 1) as f32;
+		let mut coords0 = [0; 3];
+		let mut coords1 = [0; 3];
+		let mut fracts = [0.0; 3];
+		for i in 0 .. 3
+		{
+			let coord = (c[i] * (max_coord / 255.0)).max(0.0).min(max_coord);
+			let coord_floor = coord.floor();
+			coords0[i] = coord_floor as usize;
+			coords1[i] = (coords0[i] + 1).min(self.size - 1);
+			fracts[i] = coord - coord_floor;
+		}
+
+		// Perform trilinear interpolation.
+		let mut result = [0.0; 3];
+		for corner in 0 .. 8
+		{
+			let mut weight = 1.0;
+			let mut offset = 0;
+			let mut stride = 1;
+			for i in 0 .. 3
+			{
+				if (corner >> i) & 1 != 0
+				{
+					weight *= fracts[i];
+					offset += coords1[i] * stride;
+				}
+				else
+				{
+					weight *= 1.0 - fracts[i];
+					offset += coords0[i] * stride;
+				}
+				stride *= self.size;
+			}
+
+			let value = &self.data[offset];
+			for i in 0 .. 3
+			{
+				result[i] += value[i] * weight;
+			}
+		}
+
+		result
+	}
+}
+
+// LUT, prepared for fast usage in LDR postprocessing.
+// Includes also correction of input color components, so it should be rebuilt if correction settings are changed.
+pub struct ColorGradingTable
+{
+	size: usize,
+	// Colors in range [0; 255]. Red index changes fastest, blue index changes slowest.
+	data: Vec<ColorVec>,
+}
+
+impl ColorGradingTable
+{
+	pub fn new() -> Self
+	{
+		Self {
+			size: 0,
+			data: Vec::new(),
+		}
+	}
+
+	// Correction function takes and returns color component in range [0; 255].
+	pub fn update<F: Fn(f32) -> f32>(&mut self, lut: &ColorGradingLut, input_correction_function: F)
+	{
+		let size = lut.size;
+		let step = 255.0 / ((size - 1) as f32);
+		let corrected_components = (0 .. size)
+			.map(|i| input_correction_function((i as f32) * step))
+			.collect::<Vec<_>>();
+
+		self.size = size;
+		self.data.clear();
+		for b in 0 .. size
+		{
+			for g in 0 .. size
+			{
+				for r in 0 .. size
+				{
+					let c = lut.apply(&[
+						corrected_components[r],
+						corrected_components[g],
+						corrected_components[b],
+					]);
+					self.data.push(ColorVec::from_color_f32x3(&c));
+				}
+			}
+		}
+	}
+
+	// Result is in range [0; 255].
+	pub fn apply(&self, rgb: &[u8; 3]) -> ColorVec
+	{
+		let max_coord = self.size - 1;
+		let strides = [1, self.size, self.size * self.size];
+		let mut offset = 0;
+		let mut steps = [0; 3];
+		let mut fracts = [0.0; 3];
+		for i in 0 .. 3
+		{
+			let coord_scaled = (rgb[i] as usize) * max_coord;
+			let coord = coord_scaled / 255;
+			offset += coord * strides[i];
+			// Fraction is zero for last coordinate, so, there is no need to fetch next value.
+			steps[i] = if coord < max_coord { strides[i] } else { 0 };
+			fracts[i] = ((coord_scaled % 255) as f32) * (1.0 / 255.0);
+		}
+
+		let fetch = |o| self.data[offset + o];
+		let lerp = |a: &ColorVec, b: &ColorVec, k| ColorVec::mul_scalar_add(b, k, &ColorVec::scalar_mul(a, 1.0 - k));
+
+		// Perform trilinear interpolation.
+		let mut c = [ColorVec::zero(); 4];
+		for (index, dst) in c.iter_mut().enumerate()
+		{
+			let o = (index & 1) * steps[1] + (index >> 1) * steps[2];
+			*dst = lerp(&fetch(o), &fetch(o + steps[0]), fracts[0]);
+		}
+		let c_g0 = lerp(&c[0], &c[1], fracts[1]);
+		let c_g1 = lerp(&c[2], &c[3], fracts[1]);
+		lerp(&c_g0, &c_g1, fracts[2])
+	}
+}
+
+// Per-map color grading settings, read from world entity keys.
+#[derive(Default)]
+pub struct MapColorGrading
+{
+	pub lut: Option<ColorGradingLut>,
+	// If some - override tonemapping operator from config.
+	pub tonemapping_operator: Option<TonemappingOperator>,
+}
+
+pub fn load_map_color_grading(
+	map: &bsp_map_compact::BSPMap,
+	resources_manager: &mut ResourcesManager,
+	console: &console::ConsoleSharedPtr,
+) -> MapColorGrading
+{
+	let world_entity = if let Some(e) = map.entities.first()
+	{
+		e
+	}
+	else
+	{
+		return MapColorGrading::default();
+	};
+
+	let lut = bsp_map_compact::get_entity_key_value(map, world_entity, "color_grading_lut").and_then(|file_name| {
+		let image = resources_manager.get_image(&file_name.to_string());
+		let lut = ColorGradingLut::from_image(&image);
+		if lut.is_none()
+		{
+			console.lock().unwrap().add_text(format!(
+				"Invalid color grading LUT image \"{}\" with size {:?}, expected strip of N slices with size N x N",
+				file_name, image.size
+			));
+		}
+		lut
+	});
+
+	let tonemapping_operator = bsp_map_compact::get_entity_key_value(map, world_entity, "tonemapping_operator")
+		.and_then(|v| map_file_common::parse_number(&mut &v[..]).ok())
+		.map(|v| TonemappingOperator::from_config_value(v.max(0.0) as u32));
+
+	MapColorGrading {
+		lut,
+		tonemapping_operator,
+	}
+}
@@ -0,0 +1,157 @@
+use std::sync::atomic::{AtomicU8, Ordering};
+
+// Set of SIMD instructions, used for hot loops of rendering code.
+// Such loops are instantiated separately for each level and proper version is selected in runtime.
+#[derive(Copy, Clone, PartialEq, Eq, PartialOrd, Ord, Debug)]
+pub enum SimdLevel
+{
+	Scalar,
+	Sse41,
+	Avx2,
+}
+
+impl SimdLevel
+{
+	// Returns None for automatic selection.
+	pub fn from_config_value(value: u32) -> Option<Self>
+	{
+		match value
+		{
+			0 => None,
+			1 => Some(SimdLevel::Scalar),
+			2 => Some(SimdLevel::Sse41),
+			_ => Some(SimdLevel::Avx2),
+		}
+	}
+
+	pub fn get_name(&self) -> &'static str
+	{
+		match self
+		{
+			SimdLevel::Scalar => "scalar",
+			SimdLevel::Sse41 => "SSE4.1",
+			SimdLevel::Avx2 => "AVX2",
+		}
+	}
+
+	fn from_u8(value: u8) -> Self
+	{
+		match value
+		{
+			0 => SimdLevel::Scalar,
+			1 => SimdLevel::Sse41,
+			_ => SimdLevel::Avx2,
+		}
+	}
+}
+
+// Get best SIMD level, supported by current CPU.
+pub fn get_supported_simd_level() -> SimdLevel
+{
+	#[cfg(target_arch = "x86_64")]
+	{
+		if is_x86_feature_detected!("avx2") && is_x86_feature_detected!("fma")
+		{
+			return SimdLevel::Avx2;
+		}
+		if is_x86_feature_detected!("sse4.1")
+		{
+			return SimdLevel::Sse41;
+		}
+	}
+
+	SimdLevel::Scalar
+}
+
+// Get SIMD level, which should be used now.
+pub fn get_simd_level() -> SimdLevel
+{
+	let value = CURRENT_SIMD_LEVEL.load(Ordering::Relaxed);
+	if value != SIMD_LEVEL_UNDETECTED
+	{
+		return SimdLevel::from_u8(value);
+	}
+
+	let level = get_supported_simd_level();
+	CURRENT_SIMD_LEVEL.store(level as u8, Ordering::Relaxed);
+	level
+}
+
+// Limit SIMD level (for debugging and performance comparison). None - use best supported level.
+// Levels not supported by current CPU are never used.
+pub fn set_max_simd_level(max_level: Option<SimdLevel>)
+{
+	let supported_level = get_supported_simd_level();
+	let level = if let Some(max_level) = max_level
+	{
+		max_level.min(supported_level)
+	}
+	else
+	{
+		supported_level
+	};
+	CURRENT_SIMD_LEVEL.store(level as u8, Ordering::Relaxed);
+}
+
+// Values of SIMD level, used as const generic param of SIMD types and functions (see "fast_math").
+pub const SIMD_LEVEL_SCALAR: u32 = SimdLevel::Scalar as u32;
+pub const SIMD_LEVEL_SSE41: u32 = SimdLevel::Sse41 as u32;
+pub const SIMD_LEVEL_AVX2: u32 = SimdLevel::Avx2 as u32;
+
+// Evaluate expression with constant "$simd_level", equal to current SIMD level.
+// This constant should be passed as SIMD level param to functions, called in this expression.
+// Such functions use SIMD types and instructions explicitly for given level, so, correctness doesn't depend on inlining.
+// Expression is evaluated inside entry function with target features of current level.
+// Functions called here should be "#[inline(always)]" in order to be compiled with these target features,
+// which allows inlining of intrinsics and makes generated code faster.
+macro_rules! call_with_simd_level {
+	($simd_level: ident, $e: expr) => {
+		match $crate::square_wheel::cpu_features::get_simd_level()
+		{
+			#[cfg(target_arch = "x86_64")]
+			$crate::square_wheel::cpu_features::SimdLevel::Avx2 =>
+			{
+				const $simd_level: u32 = $crate::square_wheel::cpu_features::SIMD_LEVEL_AVX2;
+				let f = || $e;
+				// SAFETY: "get_simd_level" returns only levels supported by current CPU.
+				unsafe { $crate::square_wheel::cpu_features::call_avx2(f) }
+			},
+			#[cfg(target_arch = "x86_64")]
+			$crate::square_wheel::cpu_features::SimdLevel::Sse41 =>
+			{
+				const $simd_level: u32 = $crate::square_wheel::cpu_features::SIMD_LEVEL_SSE41;
+				let f = || $e;
+				// SAFETY: "get_simd_level" returns only levels supported by current CPU.
+				unsafe { $crate::square_wheel::cpu_features::call_sse41(f) }
+			},
+			_ =>
+			{
+				const $simd_level: u32 = $crate::square_wheel::cpu_features::SIMD_LEVEL_SCALAR;
+				$e
+			},
+		}
+	};
+}
+
+pub(crate) use call_with_simd_level;
+
+// Entry functions with target features of each SIMD level.
+// Given closure is called only once, so, it is inlined together with all "#[inline(always)]" functions called inside it.
+#[cfg(target_arch = "x86_64")]
+#[target_feature(enable = "avx2,fma")]
+pub(crate) unsafe fn call_avx2<R, F: FnOnce() -> R>(f: F) -> R
+{
+	f()
+}
+
+#[cfg(target_arch = "x86_64")]
+#[target_feature(enable = "sse4.1")]
+pub(crate) unsafe fn call_sse41<R, F: FnOnce() -> R>(f: F) -> R
+{
+	f()
+}
+
+const SIMD_LEVEL_UNDETECTED: u8 = 255;
+
+// Process-wide value, since CPU features are same for all threads.
+static CURRENT_SIMD_LEVEL: AtomicU8 = AtomicU8::new(SIMD_LEVEL_UNDETECTED);
@@ -1,6 +1,6 @@
 use crate::common::{bbox::*, math_types::*, matrix::*, plane::*};
 
-pub type BBoxForDrawOrdering = (u32, ProjectedBBox);
+pub type BBoxForDrawOrdering<T> = (T, ProjectedBBox);
 
 #[derive(Copy, Clone)]
 pub struct ProjectedBBox
@@ -36,7 +36,7 @@ pub fn project_bbox(bbox: &BBox, camera_matrices: &CameraMatrices) -> ProjectedB
 	}
 }
 
-pub fn order_bboxes(bboxes: &mut [BBoxForDrawOrdering])
+pub fn order_bboxes<T>(bboxes: &mut [BBoxForDrawOrdering<T>])
 {
 	if bboxes.len() <= 1
 	{
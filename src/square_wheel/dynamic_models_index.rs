@@ -1,4 +1,4 @@
-use super::{frame_info::*, triangle_models_rendering::*};
+use super::{frame_info::*, sprites_rendering::*, triangle_models_rendering::*};
 use crate::common::{bbox::*, bsp_map_compact, math_types::*, matrix::*};
 use std::sync::Arc;
 
@@ -89,6 +89,43 @@ impl DynamicModelsIndex
 		}
 	}
 
+	// Reset internal state and position new set of sprites.
+	pub fn position_sprites(&mut self, sprites: &[Sprite])
+	{
+		// Clear previous sprites.
+		self.clear();
+
+		// Position new sprites.
+		self.models_info.resize(sprites.len(), ModelInfo::default());
+		for (index, sprite) in sprites.iter().enumerate()
+		{
+			self.position_model_bbox(index as ModelId, &get_sprite_bbox(sprite), &Mat4f::identity());
+		}
+	}
+
+	// Reset internal state and position new set of portals.
+	// Each portal is represented as list of world space vertices.
+	pub fn position_portals(&mut self, portals_vertices: &[Vec<Vec3f>])
+	{
+		// Clear previous portals.
+		self.clear();
+
+		// Position new portals.
+		self.models_info.resize(portals_vertices.len(), ModelInfo::default());
+		for (index, vertices) in portals_vertices.iter().enumerate()
+		{
+			if let Some(first_vertex) = vertices.first()
+			{
+				let mut bbox = BBox::from_point(first_vertex);
+				for v in &vertices[1 ..]
+				{
+					bbox.extend_with_point(v);
+				}
+				self.position_model_bbox(index as ModelId, &bbox, &Mat4f::identity());
+			}
+		}
+	}
+
 	fn position_model_bbox(&mut self, id: ModelId, bbox: &BBox, transform_matrix: &Mat4f)
 	{
 		// transform bbox vertices.
@@ -0,0 +1,138 @@
+use super::fast_math::*;
+use crate::common::color::*;
+
+#[derive(Copy, Clone, PartialEq)]
+pub enum ExposureMeteringMode
+{
+	// Average brightness of whole screen.
+	Average,
+	// Average brightness with larger weight of screen center.
+	CenterWeighted,
+	// Average brightness of small area in screen center.
+	Spot,
+	// Logarithmic average of brightness with excluding of darkest and brightest parts of screen.
+	Histogram,
+}
+
+impl ExposureMeteringMode
+{
+	pub fn from_config_value(value: u32) -> Self
+	{
+		match value
+		{
+			0 => ExposureMeteringMode::Average,
+			1 => ExposureMeteringMode::CenterWeighted,
+			2 => ExposureMeteringMode::Spot,
+			_ => ExposureMeteringMode::Histogram,
+		}
+	}
+}
+
+// Percentiles of samples (in range [0; 1]), used for histogram metering.
+#[derive(Copy, Clone)]
+pub struct HistogramPercentiles
+{
+	pub low: f32,
+	pub high: f32,
+}
+
+// Calculate brightness of HDR image, using sparse grid of samples.
+// Result is in range of color components (255 - maximum of LDR image).
+pub fn calculate_metered_brightness(
+	hdr_buffer: &[Color64],
+	size: [usize; 2],
+	mode: ExposureMeteringMode,
+	histogram_percentiles: &HistogramPercentiles,
+) -> f32
+{
+	let grid_size = [size[0].min(METERING_GRID_SIZE[0]), size[1].min(METERING_GRID_SIZE[1])];
+	if grid_size[0] == 0 || grid_size[1] == 0
+	{
+		return 0.0;
+	}
+
+	// Use coordinates relative to screen center, scaled by half of screen height.
+	let half_height = (size[1] as f32) * 0.5;
+	let inv_half_height = 1.0 / half_height;
+
+	let mut weighted_sum = 0.0;
+	let mut weights_sum = 0.0;
+	let mut samples = Vec::with_capacity(grid_size[0] * grid_size[1]);
+	for grid_y in 0 .. grid_size[1]
+	{
+		let y = (grid_y * 2 + 1) * size[1] / (grid_size[1] * 2);
+		let dy = ((y as f32) + 0.5 - half_height) * inv_half_height;
+		for grid_x in 0 .. grid_size[0]
+		{
+			let x = (grid_x * 2 + 1) * size[0] / (grid_size[0] * 2);
+			let dx = ((x as f32) + 0.5 - (size[0] as f32) * 0.5) * inv_half_height;
+
+			let components = ColorVec::from_color64(hdr_buffer[x + y * size[0]]).into_color_f32x3();
+			let brightness = (components[0] + components[1] + components[2]) * 0.33;
+
+			let square_distance = dx * dx + dy * dy;
+			match mode
+			{
+				ExposureMeteringMode::Average =>
+				{
+					weighted_sum += brightness;
+					weights_sum += 1.0;
+				},
+				ExposureMeteringMode::CenterWeighted =>
+				{
+					let weight = (-square_distance * CENTER_WEIGHT_SCALE).exp();
+					weighted_sum += brightness * weight;
+					weights_sum += weight;
+				},
+				ExposureMeteringMode::Spot =>
+				{
+					if square_distance <= SPOT_RADIUS * SPOT_RADIUS
+					{
+						weighted_sum += brightness;
+						weights_sum += 1.0;
+					}
+				},
+				ExposureMeteringMode::Histogram =>
+				{
+					samples.push(brightness.max(MIN_BRIGHTNESS).log2());
+				},
+			}
+		}
+	}
+
+	if mode == ExposureMeteringMode::Histogram
+	{
+		samples.sort_by(|a, b| a.partial_cmp(b).unwrap_or(std::cmp::Ordering::Equal));
+
+		let low = histogram_percentiles.low.max(0.0).min(1.0);
+		let high = histogram_percentiles.high.max(low).min(1.0);
+		let start = ((samples.len() as f32) * low) as usize;
+		let end = (((samples.len() as f32) * high).ceil() as usize)
+			.max(start + 1)
+			.min(samples.len());
+		let range = &samples[start.min(end - 1) .. end];
+
+		let log_average = range.iter().sum::<f32>() / (range.len() as f32);
+		return log_average.exp2();
+	}
+
+	if weights_sum > 0.0
+	{
+		weighted_sum / weights_sum
+	}
+	else
+	{
+		0.0
+	}
+}
+
+// Number of samples used for metering.
+const METERING_GRID_SIZE: [usize; 2] = [64, 36];
+
+// Factor for exponential weight function. Larger value - less weight of screen borders.
+const CENTER_WEIGHT_SCALE: f32 = 2.0;
+
+// Radius of spot relative to half of screen height.
+const SPOT_RADIUS: f32 = 0.2;
+
+const MIN_BRIGHTNESS: f32 = 1.0 / 1024.0;
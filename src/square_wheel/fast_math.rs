@@ -1,8 +1,16 @@
 // This module contains helper functions, based on various intrinsincs.
+// Color vectors and some functions are parametrized by SIMD level (see "cpu_features") and use instructions of this level.
+// Levels above scalar are allowed only in code called via "call_with_simd_level", which checks that CPU supports them.
+
+use super::cpu_features::*;
+use crate::common::color::*;
+#[cfg(target_arch = "x86_64")]
+use core::arch::x86_64::*;
 
 pub use fast_math_impl::*;
 
-#[cfg(all(target_arch = "x86_64", target_feature = "sse4.1"))]
+// SSE2 is always available on x86_64, so, it is used for functions not parametrized by SIMD level.
+#[cfg(target_arch = "x86_64")]
 mod fast_math_impl
 {
 	use crate::common::color::*;
@@ -81,295 +89,22 @@ mod fast_math_impl
 	{
 		unsafe {
 			let values_8bit = _mm_cvtsi32_si128(b);
-			let values_32bit = _mm_cvtepi8_epi32(values_8bit);
+			// Move each byte into highest byte of 32-bit value and shift it back in order to extend sign.
+			let values_16bit = _mm_unpacklo_epi8(values_8bit, values_8bit);
+			let values_32bit = _mm_srai_epi32(_mm_unpacklo_epi16(values_16bit, values_16bit), 24);
 			let values_f4 = _mm_cvtepi32_ps(values_32bit);
 			let scale = _mm_set_ps(unpack_scale[3], unpack_scale[2], unpack_scale[1], unpack_scale[0]);
 			let values_scaled = _mm_mul_ps(values_f4, scale);
-			[
-				f32::from_bits(_mm_extract_ps(values_scaled, 0) as u32),
-				f32::from_bits(_mm_extract_ps(values_scaled, 1) as u32),
-				f32::from_bits(_mm_extract_ps(values_scaled, 2) as u32),
-				f32::from_bits(_mm_extract_ps(values_scaled, 3) as u32),
-			]
-		}
-	}
-
-	#[repr(C, align(32))]
-	#[derive(Copy, Clone)]
-	pub struct ColorVec(__m128);
-
-	impl ColorVec
-	{
-		pub fn zero() -> Self
-		{
-			unsafe { Self(_mm_setzero_ps()) }
-		}
-
-		pub fn from_color32(c: Color32) -> Self
-		{
-			unsafe {
-				let color_32bit = c.get_raw() as i32;
-				let values_8bit = _mm_cvtsi32_si128(color_32bit);
-				let values_32bit = _mm_cvtepu8_epi32(values_8bit);
-				let values_f4 = _mm_cvtepi32_ps(values_32bit);
-				Self(values_f4)
-			}
-		}
-
-		pub fn from_color64(c: Color64) -> Self
-		{
-			unsafe {
-				let color_64bit = c.get_raw() as i64;
-				let values_16bit = _mm_cvtsi64_si128(color_64bit);
-				let values_32bit = _mm_cvtepu16_epi32(values_16bit);
-				let values_f4 = _mm_cvtepi32_ps(values_32bit);
-				Self(values_f4)
-			}
-		}
-
-		pub fn into_color32(&self) -> Color32
-		{
-			// Here we 100% sure that components overflow is not possible (because of "min").
-			// NaNs are not possible here too.
-			unsafe {
-				let max_val = 255.0;
-				let values_clamped = _mm_min_ps(self.0, _mm_set_ps(max_val, max_val, max_val, max_val));
-				let values_32bit = _mm_cvtps_epi32(values_clamped);
-				let shuffle_mask = _mm_set_epi8(0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 12, 8, 4, 0);
-				let values_8bit = _mm_shuffle_epi8(values_32bit, shuffle_mask);
-				let color_32bit = _mm_cvtsi128_si32(values_8bit);
-				Color32::from_raw(color_32bit as u32)
-			}
-		}
-
-		pub fn into_color64(&self) -> Color64
-		{
-			// Here we 100% sure that components overflow is not possible (because of "min").
-			// NaNs are not possible here too.
-			unsafe {
-				let max_val = 65535.0;
-				let values_clamped = _mm_min_ps(self.0, _mm_set_ps(max_val, max_val, max_val, max_val));
-				let values_32bit = _mm_cvtps_epi32(values_clamped);
-				// TODO - check correctness of this mask.
-				let shuffle_mask = _mm_set_epi8(0, 0, 0, 0, 0, 0, 0, 0, 13, 12, 9, 8, 5, 4, 1, 0);
-				let values_16bit = _mm_shuffle_epi8(values_32bit, shuffle_mask);
-				let color_64bit = _mm_cvtsi128_si64(values_16bit);
-				Color64::from_raw(color_64bit as u64)
-			}
-		}
-
-		pub fn from_color_f32x3(c: &[f32; 3]) -> Self
-		{
-			Self::from_color_f32x3_with_zero(c)
-		}
-
-		pub fn from_color_f32x3_with_zero(c: &[f32; 3]) -> Self
-		{
-			unsafe { Self(_mm_set_ps(0.0, c[0], c[1], c[2])) }
-		}
-
-		pub fn from_color_f32x3_with_one(c: &[f32; 3]) -> Self
-		{
-			unsafe { Self(_mm_set_ps(1.0, c[0], c[1], c[2])) }
-		}
-
-		pub fn into_color_f32x3(&self) -> [f32; 3]
-		{
-			// TODO - check this.
-			unsafe {
-				[
-					f32::from_bits(_mm_extract_ps(self.0, 2) as u32),
-					f32::from_bits(_mm_extract_ps(self.0, 1) as u32),
-					f32::from_bits(_mm_extract_ps(self.0, 0) as u32),
-				]
-			}
-		}
-
-		pub fn add(&self, other: &Self) -> Self
-		{
-			unsafe { Self(_mm_add_ps(self.0, other.0)) }
-		}
-
-		pub fn mul(&self, other: &Self) -> Self
-		{
-			unsafe { Self(_mm_mul_ps(self.0, other.0)) }
-		}
-
-		pub fn scalar_mul(&self, scalar: f32) -> Self
-		{
-			unsafe { Self(_mm_mul_ps(self.0, _mm_broadcastss_ps(_mm_set1_ps(scalar)))) }
-		}
-
-		pub fn mul_scalar_add(&self, scalar: f32, b: &Self) -> Self
-		{
-			unsafe { Self(_mm_fmadd_ps(self.0, _mm_broadcastss_ps(_mm_set1_ps(scalar)), b.0)) }
-		}
-
-		pub fn mul_add(&self, b: &Self, c: &Self) -> Self
-		{
-			unsafe { Self(_mm_fmadd_ps(self.0, b.0, c.0)) }
-		}
-
-		pub fn div(&self, other: &Self) -> Self
-		{
-			unsafe { Self(_mm_div_ps(self.0, other.0)) }
-		}
-
-		pub fn insert<const INDEX: i32>(&mut self, scalar: f32)
-		{
-			unsafe {
-				let scalar_vec = _mm_broadcastss_ps(_mm_set1_ps(scalar));
-				match INDEX
-				{
-					0 =>
-					{
-						self.0 = _mm_blend_ps(self.0, scalar_vec, 1 << 0);
-					},
-					1 =>
-					{
-						self.0 = _mm_blend_ps(self.0, scalar_vec, 1 << 1);
-					},
-					2 =>
-					{
-						self.0 = _mm_blend_ps(self.0, scalar_vec, 1 << 2);
-					},
-					3 =>
-					{
-						self.0 = _mm_blend_ps(self.0, scalar_vec, 1 << 3);
-					},
-					_ =>
-					{
-						panic!("wrong index!")
-					},
-				}
-			}
-		}
-	} // impl ColorVec
-
-	impl From<ColorVecI> for ColorVec
-	{
-		fn from(v: ColorVecI) -> ColorVec
-		{
-			unsafe { ColorVec(_mm_cvtepi32_ps(v.0)) }
+			let mut res = [0.0; 4];
+			_mm_storeu_ps(res.as_mut_ptr(), values_scaled);
+			res
 		}
 	}
-
-	#[repr(C, align(32))]
-	#[derive(Copy, Clone)]
-	pub struct ColorVecI(__m128i);
-
-	impl ColorVecI
-	{
-		pub fn zero() -> Self
-		{
-			unsafe { Self(_mm_setzero_si128()) }
-		}
-
-		pub fn from_color_u32x3(c: &[u32; 3]) -> Self
-		{
-			unsafe { Self(_mm_set_epi32(0, c[0] as i32, c[1] as i32, c[2] as i32)) }
-		}
-
-		pub fn from_color_i32x3(c: &[i32; 3]) -> Self
-		{
-			Self::from_color_i32x3_with_zero(c)
-		}
-
-		pub fn from_color_i32x3_with_zero(c: &[i32; 3]) -> Self
-		{
-			unsafe { Self(_mm_set_epi32(0, c[0], c[1], c[2])) }
-		}
-
-		pub fn from_color_i32x3_with_one(c: &[i32; 3]) -> Self
-		{
-			unsafe { Self(_mm_set_epi32(1, c[0], c[1], c[2])) }
-		}
-
-		pub fn from_color_f32x3(c: &[f32; 3]) -> Self
-		{
-			unsafe {
-				let f32_vec = _mm_set_ps(0.0, c[0], c[1], c[2]);
-				Self(_mm_cvtps_epi32(f32_vec))
-			}
-		}
-
-		pub fn from_color32(c: Color32) -> Self
-		{
-			unsafe {
-				let color_32bit = c.get_raw() as i32;
-				let values_16bit = _mm_cvtsi32_si128(color_32bit);
-				let values_32bit = _mm_cvtepu8_epi32(values_16bit);
-				Self(values_32bit)
-			}
-		}
-
-		pub fn from_color64(c: Color64) -> Self
-		{
-			unsafe {
-				let color_64bit = c.get_raw() as i64;
-				let values_16bit = _mm_cvtsi64_si128(color_64bit);
-				let values_32bit = _mm_cvtepu16_epi32(values_16bit);
-				Self(values_32bit)
-			}
-		}
-
-		pub fn into_color32(&self) -> Color32
-		{
-			unsafe {
-				let zero = _mm_setzero_si128();
-				let values_16bit = _mm_packus_epi32(self.0, zero);
-				let values_8bit = _mm_packus_epi16(values_16bit, zero);
-				let color_32bit = _mm_cvtsi128_si32(values_8bit);
-				Color32::from_raw(color_32bit as u32)
-			}
-		}
-
-		pub fn into_color64(&self) -> Color64
-		{
-			unsafe {
-				let zero = _mm_setzero_si128();
-				let values_16bit = _mm_packus_epi32(self.0, zero);
-				let color_64bit = _mm_cvtsi128_si64(values_16bit);
-				Color64::from_raw(color_64bit as u64)
-			}
-		}
-
-		pub fn add(&self, other: &Self) -> Self
-		{
-			unsafe { Self(_mm_add_epi32(self.0, other.0)) }
-		}
-
-		pub fn mul(&self, other: &Self) -> Self
-		{
-			unsafe { Self(_mm_mullo_epi32(self.0, other.0)) }
-		}
-
-		pub fn mul_scalar(&self, scalar: i32) -> Self
-		{
-			unsafe { Self(_mm_mullo_epi32(self.0, _mm_set_epi32(scalar, scalar, scalar, scalar))) }
-		}
-
-		pub fn shift_left<const COUNT: i32>(&self) -> Self
-		{
-			unsafe { Self(_mm_slli_epi32(self.0, COUNT)) }
-		}
-
-		pub fn shift_right<const COUNT: i32>(&self) -> Self
-		{
-			unsafe { Self(_mm_srai_epi32(self.0, COUNT)) }
-		}
-
-		pub fn extract<const INDEX: i32>(&mut self) -> i32
-		{
-			unsafe { _mm_extract_epi32(self.0, INDEX) }
-		}
-	} // impl ColorVecI
 }
 
-#[cfg(not(all(target_arch = "x86_64", target_feature = "sse4.1")))]
+#[cfg(not(target_arch = "x86_64"))]
 mod fast_math_impl
 {
-	use super::*;
 	use crate::common::color::*;
 
 	pub fn inv_sqrt_fast(x: f32) -> f32
@@ -381,7 +116,6 @@ mod fast_math_impl
 	{
 		1.0 / x
 	}
-
 	pub fn color32_saturated_sum(a: Color32, b: Color32) -> Color32
 	{
 		let mut r = 0;
@@ -435,305 +169,626 @@ mod fast_math_impl
 		}
 		res
 	}
+}
 
-	// TODO - maybe use here array of 3 floats?
-	#[repr(C, align(32))]
-	#[derive(Copy, Clone)]
-	pub struct ColorVec([f32; 4]);
+// Vector of 4 color components.
+// Representation is same for all SIMD levels, only used instructions are different.
+#[repr(C, align(32))]
+#[derive(Copy, Clone)]
+pub struct SimdColorVec<const SIMD_LEVEL: u32>([f32; 4]);
 
-	impl ColorVec
+// Vector for code, that is not specialized for SIMD level.
+pub type ColorVec = SimdColorVec<SIMD_LEVEL_SCALAR>;
+
+impl<const SIMD_LEVEL: u32> SimdColorVec<SIMD_LEVEL>
+{
+	#[inline(always)]
+	pub fn zero() -> Self
+	{
+		Self([0.0, 0.0, 0.0, 0.0])
+	}
+
+	#[inline(always)]
+	pub fn from_color32(c: Color32) -> Self
 	{
-		pub fn zero() -> Self
+		#[cfg(target_arch = "x86_64")]
+		if SIMD_LEVEL >= SIMD_LEVEL_SSE41
 		{
-			Self([0.0, 0.0, 0.0, 0.0])
+			return unsafe {
+				let color_32bit = c.get_raw() as i32;
+				let values_8bit = _mm_cvtsi32_si128(color_32bit);
+				let values_32bit = _mm_cvtepu8_epi32(values_8bit);
+				let values_f4 = _mm_cvtepi32_ps(values_32bit);
+				Self::from_m128(values_f4)
+			};
 		}
 
-		pub fn from_color32(c: Color32) -> Self
+		let mut res = [0.0; 4];
+		for i in 0 .. 4
 		{
-			let mut res = [0.0; 4];
+			res[i] = ((c.get_raw() >> (i * 8)) & 0xFF) as f32;
+		}
+		Self(res)
+	}
+
+	#[inline(always)]
+	pub fn from_color64(c: Color64) -> Self
+	{
+		#[cfg(target_arch = "x86_64")]
+		if SIMD_LEVEL >= SIMD_LEVEL_SSE41
+		{
+			return unsafe {
+				let color_64bit = c.get_raw() as i64;
+				let values_16bit = _mm_cvtsi64_si128(color_64bit);
+				let values_32bit = _mm_cvtepu16_epi32(values_16bit);
+				let values_f4 = _mm_cvtepi32_ps(values_32bit);
+				Self::from_m128(values_f4)
+			};
+		}
+
+		let mut res = [0.0; 4];
+		for i in 0 .. 4
+		{
+			res[i] = ((c.get_raw() >> (i * 16)) & 0xFFFF) as f32;
+		}
+		Self(res)
+	}
+
+	#[inline(always)]
+	pub fn into_color32(&self) -> Color32
+	{
+		// Here we 100% sure that components overflow is not possible (because of "min").
+		// NaNs are not possible here too.
+		#[cfg(target_arch = "x86_64")]
+		if SIMD_LEVEL >= SIMD_LEVEL_SSE41
+		{
+			return unsafe {
+				let values_clamped = _mm_min_ps(self.get_m128(), _mm_set1_ps(255.0));
+				let values_32bit = _mm_cvtps_epi32(values_clamped);
+				let shuffle_mask = _mm_set_epi8(0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 12, 8, 4, 0);
+				let values_8bit = _mm_shuffle_epi8(values_32bit, shuffle_mask);
+				let color_32bit = _mm_cvtsi128_si32(values_8bit);
+				Color32::from_raw(color_32bit as u32)
+			};
+		}
+
+		let mut res = 0;
+		unsafe {
 			for i in 0 .. 4
 			{
-				res[i] = ((c.get_raw() >> (i * 8)) & 0xFF) as f32;
+				res |= self.0[i].min(255.0).to_int_unchecked::<u32>() << (i * 8);
 			}
-			Self(res)
 		}
+		Color32::from_raw(res)
+	}
 
-		pub fn from_color64(c: Color64) -> Self
+	#[inline(always)]
+	pub fn into_color64(&self) -> Color64
+	{
+		// Here we 100% sure that components overflow is not possible (because of "min").
+		// NaNs are not possible here too.
+		#[cfg(target_arch = "x86_64")]
+		if SIMD_LEVEL >= SIMD_LEVEL_SSE41
 		{
-			let mut res = [0.0; 4];
+			return unsafe {
+				let values_clamped = _mm_min_ps(self.get_m128(), _mm_set1_ps(65535.0));
+				let values_32bit = _mm_cvtps_epi32(values_clamped);
+				let shuffle_mask = _mm_set_epi8(0, 0, 0, 0, 0, 0, 0, 0, 13, 12, 9, 8, 5, 4, 1, 0);
+				let values_16bit = _mm_shuffle_epi8(values_32bit, shuffle_mask);
+				let color_64bit = _mm_cvtsi128_si64(values_16bit);
+				Color64::from_raw(color_64bit as u64)
+			};
+		}
+
+		let mut res = 0;
+		unsafe {
 			for i in 0 .. 4
 			{
-				res[i] = ((c.get_raw() >> (i * 16)) & 0xFFFF) as f32;
+				res |= self.0[i].min(65535.0).to_int_unchecked::<u64>() << (i * 16);
 			}
-			Self(res)
 		}
+		Color64::from_raw(res)
+	}
 
-		pub fn into_color32(&self) -> Color32
+	#[inline(always)]
+	pub fn from_color_f32x3(c: &[f32; 3]) -> Self
+	{
+		Self::from_color_f32x3_with_zero(c)
+	}
+
+	#[inline(always)]
+	pub fn from_color_f32x3_with_zero(c: &[f32; 3]) -> Self
+	{
+		Self([c[2], c[1], c[0], 0.0])
+	}
+
+	#[inline(always)]
+	pub fn from_color_f32x3_with_one(c: &[f32; 3]) -> Self
+	{
+		Self([c[2], c[1], c[0], 1.0])
+	}
+
+	#[inline(always)]
+	pub fn into_color_f32x3(&self) -> [f32; 3]
+	{
+		[self.0[2], self.0[1], self.0[0]]
+	}
+
+	// Conversion into vector for code, that is not specialized for SIMD level.
+	#[inline(always)]
+	pub fn into_scalar_level(&self) -> ColorVec
+	{
+		SimdColorVec(self.0)
+	}
+
+	#[inline(always)]
+	pub fn add(&self, other: &Self) -> Self
+	{
+		#[cfg(target_arch = "x86_64")]
+		if SIMD_LEVEL >= SIMD_LEVEL_SSE41
 		{
-			// Here we 100% sure that components overflow is not possible (because of "min").
-			// NaNs are not possible here too.
-			let mut res = 0;
-			unsafe {
-				for i in 0 .. 4
-				{
-					res |= self.0[i].min(255.0).to_int_unchecked::<u32>() << (i * 8);
-				}
-			}
-			Color32::from_raw(res)
+			return unsafe { Self::from_m128(_mm_add_ps(self.get_m128(), other.get_m128())) };
 		}
 
-		pub fn into_color64(&self) -> Color64
+		Self([
+			self.0[0] + other.0[0],
+			self.0[1] + other.0[1],
+			self.0[2] + other.0[2],
+			self.0[3] + other.0[3],
+		])
+	}
+
+	#[inline(always)]
+	pub fn mul(&self, other: &Self) -> Self
+	{
+		#[cfg(target_arch = "x86_64")]
+		if SIMD_LEVEL >= SIMD_LEVEL_SSE41
 		{
-			// Here we 100% sure that components overflow is not possible (because of "min").
-			// NaNs are not possible here too.
-			let mut res = 0;
-			unsafe {
-				for i in 0 .. 4
-				{
-					res |= self.0[i].min(65535.0).to_int_unchecked::<u64>() << (i * 16);
-				}
-			}
-			Color64::from_raw(res)
+			return unsafe { Self::from_m128(_mm_mul_ps(self.get_m128(), other.get_m128())) };
 		}
 
-		pub fn from_color_f32x3(c: &[f32; 3]) -> Self
+		Self([
+			self.0[0] * other.0[0],
+			self.0[1] * other.0[1],
+			self.0[2] * other.0[2],
+			self.0[3] * other.0[3],
+		])
+	}
+
+	#[inline(always)]
+	pub fn scalar_mul(&self, scalar: f32) -> Self
+	{
+		#[cfg(target_arch = "x86_64")]
+		if SIMD_LEVEL >= SIMD_LEVEL_SSE41
 		{
-			Self::from_color_f32x3_with_zero(c)
+			return unsafe { Self::from_m128(_mm_mul_ps(self.get_m128(), _mm_set1_ps(scalar))) };
 		}
 
-		pub fn from_color_f32x3_with_zero(c: &[f32; 3]) -> Self
+		Self([
+			self.0[0] * scalar,
+			self.0[1] * scalar,
+			self.0[2] * scalar,
+			self.0[3] * scalar,
+		])
+	}
+
+	#[inline(always)]
+	pub fn mul_scalar_add(&self, scalar: f32, b: &Self) -> Self
+	{
+		#[cfg(target_arch = "x86_64")]
+		if SIMD_LEVEL >= SIMD_LEVEL_AVX2
 		{
-			Self([c[2], c[1], c[0], 0.0])
+			return unsafe { Self::from_m128(_mm_fmadd_ps(self.get_m128(), _mm_set1_ps(scalar), b.get_m128())) };
 		}
-
-		pub fn from_color_f32x3_with_one(c: &[f32; 3]) -> Self
+		#[cfg(target_arch = "x86_64")]
+		if SIMD_LEVEL >= SIMD_LEVEL_SSE41
 		{
-			Self([c[2], c[1], c[0], 1.0])
+			return unsafe {
+				Self::from_m128(_mm_add_ps(
+					_mm_mul_ps(self.get_m128(), _mm_set1_ps(scalar)),
+					b.get_m128(),
+				))
+			};
 		}
 
-		pub fn into_color_f32x3(&self) -> [f32; 3]
+		Self([
+			f32_mul_add::<SIMD_LEVEL>(self.0[0], scalar, b.0[0]),
+			f32_mul_add::<SIMD_LEVEL>(self.0[1], scalar, b.0[1]),
+			f32_mul_add::<SIMD_LEVEL>(self.0[2], scalar, b.0[2]),
+			f32_mul_add::<SIMD_LEVEL>(self.0[3], scalar, b.0[3]),
+		])
+	}
+
+	#[inline(always)]
+	pub fn mul_add(&self, b: &Self, c: &Self) -> Self
+	{
+		#[cfg(target_arch = "x86_64")]
+		if SIMD_LEVEL >= SIMD_LEVEL_AVX2
 		{
-			[self.0[0], self.0[1], self.0[2]]
+			return unsafe { Self::from_m128(_mm_fmadd_ps(self.get_m128(), b.get_m128(), c.get_m128())) };
 		}
-
-		pub fn add(&self, other: &Self) -> Self
+		#[cfg(target_arch = "x86_64")]
+		if SIMD_LEVEL >= SIMD_LEVEL_SSE41
 		{
-			Self([
-				self.0[0] + other.0[0],
-				self.0[1] + other.0[1],
-				self.0[2] + other.0[2],
-				self.0[3] + other.0[3],
-			])
+			return unsafe { Self::from_m128(_mm_add_ps(_mm_mul_ps(self.get_m128(), b.get_m128()), c.get_m128())) };
 		}
 
-		pub fn mul(&self, other: &Self) -> Self
+		Self([
+			f32_mul_add::<SIMD_LEVEL>(self.0[0], b.0[0], c.0[0]),
+			f32_mul_add::<SIMD_LEVEL>(self.0[1], b.0[1], c.0[1]),
+			f32_mul_add::<SIMD_LEVEL>(self.0[2], b.0[2], c.0[2]),
+			f32_mul_add::<SIMD_LEVEL>(self.0[3], b.0[3], c.0[3]),
+		])
+	}
+
+	#[inline(always)]
+	pub fn div(&self, other: &Self) -> Self
+	{
+		#[cfg(target_arch = "x86_64")]
+		if SIMD_LEVEL >= SIMD_LEVEL_SSE41
 		{
-			Self([
-				self.0[0] * other.0[0],
-				self.0[1] * other.0[1],
-				self.0[2] * other.0[2],
-				self.0[3] * other.0[3],
-			])
+			return unsafe { Self::from_m128(_mm_div_ps(self.get_m128(), other.get_m128())) };
 		}
 
-		pub fn scalar_mul(&self, scalar: f32) -> Self
+		Self([
+			self.0[0] / other.0[0],
+			self.0[1] / other.0[1],
+			self.0[2] / other.0[2],
+			self.0[3] / other.0[3],
+		])
+	}
+
+	// Returns "other" component if "self" component is NaN.
+	#[inline(always)]
+	pub fn min(&self, other: &Self) -> Self
+	{
+		#[cfg(target_arch = "x86_64")]
+		if SIMD_LEVEL >= SIMD_LEVEL_SSE41
 		{
-			Self([
-				self.0[0] * scalar,
-				self.0[1] * scalar,
-				self.0[2] * scalar,
-				self.0[3] * scalar,
-			])
+			return unsafe { Self::from_m128(_mm_min_ps(self.get_m128(), other.get_m128())) };
 		}
 
-		pub fn mul_scalar_add(&self, scalar: f32, b: &Self) -> Self
+		Self([
+			self.0[0].min(other.0[0]),
+			self.0[1].min(other.0[1]),
+			self.0[2].min(other.0[2]),
+			self.0[3].min(other.0[3]),
+		])
+	}
+
+	// Returns "other" component if "self" component is NaN.
+	#[inline(always)]
+	pub fn max(&self, other: &Self) -> Self
+	{
+		#[cfg(target_arch = "x86_64")]
+		if SIMD_LEVEL >= SIMD_LEVEL_SSE41
 		{
-			Self([
-				f32_mul_add(self.0[0], scalar, b.0[0]),
-				f32_mul_add(self.0[1], scalar, b.0[1]),
-				f32_mul_add(self.0[2], scalar, b.0[2]),
-				f32_mul_add(self.0[3], scalar, b.0[3]),
-			])
+			return unsafe { Self::from_m128(_mm_max_ps(self.get_m128(), other.get_m128())) };
 		}
 
-		pub fn mul_add(&self, b: &Self, c: &Self) -> Self
+		Self([
+			self.0[0].max(other.0[0]),
+			self.0[1].max(other.0[1]),
+			self.0[2].max(other.0[2]),
+			self.0[3].max(other.0[3]),
+		])
+	}
+
+	#[inline(always)]
+	pub fn insert<const INDEX: i32>(&mut self, scalar: f32)
+	{
+		#[cfg(target_arch = "x86_64")]
+		if SIMD_LEVEL >= SIMD_LEVEL_SSE41
 		{
-			Self([
-				f32_mul_add(self.0[0], b.0[0], c.0[0]),
-				f32_mul_add(self.0[1], b.0[1], c.0[1]),
-				f32_mul_add(self.0[2], b.0[2], c.0[2]),
-				f32_mul_add(self.0[3], b.0[3], c.0[3]),
-			])
+			unsafe {
+				let v = self.get_m128();
+				let scalar_vec = _mm_set1_ps(scalar);
+				let result = match INDEX
+				{
+					0 => _mm_blend_ps(v, scalar_vec, 1 << 0),
+					1 => _mm_blend_ps(v, scalar_vec, 1 << 1),
+					2 => _mm_blend_ps(v, scalar_vec, 1 << 2),
+					3 => _mm_blend_ps(v, scalar_vec, 1 << 3),
+					_ => panic!("wrong index!"),
+				};
+				*self = Self::from_m128(result);
+			}
+			return;
 		}
 
-		pub fn div(&self, other: &Self) -> Self
+		self.0[INDEX as usize] = scalar;
+	}
+} // impl SimdColorVec
+
+#[cfg(target_arch = "x86_64")]
+impl<const SIMD_LEVEL: u32> SimdColorVec<SIMD_LEVEL>
+{
+	#[inline(always)]
+	fn from_m128(v: __m128) -> Self
+	{
+		Self(unsafe { std::mem::transmute::<__m128, [f32; 4]>(v) })
+	}
+
+	#[inline(always)]
+	fn get_m128(&self) -> __m128
+	{
+		unsafe { std::mem::transmute::<[f32; 4], __m128>(self.0) }
+	}
+}
+
+impl<const SIMD_LEVEL: u32> From<SimdColorVecI<SIMD_LEVEL>> for SimdColorVec<SIMD_LEVEL>
+{
+	#[inline(always)]
+	fn from(v: SimdColorVecI<SIMD_LEVEL>) -> Self
+	{
+		#[cfg(target_arch = "x86_64")]
+		if SIMD_LEVEL >= SIMD_LEVEL_SSE41
 		{
-			Self([
-				self.0[0] / other.0[0],
-				self.0[1] / other.0[1],
-				self.0[2] / other.0[2],
-				self.0[3] / other.0[3],
-			])
+			return unsafe { Self::from_m128(_mm_cvtepi32_ps(v.get_m128i())) };
 		}
 
-		pub fn insert<const INDEX: i32>(&mut self, scalar: f32)
+		Self([v.0[0] as f32, v.0[1] as f32, v.0[2] as f32, v.0[3] as f32])
+	}
+}
+
+// Integer vector of 4 color components.
+// Representation is same for all SIMD levels, only used instructions are different.
+#[repr(C, align(32))]
+#[derive(Copy, Clone)]
+pub struct SimdColorVecI<const SIMD_LEVEL: u32>([i32; 4]);
+
+// Integer vector for code, that is not specialized for SIMD level.
+pub type ColorVecI = SimdColorVecI<SIMD_LEVEL_SCALAR>;
+
+impl<const SIMD_LEVEL: u32> SimdColorVecI<SIMD_LEVEL>
+{
+	#[inline(always)]
+	pub fn zero() -> Self
+	{
+		Self([0; 4])
+	}
+
+	#[inline(always)]
+	pub fn from_color_u32x3(c: &[u32; 3]) -> Self
+	{
+		Self([c[2] as i32, c[1] as i32, c[0] as i32, 0])
+	}
+
+	#[inline(always)]
+	pub fn from_color_i32x3(c: &[i32; 3]) -> Self
+	{
+		Self::from_color_i32x3_with_zero(c)
+	}
+
+	#[inline(always)]
+	pub fn from_color_i32x3_with_zero(c: &[i32; 3]) -> Self
+	{
+		Self([c[2], c[1], c[0], 0])
+	}
+
+	#[inline(always)]
+	pub fn from_color_i32x3_with_one(c: &[i32; 3]) -> Self
+	{
+		Self([c[2], c[1], c[0], 1])
+	}
+
+	#[inline(always)]
+	pub fn from_color_f32x3(c: &[f32; 3]) -> Self
+	{
+		#[cfg(target_arch = "x86_64")]
+		if SIMD_LEVEL >= SIMD_LEVEL_SSE41
 		{
-			self.0[INDEX as usize] = scalar;
+			return unsafe { Self::from_m128i(_mm_cvtps_epi32(_mm_set_ps(0.0, c[0], c[1], c[2]))) };
 		}
-	} // impl ColorVec
 
-	impl From<ColorVecI> for ColorVec
+		Self([c[2] as i32, c[1] as i32, c[0] as i32, 0])
+	}
+
+	#[inline(always)]
+	pub fn from_color32(c: Color32) -> Self
 	{
-		fn from(v: ColorVecI) -> ColorVec
+		#[cfg(target_arch = "x86_64")]
+		if SIMD_LEVEL >= SIMD_LEVEL_SSE41
+		{
+			return unsafe {
+				let color_32bit = c.get_raw() as i32;
+				let values_8bit = _mm_cvtsi32_si128(color_32bit);
+				Self::from_m128i(_mm_cvtepu8_epi32(values_8bit))
+			};
+		}
+
+		let mut res = [0; 4];
+		for i in 0 .. 4
 		{
-			ColorVec([v.0[0] as f32, v.0[1] as f32, v.0[2] as f32, v.0[3] as f32])
+			res[i] = ((c.get_raw() >> (i * 8)) & 0xFF) as i32;
 		}
+		Self(res)
 	}
 
-	#[repr(C, align(32))]
-	#[derive(Copy, Clone)]
-	pub struct ColorVecI([i32; 4]);
-
-	impl ColorVecI
+	#[inline(always)]
+	pub fn from_color64(c: Color64) -> Self
 	{
-		pub fn zero() -> Self
+		#[cfg(target_arch = "x86_64")]
+		if SIMD_LEVEL >= SIMD_LEVEL_SSE41
 		{
-			Self([0; 4])
+			return unsafe {
+				let color_64bit = c.get_raw() as i64;
+				let values_16bit = _mm_cvtsi64_si128(color_64bit);
+				Self::from_m128i(_mm_cvtepu16_epi32(values_16bit))
+			};
 		}
 
-		pub fn from_color_u32x3(c: &[u32; 3]) -> Self
+		let mut res = [0; 4];
+		for i in 0 .. 4
 		{
-			Self([c[2] as i32, c[1] as i32, c[0] as i32, 0])
+			res[i] = ((c.get_raw() >> (i * 16)) & 0xFFFF) as i32;
 		}
+		Self(res)
+	}
 
-		pub fn from_color_i32x3(c: &[i32; 3]) -> Self
+	#[inline(always)]
+	pub fn into_color32(&self) -> Color32
+	{
+		#[cfg(target_arch = "x86_64")]
+		if SIMD_LEVEL >= SIMD_LEVEL_SSE41
 		{
-			Self::from_color_i32x3_with_zero(c)
+			return unsafe {
+				let zero = _mm_setzero_si128();
+				let values_16bit = _mm_packus_epi32(self.get_m128i(), zero);
+				let values_8bit = _mm_packus_epi16(values_16bit, zero);
+				let color_32bit = _mm_cvtsi128_si32(values_8bit);
+				Color32::from_raw(color_32bit as u32)
+			};
 		}
 
-		pub fn from_color_i32x3_with_zero(c: &[i32; 3]) -> Self
+		let mut res = 0;
+		for i in 0 .. 4
 		{
-			Self([c[2], c[1], c[0], 0])
+			res |= (self.0[i].max(0) as u32).min(255) << (i * 8);
 		}
+		Color32::from_raw(res)
+	}
 
-		pub fn from_color_i32x3_with_one(c: &[i32; 3]) -> Self
+	#[inline(always)]
+	pub fn into_color64(&self) -> Color64
+	{
+		#[cfg(target_arch = "x86_64")]
+		if SIMD_LEVEL >= SIMD_LEVEL_SSE41
 		{
-			Self([c[2], c[1], c[0], 1])
+			return unsafe {
+				let zero = _mm_setzero_si128();
+				let values_16bit = _mm_packus_epi32(self.get_m128i(), zero);
+				let color_64bit = _mm_cvtsi128_si64(values_16bit);
+				Color64::from_raw(color_64bit as u64)
+			};
 		}
 
-		pub fn from_color_f32x3(c: &[f32; 3]) -> Self
+		let mut res = 0;
+		for i in 0 .. 4
 		{
-			Self([c[2] as i32, c[1] as i32, c[0] as i32, 0])
+			res |= (self.0[i].max(0) as u64).min(65535) << (i * 16);
 		}
+		Color64::from_raw(res)
+	}
 
-		pub fn from_color32(c: Color32) -> Self
+	#[inline(always)]
+	pub fn add(&self, other: &Self) -> Self
+	{
+		#[cfg(target_arch = "x86_64")]
+		if SIMD_LEVEL >= SIMD_LEVEL_SSE41
 		{
-			let mut res = [0; 4];
-			for i in 0 .. 4
-			{
-				res[i] = ((c.get_raw() >> (i * 8)) & 0xFF) as i32;
-			}
-			Self(res)
+			return unsafe { Self::from_m128i(_mm_add_epi32(self.get_m128i(), other.get_m128i())) };
 		}
 
-		pub fn from_color64(c: Color64) -> Self
+		let mut res = [0; 4];
+		for i in 0 .. 4
 		{
-			let mut res = [0; 4];
-			for i in 0 .. 4
-			{
-				res[i] = ((c.get_raw() >> (i * 16)) & 0xFFFF) as i32;
-			}
-			Self(res)
+			res[i] = self.0[i] + other.0[i]
 		}
+		Self(res)
+	}
 
-		pub fn into_color32(&self) -> Color32
+	#[inline(always)]
+	pub fn mul(&self, other: &Self) -> Self
+	{
+		#[cfg(target_arch = "x86_64")]
+		if SIMD_LEVEL >= SIMD_LEVEL_SSE41
 		{
-			let mut res = 0;
-			for i in 0 .. 4
-			{
-				res |= (self.0[i] as u32).min(255) << (i * 8);
-			}
-			Color32::from_raw(res)
+			return unsafe { Self::from_m128i(_mm_mullo_epi32(self.get_m128i(), other.get_m128i())) };
 		}
 
-		pub fn into_color64(&self) -> Color64
+		let mut res = [0; 4];
+		for i in 0 .. 4
 		{
-			let mut res = 0;
-			for i in 0 .. 4
-			{
-				res |= (self.0[i] as u64).min(65535) << (i * 16);
-			}
-			Color64::from_raw(res)
+			res[i] = self.0[i] * other.0[i]
 		}
+		Self(res)
+	}
 
-		pub fn add(&self, other: &Self) -> Self
+	#[inline(always)]
+	pub fn mul_scalar(&self, scalar: i32) -> Self
+	{
+		#[cfg(target_arch = "x86_64")]
+		if SIMD_LEVEL >= SIMD_LEVEL_SSE41
 		{
-			let mut res = [0; 4];
-			for i in 0 .. 4
-			{
-				res[i] = self.0[i] + other.0[i]
-			}
-			Self(res)
+			return unsafe { Self::from_m128i(_mm_mullo_epi32(self.get_m128i(), _mm_set1_epi32(scalar))) };
 		}
 
-		pub fn mul(&self, other: &Self) -> Self
+		let mut res = [0; 4];
+		for i in 0 .. 4
 		{
-			let mut res = [0; 4];
-			for i in 0 .. 4
-			{
-				res[i] = self.0[i] * other.0[i]
-			}
-			Self(res)
+			res[i] = self.0[i] * scalar
 		}
+		Self(res)
+	}
 
-		pub fn mul_scalar(&self, scalar: i32) -> Self
+	#[inline(always)]
+	pub fn shift_left<const COUNT: i32>(&self) -> Self
+	{
+		#[cfg(target_arch = "x86_64")]
+		if SIMD_LEVEL >= SIMD_LEVEL_SSE41
 		{
-			let mut res = [0; 4];
-			for i in 0 .. 4
-			{
-				res[i] = self.0[i] * scalar
-			}
-			Self(res)
+			return unsafe { Self::from_m128i(_mm_slli_epi32::<COUNT>(self.get_m128i())) };
 		}
 
-		pub fn shift_left<const COUNT: i32>(&self) -> Self
+		let mut res = [0; 4];
+		for i in 0 .. 4
 		{
-			let mut res = [0; 4];
-			for i in 0 .. 4
-			{
-				res[i] = self.0[i] << COUNT
-			}
-			Self(res)
+			res[i] = self.0[i] << COUNT
 		}
+		Self(res)
+	}
 
-		pub fn shift_right<const COUNT: i32>(&self) -> Self
+	#[inline(always)]
+	pub fn shift_right<const COUNT: i32>(&self) -> Self
+	{
+		#[cfg(target_arch = "x86_64")]
+		if SIMD_LEVEL >= SIMD_LEVEL_SSE41
 		{
-			let mut res = [0; 4];
-			for i in 0 .. 4
-			{
-				res[i] = self.0[i] >> COUNT
-			}
-			Self(res)
+			return unsafe { Self::from_m128i(_mm_srai_epi32::<COUNT>(self.get_m128i())) };
 		}
 
-		pub fn extract<const INDEX: i32>(&mut self) -> i32
+		let mut res = [0; 4];
+		for i in 0 .. 4
 		{
-			self.0[INDEX as usize]
+			res[i] = self.0[i] >> COUNT
 		}
-	} // impl ColorVecI
-}
+		Self(res)
+	}
 
-// On relatively modern CPUs FMA instruction is faster than sequential multiplication and addition. So, use native "mul_add" on such CPUs.
-// But on old CPUs there is no such instruction, so, compiler uses ridiculously slow emulation. To avoid this just use combination of  multiplication and addition.
+	#[inline(always)]
+	pub fn extract<const INDEX: i32>(&mut self) -> i32
+	{
+		self.0[INDEX as usize]
+	}
+} // impl SimdColorVecI
 
-#[cfg(all(target_arch = "x86_64", target_feature = "fma"))]
-pub fn f32_mul_add(x: f32, y: f32, z: f32) -> f32
+#[cfg(target_arch = "x86_64")]
+impl<const SIMD_LEVEL: u32> SimdColorVecI<SIMD_LEVEL>
 {
-	f32::mul_add(x, y, z)
+	#[inline(always)]
+	fn from_m128i(v: __m128i) -> Self
+	{
+		Self(unsafe { std::mem::transmute::<__m128i, [i32; 4]>(v) })
+	}
+
+	#[inline(always)]
+	fn get_m128i(&self) -> __m128i
+	{
+		unsafe { std::mem::transmute::<[i32; 4], __m128i>(self.0) }
+	}
 }
 
-#[cfg(not(all(target_arch = "x86_64", target_feature = "fma")))]
-pub fn f32_mul_add(x: f32, y: f32, z: f32) -> f32
+// On relatively modern CPUs FMA instruction is faster than sequential multiplication and addition. So, use it for AVX2 level, which requires FMA support.
+// But on old CPUs there is no such instruction, so, "f32::mul_add" uses ridiculously slow emulation. To avoid this just use combination of multiplication and addition.
+#[inline(always)]
+pub fn f32_mul_add<const SIMD_LEVEL: u32>(x: f32, y: f32, z: f32) -> f32
 {
+	#[cfg(target_arch = "x86_64")]
+	if SIMD_LEVEL >= SIMD_LEVEL_AVX2
+	{
+		return unsafe { _mm_cvtss_f32(_mm_fmadd_ss(_mm_set_ss(x), _mm_set_ss(y), _mm_set_ss(z))) };
+	}
+
 	x * y + z
 }
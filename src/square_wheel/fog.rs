@@ -0,0 +1,167 @@
+use crate::common::{bbox::*, bsp_map_compact, map_file_common, math_types::*};
+
+#[derive(Copy, Clone, PartialEq)]
+pub struct FogParams
+{
+	// In range [0; 255].
+	pub color: [f32; 3],
+	// Exponential density (per unit of distance).
+	pub density: f32,
+	// Fog is not applied closer than this distance.
+	pub start: f32,
+	// Fog is fully opaque beyond this distance. Zero - no limit.
+	pub end: f32,
+}
+
+// Fog inside box-shaped volume.
+#[derive(Copy, Clone)]
+pub struct FogVolume
+{
+	pub bbox: BBox,
+	pub params: FogParams,
+}
+
+// Read global fog params from world entity keys.
+pub fn get_map_fog_params(map: &bsp_map_compact::BSPMap) -> Option<FogParams>
+{
+	map.entities.first().and_then(|e| parse_entity_fog_params(map, e))
+}
+
+// Fog volumes are brush entities with "fog_volume" class name.
+// Brushes of such entities should have invisible and non-solid material.
+pub fn get_map_fog_volumes(map: &bsp_map_compact::BSPMap) -> Vec<FogVolume>
+{
+	let mut result = Vec::new();
+	for entity in &map.entities
+	{
+		if entity.submodel_index as usize >= map.submodels.len() ||
+			bsp_map_compact::get_entity_key_value(map, entity, "classname") != Some("fog_volume")
+		{
+			continue;
+		}
+
+		if let Some(params) = parse_entity_fog_params(map, entity)
+		{
+			let bbox = bsp_map_compact::get_submodel_bbox(map, &map.submodels[entity.submodel_index as usize]);
+			if bbox.is_valid_and_non_empty()
+			{
+				result.push(FogVolume { bbox, params });
+			}
+		}
+	}
+
+	result
+}
+
+// Result is in range [0; 1].
+pub fn get_fog_amount(params: &FogParams, distance: f32) -> f32
+{
+	if params.end > 0.0 && distance >= params.end
+	{
+		return 1.0;
+	}
+
+	let distance_in_fog = distance - params.start;
+	if distance_in_fog <= 0.0
+	{
+		return 0.0;
+	}
+
+	1.0 - (-params.density * distance_in_fog).exp()
+}
+
+// Fog for small object (triangle model, sprite, decal), calculated for single point.
+// Result color is "color * scale + add".
+#[derive(Copy, Clone)]
+pub struct ObjectFog
+{
+	pub scale: f32,
+	pub add: [f32; 3],
+}
+
+impl Default for ObjectFog
+{
+	fn default() -> Self
+	{
+		Self {
+			scale: 1.0,
+			add: [0.0; 3],
+		}
+	}
+}
+
+pub fn get_object_fog(fog_volumes: &[FogVolume], cam_pos: &Vec3f, pos: &Vec3f) -> ObjectFog
+{
+	let mut result = ObjectFog::default();
+
+	let distance = (pos - cam_pos).magnitude();
+	for fog_volume in fog_volumes
+	{
+		let fraction = get_segment_bbox_intersection_fraction(cam_pos, pos, &fog_volume.bbox);
+		if fraction <= 0.0
+		{
+			continue;
+		}
+
+		// color + (fog - color) * k
+		let fog_amount = get_fog_amount(&fog_volume.params, distance * fraction);
+		let k = 1.0 - fog_amount;
+		result.scale *= k;
+		for i in 0 .. 3
+		{
+			result.add[i] = result.add[i] * k + fog_volume.params.color[i] * fog_amount;
+		}
+	}
+
+	result
+}
+
+// Get fraction of segment inside given box.
+pub fn get_segment_bbox_intersection_fraction(start: &Vec3f, end: &Vec3f, bbox: &BBox) -> f32
+{
+	let mut t_min = 0.0f32;
+	let mut t_max = 1.0f32;
+	let dir = end - start;
+	for i in 0 .. 3
+	{
+		if dir[i] == 0.0
+		{
+			if start[i] < bbox.min[i] || start[i] > bbox.max[i]
+			{
+				return 0.0;
+			}
+			continue;
+		}
+
+		let inv_dir = 1.0 / dir[i];
+		let t0 = (bbox.min[i] - start[i]) * inv_dir;
+		let t1 = (bbox.max[i] - start[i]) * inv_dir;
+		t_min = t_min.max(t0.min(t1));
+		t_max = t_max.min(t0.max(t1));
+	}
+
+	(t_max - t_min).max(0.0)
+}
+
+fn parse_entity_fog_params(map: &bsp_map_compact::BSPMap, entity: &bsp_map_compact::Entity) -> Option<FogParams>
+{
+	let density = bsp_map_compact::get_entity_key_value(map, entity, "fog_density")
+		.and_then(|v| map_file_common::parse_number(&mut &v[..]).ok())?;
+
+	let color = bsp_map_compact::get_entity_key_value(map, entity, "fog_color")
+		.and_then(|v| map_file_common::parse_vec3(v).ok())
+		.unwrap_or_else(|| Vec3f::new(128.0, 128.0, 128.0));
+	let start = bsp_map_compact::get_entity_key_value(map, entity, "fog_start")
+		.and_then(|v| map_file_common::parse_number(&mut &v[..]).ok())
+		.unwrap_or(0.0);
+	let end = bsp_map_compact::get_entity_key_value(map, entity, "fog_end")
+		.and_then(|v| map_file_common::parse_number(&mut &v[..]).ok())
+		.unwrap_or(0.0);
+
+	Some(FogParams {
+		color: [color.x, color.y, color.z],
+		density: density.max(0.0),
+		start: start.max(0.0),
+		end: end.max(0.0),
+	})
+}
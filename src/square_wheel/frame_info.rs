@@ -1,5 +1,6 @@
-use super::{light::*, resources_manager::*, textures::*, triangle_model::*};
+use super::{fog::*, light::*, procedural_sky::*, resources_manager::*, textures::*, triangle_model::*};
 use crate::common::{bbox::*, material, math_types::*, matrix::*};
+use std::collections::HashMap;
 
 pub struct FrameInfo
 {
@@ -9,8 +10,42 @@ pub struct FrameInfo
 	pub submodel_entities: Vec<SubmodelEntityOpt>,
 	pub model_entities: Vec<ModelEntity>,
 	pub decals: Vec<Decal>,
+	pub sprites: Vec<Sprite>,
+	pub portals: Vec<ViewPortal>,
+	pub camera_views: Vec<CameraView>,
 	pub lights: Vec<PointLight>,
 	pub skybox_rotation: QuaternionF,
+	// If some - override sun of procedural sky. Use it to animate time of day.
+	pub sky_sun: Option<SkySun>,
+	// If some - override global fog of map.
+	pub fog: Option<FogParams>,
+	// Override current frame of flipbook animation of map materials with given names.
+	pub materials_texture_frames: HashMap<String, u32>,
+	// If some - blend whole screen with given color (for damage, pickup and similar effects).
+	pub screen_tint: Option<ScreenTint>,
+	// If some - override automatic exposure (for cutscenes, scripted sequences, etc.).
+	pub exposure_override: Option<ExposureOverride>,
+	// If some - override contents of camera position (one of "bsp_map_compact::CONTENTS_*"), used for underwater effects.
+	// Use CONTENTS_EMPTY in order to disable underwater effects or liquid contents in order to force them.
+	pub view_contents: Option<u32>,
+}
+
+#[derive(Copy, Clone, PartialEq)]
+pub enum ExposureOverride
+{
+	// Keep exposure of previous frame, do not adapt it.
+	Lock,
+	// Use given exposure value.
+	Fixed(f32),
+}
+
+#[derive(Copy, Clone, PartialEq)]
+pub struct ScreenTint
+{
+	// In range [0; 255].
+	pub color: [f32; 3],
+	// In range [0; 1]. 0 - no tint, 1 - whole screen is filled with tint color.
+	pub alpha: f32,
 }
 
 pub type SubmodelEntityOpt = Option<SubmodelEntity>;
@@ -21,6 +56,8 @@ pub struct SubmodelEntity
 	// Position of Bbox center.
 	pub position: Vec3f,
 	pub rotation: QuaternionF,
+	// If some - override current frame of animated textures of this submodel (for switchable screens, etc.).
+	pub texture_frame: Option<u32>,
 }
 
 #[derive(Clone)]
@@ -31,6 +68,10 @@ pub struct ModelEntity
 	pub animation: AnimationPoint,
 	pub model: SharedResourcePtr<TriangleModel>,
 	pub texture: SharedResourcePtr<TextureLiteWithMips>,
+	// Optional material texture. If present, it is used instead of "texture".
+	// Normal map and specular of this texture are used for lighting.
+	// Use "ResourcesManager::get_material_animation_frame" in order to specify frame of animated material.
+	pub material: Option<SharedResourcePtr<TextureWithMips>>,
 	pub blending_mode: material::BlendingMode,
 	pub lighting: ModelLighting,
 
@@ -84,3 +125,74 @@ pub struct Decal
 	pub lightmap_light_scale: f32,
 	pub light_add: [f32; 3],
 }
+
+#[derive(Clone)]
+pub struct Sprite
+{
+	// Position of sprite center.
+	pub position: Vec3f,
+	// Rotation angle (in radians) around sprite normal.
+	pub angle: f32,
+	// Width and height.
+	pub size: Vec2f,
+	pub orientation: SpriteOrientation,
+	pub texture: SharedResourcePtr<TextureLiteWithMips>,
+	pub blending_mode: material::BlendingMode,
+	// Light is calculated for sprite center.
+	pub lighting: ModelLighting,
+}
+
+#[derive(Clone, Copy)]
+pub enum SpriteOrientation
+{
+	// Sprite plane is always parallel to camera plane.
+	FacingTowardsCamera,
+	// Sprite up vector is always Z axis, sprite is rotated around it towards camera.
+	// Useful for things like flames, trees, etc.
+	AlignedToZAxis,
+	// Sprite has fixed orientation. Initially sprite width axis is X and height axis is Z.
+	Fixed(QuaternionF),
+}
+
+// Secondary view, rendered into texture.
+// Result is used by map materials with same camera view name.
+// Also result of previous frame is available via resources manager (for models).
+#[derive(Clone)]
+pub struct CameraView
+{
+	pub name: String,
+	// Should be built for view size.
+	pub camera_matrices: CameraMatrices,
+	// If there is a map material with this camera view, view is rendered with size of its texture instead.
+	pub size: [u32; 2],
+}
+
+// Visual portal - polygon, showing view of another place of the map.
+#[derive(Clone)]
+pub struct ViewPortal
+{
+	// Both sides of portal polygon are visible.
+	pub geometry: ViewPortalGeometry,
+	// Position and rotation of portal space on source side.
+	pub source_position: Vec3f,
+	pub source_rotation: QuaternionF,
+	// Position and rotation of portal space on destination side.
+	// View inside portal is transformed from source space into destination space.
+	pub destination_position: Vec3f,
+	pub destination_rotation: QuaternionF,
+}
+
+#[derive(Clone)]
+pub enum ViewPortalGeometry
+{
+	// Convex polygon in world space.
+	Polygon(Vec<Vec3f>),
+	// Polygon of submodel with current submodel transformation applied.
+	// Polygon index is relative to first polygon of submodel.
+	// Use material with "draw: false" for this polygon, in order to avoid drawing it over view inside portal.
+	SubmodelPolygon
+	{
+		submodel_index: u32,
+		polygon_index: u32,
+	},
+}
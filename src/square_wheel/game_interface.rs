@@ -1,5 +1,5 @@
-use super::{commands_processor::*, console::*, frame_info::*, resources_manager::*};
-use crate::common::{bsp_map_compact::*, color::*, system_window};
+use super::{commands_processor::*, console::*, frame_info::*, picking::*, resources_manager::*};
+use crate::common::{bsp_map_compact::*, color::*, material, math_types::*, system_window};
 use std::sync::Arc;
 
 pub trait GameInterface: Send + Sync
@@ -19,6 +19,21 @@ pub trait GameInterface: Send + Sync
 	fn get_frame_info(&self, surface_info: &system_window::SurfaceInfo) -> FrameInfo;
 
 	fn draw_frame_overlay(&self, _pixels: &mut [Color32], _surface_info: &system_window::SurfaceInfo) {}
+
+	// Called after frame drawing. Use given queries in order to obtain information about drawn frame.
+	fn process_frame_queries(&mut self, _queries: &dyn FrameQueries, _surface_info: &system_window::SurfaceInfo) {}
+}
+
+// Queries for last drawn frame. Pixel coordinates are in window resolution.
+pub trait FrameQueries
+{
+	// Returns None if depth output is disabled or if there is no geometry at given pixel.
+	fn get_world_position_for_pixel(&self, x: u32, y: u32) -> Option<Vec3f>;
+
+	fn pick(&self, x: u32, y: u32) -> Option<PickResult>;
+
+	// Get material by index in map textures list (as returned by picking).
+	fn get_material(&self, material_index: u32) -> &material::Material;
 }
 
 pub type GameInterfacePtr = Box<dyn GameInterface>;
@@ -1,8 +1,9 @@
 use super::{
-	commands_processor, commands_queue, config, console, debug_stats_printer::*, game_interface::*, host_config::*,
-	performance_counter::*, postprocessor::*, renderer, resources_manager::*, text_printer, ticks_counter::*,
+	anti_aliasing::*, color_grading::*, commands_processor, commands_queue, config, console, cpu_features::*,
+	debug_stats_printer::*, frame_info::*, game_interface::*, host_config::*, performance_counter::*, picking::*,
+	postprocessor::*, renderer, resources_manager::*, text_printer, ticks_counter::*, upscaler::*,
 };
-use crate::common::{color::*, system_window};
+use crate::common::{color::*, image, image_hdr, material, math_types::*, system_window};
 use sdl2::{event::Event, keyboard::Keycode};
 use std::time::Duration;
 
@@ -17,6 +18,14 @@ pub struct Host
 	console: console::ConsoleSharedPtr,
 	window: system_window::SystemWindow,
 	postprocessor: Postprocessor,
+	upscaler: Upscaler,
+	// Buffer for image with doubled resolution, used in supersampling mode.
+	supersampling_buffer: Vec<Color32>,
+	// Current scale of rendering resolution. Changes each frame in dynamic resolution mode.
+	current_resolution_scale: f32,
+	// Sum of rendering durations and number of frames since last dynamic resolution scale update.
+	dynamic_resolution_durations_sum_s: f32,
+	dynamic_resolution_num_frames: u32,
 	resources_manager: ResourcesManagerSharedPtr,
 	game_creation_function: GameCreationFunction,
 	active_map: Option<ActiveMap>,
@@ -25,6 +34,17 @@ pub struct Host
 	fps_counter: TicksCounter,
 	frame_duration_counter: PerformanceCounter,
 	quit_requested: bool,
+	// If some - save next frame into given file.
+	pending_screenshot_path: Option<std::path::PathBuf>,
+	frames_recording: Option<FramesRecording>,
+}
+
+// Recording of frames sequence with fixed timestep.
+struct FramesRecording
+{
+	directory: std::path::PathBuf,
+	fps: f32,
+	next_frame_index: u32,
 }
 
 struct ActiveMap
@@ -84,6 +104,8 @@ impl Host
 			("map", Host::command_map),
 			("quit", Host::command_quit),
 			("resize_window", Host::command_resize_window),
+			("screenshot", Host::command_screenshot),
+			("record_frames", Host::command_record_frames),
 		]);
 
 		commands_processor
@@ -103,7 +125,12 @@ impl Host
 			commands_processor,
 			console: console.clone(),
 			window: system_window::SystemWindow::new(),
-			postprocessor: Postprocessor::new(app_config.clone()),
+			postprocessor: Postprocessor::new(app_config.clone(), console.clone()),
+			upscaler: Upscaler::default(),
+			supersampling_buffer: Vec::new(),
+			current_resolution_scale: 1.0,
+			dynamic_resolution_durations_sum_s: 0.0,
+			dynamic_resolution_num_frames: 0,
 			resources_manager: ResourcesManager::new(app_config, console),
 			game_creation_function,
 			active_map: None,
@@ -112,6 +139,8 @@ impl Host
 			fps_counter: TicksCounter::new(),
 			frame_duration_counter: PerformanceCounter::new(200),
 			quit_requested: false,
+			pending_screenshot_path: None,
+			frames_recording: None,
 		};
 
 		// Process startup commands one by one.
@@ -221,6 +250,38 @@ impl Host
 			config_is_durty = true;
 		}
 
+		const MIN_RESOLUTION_SCALE: f32 = 0.25;
+		for scale in [
+			&mut self.config.resolution_scale,
+			&mut self.config.min_dynamic_resolution_scale,
+		]
+		{
+			let scale_corrected = scale.max(MIN_RESOLUTION_SCALE).min(1.0);
+			if *scale != scale_corrected
+			{
+				*scale = scale_corrected;
+				config_is_durty = true;
+			}
+		}
+
+		if self.config.upscale_mode > 2
+		{
+			self.config.upscale_mode = 2;
+			config_is_durty = true;
+		}
+
+		if self.config.simd_level > 3
+		{
+			self.config.simd_level = 3;
+			config_is_durty = true;
+		}
+		set_max_simd_level(SimdLevel::from_config_value(self.config.simd_level));
+
+		if !self.config.dynamic_resolution_scale
+		{
+			self.current_resolution_scale = self.config.resolution_scale;
+		}
+
 		if config_is_durty
 		{
 			self.config.update_app_config(&self.app_config);
@@ -262,7 +323,15 @@ impl Host
 		const MAX_TIME_DELTA: f32 = 0.1;
 
 		let cur_time = std::time::Instant::now();
-		let time_delta_s = (cur_time - self.prev_time).as_secs_f32().min(MAX_TIME_DELTA);
+		let time_delta_s = if let Some(frames_recording) = &self.frames_recording
+		{
+			// Use fixed timestep for frames recording, regardless of actual frame time.
+			1.0 / frames_recording.fps
+		}
+		else
+		{
+			(cur_time - self.prev_time).as_secs_f32().min(MAX_TIME_DELTA)
+		};
 		self.prev_time = cur_time;
 
 		self.frame_duration_counter.add_value(time_delta_s);
@@ -271,6 +340,8 @@ impl Host
 
 		let window = &mut self.window;
 		let postprocessor = &mut self.postprocessor;
+		let upscaler = &mut self.upscaler;
+		let upscale_mode = UpscaleMode::from_config_value(self.config.upscale_mode);
 		let active_map = &mut self.active_map;
 		let console = self.console.clone();
 		let fps_counter = &mut self.fps_counter;
@@ -278,24 +349,46 @@ impl Host
 		let frame_duration_counter = &self.frame_duration_counter;
 		let prev_frame_end_time = &mut self.prev_frame_end_time;
 
+		let pending_screenshot_path = &mut self.pending_screenshot_path;
+		let frames_recording = &mut self.frames_recording;
+
 		let mut frame_info = None;
 
+		// Measure only rendering time (without waiting) in order to adjust dynamic resolution scale.
+		let mut rendering_duration_s = 0.0;
+
 		// First, only prepare frame without accessing surface pixels.
 		let surface_info_initial = window.get_window_surface_info();
+		// Render frame in reduced resolution and upscale it if necessary.
+		let render_surface_info = get_scaled_surface_info(&surface_info_initial, self.current_resolution_scale);
+		let use_upscale = render_surface_info != surface_info_initial;
+		// In supersampling mode draw frame with doubled resolution and downsample it later.
+		let use_supersampling = postprocessor.get_anti_aliasing_mode() == AntiAliasingMode::Supersampling2x;
+		let draw_surface_info = if use_supersampling
+		{
+			get_supersampling_surface_info(&render_surface_info)
+		}
+		else
+		{
+			render_surface_info
+		};
+		let supersampling_buffer = &mut self.supersampling_buffer;
+
 		let mut prepare_frame_func = || {
+			let prepare_start_time = std::time::Instant::now();
 			if let Some(active_map) = active_map
 			{
 				// Process game logic.
 				active_map.game.update(&keyboard_state, &events, time_delta_s);
 
 				// Get frame info from game code.
-				frame_info = Some(active_map.game.get_frame_info(&surface_info_initial));
+				frame_info = Some(active_map.game.get_frame_info(&draw_surface_info));
 				let frame_info_ref = frame_info.as_ref().unwrap();
 
 				// Perform rendering frame preparation.
 				if postprocessor.use_hdr_rendering()
 				{
-					let hdr_buffer_size = [surface_info_initial.width, surface_info_initial.height];
+					let hdr_buffer_size = [draw_surface_info.width, draw_surface_info.height];
 					let hdr_buffer = postprocessor.get_hdr_buffer(hdr_buffer_size);
 
 					let hdr_surface_info = system_window::SurfaceInfo {
@@ -319,9 +412,10 @@ impl Host
 				{
 					active_map
 						.renderer
-						.prepare_frame::<Color32>(&surface_info_initial, frame_info_ref);
+						.prepare_frame::<Color32>(&draw_surface_info, frame_info_ref);
 				}
 			}
+			rendering_duration_s += (std::time::Instant::now() - prepare_start_time).as_secs_f32();
 		};
 
 		let mut limit_fps_func = || {
@@ -368,28 +462,127 @@ impl Host
 
 			if let Some(active_map) = active_map
 			{
-				if postprocessor.use_hdr_rendering()
+				let draw_start_time = std::time::Instant::now();
+
+				let render_pixels = if use_upscale
 				{
-					postprocessor.perform_postprocessing(
-						pixels,
-						surface_info,
-						time_delta_s,
-						&mut active_map.debug_stats_printer,
-					);
+					upscaler.get_buffer([render_surface_info.width, render_surface_info.height])
 				}
 				else
 				{
-					let frame_info_ref = frame_info.as_ref().unwrap();
-					active_map.renderer.draw_frame(
-						pixels,
-						surface_info,
-						frame_info_ref,
-						&mut active_map.debug_stats_printer,
+					&mut pixels[..]
+				};
+
+				let mut draw_func = |draw_pixels: &mut [Color32], draw_surface_info: &system_window::SurfaceInfo| {
+					if postprocessor.use_hdr_rendering()
+					{
+						postprocessor.perform_postprocessing(
+							draw_pixels,
+							draw_surface_info,
+							time_delta_s,
+							frame_info.as_ref().and_then(|f| f.exposure_override),
+							&frame_info.as_ref().unwrap().camera_matrices,
+							active_map.renderer.get_depth_buffer(),
+							&mut active_map.debug_stats_printer,
+						);
+					}
+					else
+					{
+						let frame_info_ref = frame_info.as_ref().unwrap();
+						active_map.renderer.draw_frame(
+							draw_pixels,
+							draw_surface_info,
+							frame_info_ref,
+							&mut active_map.debug_stats_printer,
+						);
+					}
+				};
+
+				if use_supersampling
+				{
+					let required_size = draw_surface_info.width * draw_surface_info.height;
+					if supersampling_buffer.len() < required_size
+					{
+						supersampling_buffer.resize(required_size, Color32::black());
+					}
+					let supersampling_pixels = &mut supersampling_buffer[.. required_size];
+
+					draw_func(supersampling_pixels, &draw_surface_info);
+					postprocessor.perform_supersampling_downscale(
+						supersampling_pixels,
+						&draw_surface_info,
+						render_pixels,
+						&render_surface_info,
 					);
 				}
+				else
+				{
+					draw_func(render_pixels, &render_surface_info);
+				}
+
+				postprocessor.perform_ldr_postprocessing(
+					render_pixels,
+					&render_surface_info,
+					frame_info.as_ref().and_then(|f| f.screen_tint.as_ref()),
+					&mut active_map.debug_stats_printer,
+				);
+
+				postprocessor.perform_anti_aliasing(
+					render_pixels,
+					&render_surface_info,
+					&mut active_map.debug_stats_printer,
+				);
+
+				if use_upscale
+				{
+					upscaler.perform_upscale(pixels, surface_info, upscale_mode);
+				}
+
+				// Quantize after upscale, since upscaling produces colors outside palette.
+				postprocessor.perform_palette_quantization(pixels, surface_info, &mut active_map.debug_stats_printer);
+
+				rendering_duration_s += (std::time::Instant::now() - draw_start_time).as_secs_f32();
 
 				active_map.game.draw_frame_overlay(pixels, surface_info);
 
+				// Collect paths of files for saving of current frame.
+				// Claim them only if frame is actually drawn, in order to avoid gaps in frames numbering.
+				// HDR image is saved only for screenshots, since it's too big.
+				let mut frame_save_paths = Vec::new();
+				let screenshot_path_for_hdr = pending_screenshot_path.take();
+				if let Some(path) = &screenshot_path_for_hdr
+				{
+					frame_save_paths.push(path.clone());
+				}
+				if let Some(frames_recording) = frames_recording
+				{
+					let mut path = frames_recording.directory.clone();
+					path.push(format!("frame_{:06}.bmp", frames_recording.next_frame_index));
+					frame_save_paths.push(path);
+					frames_recording.next_frame_index += 1;
+				}
+
+				// Save frame without debug stats, console and other host overlays.
+				for path in &frame_save_paths
+				{
+					if !save_frame_image(pixels, surface_info, path)
+					{
+						console
+							.lock()
+							.unwrap()
+							.add_text(format!("Failed to save frame into {:?}", path));
+					}
+				}
+				if let (Some(path), Some(hdr_image)) = (&screenshot_path_for_hdr, postprocessor.get_hdr_image())
+				{
+					let mut hdr_path = path.clone();
+					hdr_path.set_extension("hdr");
+					if let Err(e) = image_hdr::save(&hdr_image, &hdr_path)
+					{
+						console.lock().unwrap().add_text(e);
+					}
+				}
+
 				active_map.debug_stats_printer.flush(pixels, surface_info);
 			}
 			else
@@ -399,6 +592,14 @@ impl Host
 				{
 					*pixel = Color32::black();
 				}
+
+				if let Some(path) = pending_screenshot_path.take()
+				{
+					console
+						.lock()
+						.unwrap()
+						.add_text(format!("Can not save screenshot into {:?} - no active map", path));
+				}
 			}
 
 			console.lock().unwrap().draw(pixels, surface_info);
@@ -417,6 +618,19 @@ impl Host
 			);
 		});
 
+		if let (Some(active_map), Some(frame_info)) = (&mut self.active_map, &frame_info)
+		{
+			let queries = RendererFrameQueries {
+				renderer: &active_map.renderer,
+				frame_info,
+				scale: [
+					(draw_surface_info.width as f32) / (surface_info_initial.width.max(1) as f32),
+					(draw_surface_info.height as f32) / (surface_info_initial.height.max(1) as f32),
+				],
+			};
+			active_map.game.process_frame_queries(&queries, &surface_info_initial);
+		}
+
 		if !parallel_swap_buffers
 		{
 			// Finally, swap buffers for CURRENT frame.
@@ -426,9 +640,56 @@ impl Host
 
 		fps_counter.tick();
 
+		if self.config.dynamic_resolution_scale && self.active_map.is_some()
+		{
+			self.update_dynamic_resolution_scale(rendering_duration_s);
+		}
+
 		!self.quit_requested
 	}
 
+	fn update_dynamic_resolution_scale(&mut self, rendering_duration_s: f32)
+	{
+		const DEFAULT_TARGET_FPS: f32 = 60.0;
+		let target_fps = if self.config.max_fps > 0.0
+		{
+			self.config.max_fps
+		}
+		else
+		{
+			DEFAULT_TARGET_FPS
+		};
+		let target_duration_s = 1.0 / target_fps;
+
+		// Use average duration of several frames in order to avoid reaction on single slow or fast frames.
+		const NUM_FRAMES_TO_AVERAGE: u32 = 8;
+		self.dynamic_resolution_durations_sum_s += rendering_duration_s;
+		self.dynamic_resolution_num_frames += 1;
+		if self.dynamic_resolution_num_frames < NUM_FRAMES_TO_AVERAGE
+		{
+			return;
+		}
+		let average_duration_s = self.dynamic_resolution_durations_sum_s / (self.dynamic_resolution_num_frames as f32);
+		self.dynamic_resolution_durations_sum_s = 0.0;
+		self.dynamic_resolution_num_frames = 0;
+
+		// Reduce resolution fast if frame is too slow, increase it slowly if there is enough time.
+		// Do not change resolution if duration is within dead band below target in order to avoid oscillations.
+		const DEAD_BAND_LOWER_BOUND: f32 = 0.75;
+		let mut scale = self.current_resolution_scale;
+		if average_duration_s > target_duration_s
+		{
+			// Number of pixels is proportional to square of scale.
+			scale *= (target_duration_s / average_duration_s).sqrt().max(0.85);
+		}
+		else if average_duration_s < target_duration_s * DEAD_BAND_LOWER_BOUND
+		{
+			scale *= 1.05;
+		}
+
+		self.current_resolution_scale = scale.max(self.config.min_dynamic_resolution_scale).min(1.0);
+	}
+
 	fn command_map(&mut self, args: commands_queue::CommandArgs)
 	{
 		if args.is_empty()
@@ -440,6 +701,8 @@ impl Host
 			return;
 		}
 		self.active_map = None;
+		self.postprocessor.set_map_color_grading(MapColorGrading::default());
+		self.postprocessor.reset_prev_camera();
 
 		let map_name = &args[0];
 
@@ -482,6 +745,10 @@ impl Host
 				debug_stats_printer: DebugStatsPrinter::new(self.config.show_debug_stats),
 			});
 
+			let map_color_grading =
+				load_map_color_grading(&map, &mut self.resources_manager.lock().unwrap(), &self.console);
+			self.postprocessor.set_map_color_grading(map_color_grading);
+
 			// Clear unused resources from previous map.
 			self.resources_manager.lock().unwrap().clear_cache();
 
@@ -494,6 +761,116 @@ impl Host
 		}
 	}
 
+	fn command_screenshot(&mut self, args: commands_queue::CommandArgs)
+	{
+		if self.active_map.is_none()
+		{
+			self.console
+				.lock()
+				.unwrap()
+				.add_text("Can't make screenshot without active map".to_string());
+			return;
+		}
+
+		let path = if let Some(file_name) = args.first()
+		{
+			std::path::PathBuf::from(file_name)
+		}
+		else
+		{
+			// Find first unused file name in screenshots directory.
+			let directory = std::path::PathBuf::from(self.config.screenshots_path.clone());
+			if let Err(e) = std::fs::create_dir_all(&directory)
+			{
+				self.console
+					.lock()
+					.unwrap()
+					.add_text(format!("Failed to create directory {:?}: {}", directory, e));
+				return;
+			}
+
+			let mut index = 0;
+			loop
+			{
+				let mut path = directory.clone();
+				path.push(format!("screenshot_{:04}.bmp", index));
+				if !path.exists()
+				{
+					break path;
+				}
+				index += 1;
+			}
+		};
+
+		self.console
+			.lock()
+			.unwrap()
+			.add_text(format!("Saving screenshot into {:?}", path));
+		self.pending_screenshot_path = Some(path);
+	}
+
+	fn command_record_frames(&mut self, args: commands_queue::CommandArgs)
+	{
+		if args.is_empty()
+		{
+			if let Some(frames_recording) = self.frames_recording.take()
+			{
+				self.console.lock().unwrap().add_text(format!(
+					"Stopped frames recording, {} frames written",
+					frames_recording.next_frame_index
+				));
+			}
+			else
+			{
+				self.console
+					.lock()
+					.unwrap()
+					.add_text("Expected directory and fps. Call without args to stop recording".to_string());
+			}
+			return;
+		}
+
+		let fps = if let Some(fps_str) = args.get(1)
+		{
+			match fps_str.parse::<f32>()
+			{
+				Ok(fps) if (1.0 ..= 1000.0).contains(&fps) => fps,
+				_ =>
+				{
+					self.console
+						.lock()
+						.unwrap()
+						.add_text("Invalid fps, expected value in range [1; 1000]".to_string());
+					return;
+				},
+			}
+		}
+		else
+		{
+			DEFAULT_FRAMES_RECORDING_FPS
+		};
+
+		let directory = std::path::PathBuf::from(&args[0]);
+		if let Err(e) = std::fs::create_dir_all(&directory)
+		{
+			self.console
+				.lock()
+				.unwrap()
+				.add_text(format!("Failed to create directory {:?}: {}", directory, e));
+			return;
+		}
+
+		self.console
+			.lock()
+			.unwrap()
+			.add_text(format!("Recording frames into {:?} with {} fps", directory, fps));
+		self.frames_recording = Some(FramesRecording {
+			directory,
+			fps,
+			next_frame_index: 0,
+		});
+	}
+
 	fn command_quit(&mut self, _args: commands_queue::CommandArgs)
 	{
 		self.quit_requested = true;
@@ -521,6 +898,22 @@ impl Host
 	}
 }
 
+const DEFAULT_FRAMES_RECORDING_FPS: f32 = 30.0;
+
+fn save_frame_image(pixels: &[Color32], surface_info: &system_window::SurfaceInfo, path: &std::path::Path) -> bool
+{
+	let mut image = image::Image {
+		size: [surface_info.width as u32, surface_info.height as u32],
+		pixels: Vec::with_capacity(surface_info.width * surface_info.height),
+	};
+	for line in pixels.chunks(surface_info.pitch).take(surface_info.height)
+	{
+		image.pixels.extend_from_slice(&line[.. surface_info.width]);
+	}
+
+	image::save(&image, path)
+}
+
 impl Drop for Host
 {
 	fn drop(&mut self)
@@ -528,3 +921,44 @@ impl Drop for Host
 		config::save(&self.app_config.lock().unwrap(), &self.config_file_path);
 	}
 }
+
+// Frame queries with conversion of window pixel coordinates into coordinates of rendered frame.
+// Rendered frame size may differ from window size because of resolution scale and supersampling.
+struct RendererFrameQueries<'a>
+{
+	renderer: &'a renderer::Renderer,
+	frame_info: &'a FrameInfo,
+	scale: [f32; 2],
+}
+
+impl<'a> RendererFrameQueries<'a>
+{
+	fn convert_pixel_coordinates(&self, x: u32, y: u32) -> (u32, u32)
+	{
+		(
+			(((x as f32) + 0.5) * self.scale[0]) as u32,
+			(((y as f32) + 0.5) * self.scale[1]) as u32,
+		)
+	}
+}
+
+impl<'a> FrameQueries for RendererFrameQueries<'a>
+{
+	fn get_world_position_for_pixel(&self, x: u32, y: u32) -> Option<Vec3f>
+	{
+		let (x, y) = self.convert_pixel_coordinates(x, y);
+		self.renderer.get_world_position_for_pixel(x, y)
+	}
+
+	fn pick(&self, x: u32, y: u32) -> Option<PickResult>
+	{
+		let (x, y) = self.convert_pixel_coordinates(x, y);
+		self.renderer
+			.pick(&self.frame_info.camera_matrices, x, y, self.frame_info)
+	}
+
+	fn get_material(&self, material_index: u32) -> &material::Material
+	{
+		self.renderer.get_material(material_index)
+	}
+}
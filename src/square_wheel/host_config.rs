@@ -18,6 +18,31 @@ pub struct HostConfig
 
 	#[serde(default)]
 	pub fullscreen_mode: u32,
+
+	// Scale of rendering resolution relative to window resolution. Rendered image is upscaled to window size.
+	#[serde(default = "default_one")]
+	pub resolution_scale: f32,
+
+	// If true - adjust resolution scale in order to reach "max_fps".
+	#[serde(default)]
+	pub dynamic_resolution_scale: bool,
+
+	// Minimum resolution scale for dynamic mode.
+	#[serde(default = "default_min_dynamic_resolution_scale")]
+	pub min_dynamic_resolution_scale: f32,
+
+	// 0 - nearest, 1 - bilinear, 2 - bilinear with sharpening.
+	#[serde(default = "default_upscale_mode")]
+	pub upscale_mode: u32,
+
+	// SIMD instructions used for rendering. 0 - best supported by CPU, 1 - scalar, 2 - SSE4.1, 3 - AVX2.
+	// Instructions not supported by CPU are never used.
+	#[serde(default)]
+	pub simd_level: u32,
+
+	// Directory for screenshots, made without explicit file name.
+	#[serde(default = "default_screenshots_path")]
+	pub screenshots_path: String,
 }
 
 impl HostConfig
@@ -41,6 +66,26 @@ fn max_fps_default() -> f32
 	120.0
 }
 
+fn default_one() -> f32
+{
+	1.0
+}
+
+fn default_min_dynamic_resolution_scale() -> f32
+{
+	0.5
+}
+
+fn default_upscale_mode() -> u32
+{
+	1
+}
+
+fn default_screenshots_path() -> String
+{
+	"screenshots".to_string()
+}
+
 fn default_true() -> bool
 {
 	true
@@ -1,5 +1,5 @@
-use super::{abstract_color::*, fast_math::*, resources_manager::*, textures::*};
-use crate::common::{bsp_map_compact, color::*, material::*};
+use super::{abstract_color::*, cpu_features::*, fast_math::*, resources_manager::*, textures::*};
+use crate::common::{bsp_map_compact, color::*, image, material::*};
 use std::{borrow::Borrow, collections::HashMap};
 
 pub struct MapMaterialsProcessor
@@ -8,8 +8,19 @@ pub struct MapMaterialsProcessor
 	textures: Vec<SharedResourcePtr<TextureWithMips>>,
 	skybox_textures_32: HashMap<u32, SharedResourcePtr<SkyboxTextures<Color32>>>,
 	skybox_textures_64: HashMap<u32, SharedResourcePtr<SkyboxTextures<Color64>>>,
+	// Names of materials, used for frame override by game code.
+	material_names: Vec<String>,
+	// Frames of flipbook-animated textures. Empty for non-animated materials.
+	animation_frames: Vec<SharedResourcePtr<Vec<SharedResourcePtr<TextureWithMips>>>>,
+	// Current frame of flipbook animation for each material.
+	current_animation_frames: Vec<u32>,
 	// Store here only animated textures.
 	textures_modified: Vec<TextureWithMips>,
+	// Images of camera views for materials with camera view. Used instead of regular textures.
+	// Memory is allocated once and reused.
+	camera_view_textures: Vec<TextureWithMips>,
+	// Camera view textures are valid only if set for current frame.
+	camera_view_textures_set: Vec<bool>,
 	temp_buffer: Vec<TextureElement>,
 }
 
@@ -21,7 +32,9 @@ impl MapMaterialsProcessor
 		let all_materials = r.get_materials();
 
 		let mut materials = Vec::with_capacity(map.textures.len());
+		let mut material_names = Vec::with_capacity(map.textures.len());
 		let mut textures = Vec::with_capacity(map.textures.len());
+		let mut animation_frames = Vec::with_capacity(map.textures.len());
 		let mut skybox_textures_32 = HashMap::new();
 		let mut skybox_textures_64 = HashMap::new();
 		for (texture_index, texture_name) in map.textures.iter().enumerate()
@@ -49,32 +62,73 @@ impl MapMaterialsProcessor
 
 			materials.push(material);
 			textures.push(r.get_material_texture(&material_name));
+			animation_frames.push(r.get_material_animation_frames(&material_name));
+			material_names.push(material_name);
 		}
 
 		let textures_modified = vec![TextureWithMips::default(); textures.len()];
+		let current_animation_frames = vec![0; textures.len()];
+		let camera_view_textures = vec![TextureWithMips::default(); textures.len()];
+		let camera_view_textures_set = vec![false; textures.len()];
 
 		Self {
 			materials,
+			material_names,
 			textures,
+			animation_frames,
+			current_animation_frames,
 			textures_modified,
+			camera_view_textures,
+			camera_view_textures_set,
 			skybox_textures_32,
 			skybox_textures_64,
 			temp_buffer: Vec::new(),
 		}
 	}
 
-	pub fn update(&mut self, current_time_s: f32)
+	// "texture_frames_override" - frames of flipbook animation, specified by game code for materials with given names.
+	pub fn update(&mut self, current_time_s: f32, texture_frames_override: &HashMap<String, u32>)
 	{
 		// TODO - maybe perform lazy update (on demand)?
 
+		for (material, ((frames, current_frame), material_name)) in self.materials.iter().zip(
+			self.animation_frames
+				.iter()
+				.zip(self.current_animation_frames.iter_mut())
+				.zip(self.material_names.iter()),
+		)
+		{
+			if frames.is_empty()
+			{
+				continue;
+			}
+			if let Some(frame) = texture_frames_override.get(material_name)
+			{
+				*current_frame = frame % (frames.len() as u32);
+			}
+			else if let Some(animation) = &material.animation
+			{
+				*current_frame = get_animation_frame(animation, frames.len() as u32, current_time_s);
+			}
+		}
+
 		// TODO - maybe use parallel for here?
-		for (material, (src_texture, dst_texture)) in self
-			.materials
-			.iter()
-			.zip(self.textures.iter().zip(self.textures_modified.iter_mut()))
+		for (material_index, (material, dst_texture)) in
+			self.materials.iter().zip(self.textures_modified.iter_mut()).enumerate()
 		{
 			if let Some(turb) = &material.turb
 			{
+				// Apply turbulence on top of current animation frame.
+				let frames = &self.animation_frames[material_index];
+				let src_texture = if frames.is_empty()
+				{
+					&self.textures[material_index]
+				}
+				else
+				{
+					&frames[self.current_animation_frames[material_index] as usize]
+				};
+
 				for mip_index in 0 .. NUM_MIPS
 				{
 					let src_mip = &src_texture[mip_index];
@@ -90,6 +144,78 @@ impl MapMaterialsProcessor
 		}
 	}
 
+	// Get size of texture of first material with given camera view name.
+	// Camera view should be rendered with this size in order to preserve texture coordinates.
+	pub fn get_camera_view_texture_size(&self, name: &str) -> Option<[u32; 2]>
+	{
+		for (material, texture) in self.materials.iter().zip(self.textures.iter())
+		{
+			if material.camera_view.as_deref() == Some(name)
+			{
+				return Some(texture[0].size);
+			}
+		}
+		None
+	}
+
+	// Reset camera view textures of previous frame.
+	pub fn reset_camera_view_images(&mut self)
+	{
+		self.camera_view_textures_set.fill(false);
+	}
+
+	// Set image for all materials with given camera view name for current frame.
+	// Image is resized to size of material texture, if it is necessary.
+	pub fn set_camera_view_image(&mut self, name: &str, image: &image::Image)
+	{
+		for (material_index, material) in self.materials.iter().enumerate()
+		{
+			if material.camera_view.as_deref() != Some(name)
+			{
+				continue;
+			}
+
+			let size = self.textures[material_index][0].size;
+			let dst = &mut self.camera_view_textures[material_index];
+			if dst[0].size != size
+			{
+				// Allocate texture only once.
+				*dst = build_texture_mips(make_texture(
+					image::Image {
+						size,
+						pixels: vec![Color32::black(); (size[0] * size[1]) as usize],
+					},
+					None,
+					1.0,
+					None,
+					false,
+					None,
+					1.0,
+				));
+			}
+
+			// Normally image has proper size, resizing is needed only for materials with same camera view and different sizes.
+			let image_resized;
+			let src_image = if image.size == size
+			{
+				image
+			}
+			else
+			{
+				image_resized = resize_image(image, size);
+				&image_resized
+			};
+
+			for (dst, src) in dst[0].pixels.iter_mut().zip(src_image.pixels.iter())
+			{
+				dst.diffuse = *src;
+			}
+			update_texture_diffuse_mips(dst);
+
+			self.camera_view_textures_set[material_index] = true;
+		}
+	}
+
 	pub fn get_material(&self, material_index: u32) -> &Material
 	{
 		&self.materials[material_index as usize]
@@ -97,6 +223,11 @@ impl MapMaterialsProcessor
 
 	pub fn get_texture(&self, material_index: u32) -> &TextureWithMips
 	{
+		if self.camera_view_textures_set[material_index as usize]
+		{
+			return &self.camera_view_textures[material_index as usize];
+		}
+
 		let animated_texture = &self.textures_modified[material_index as usize];
 		if !animated_texture[0].pixels.is_empty()
 		{
@@ -104,10 +235,30 @@ impl MapMaterialsProcessor
 			return animated_texture;
 		}
 
+		let frames = &self.animation_frames[material_index as usize];
+		if !frames.is_empty()
+		{
+			// Return current frame of flipbook animation.
+			return &frames[self.current_animation_frames[material_index as usize] as usize];
+		}
+
 		// Return source texture.
 		&self.textures[material_index as usize]
 	}
 
+	// Get texture with frame of flipbook animation specified explicitly (by game code).
+	// Turbulence effect is not applied in such case.
+	pub fn get_texture_with_frame(&self, material_index: u32, frame: u32) -> &TextureWithMips
+	{
+		let frames = &self.animation_frames[material_index as usize];
+		if frames.is_empty()
+		{
+			return self.get_texture(material_index);
+		}
+
+		&frames[(frame as usize) % frames.len()]
+	}
+
 	pub fn get_skybox_textures<ColorT: AbstractColor>(&self, material_index: u32) -> Option<&SkyboxTextures<ColorT>>
 	{
 		// Use an ugly hack to return proper skybox texture.
@@ -139,6 +290,34 @@ impl MapMaterialsProcessor
 	}
 }
 
+fn get_animation_frame(animation: &TextureAnimationParams, num_frames: u32, current_time_s: f32) -> u32
+{
+	if num_frames <= 1 || animation.frames_per_second <= 0.0
+	{
+		return 0;
+	}
+
+	let frame = (current_time_s * animation.frames_per_second).max(0.0) as u32;
+	match animation.loop_mode
+	{
+		AnimationLoopMode::Loop => frame % num_frames,
+		AnimationLoopMode::Once => frame.min(num_frames - 1),
+		AnimationLoopMode::PingPong =>
+		{
+			let period = (num_frames - 1) * 2;
+			let frame_in_period = frame % period;
+			if frame_in_period < num_frames
+			{
+				frame_in_period
+			}
+			else
+			{
+				period - frame_in_period
+			}
+		},
+	}
+}
+
 fn make_turb_distortion(
 	turb: &TurbParams,
 	current_time_s: f32,
@@ -164,8 +343,8 @@ fn make_turb_distortion(
 	// Shift rows.
 	for y in 0 .. size[1]
 	{
-		let shift = f32_mul_add(
-			f32_mul_add(y as f32, frequency_scaled, time_based_shift).sin(),
+		let shift = f32_mul_add::<SIMD_LEVEL_SCALAR>(
+			f32_mul_add::<SIMD_LEVEL_SCALAR>(y as f32, frequency_scaled, time_based_shift).sin(),
 			amplitude_corrected,
 			constant_shift[0],
 		)
@@ -198,8 +377,8 @@ fn make_turb_distortion(
 			*temp_dst = dst.pixels[(x + y * size[0]) as usize];
 		}
 
-		let shift = f32_mul_add(
-			f32_mul_add(x as f32, frequency_scaled, time_based_shift).sin(),
+		let shift = f32_mul_add::<SIMD_LEVEL_SCALAR>(
+			f32_mul_add::<SIMD_LEVEL_SCALAR>(x as f32, frequency_scaled, time_based_shift).sin(),
 			amplitude_corrected,
 			constant_shift[1],
 		)
@@ -1,5 +1,5 @@
 use super::frame_number::*;
-use crate::common::{bsp_map_compact, clipping::*, clipping_polygon::*, math_types::*, matrix::*};
+use crate::common::{bsp_map_compact, clipping::*, clipping_polygon::*, math_types::*, matrix::*, plane::*};
 use std::sync::Arc;
 
 pub struct MapVisibilityCalculator
@@ -54,11 +54,28 @@ impl MapVisibilityCalculator
 		self.current_frame.next();
 		let root_node = bsp_map_compact::get_root_node_index(&self.map);
 		let current_leaf = self.find_current_leaf(root_node, &camera_matrices.planes_matrix);
-		self.mark_reachable_leafs_iterative(current_leaf, camera_matrices, &frame_bounds);
+		self.mark_reachable_leafs_iterative(current_leaf, camera_matrices, &frame_bounds, None);
 
 		self.is_inside_leaf_volume = self.is_inside_leaf_volume(camera_matrices, current_leaf);
 	}
 
+	// Update visibility starting from given leaf, not from leaf where camera is located.
+	// Portals fully behind given clip plane are ignored.
+	// This is used for views inside mirrors, where camera is located behind mirror plane.
+	pub fn update_visibility_from_leaf(
+		&mut self,
+		camera_matrices: &CameraMatrices,
+		frame_bounds: &ClippingPolygon,
+		start_leaf: u32,
+		clip_plane: &Plane,
+	)
+	{
+		self.current_frame.next();
+		self.mark_reachable_leafs_iterative(start_leaf, camera_matrices, &frame_bounds, Some(clip_plane));
+
+		self.is_inside_leaf_volume = false;
+	}
+
 	pub fn get_current_frame_leaf_bounds(&self, leaf_index: u32) -> Option<ClippingPolygon>
 	{
 		let leaf_data = &self.leafs_data[leaf_index as usize];
@@ -104,6 +121,7 @@ impl MapVisibilityCalculator
 		start_leaf: u32,
 		camera_matrices: &CameraMatrices,
 		start_bounds: &ClippingPolygon,
+		clip_plane: Option<&Plane>,
 	)
 	{
 		let cur_wave = &mut self.leafs_search_waves.0;
@@ -151,6 +169,14 @@ impl MapVisibilityCalculator
 						next_leaf = portal_value.leafs[0];
 					}
 
+					if let Some(clip_plane) = clip_plane
+					{
+						if is_portal_behind_plane(portal_value, &self.map, clip_plane)
+						{
+							continue;
+						}
+					}
+
 					// Same portal may be visited multiple times.
 					// So, cache calculation of portal bounds.
 					let portal_data = &mut self.portals_data[portal as usize];
@@ -226,6 +252,18 @@ impl MapVisibilityCalculator
 	}
 }
 
+fn is_portal_behind_plane(portal: &bsp_map_compact::Portal, map: &bsp_map_compact::BSPMap, plane: &Plane) -> bool
+{
+	for v in &map.vertices[(portal.first_vertex as usize) .. ((portal.first_vertex + portal.num_vertices) as usize)]
+	{
+		if plane.vec.dot(*v) > plane.dist
+		{
+			return false;
+		}
+	}
+	true
+}
+
 fn project_portal(
 	portal: &bsp_map_compact::Portal,
 	map: &bsp_map_compact::BSPMap,
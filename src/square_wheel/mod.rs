@@ -1,16 +1,21 @@
 #![cfg_attr(feature = "rasterizer_unchecked_div", feature(core_intrinsics))]
 
 pub mod abstract_color;
+pub mod anti_aliasing;
+pub mod color_grading;
 pub mod commands_processor;
 pub mod commands_queue;
 pub mod config;
 pub mod console;
+pub mod cpu_features;
 pub mod debug_stats_printer;
 pub mod depth_renderer;
 pub mod draw_ordering;
 pub mod dynamic_models_index;
 pub mod equations;
+pub mod exposure_metering;
 pub mod fast_math;
+pub mod fog;
 pub mod frame_info;
 pub mod frame_number;
 pub mod game_interface;
@@ -20,9 +25,12 @@ pub mod inline_models_index;
 pub mod light;
 pub mod map_materials_processor;
 pub mod map_visibility_calculator;
+pub mod palette;
 pub mod performance_counter;
+pub mod picking;
 pub mod postprocessor;
 pub mod postprocessor_config;
+pub mod procedural_sky;
 pub mod rasterizer;
 pub mod rect_splitting;
 pub mod renderer;
@@ -30,6 +38,7 @@ pub mod renderer_config;
 pub mod resources_manager;
 pub mod resources_manager_config;
 pub mod shadow_map;
+pub mod sprites_rendering;
 pub mod surfaces;
 pub mod text_printer;
 pub mod textures;
@@ -39,3 +48,5 @@ pub mod triangle_model_iqm;
 pub mod triangle_model_loading;
 pub mod triangle_model_md3;
 pub mod triangle_models_rendering;
+pub mod underwater_effects;
+pub mod upscaler;
@@ -0,0 +1,154 @@
+use crate::common::{color::*, image, system_window};
+use rayon::prelude::*;
+
+// Palette with up to 256 colors and lookup table for fast search of nearest palette color.
+pub struct Palette
+{
+	colors: Vec<Color32>,
+	// Index of nearest palette color for each cell of quantized RGB space.
+	lookup_table: Vec<u8>,
+}
+
+impl Palette
+{
+	// Load palette from Quake "palette.lmp" (256 RGB triplets) or from image (first 256 pixels are used).
+	pub fn load(file_path: &std::path::Path) -> Option<Self>
+	{
+		let is_lmp = file_path
+			.extension()
+			.map(|e| e.to_string_lossy().to_lowercase() == "lmp")
+			.unwrap_or(false);
+
+		let colors = if is_lmp
+		{
+			let data = std::fs::read(file_path).ok()?;
+			if data.len() < PALETTE_MAX_COLORS * 3
+			{
+				return None;
+			}
+			data.chunks(3)
+				.take(PALETTE_MAX_COLORS)
+				.map(|c| Color32::from_rgb(c[0], c[1], c[2]))
+				.collect::<Vec<_>>()
+		}
+		else
+		{
+			let image = image::load(file_path)?;
+			image
+				.pixels
+				.iter()
+				.take(PALETTE_MAX_COLORS)
+				.map(|c| {
+					let rgb = c.get_rgb();
+					Color32::from_rgb(rgb[0], rgb[1], rgb[2])
+				})
+				.collect::<Vec<_>>()
+		};
+
+		if colors.is_empty()
+		{
+			return None;
+		}
+
+		Some(Self::from_colors(colors))
+	}
+
+	pub fn from_colors(colors: Vec<Color32>) -> Self
+	{
+		let colors_rgb = colors.iter().map(|c| c.get_rgb()).collect::<Vec<_>>();
+
+		let size = 1 << LOOKUP_TABLE_BITS;
+		let mut lookup_table = vec![0; size * size * size];
+		lookup_table.par_iter_mut().enumerate().for_each(|(index, dst)| {
+			// Use center of cell.
+			let cell_color = [
+				(((index & (size - 1)) << LOOKUP_TABLE_SHIFT) + (1 << (LOOKUP_TABLE_SHIFT - 1))) as i32,
+				((((index >> LOOKUP_TABLE_BITS) & (size - 1)) << LOOKUP_TABLE_SHIFT) + (1 << (LOOKUP_TABLE_SHIFT - 1)))
+					as i32,
+				(((index >> (LOOKUP_TABLE_BITS * 2)) << LOOKUP_TABLE_SHIFT) + (1 << (LOOKUP_TABLE_SHIFT - 1))) as i32,
+			];
+
+			let mut best_index = 0;
+			let mut best_distance = i32::MAX;
+			for (color_index, rgb) in colors_rgb.iter().enumerate()
+			{
+				let dr = cell_color[0] - rgb[0] as i32;
+				let dg = cell_color[1] - rgb[1] as i32;
+				let db = cell_color[2] - rgb[2] as i32;
+				// Use weights similar to luminance weights.
+				let distance = dr * dr * 3 + dg * dg * 4 + db * db * 2;
+				if distance < best_distance
+				{
+					best_distance = distance;
+					best_index = color_index;
+				}
+			}
+			*dst = best_index as u8;
+		});
+
+		Self { colors, lookup_table }
+	}
+
+	pub fn get_nearest_color(&self, rgb: &[i32; 3]) -> Color32
+	{
+		let r = (rgb[0].max(0).min(255) as usize) >> LOOKUP_TABLE_SHIFT;
+		let g = (rgb[1].max(0).min(255) as usize) >> LOOKUP_TABLE_SHIFT;
+		let b = (rgb[2].max(0).min(255) as usize) >> LOOKUP_TABLE_SHIFT;
+		let index = self.lookup_table[r | (g << LOOKUP_TABLE_BITS) | (b << (LOOKUP_TABLE_BITS * 2))];
+		self.colors[index as usize]
+	}
+}
+
+// Replace each pixel with nearest palette color. Ordered dithering may be used to reduce banding.
+pub fn perform_palette_quantization(
+	pixels: &mut [Color32],
+	surface_info: &system_window::SurfaceInfo,
+	palette: &Palette,
+	dithering: bool,
+)
+{
+	let func = |(y, line): (usize, &mut [Color32])| {
+		let dither_line = &DITHER_MATRIX[y & 3];
+		for (x, pixel) in line[.. surface_info.width].iter_mut().enumerate()
+		{
+			let rgb = pixel.get_rgb();
+			let offset = if dithering
+			{
+				// Offset is in range [-15; 15].
+				dither_line[x & 3] * 2 - 15
+			}
+			else
+			{
+				0
+			};
+			*pixel =
+				palette.get_nearest_color(&[rgb[0] as i32 + offset, rgb[1] as i32 + offset, rgb[2] as i32 + offset]);
+		}
+	};
+
+	if rayon::current_num_threads() == 1
+	{
+		pixels
+			.chunks_mut(surface_info.pitch)
+			.take(surface_info.height)
+			.enumerate()
+			.for_each(func);
+	}
+	else
+	{
+		pixels
+			.par_chunks_mut(surface_info.pitch)
+			.take(surface_info.height)
+			.enumerate()
+			.for_each(func);
+	}
+}
+
+const PALETTE_MAX_COLORS: usize = 256;
+
+// Use 5 bits per color component for lookup table.
+const LOOKUP_TABLE_BITS: usize = 5;
+const LOOKUP_TABLE_SHIFT: usize = 8 - LOOKUP_TABLE_BITS;
+
+// 4x4 Bayer matrix.
+const DITHER_MATRIX: [[i32; 4]; 4] = [[0, 8, 2, 10], [12, 4, 14, 6], [3, 11, 1, 9], [15, 7, 13, 5]];
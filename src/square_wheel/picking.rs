@@ -0,0 +1,270 @@
+use super::{frame_info::*, inline_models_index::*, triangle_model::*, triangle_models_rendering::*};
+use crate::common::{bsp_map_compact, clipping::*, map_trace::*, math_types::*, matrix::*};
+
+// Result of screen picking - nearest object under given pixel.
+#[derive(Copy, Clone)]
+pub struct PickResult
+{
+	// Hit position in world space.
+	pub pos: Vec3f,
+	pub distance: f32,
+	pub target: PickTarget,
+}
+
+#[derive(Copy, Clone, PartialEq)]
+pub enum PickTarget
+{
+	// Polygon of map itself.
+	Polygon
+	{
+		polygon_index: u32,
+		// Index in map textures list.
+		material_index: u32,
+	},
+	// Polygon of submodel (door, platform, etc.).
+	SubmodelPolygon
+	{
+		submodel_index: u32,
+		polygon_index: u32,
+		material_index: u32,
+	},
+	// Index of model entity in frame info. Models are checked against triangles of current animation frame.
+	ModelEntity(u32),
+}
+
+// Maximum distance of picking ray.
+pub const MAX_PICK_DISTANCE: f32 = 65536.0;
+
+// Find nearest object under given screen point.
+// Submodels must be already positioned for current frame.
+// Only polygons for which given filter returns true are checked.
+pub fn pick<PolygonFilter: Fn(&bsp_map_compact::Polygon) -> bool>(
+	map: &bsp_map_compact::BSPMap,
+	inline_models_index: &InlineModelsIndex,
+	camera_matrices: &CameraMatrices,
+	x: f32,
+	y: f32,
+	model_entities: &[ModelEntity],
+	polygon_filter: &PolygonFilter,
+) -> Option<PickResult>
+{
+	let from = camera_matrices.position;
+	let dir = get_screen_point_world_position(camera_matrices, x, y, 1.0)? - from;
+	let to = from + dir * (MAX_PICK_DISTANCE / dir.magnitude().max(0.000001));
+
+	let mut result: Option<PickResult> = None;
+	let mut vertices_buffer = Vec::new();
+	let mut add_candidate = |pos: Vec3f, target: PickTarget| {
+		let distance = (pos - from).magnitude();
+		if result.map(|r| distance < r.distance).unwrap_or(true)
+		{
+			result = Some(PickResult { pos, distance, target });
+		}
+	};
+
+	if let Some(trace_result) = trace_segment(map, &from, &to, polygon_filter)
+	{
+		add_candidate(
+			trace_result.pos,
+			PickTarget::Polygon {
+				polygon_index: trace_result.polygon_index,
+				material_index: map.polygons[trace_result.polygon_index as usize].texture,
+			},
+		);
+	}
+
+	// Trace submodels in their own space, using same matrices as for rendering.
+	for submodel_index in 0 .. map.submodels.len() as u32
+	{
+		let model_matrix = if let Some(m) = inline_models_index.get_model_matrix(submodel_index)
+		{
+			m
+		}
+		else
+		{
+			continue;
+		};
+		let model_matrix_inverse = if let Some(m) = model_matrix.invert()
+		{
+			m
+		}
+		else
+		{
+			continue;
+		};
+
+		let from_transformed = (model_matrix_inverse * from.extend(1.0)).truncate();
+		let to_transformed = (model_matrix_inverse * to.extend(1.0)).truncate();
+		if let Some(trace_result) =
+			trace_segment_submodel(map, submodel_index, &from_transformed, &to_transformed, polygon_filter)
+		{
+			add_candidate(
+				(model_matrix * trace_result.pos.extend(1.0)).truncate(),
+				PickTarget::SubmodelPolygon {
+					submodel_index,
+					polygon_index: trace_result.polygon_index,
+					material_index: map.polygons[trace_result.polygon_index as usize].texture,
+				},
+			);
+		}
+	}
+
+	for (index, model) in model_entities.iter().enumerate()
+	{
+		if model.is_view_model
+		{
+			continue;
+		}
+
+		let model_matrix = get_object_matrix(model.position, model.rotation);
+		let model_matrix_inverse = if let Some(m) = model_matrix.invert()
+		{
+			m
+		}
+		else
+		{
+			continue;
+		};
+
+		// Reject model quickly using bbox.
+		let from_transformed = (model_matrix_inverse * from.extend(1.0)).truncate();
+		let to_transformed = (model_matrix_inverse * to.extend(1.0)).truncate();
+		let bbox = get_current_triangle_model_bbox(&model.model, &model.animation);
+		if get_segment_bbox_entry_fraction(&from_transformed, &to_transformed, &bbox).is_none()
+		{
+			continue;
+		}
+
+		if let Some(fraction) = get_segment_triangle_model_intersection_fraction(
+			&model.model,
+			&model.animation,
+			&from_transformed,
+			&to_transformed,
+			&mut vertices_buffer,
+		)
+		{
+			add_candidate(from + (to - from) * fraction, PickTarget::ModelEntity(index as u32));
+		}
+	}
+
+	result
+}
+
+// Find nearest intersection of segment (in model space) with triangles of current animation frame of given model.
+fn get_segment_triangle_model_intersection_fraction(
+	model: &TriangleModel,
+	animation: &AnimationPoint,
+	from: &Vec3f,
+	to: &Vec3f,
+	vertices_buffer: &mut Vec<ModelVertex3d>,
+) -> Option<f32>
+{
+	// Model vertices are animated in the same way as for rendering.
+	// Resulting vertex position is (x, y, w), so, use matrix that writes z into w.
+	let model_view_matrix = Mat4f::from_cols(
+		Vec4f::new(1.0, 0.0, 0.0, 0.0),
+		Vec4f::new(0.0, 1.0, 0.0, 0.0),
+		Vec4f::new(0.0, 0.0, 0.0, 1.0),
+		Vec4f::zero(),
+	);
+	let light = bsp_map_compact::LightGridElement {
+		light_cube: [[0.0; 3]; 6],
+		light_direction_vector_scaled: Vec3f::zero(),
+		directional_light_color: [0.0; 3],
+	};
+
+	let mut nearest_fraction: Option<f32> = None;
+	for mesh in &model.meshes
+	{
+		let num_vertices = match &mesh.vertex_data
+		{
+			VertexData::NonAnimated(v) => v.len(),
+			VertexData::VertexAnimated { constant, .. } => constant.len(),
+			VertexData::SkeletonAnimated(v) => v.len(),
+		};
+		vertices_buffer.resize(
+			num_vertices,
+			ModelVertex3d {
+				pos: Vec3f::zero(),
+				tc: Vec2f::zero(),
+				light: [0.0; 3],
+				tangent_space_light: ModelVertexTangentSpaceLight::zero(),
+			},
+		);
+		animate_and_transform_triangle_mesh_vertices(
+			model,
+			mesh,
+			animation,
+			&light,
+			&[],
+			false,
+			false,
+			&Vec3f::zero(),
+			&Mat4f::identity(),
+			&model_view_matrix,
+			&Vec2f::new(1.0, 1.0),
+			&Vec2f::zero(),
+			vertices_buffer,
+		);
+
+		for triangle in &mesh.triangles
+		{
+			let [v0, v1, v2] = triangle.map(|index| vertices_buffer[index as usize].pos);
+			if let Some(fraction) = get_segment_triangle_intersection_fraction(from, to, &v0, &v1, &v2)
+			{
+				if nearest_fraction.map(|f| fraction < f).unwrap_or(true)
+				{
+					nearest_fraction = Some(fraction);
+				}
+			}
+		}
+	}
+
+	nearest_fraction
+}
+
+// Find intersection of segment with triangle (both sides), using Moller-Trumbore algorithm.
+fn get_segment_triangle_intersection_fraction(
+	from: &Vec3f,
+	to: &Vec3f,
+	v0: &Vec3f,
+	v1: &Vec3f,
+	v2: &Vec3f,
+) -> Option<f32>
+{
+	let dir = to - from;
+	let edge1 = v1 - v0;
+	let edge2 = v2 - v0;
+	let p = dir.cross(edge2);
+	let det = edge1.dot(p);
+	if det.abs() < MIN_POSITIVE_VALUE
+	{
+		// Segment is parallel to triangle plane.
+		return None;
+	}
+	let inv_det = 1.0 / det;
+
+	let vec_to_from = from - v0;
+	let u = vec_to_from.dot(p) * inv_det;
+	if u < 0.0 || u > 1.0
+	{
+		return None;
+	}
+
+	let q = vec_to_from.cross(edge1);
+	let v = dir.dot(q) * inv_det;
+	if v < 0.0 || u + v > 1.0
+	{
+		return None;
+	}
+
+	let fraction = edge2.dot(q) * inv_det;
+	if fraction >= 0.0 && fraction <= 1.0
+	{
+		Some(fraction)
+	}
+	else
+	{
+		None
+	}
+}
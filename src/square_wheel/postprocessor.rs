@@ -1,24 +1,48 @@
-use super::{config, debug_stats_printer::*, fast_math::*, performance_counter::*, postprocessor_config::*};
-use crate::common::{color::*, shared_mut_slice::*, system_window};
+use super::{
+	anti_aliasing::*, color_grading::*, config, console, cpu_features::*, debug_stats_printer::*, exposure_metering::*,
+	fast_math::*, frame_info::*, palette::*, performance_counter::*, postprocessor_config::*,
+	resources_manager_config::*,
+};
+use crate::common::{color::*, image_hdr, math_types::*, matrix::*, shared_mut_slice::*, system_window};
+#[cfg(target_arch = "x86_64")]
+use core::arch::x86_64::*;
 use rayon::prelude::*;
 
 pub struct Postprocessor
 {
 	app_config: config::ConfigSharedPtr,
 	config: PostprocessorConfig,
+	console: console::ConsoleSharedPtr,
 
 	hdr_buffer_size: [usize; 2],
 	hdr_buffer: Vec<Color64>,
 	performance_counters: PostprocessorPerformanceCounters,
 	bloom_buffer_size: [usize; 2],
 	bloom_buffers: [Vec<Color64>; 2],
+	// Destination buffer for motion blur, swapped with HDR buffer after blur.
+	motion_blur_buffer: Vec<Color64>,
+	// Camera matrices of previous frame and size of HDR buffer for them.
+	prev_camera_matrices: Option<(CameraMatrices, [usize; 2])>,
 	current_exposure: f32,
+	map_color_grading: MapColorGrading,
+	// Table for color grading in LDR postprocessing and brightness, contrast and gamma used for it.
+	color_grading_table: ColorGradingTable,
+	color_grading_table_params: Option<[f32; 3]>,
+	// Temp buffers for FXAA.
+	fxaa_src_buffer: Vec<Color32>,
+	fxaa_luma_buffer: Vec<u8>,
+	// Palette for palette output mode and name of its file. Loaded on demand.
+	palette: Option<(String, Option<Palette>)>,
 }
 
 struct PostprocessorPerformanceCounters
 {
 	tonemapping_duration: PerformanceCounter,
 	bloom_duration: PerformanceCounter,
+	motion_blur_duration: PerformanceCounter,
+	ldr_postprocessing_duration: PerformanceCounter,
+	anti_aliasing_duration: PerformanceCounter,
+	palette_quantization_duration: PerformanceCounter,
 }
 
 impl PostprocessorPerformanceCounters
@@ -29,6 +53,10 @@ impl PostprocessorPerformanceCounters
 		Self {
 			tonemapping_duration: PerformanceCounter::new(window_size),
 			bloom_duration: PerformanceCounter::new(window_size),
+			motion_blur_duration: PerformanceCounter::new(window_size),
+			ldr_postprocessing_duration: PerformanceCounter::new(window_size),
+			anti_aliasing_duration: PerformanceCounter::new(window_size),
+			palette_quantization_duration: PerformanceCounter::new(window_size),
 		}
 	}
 }
@@ -36,7 +64,7 @@ type Clock = std::time::Instant;
 
 impl Postprocessor
 {
-	pub fn new(app_config: config::ConfigSharedPtr) -> Self
+	pub fn new(app_config: config::ConfigSharedPtr, console: console::ConsoleSharedPtr) -> Self
 	{
 		let config_parsed = PostprocessorConfig::from_app_config(&app_config);
 		config_parsed.update_app_config(&app_config); // Update JSON with struct fields.
@@ -44,15 +72,36 @@ impl Postprocessor
 		Self {
 			app_config,
 			config: config_parsed,
+			console,
 			hdr_buffer_size: [0, 0],
 			hdr_buffer: Vec::new(),
 			performance_counters: PostprocessorPerformanceCounters::new(),
 			bloom_buffer_size: [0, 0],
 			bloom_buffers: [Vec::new(), Vec::new()],
+			motion_blur_buffer: Vec::new(),
+			prev_camera_matrices: None,
 			current_exposure: 1.0,
+			map_color_grading: MapColorGrading::default(),
+			color_grading_table: ColorGradingTable::new(),
+			color_grading_table_params: None,
+			fxaa_src_buffer: Vec::new(),
+			fxaa_luma_buffer: Vec::new(),
+			palette: None,
 		}
 	}
 
+	pub fn set_map_color_grading(&mut self, map_color_grading: MapColorGrading)
+	{
+		self.map_color_grading = map_color_grading;
+		self.color_grading_table_params = None;
+	}
+
+	// Forget camera of previous frame in order to avoid motion blur after map change.
+	pub fn reset_prev_camera(&mut self)
+	{
+		self.prev_camera_matrices = None;
+	}
+
 	pub fn get_hdr_buffer(&mut self, size: [usize; 2]) -> &mut [Color64]
 	{
 		let required_size = size[0] * size[1];
@@ -65,14 +114,49 @@ impl Postprocessor
 		&mut self.hdr_buffer[.. required_size]
 	}
 
+	// Get copy of HDR buffer of last frame (if HDR rendering is enabled). 1.0 is maximum of LDR image.
+	pub fn get_hdr_image(&self) -> Option<image_hdr::ImageHDR>
+	{
+		if !self.config.hdr_rendering || self.hdr_buffer_size[0] == 0 || self.hdr_buffer_size[1] == 0
+		{
+			return None;
+		}
+
+		let inv_255 = 1.0 / 255.0;
+		let num_pixels = self.hdr_buffer_size[0] * self.hdr_buffer_size[1];
+		Some(image_hdr::ImageHDR {
+			size: [self.hdr_buffer_size[0] as u32, self.hdr_buffer_size[1] as u32],
+			pixels: self.hdr_buffer[.. num_pixels]
+				.iter()
+				.map(|c| {
+					let components = ColorVec::from_color64(*c).into_color_f32x3();
+					[
+						components[0] * inv_255,
+						components[1] * inv_255,
+						components[2] * inv_255,
+					]
+				})
+				.collect(),
+		})
+	}
+
 	pub fn perform_postprocessing(
 		&mut self,
 		pixels: &mut [Color32],
 		surface_info: &system_window::SurfaceInfo,
 		frame_duration_s: f32,
+		exposure_override: Option<ExposureOverride>,
+		camera_matrices: &CameraMatrices,
+		// Inverse depth of pixels and its size.
+		depth_buffer: Option<(&[f32], [u32; 2])>,
 		debug_stats_printer: &mut DebugStatsPrinter,
 	)
 	{
+		if let Some(ExposureOverride::Fixed(exposure)) = exposure_override
+		{
+			self.current_exposure = exposure.max(self.config.min_exposure).min(self.config.max_exposure);
+		}
+
 		let surface_size = [surface_info.width, surface_info.height];
 		if self.hdr_buffer_size != surface_size
 		{
@@ -82,6 +166,32 @@ impl Postprocessor
 			);
 		}
 
+		let prev_camera_matrices = self
+			.prev_camera_matrices
+			.replace((*camera_matrices, self.hdr_buffer_size));
+		if self.config.motion_blur
+		{
+			if let Some((prev_camera_matrices, prev_hdr_buffer_size)) = prev_camera_matrices
+			{
+				// Matrices are valid only for same viewport size.
+				// Do not blur if camera was teleported.
+				let camera_shift_square = (camera_matrices.position - prev_camera_matrices.position).magnitude2();
+				if prev_hdr_buffer_size == self.hdr_buffer_size &&
+					camera_shift_square <= MAX_MOTION_BLUR_CAMERA_SHIFT * MAX_MOTION_BLUR_CAMERA_SHIFT
+				{
+					let motion_blur_start_time = Clock::now();
+
+					self.perform_motion_blur(&prev_camera_matrices, camera_matrices, depth_buffer);
+
+					let motion_blur_end_time = Clock::now();
+					let motion_blur_duration_s = (motion_blur_end_time - motion_blur_start_time).as_secs_f32();
+					self.performance_counters
+						.motion_blur_duration
+						.add_value(motion_blur_duration_s);
+				}
+			}
+		}
+
 		let use_bloom = self.config.bloom_sigma > 0.0;
 
 		let mut bloom_buffer_scale = 1;
@@ -101,7 +211,13 @@ impl Postprocessor
 
 		let tonemapping_start_time = Clock::now();
 
-		let tonemapping_function = TonemappingFunction::new(self.current_exposure);
+		let tonemapping_function = TonemappingFunction::new(
+			self.map_color_grading
+				.tonemapping_operator
+				.unwrap_or_else(|| TonemappingOperator::from_config_value(self.config.tonemapping_operator)),
+			self.current_exposure,
+			self.config.tonemapping_white_point,
+		);
 
 		let num_threads = rayon::current_num_threads();
 
@@ -185,10 +301,38 @@ impl Postprocessor
 			.add_value(tonemapping_duration_s);
 
 		// Calculate exposure for next frame based on brightness of current frame.
-		self.update_exposure(&average_color, frame_duration_s);
+		if exposure_override.is_none()
+		{
+			let metering_mode = ExposureMeteringMode::from_config_value(self.config.exposure_metering_mode);
+			let brightness = if metering_mode == ExposureMeteringMode::Average
+			{
+				// Reuse average color, calculated during tonemapping or bloom.
+				get_color_brightness(&average_color)
+			}
+			else
+			{
+				calculate_metered_brightness(
+					&self.hdr_buffer,
+					self.hdr_buffer_size,
+					metering_mode,
+					&HistogramPercentiles {
+						low: self.config.exposure_histogram_low_percentile,
+						high: self.config.exposure_histogram_high_percentile,
+					},
+				)
+			};
+			self.update_exposure(brightness, frame_duration_s);
+		}
 
 		if debug_stats_printer.show_debug_stats()
 		{
+			if self.config.motion_blur
+			{
+				debug_stats_printer.add_line(format!(
+					"motion blur time: {:04.2}ms",
+					self.performance_counters.motion_blur_duration.get_average_value() * 1000.0,
+				));
+			}
 			debug_stats_printer.add_line(format!(
 				"bloom time: {:04.2}ms (scale {})",
 				self.performance_counters.bloom_duration.get_average_value() * 1000.0,
@@ -202,9 +346,196 @@ impl Postprocessor
 		}
 	}
 
-	fn update_exposure(&mut self, average_color: &ColorVec, frame_duration_s: f32)
+	// Perform final color correction, color grading and apply screen tint. Used both in LDR and HDR modes.
+	pub fn perform_ldr_postprocessing(
+		&mut self,
+		pixels: &mut [Color32],
+		surface_info: &system_window::SurfaceInfo,
+		screen_tint: Option<&ScreenTint>,
+		debug_stats_printer: &mut DebugStatsPrinter,
+	)
+	{
+		let start_time = Clock::now();
+
+		let color_grading_table = match (&self.map_color_grading.lut, self.config.color_grading)
+		{
+			(Some(lut), true) =>
+			{
+				// Rebuild table only if color correction settings were changed since last frame.
+				let config = &self.config;
+				let table_params = [config.brightness, config.contrast, config.gamma];
+				if self.color_grading_table_params != Some(table_params)
+				{
+					self.color_grading_table
+						.update(lut, |c| correct_color_component(c, config));
+					self.color_grading_table_params = Some(table_params);
+				}
+				Some(&self.color_grading_table)
+			},
+			_ => None,
+		};
+
+		let params = LdrPostprocessingParams::new(&self.config, surface_info, screen_tint, color_grading_table);
+		if !params.is_identity()
+		{
+			let num_threads = rayon::current_num_threads();
+			if num_threads == 1 || !self.config.use_multithreadig
+			{
+				perform_ldr_postprocessing_for_rows(pixels, surface_info, 0, surface_info.height, &params);
+			}
+			else
+			{
+				// It is safe to share destination buffer since each thead writes into its own regon.
+				let pixels_shared = SharedMutSlice::new(pixels);
+
+				let mut ranges = [[0, 0]; MAX_THREADS];
+				for i in 0 .. num_threads
+				{
+					ranges[i] = [
+						surface_info.height * i / num_threads,
+						surface_info.height * (i + 1) / num_threads,
+					];
+				}
+				ranges[.. num_threads].par_iter().for_each(|range| {
+					let pixels = unsafe { pixels_shared.get() };
+					perform_ldr_postprocessing_for_rows(pixels, surface_info, range[0], range[1], &params);
+				});
+			}
+		}
+
+		let end_time = Clock::now();
+		self.performance_counters
+			.ldr_postprocessing_duration
+			.add_value((end_time - start_time).as_secs_f32());
+
+		if debug_stats_printer.show_debug_stats()
+		{
+			debug_stats_printer.add_line(format!(
+				"LDR postprocessing time: {:04.2}ms",
+				self.performance_counters
+					.ldr_postprocessing_duration
+					.get_average_value() *
+					1000.0,
+			));
+		}
+	}
+
+	// Perform post-process anti-aliasing of final image (if it is enabled).
+	pub fn perform_anti_aliasing(
+		&mut self,
+		pixels: &mut [Color32],
+		surface_info: &system_window::SurfaceInfo,
+		debug_stats_printer: &mut DebugStatsPrinter,
+	)
+	{
+		let mode = self.get_anti_aliasing_mode();
+		if mode == AntiAliasingMode::Fxaa
+		{
+			let start_time = Clock::now();
+
+			perform_fxaa(
+				pixels,
+				surface_info,
+				&mut self.fxaa_src_buffer,
+				&mut self.fxaa_luma_buffer,
+			);
+
+			let end_time = Clock::now();
+			self.performance_counters
+				.anti_aliasing_duration
+				.add_value((end_time - start_time).as_secs_f32());
+		}
+
+		if mode != AntiAliasingMode::None && debug_stats_printer.show_debug_stats()
+		{
+			debug_stats_printer.add_line(format!(
+				"anti-aliasing time: {:04.2}ms",
+				self.performance_counters.anti_aliasing_duration.get_average_value() * 1000.0,
+			));
+		}
+	}
+
+	// Quantize final image to palette colors (if palette output is enabled).
+	pub fn perform_palette_quantization(
+		&mut self,
+		pixels: &mut [Color32],
+		surface_info: &system_window::SurfaceInfo,
+		debug_stats_printer: &mut DebugStatsPrinter,
+	)
 	{
-		let brightness = get_color_brightness(average_color).max(1.0 / 1024.0).min(65536.0) / 255.0;
+		if !self.config.palette_output
+		{
+			return;
+		}
+
+		if self
+			.palette
+			.as_ref()
+			.map(|(file_name, _)| *file_name != self.config.palette_file)
+			.unwrap_or(true)
+		{
+			// Palette file path is relative to textures path.
+			let mut palette_path =
+				std::path::PathBuf::from(ResourcesManagerConfig::from_app_config(&self.app_config).textures_path);
+			palette_path.push(&self.config.palette_file);
+
+			let palette = Palette::load(&palette_path);
+			if palette.is_none()
+			{
+				self.console
+					.lock()
+					.unwrap()
+					.add_text(format!("Failed to load palette {:?}", palette_path));
+			}
+			self.palette = Some((self.config.palette_file.clone(), palette));
+		}
+
+		if let Some((_, Some(palette))) = &self.palette
+		{
+			let start_time = Clock::now();
+
+			perform_palette_quantization(pixels, surface_info, palette, self.config.palette_dithering);
+
+			let end_time = Clock::now();
+			self.performance_counters
+				.palette_quantization_duration
+				.add_value((end_time - start_time).as_secs_f32());
+
+			if debug_stats_printer.show_debug_stats()
+			{
+				debug_stats_printer.add_line(format!(
+					"palette quantization time: {:04.2}ms",
+					self.performance_counters
+						.palette_quantization_duration
+						.get_average_value() *
+						1000.0,
+				));
+			}
+		}
+	}
+
+	// Downsample image, rendered with doubled resolution in supersampling mode.
+	pub fn perform_supersampling_downscale(
+		&mut self,
+		src: &[Color32],
+		src_surface_info: &system_window::SurfaceInfo,
+		dst: &mut [Color32],
+		dst_surface_info: &system_window::SurfaceInfo,
+	)
+	{
+		let start_time = Clock::now();
+
+		perform_supersampling_downscale(src, src_surface_info, dst, dst_surface_info);
+
+		let end_time = Clock::now();
+		self.performance_counters
+			.anti_aliasing_duration
+			.add_value((end_time - start_time).as_secs_f32());
+	}
+
+	fn update_exposure(&mut self, scene_brightness: f32, frame_duration_s: f32)
+	{
+		let brightness = scene_brightness.max(1.0 / 1024.0).min(65536.0) / 255.0;
 
 		// Use power factor in order to add some dependency of result image brightness from original brightness.
 		let target_exposure = (brightness / self.config.zero_level_brightness).powf(self.config.brightness_scale_power) *
@@ -214,7 +545,16 @@ impl Postprocessor
 			.max(self.config.min_exposure)
 			.min(self.config.max_exposure);
 
-		let mix_factor = (-self.config.exposure_update_speed * frame_duration_s).exp();
+		// Exposure decreases if scene becomes brighter.
+		let update_speed = if taget_exposure_clamped < self.current_exposure
+		{
+			self.config.exposure_update_speed_to_light
+		}
+		else
+		{
+			self.config.exposure_update_speed_to_dark
+		};
+		let mix_factor = (-update_speed * frame_duration_s).exp();
 
 		// Mix logarithms of values.
 		self.current_exposure =
@@ -230,28 +570,125 @@ impl Postprocessor
 		y_end: usize,
 		tonemapping_function: TonemappingFunction,
 	) -> ColorVec
+	{
+		#[cfg(target_arch = "x86_64")]
+		if get_simd_level() == SimdLevel::Avx2
+		{
+			// SAFETY: "get_simd_level" returns AVX2 level only if it is supported by current CPU.
+			return unsafe {
+				self.perform_tonemapping_avx2(pixels, surface_info, y_start, y_end, tonemapping_function)
+			};
+		}
+
+		call_with_simd_level!(
+			SIMD_LEVEL,
+			self.perform_tonemapping_impl::<SIMD_LEVEL>(pixels, surface_info, y_start, y_end, tonemapping_function)
+		)
+	}
+
+	// Explicit AVX2 version of tonemapping.
+	// Processes two pixels (8 color components) at once.
+	#[cfg(target_arch = "x86_64")]
+	#[target_feature(enable = "avx2,fma")]
+	unsafe fn perform_tonemapping_avx2(
+		&self,
+		pixels: &mut [Color32],
+		surface_info: &system_window::SurfaceInfo,
+		y_start: usize,
+		y_end: usize,
+		tonemapping_function: TonemappingFunction,
+	) -> ColorVec
+	{
+		let width = self.hdr_buffer_size[0];
+
+		let mut colors_sum = SimdColorVec::<SIMD_LEVEL_AVX2>::zero();
+		for y in y_start .. y_end
+		{
+			let src_line = &self.hdr_buffer[y * width .. (y + 1) * width];
+			let dst_line = &mut pixels[y * surface_info.pitch .. y * surface_info.pitch + width];
+
+			let mut line_colors_sum = _mm256_setzero_ps();
+			for x in (0 .. width & !1).step_by(2)
+			{
+				// Load two pixels and convert their 16-bit components into floats.
+				let src_ptr = src_line.as_ptr().add(x) as *const __m128i;
+				let c = _mm256_cvtepi32_ps(_mm256_cvtepu16_epi32(_mm_loadu_si128(src_ptr)));
+				line_colors_sum = _mm256_add_ps(line_colors_sum, c);
+
+				let c_mapped = tonemapping_function.do_it_avx2(c);
+
+				// Pack components into bytes. Result pixels are in lower 32 bits of each 128-bit lane.
+				let c_32bit = _mm256_cvtps_epi32(c_mapped);
+				let c_16bit = _mm256_packus_epi32(c_32bit, c_32bit);
+				let c_8bit = _mm256_packus_epi16(c_16bit, c_16bit);
+				debug_checked_store(dst_line, x, Color32::from_raw(_mm256_extract_epi32(c_8bit, 0) as u32));
+				debug_checked_store(
+					dst_line,
+					x + 1,
+					Color32::from_raw(_mm256_extract_epi32(c_8bit, 4) as u32),
+				);
+			}
+
+			let sum_halves = _mm_add_ps(
+				_mm256_castps256_ps128(line_colors_sum),
+				_mm256_extractf128_ps(line_colors_sum, 1),
+			);
+			let mut sum_components = [0.0; 4];
+			_mm_storeu_ps(sum_components.as_mut_ptr(), sum_halves);
+			let mut line_colors_sum_vec = SimdColorVec::<SIMD_LEVEL_AVX2>::from_color_f32x3(&[
+				sum_components[2],
+				sum_components[1],
+				sum_components[0],
+			]);
+
+			if width % 2 != 0
+			{
+				// Process last pixel of odd line.
+				let c = SimdColorVec::<SIMD_LEVEL_AVX2>::from_color64(debug_checked_fetch(src_line, width - 1));
+				line_colors_sum_vec = SimdColorVec::add(&line_colors_sum_vec, &c);
+				debug_checked_store(dst_line, width - 1, tonemapping_function.do_it(&c).into_color32());
+			}
+
+			// Perfom summation of line colors, than summation of sums.
+			// Do this in order to avoid precision losses due to sum of values with large magnitude.
+			colors_sum = SimdColorVec::add(&colors_sum, &line_colors_sum_vec);
+		}
+
+		colors_sum.into_scalar_level()
+	}
+
+	// Should be inlined into SIMD level entry function in order to be compiled with its target features.
+	#[inline(always)]
+	fn perform_tonemapping_impl<const SIMD_LEVEL: u32>(
+		&self,
+		pixels: &mut [Color32],
+		surface_info: &system_window::SurfaceInfo,
+		y_start: usize,
+		y_end: usize,
+		tonemapping_function: TonemappingFunction,
+	) -> ColorVec
 	{
 		// In case if bloom is disabled calculate average color during tonemapping process.
-		let mut colors_sum = ColorVec::zero();
+		let mut colors_sum = SimdColorVec::<SIMD_LEVEL>::zero();
 		for y in y_start .. y_end
 		{
-			let mut line_colors_sum = ColorVec::zero();
+			let mut line_colors_sum = SimdColorVec::<SIMD_LEVEL>::zero();
 			let src_line = &self.hdr_buffer[y * self.hdr_buffer_size[0] .. (y + 1) * self.hdr_buffer_size[0]];
 			let dst_line = &mut pixels[y * surface_info.pitch .. (y + 1) * surface_info.pitch];
 			for (dst, &src) in dst_line.iter_mut().zip(src_line.iter())
 			{
-				let c = ColorVec::from_color64(src);
-				line_colors_sum = ColorVec::add(&line_colors_sum, &c);
+				let c = SimdColorVec::<SIMD_LEVEL>::from_color64(src);
+				line_colors_sum = SimdColorVec::add(&line_colors_sum, &c);
 				let c_mapped = tonemapping_function.do_it(&c);
-				*dst = c_mapped.into();
+				*dst = c_mapped.into_color32();
 			}
 
 			// Perfom summation of line colors, than summation of sums.
 			// Do this in order to avoid precision losses due to sum of values with large magnitude.
-			colors_sum = ColorVec::add(&colors_sum, &line_colors_sum);
+			colors_sum = SimdColorVec::add(&colors_sum, &line_colors_sum);
 		}
 
-		colors_sum
+		colors_sum.into_scalar_level()
 	}
 
 	fn perform_tonemapping_with_bloom(
@@ -319,10 +756,33 @@ impl Postprocessor
 		src_y_end: usize,
 		tonemapping_function: TonemappingFunction,
 	)
+	{
+		call_with_simd_level!(
+			SIMD_LEVEL,
+			self.perform_tonemapping_with_bloom_impl_2::<BLOOM_BUFFER_SCALE, SIMD_LEVEL>(
+				pixels,
+				surface_info,
+				src_y_start,
+				src_y_end,
+				tonemapping_function,
+			)
+		)
+	}
+
+	// Should be inlined into SIMD level entry function in order to be compiled with its target features.
+	#[inline(always)]
+	fn perform_tonemapping_with_bloom_impl_2<const BLOOM_BUFFER_SCALE: usize, const SIMD_LEVEL: u32>(
+		&self,
+		pixels: &mut [Color32],
+		surface_info: &system_window::SurfaceInfo,
+		src_y_start: usize,
+		src_y_end: usize,
+		tonemapping_function: TonemappingFunction,
+	)
 	{
 		if !self.config.linear_bloom_filter
 		{
-			self.perform_tonemapping_with_bloom_nearest::<BLOOM_BUFFER_SCALE>(
+			self.perform_tonemapping_with_bloom_nearest::<BLOOM_BUFFER_SCALE, SIMD_LEVEL>(
 				pixels,
 				surface_info,
 				src_y_start,
@@ -332,7 +792,7 @@ impl Postprocessor
 		}
 		else
 		{
-			self.perform_tonemapping_with_bloom_linear::<BLOOM_BUFFER_SCALE>(
+			self.perform_tonemapping_with_bloom_linear::<BLOOM_BUFFER_SCALE, SIMD_LEVEL>(
 				pixels,
 				surface_info,
 				src_y_start,
@@ -342,7 +802,8 @@ impl Postprocessor
 		}
 	}
 
-	fn perform_tonemapping_with_bloom_nearest<const BLOOM_BUFFER_SCALE: usize>(
+	#[inline(always)]
+	fn perform_tonemapping_with_bloom_nearest<const BLOOM_BUFFER_SCALE: usize, const SIMD_LEVEL: u32>(
 		&self,
 		pixels: &mut [Color32],
 		surface_info: &system_window::SurfaceInfo,
@@ -362,7 +823,7 @@ impl Postprocessor
 			{
 				let dst_x_base = src_x * BLOOM_BUFFER_SCALE;
 				let bloom_src = debug_checked_fetch(&self.bloom_buffers[0], src_x + src_line_offset);
-				let bloom_c = ColorVec::from_color64(bloom_src);
+				let bloom_c = SimdColorVec::<SIMD_LEVEL>::from_color64(bloom_src);
 				for dy in 0 .. BLOOM_BUFFER_SCALE
 				{
 					let dst_y = dst_y_base + dy;
@@ -371,11 +832,11 @@ impl Postprocessor
 					for dx in 0 .. BLOOM_BUFFER_SCALE
 					{
 						let c = debug_checked_fetch(&self.hdr_buffer, dx + hdr_buffer_line_offset);
-						let c_vec = ColorVec::from_color64(c);
-						let sum = ColorVec::add(&bloom_c, &c_vec);
+						let c_vec = SimdColorVec::<SIMD_LEVEL>::from_color64(c);
+						let sum = SimdColorVec::add(&bloom_c, &c_vec);
 						// let sum = bloom_c;
 						let c_mapped = tonemapping_function.do_it(&sum);
-						debug_checked_store(pixels, dx + pixels_line_offset, c_mapped.into());
+						debug_checked_store(pixels, dx + pixels_line_offset, c_mapped.into_color32());
 					}
 				}
 			}
@@ -387,7 +848,7 @@ impl Postprocessor
 					&self.bloom_buffers[0],
 					(self.bloom_buffer_size[0] - 1) + src_line_offset,
 				);
-				let bloom_c = ColorVec::from_color64(bloom_src);
+				let bloom_c = SimdColorVec::<SIMD_LEVEL>::from_color64(bloom_src);
 				for dy in 0 .. BLOOM_BUFFER_SCALE
 				{
 					let dst_y = dst_y_base + dy;
@@ -396,10 +857,10 @@ impl Postprocessor
 					for dx in 0 .. columns_left
 					{
 						let c = debug_checked_fetch(&self.hdr_buffer, dx + hdr_buffer_line_offset);
-						let c_vec = ColorVec::from_color64(c);
-						let sum = ColorVec::add(&bloom_c, &c_vec);
+						let c_vec = SimdColorVec::<SIMD_LEVEL>::from_color64(c);
+						let sum = SimdColorVec::add(&bloom_c, &c_vec);
 						let c_mapped = tonemapping_function.do_it(&sum);
-						debug_checked_store(pixels, dx + pixels_line_offset, c_mapped.into());
+						debug_checked_store(pixels, dx + pixels_line_offset, c_mapped.into_color32());
 					}
 				}
 			}
@@ -413,7 +874,7 @@ impl Postprocessor
 			{
 				let dst_x_base = src_x * BLOOM_BUFFER_SCALE;
 				let bloom_src = debug_checked_fetch(&self.bloom_buffers[0], src_x + src_line_offset);
-				let bloom_c = ColorVec::from_color64(bloom_src);
+				let bloom_c = SimdColorVec::<SIMD_LEVEL>::from_color64(bloom_src);
 				for dy in 0 .. lines_left
 				{
 					let dst_y = dst_y_base + dy;
@@ -422,10 +883,10 @@ impl Postprocessor
 					for dx in 0 .. BLOOM_BUFFER_SCALE
 					{
 						let c = debug_checked_fetch(&self.hdr_buffer, dx + hdr_buffer_line_offset);
-						let c_vec = ColorVec::from_color64(c);
-						let sum = ColorVec::add(&bloom_c, &c_vec);
+						let c_vec = SimdColorVec::<SIMD_LEVEL>::from_color64(c);
+						let sum = SimdColorVec::add(&bloom_c, &c_vec);
 						let c_mapped = tonemapping_function.do_it(&sum);
-						debug_checked_store(pixels, dx + pixels_line_offset, c_mapped.into());
+						debug_checked_store(pixels, dx + pixels_line_offset, c_mapped.into_color32());
 					}
 				}
 			}
@@ -437,7 +898,7 @@ impl Postprocessor
 					&self.bloom_buffers[0],
 					(self.bloom_buffer_size[0] - 1) + src_line_offset,
 				);
-				let bloom_c = ColorVec::from_color64(bloom_src);
+				let bloom_c = SimdColorVec::<SIMD_LEVEL>::from_color64(bloom_src);
 				for dy in 0 .. lines_left
 				{
 					let dst_y = dst_y_base + dy;
@@ -446,17 +907,18 @@ impl Postprocessor
 					for dx in 0 .. columns_left
 					{
 						let c = debug_checked_fetch(&self.hdr_buffer, dx + hdr_buffer_line_offset);
-						let c_vec = ColorVec::from_color64(c);
-						let sum = ColorVec::add(&bloom_c, &c_vec);
+						let c_vec = SimdColorVec::<SIMD_LEVEL>::from_color64(c);
+						let sum = SimdColorVec::add(&bloom_c, &c_vec);
 						let c_mapped = tonemapping_function.do_it(&sum);
-						debug_checked_store(pixels, dx + pixels_line_offset, c_mapped.into());
+						debug_checked_store(pixels, dx + pixels_line_offset, c_mapped.into_color32());
 					}
 				}
 			}
 		}
 	}
 
-	fn perform_tonemapping_with_bloom_linear<const BLOOM_BUFFER_SCALE: usize>(
+	#[inline(always)]
+	fn perform_tonemapping_with_bloom_linear<const BLOOM_BUFFER_SCALE: usize, const SIMD_LEVEL: u32>(
 		&self,
 		pixels: &mut [Color32],
 		surface_info: &system_window::SurfaceInfo,
@@ -483,12 +945,14 @@ impl Postprocessor
 		if src_y_start == 0
 		{
 			let dst_y_base = 0;
-			let mut bloom_src_0_0 = ColorVec::from_color64(debug_checked_fetch(&self.bloom_buffers[0], 0));
+			let mut bloom_src_0_0 =
+				SimdColorVec::<SIMD_LEVEL>::from_color64(debug_checked_fetch(&self.bloom_buffers[0], 0));
 			for src_x in 0 .. self.bloom_buffer_size[0] - 1
 			{
 				let dst_x_base = src_x * BLOOM_BUFFER_SCALE + BLOOM_BUFFER_SCALE / 2;
 
-				let bloom_src_1_0 = ColorVec::from_color64(debug_checked_fetch(&self.bloom_buffers[0], src_x + 1));
+				let bloom_src_1_0 =
+					SimdColorVec::<SIMD_LEVEL>::from_color64(debug_checked_fetch(&self.bloom_buffers[0], src_x + 1));
 
 				for dy in 0 .. BLOOM_BUFFER_SCALE / 2
 				{
@@ -500,13 +964,17 @@ impl Postprocessor
 						let c = debug_checked_fetch(&self.hdr_buffer, dx + hdr_buffer_line_offset);
 						let x_mix_factor = mix_factors[dx];
 						let one_minus_x_mix_factor = 1.0 - x_mix_factor;
-						let sum = ColorVec::mul_scalar_add(
+						let sum = SimdColorVec::mul_scalar_add(
 							&bloom_src_0_0,
 							one_minus_x_mix_factor,
-							&ColorVec::mul_scalar_add(&bloom_src_1_0, x_mix_factor, &ColorVec::from_color64(c)),
+							&SimdColorVec::mul_scalar_add(
+								&bloom_src_1_0,
+								x_mix_factor,
+								&SimdColorVec::<SIMD_LEVEL>::from_color64(c),
+							),
 						);
 						let c_mapped = tonemapping_function.do_it(&sum);
-						debug_checked_store(pixels, dx + pixels_line_offset, c_mapped.into());
+						debug_checked_store(pixels, dx + pixels_line_offset, c_mapped.into_color32());
 					}
 				}
 
@@ -520,8 +988,8 @@ impl Postprocessor
 			let src_line_offset = src_y * self.bloom_buffer_size[0];
 
 			let mut bloom_src_0_0 =
-				ColorVec::from_color64(debug_checked_fetch(&self.bloom_buffers[0], src_line_offset));
-			let mut bloom_src_0_1 = ColorVec::from_color64(debug_checked_fetch(
+				SimdColorVec::<SIMD_LEVEL>::from_color64(debug_checked_fetch(&self.bloom_buffers[0], src_line_offset));
+			let mut bloom_src_0_1 = SimdColorVec::<SIMD_LEVEL>::from_color64(debug_checked_fetch(
 				&self.bloom_buffers[0],
 				src_line_offset + self.bloom_buffer_size[0],
 			));
@@ -531,10 +999,10 @@ impl Postprocessor
 			{
 				let y_mix_factor = mix_factors[dy];
 				let one_minus_y_mix_factor = 1.0 - y_mix_factor;
-				let bloom_src = ColorVec::mul_scalar_add(
+				let bloom_src = SimdColorVec::mul_scalar_add(
 					&bloom_src_0_0,
 					one_minus_y_mix_factor,
-					&ColorVec::scalar_mul(&bloom_src_0_1, y_mix_factor),
+					&SimdColorVec::scalar_mul(&bloom_src_0_1, y_mix_factor),
 				);
 
 				let dst_y = dst_y_base + dy;
@@ -543,9 +1011,9 @@ impl Postprocessor
 				for dx in 0 .. BLOOM_BUFFER_SCALE / 2
 				{
 					let c = debug_checked_fetch(&self.hdr_buffer, dx + hdr_buffer_line_offset);
-					let sum = ColorVec::add(&bloom_src, &ColorVec::from_color64(c));
+					let sum = SimdColorVec::add(&bloom_src, &SimdColorVec::<SIMD_LEVEL>::from_color64(c));
 					let c_mapped = tonemapping_function.do_it(&sum);
-					debug_checked_store(pixels, dx + pixels_line_offset, c_mapped.into());
+					debug_checked_store(pixels, dx + pixels_line_offset, c_mapped.into_color32());
 				}
 			}
 			// Main image.
@@ -553,9 +1021,11 @@ impl Postprocessor
 			{
 				let dst_x_base = src_x * BLOOM_BUFFER_SCALE + BLOOM_BUFFER_SCALE / 2;
 
-				let bloom_src_1_0 =
-					ColorVec::from_color64(debug_checked_fetch(&self.bloom_buffers[0], src_x + 1 + src_line_offset));
-				let bloom_src_1_1 = ColorVec::from_color64(debug_checked_fetch(
+				let bloom_src_1_0 = SimdColorVec::<SIMD_LEVEL>::from_color64(debug_checked_fetch(
+					&self.bloom_buffers[0],
+					src_x + 1 + src_line_offset,
+				));
+				let bloom_src_1_1 = SimdColorVec::<SIMD_LEVEL>::from_color64(debug_checked_fetch(
 					&self.bloom_buffers[0],
 					src_x + 1 + src_line_offset + self.bloom_buffer_size[0],
 				));
@@ -564,15 +1034,15 @@ impl Postprocessor
 				{
 					let y_mix_factor = mix_factors[dy];
 					let one_minus_y_mix_factor = 1.0 - y_mix_factor;
-					let bloom_src_left = ColorVec::mul_scalar_add(
+					let bloom_src_left = SimdColorVec::mul_scalar_add(
 						&bloom_src_0_0,
 						one_minus_y_mix_factor,
-						&ColorVec::scalar_mul(&bloom_src_0_1, y_mix_factor),
+						&SimdColorVec::scalar_mul(&bloom_src_0_1, y_mix_factor),
 					);
-					let bloom_src_right = ColorVec::mul_scalar_add(
+					let bloom_src_right = SimdColorVec::mul_scalar_add(
 						&bloom_src_1_0,
 						one_minus_y_mix_factor,
-						&ColorVec::scalar_mul(&bloom_src_1_1, y_mix_factor),
+						&SimdColorVec::scalar_mul(&bloom_src_1_1, y_mix_factor),
 					);
 
 					let dst_y = dst_y_base + dy;
@@ -583,13 +1053,17 @@ impl Postprocessor
 						let c = debug_checked_fetch(&self.hdr_buffer, dx + hdr_buffer_line_offset);
 						let x_mix_factor = mix_factors[dx];
 						let one_minus_x_mix_factor = 1.0 - x_mix_factor;
-						let sum = ColorVec::mul_scalar_add(
+						let sum = SimdColorVec::mul_scalar_add(
 							&bloom_src_left,
 							one_minus_x_mix_factor,
-							&ColorVec::mul_scalar_add(&bloom_src_right, x_mix_factor, &ColorVec::from_color64(c)),
+							&SimdColorVec::mul_scalar_add(
+								&bloom_src_right,
+								x_mix_factor,
+								&SimdColorVec::<SIMD_LEVEL>::from_color64(c),
+							),
 						);
 						let c_mapped = tonemapping_function.do_it(&sum);
-						debug_checked_store(pixels, dx + pixels_line_offset, c_mapped.into());
+						debug_checked_store(pixels, dx + pixels_line_offset, c_mapped.into_color32());
 					}
 				}
 
@@ -601,10 +1075,10 @@ impl Postprocessor
 			{
 				let y_mix_factor = mix_factors[dy];
 				let one_minus_y_mix_factor = 1.0 - y_mix_factor;
-				let bloom_src = ColorVec::mul_scalar_add(
+				let bloom_src = SimdColorVec::mul_scalar_add(
 					&bloom_src_0_0,
 					one_minus_y_mix_factor,
-					&ColorVec::scalar_mul(&bloom_src_0_1, y_mix_factor),
+					&SimdColorVec::scalar_mul(&bloom_src_0_1, y_mix_factor),
 				);
 
 				let dst_y = dst_y_base + dy;
@@ -613,9 +1087,9 @@ impl Postprocessor
 				for dx in 0 .. right_border_size
 				{
 					let c = debug_checked_fetch(&self.hdr_buffer, dx + hdr_buffer_line_offset);
-					let sum = ColorVec::add(&bloom_src, &ColorVec::from_color64(c));
+					let sum = SimdColorVec::add(&bloom_src, &SimdColorVec::<SIMD_LEVEL>::from_color64(c));
 					let c_mapped = tonemapping_function.do_it(&sum);
-					debug_checked_store(pixels, dx + pixels_line_offset, c_mapped.into());
+					debug_checked_store(pixels, dx + pixels_line_offset, c_mapped.into_color32());
 				}
 			}
 		}
@@ -626,13 +1100,15 @@ impl Postprocessor
 
 			let src_line_offset = (self.bloom_buffer_size[1] - 1) * self.bloom_buffer_size[0];
 			let mut bloom_src_0_0 =
-				ColorVec::from_color64(debug_checked_fetch(&self.bloom_buffers[0], src_line_offset));
+				SimdColorVec::<SIMD_LEVEL>::from_color64(debug_checked_fetch(&self.bloom_buffers[0], src_line_offset));
 			for src_x in 0 .. self.bloom_buffer_size[0] - 1
 			{
 				let dst_x_base = src_x * BLOOM_BUFFER_SCALE + BLOOM_BUFFER_SCALE / 2;
 
-				let bloom_src_1_0 =
-					ColorVec::from_color64(debug_checked_fetch(&self.bloom_buffers[0], src_x + 1 + src_line_offset));
+				let bloom_src_1_0 = SimdColorVec::<SIMD_LEVEL>::from_color64(debug_checked_fetch(
+					&self.bloom_buffers[0],
+					src_x + 1 + src_line_offset,
+				));
 
 				for dy in 0 .. upper_border_size
 				{
@@ -644,13 +1120,17 @@ impl Postprocessor
 						let c = debug_checked_fetch(&self.hdr_buffer, dx + hdr_buffer_line_offset);
 						let x_mix_factor = mix_factors[dx];
 						let one_minus_x_mix_factor = 1.0 - x_mix_factor;
-						let sum = ColorVec::mul_scalar_add(
+						let sum = SimdColorVec::mul_scalar_add(
 							&bloom_src_0_0,
 							one_minus_x_mix_factor,
-							&ColorVec::mul_scalar_add(&bloom_src_1_0, x_mix_factor, &ColorVec::from_color64(c)),
+							&SimdColorVec::mul_scalar_add(
+								&bloom_src_1_0,
+								x_mix_factor,
+								&SimdColorVec::<SIMD_LEVEL>::from_color64(c),
+							),
 						);
 						let c_mapped = tonemapping_function.do_it(&sum);
-						debug_checked_store(pixels, dx + pixels_line_offset, c_mapped.into());
+						debug_checked_store(pixels, dx + pixels_line_offset, c_mapped.into_color32());
 					}
 				}
 
@@ -667,9 +1147,9 @@ impl Postprocessor
 				for x in x_start .. x_end
 				{
 					let c = debug_checked_fetch(&self.hdr_buffer, x + hdr_buffer_line_offset);
-					let sum = ColorVec::add(&bloom_value, &ColorVec::from_color64(c));
+					let sum = SimdColorVec::add(&bloom_value, &SimdColorVec::<SIMD_LEVEL>::from_color64(c));
 					let c_mapped = tonemapping_function.do_it(&sum);
-					debug_checked_store(pixels, x + pixels_line_offset, c_mapped.into());
+					debug_checked_store(pixels, x + pixels_line_offset, c_mapped.into_color32());
 				}
 			}
 		};
@@ -681,14 +1161,14 @@ impl Postprocessor
 				BLOOM_BUFFER_SCALE / 2,
 				0,
 				BLOOM_BUFFER_SCALE / 2,
-				ColorVec::from_color64(self.bloom_buffers[0][0]),
+				SimdColorVec::<SIMD_LEVEL>::from_color64(self.bloom_buffers[0][0]),
 			);
 			process_corner(
 				right_border_begin_x,
 				self.hdr_buffer_size[0],
 				0,
 				BLOOM_BUFFER_SCALE / 2,
-				ColorVec::from_color64(self.bloom_buffers[0][self.bloom_buffer_size[0] - 1]),
+				SimdColorVec::<SIMD_LEVEL>::from_color64(self.bloom_buffers[0][self.bloom_buffer_size[0] - 1]),
 			);
 		}
 		if src_y_end >= self.bloom_buffer_size[1]
@@ -698,7 +1178,7 @@ impl Postprocessor
 				BLOOM_BUFFER_SCALE / 2,
 				upper_border_begin_y,
 				self.hdr_buffer_size[1],
-				ColorVec::from_color64(
+				SimdColorVec::<SIMD_LEVEL>::from_color64(
 					self.bloom_buffers[0][(self.bloom_buffer_size[1] - 1) * self.bloom_buffer_size[0]],
 				),
 			);
@@ -707,7 +1187,7 @@ impl Postprocessor
 				self.hdr_buffer_size[0],
 				upper_border_begin_y,
 				self.hdr_buffer_size[1],
-				ColorVec::from_color64(
+				SimdColorVec::<SIMD_LEVEL>::from_color64(
 					self.bloom_buffers[0]
 						[self.bloom_buffer_size[0] - 1 + (self.bloom_buffer_size[1] - 1) * self.bloom_buffer_size[0]],
 				),
@@ -715,6 +1195,163 @@ impl Postprocessor
 		}
 	}
 
+	// Blur HDR buffer along screen-space motion of pixels between previous and current camera positions.
+	// Motion of pixels is calculated by reprojection of pixels into previous frame using depth buffer.
+	// If depth buffer is not available all pixels are considered to be infinitely far.
+	// Pixels without depth are not blurred.
+	fn perform_motion_blur(
+		&mut self,
+		prev_camera_matrices: &CameraMatrices,
+		camera_matrices: &CameraMatrices,
+		depth_buffer: Option<(&[f32], [u32; 2])>,
+	)
+	{
+		let reprojection_matrix = if let Some(inv_view_matrix) = camera_matrices.view_matrix.invert()
+		{
+			prev_camera_matrices.view_matrix * inv_view_matrix
+		}
+		else
+		{
+			return;
+		};
+
+		// Use depth only if it is calculated for same size.
+		let depth_buffer = depth_buffer.and_then(|(depth_buffer, size)| {
+			if size[0] as usize == self.hdr_buffer_size[0] && size[1] as usize == self.hdr_buffer_size[1]
+			{
+				Some(depth_buffer)
+			}
+			else
+			{
+				None
+			}
+		});
+
+		let required_size = self.hdr_buffer_size[0] * self.hdr_buffer_size[1];
+		let mut dst_buffer = std::mem::take(&mut self.motion_blur_buffer);
+		if dst_buffer.len() < required_size
+		{
+			dst_buffer.resize(required_size, Color64::black());
+		}
+
+		// Use approach with constant number of samples in order to use optimized (unrolled) code for each number.
+		let dst = &mut dst_buffer[.. required_size];
+		match self.config.motion_blur_samples
+		{
+			0 ..= 2 => self.perform_motion_blur_impl::<2>(&reprojection_matrix, depth_buffer, dst),
+			3 => self.perform_motion_blur_impl::<3>(&reprojection_matrix, depth_buffer, dst),
+			4 => self.perform_motion_blur_impl::<4>(&reprojection_matrix, depth_buffer, dst),
+			5 => self.perform_motion_blur_impl::<5>(&reprojection_matrix, depth_buffer, dst),
+			6 => self.perform_motion_blur_impl::<6>(&reprojection_matrix, depth_buffer, dst),
+			7 => self.perform_motion_blur_impl::<7>(&reprojection_matrix, depth_buffer, dst),
+			8 => self.perform_motion_blur_impl::<8>(&reprojection_matrix, depth_buffer, dst),
+			9 => self.perform_motion_blur_impl::<9>(&reprojection_matrix, depth_buffer, dst),
+			10 => self.perform_motion_blur_impl::<10>(&reprojection_matrix, depth_buffer, dst),
+			11 => self.perform_motion_blur_impl::<11>(&reprojection_matrix, depth_buffer, dst),
+			12 => self.perform_motion_blur_impl::<12>(&reprojection_matrix, depth_buffer, dst),
+			13 => self.perform_motion_blur_impl::<13>(&reprojection_matrix, depth_buffer, dst),
+			14 => self.perform_motion_blur_impl::<14>(&reprojection_matrix, depth_buffer, dst),
+			15 => self.perform_motion_blur_impl::<15>(&reprojection_matrix, depth_buffer, dst),
+			_ => self.perform_motion_blur_impl::<MAX_MOTION_BLUR_SAMPLES>(&reprojection_matrix, depth_buffer, dst),
+		}
+
+		// Blurred image becomes new HDR image.
+		self.motion_blur_buffer = std::mem::replace(&mut self.hdr_buffer, dst_buffer);
+	}
+
+	fn perform_motion_blur_impl<const NUM_SAMPLES: usize>(
+		&self,
+		reprojection_matrix: &Mat4f,
+		depth_buffer: Option<&[f32]>,
+		dst: &mut [Color64],
+	)
+	{
+		const COLOR_SHIFT: i32 = 12;
+		let sample_scale = (1 << COLOR_SHIFT) / (NUM_SAMPLES as i32);
+
+		let width = self.hdr_buffer_size[0];
+		let height = self.hdr_buffer_size[1];
+		let src = &self.hdr_buffer[.. width * height];
+		let blur_scale = self.config.motion_blur_scale;
+		let max_blur_length = (width as f32) * MAX_MOTION_BLUR_LENGTH_RELATIVE;
+		let m = reprojection_matrix;
+
+		let func = |(y, dst_line): (usize, &mut [Color64])| {
+			let line_offset = y * width;
+			let screen_y = (y as f32) + 0.5;
+			// Part of reprojection, which is constant for whole line.
+			let line_start = m.y * screen_y + m.w + m.x * 0.5;
+			for (x, dst_pixel) in dst_line.iter_mut().enumerate()
+			{
+				let screen_x = (x as f32) + 0.5;
+				let inv_z = if let Some(depth_buffer) = depth_buffer
+				{
+					let inv_z = debug_checked_fetch(depth_buffer, x + line_offset);
+					if inv_z <= 0.0
+					{
+						// Nothing was written into depth buffer for this pixel.
+						*dst_pixel = debug_checked_fetch(src, x + line_offset);
+						continue;
+					}
+					inv_z
+				}
+				else
+				{
+					0.0
+				};
+				// Use homogeneous coordinates divided by depth in order to handle infinitely far points.
+				let prev = line_start + m.x * (x as f32) + m.z * inv_z;
+				if prev.w <= MIN_POSITIVE_VALUE
+				{
+					// Point is behind previous camera.
+					*dst_pixel = debug_checked_fetch(src, x + line_offset);
+					continue;
+				}
+
+				let inv_w = 1.0 / prev.w;
+				let mut velocity = [
+					(screen_x - prev.x * inv_w) * blur_scale,
+					(screen_y - prev.y * inv_w) * blur_scale,
+				];
+				let square_length = velocity[0] * velocity[0] + velocity[1] * velocity[1];
+				if square_length < MIN_MOTION_BLUR_LENGTH * MIN_MOTION_BLUR_LENGTH
+				{
+					*dst_pixel = debug_checked_fetch(src, x + line_offset);
+					continue;
+				}
+				if square_length > max_blur_length * max_blur_length
+				{
+					let scale = max_blur_length / square_length.sqrt();
+					velocity[0] *= scale;
+					velocity[1] *= scale;
+				}
+
+				// Take samples along motion segment with center in current pixel.
+				let mut sum = ColorVecI::zero();
+				for i in 0 .. NUM_SAMPLES
+				{
+					let t = ((i as f32) + 0.5) / (NUM_SAMPLES as f32) - 0.5;
+					let sample_x = ((screen_x + velocity[0] * t) as i32).max(0).min(width as i32 - 1) as usize;
+					let sample_y = ((screen_y + velocity[1] * t) as i32).max(0).min(height as i32 - 1) as usize;
+					let c = debug_checked_fetch(src, sample_x + sample_y * width);
+					sum = ColorVecI::add(&sum, &ColorVecI::from_color64(c));
+				}
+
+				*dst_pixel =
+					ColorVecI::shift_right::<COLOR_SHIFT>(&ColorVecI::mul_scalar(&sum, sample_scale)).into_color64();
+			}
+		};
+
+		if rayon::current_num_threads() == 1 || !self.config.use_multithreadig
+		{
+			dst.chunks_mut(width).enumerate().for_each(func);
+		}
+		else
+		{
+			dst.par_chunks_mut(width).enumerate().for_each(func);
+		}
+	}
+
 	// Returns bloom buffer scale and average color.
 	fn perform_bloom(&mut self) -> (usize, ColorVec)
 	{
@@ -936,17 +1573,39 @@ impl Postprocessor
 
 		self.config.min_exposure = self.config.min_exposure.max(1.0 / 65536.0).min(16.0);
 		self.config.max_exposure = self.config.max_exposure.max(1.0).min(65536.0);
-		self.config.exposure_update_speed = self.config.exposure_update_speed.max(0.5).min(16.0);
+		self.config.exposure_update_speed_to_light = self.config.exposure_update_speed_to_light.max(0.05).min(16.0);
+		self.config.exposure_update_speed_to_dark = self.config.exposure_update_speed_to_dark.max(0.05).min(16.0);
+		self.config.exposure_histogram_low_percentile =
+			self.config.exposure_histogram_low_percentile.max(0.0).min(0.99);
+		self.config.exposure_histogram_high_percentile = self
+			.config
+			.exposure_histogram_high_percentile
+			.max(self.config.exposure_histogram_low_percentile + 0.01)
+			.min(1.0);
 		self.config.base_brightness = self.config.base_brightness.max(0.5).min(2.0);
 		self.config.zero_level_brightness = self.config.zero_level_brightness.max(1.0 / 16.0).min(16.0);
 		self.config.brightness_scale_power = self.config.brightness_scale_power.max(0.01).min(0.5);
 		self.config.bloom_sigma = self.config.bloom_sigma.max(0.0).min(40.0);
+		self.config.motion_blur_scale = self.config.motion_blur_scale.max(0.0).min(1.0);
+		self.config.motion_blur_samples = self
+			.config
+			.motion_blur_samples
+			.max(2)
+			.min(MAX_MOTION_BLUR_SAMPLES as u32);
 		self.config.bloom_buffer_scale_log2 = self
 			.config
 			.bloom_buffer_scale_log2
 			.max(MIN_BLOOM_BUFFER_SCALE_LOG2 as u32)
 			.min(MAX_BLOOM_BUFFER_SCALE_LOG2 as u32);
 		self.config.bloom_scale = self.config.bloom_scale.max(1.0 / 16.0).min(1.0 / 2.0);
+		self.config.tonemapping_white_point = self.config.tonemapping_white_point.max(1.0).min(64.0);
+		self.config.brightness = self.config.brightness.max(0.25).min(4.0);
+		self.config.gamma = self.config.gamma.max(0.25).min(4.0);
+		self.config.contrast = self.config.contrast.max(0.0).min(4.0);
+		self.config.saturation = self.config.saturation.max(0.0).min(4.0);
+		self.config.vignette = self.config.vignette.max(0.0).min(1.0);
+		// 0 - none, 1 - FXAA, 2 - supersampling.
+		self.config.anti_aliasing = self.config.anti_aliasing.min(2);
 
 		self.config.update_app_config(&self.app_config);
 	}
@@ -955,6 +1614,11 @@ impl Postprocessor
 	{
 		self.config.hdr_rendering
 	}
+
+	pub fn get_anti_aliasing_mode(&self) -> AntiAliasingMode
+	{
+		AntiAliasingMode::from_config_value(self.config.anti_aliasing)
+	}
 }
 
 // Support only power of two scale to reduce specialized code size.
@@ -968,30 +1632,387 @@ const MAX_GAUSSIAN_KERNEL_SIZE: usize = 1 + 2 * MAX_GAUSSIAN_KERNEL_RADIUS;
 
 const MAX_THREADS: usize = 64;
 
+const MAX_MOTION_BLUR_SAMPLES: usize = 16;
+// Maximum length of motion blur relative to screen width.
+const MAX_MOTION_BLUR_LENGTH_RELATIVE: f32 = 1.0 / 16.0;
+// Do not blur pixels with motion less than this (in pixels).
+const MIN_MOTION_BLUR_LENGTH: f32 = 0.5;
+// Do not perform motion blur if camera moved more than this distance since previous frame.
+const MAX_MOTION_BLUR_CAMERA_SHIFT: f32 = 128.0;
+const MIN_POSITIVE_VALUE: f32 = 1.0 / ((1 << 30) as f32);
+
+#[derive(Copy, Clone, PartialEq)]
+pub enum TonemappingOperator
+{
+	Reinhard,
+	ExtendedReinhard,
+	Aces,
+	Uncharted2,
+	LinearClamp,
+}
+
+impl TonemappingOperator
+{
+	pub fn from_config_value(value: u32) -> Self
+	{
+		match value
+		{
+			0 => TonemappingOperator::Reinhard,
+			1 => TonemappingOperator::ExtendedReinhard,
+			2 => TonemappingOperator::Aces,
+			3 => TonemappingOperator::Uncharted2,
+			_ => TonemappingOperator::LinearClamp,
+		}
+	}
+}
+
 #[derive(Copy, Clone)]
 struct TonemappingFunction
 {
-	inv_scale_vec: ColorVec,
-	inv_255_vec: ColorVec,
+	operator: TonemappingOperator,
+	inv_scale: f32,
+	// Scale for conversion of color into brightness, where 1.0 is average.
+	exposure_scale: f32,
+	inv_white_point_square: f32,
+	uncharted2_inv_white: f32,
 }
 
 impl TonemappingFunction
 {
-	fn new(exposure: f32) -> Self
+	fn new(operator: TonemappingOperator, exposure: f32, white_point: f32) -> Self
 	{
-		let inv_scale = 1.0 / exposure;
-		let inv_255 = 1.0 / 255.0;
+		Self {
+			operator,
+			inv_scale: 1.0 / exposure,
+			exposure_scale: exposure * INV_255,
+			inv_white_point_square: 1.0 / (white_point * white_point),
+			uncharted2_inv_white: 1.0 / uncharted2_curve(white_point),
+		}
+	}
+
+	// Result is in range [0; 255].
+	#[inline(always)]
+	fn do_it<const SIMD_LEVEL: u32>(&self, c: &SimdColorVec<SIMD_LEVEL>) -> SimdColorVec<SIMD_LEVEL>
+	{
+		if self.operator == TonemappingOperator::Reinhard
+		{
+			// Use fast path for Reinhard formula.
+			return SimdColorVec::div(
+				c,
+				&SimdColorVec::mul_add(c, &splat_color_vec(INV_255), &splat_color_vec(self.inv_scale)),
+			);
+		}
+
+		let one = splat_color_vec(1.0);
+		let x = SimdColorVec::scalar_mul(c, self.exposure_scale);
+		let result = match self.operator
+		{
+			TonemappingOperator::Reinhard => SimdColorVec::div(&x, &SimdColorVec::add(&x, &one)),
+			TonemappingOperator::ExtendedReinhard => SimdColorVec::div(
+				&SimdColorVec::mul(
+					&x,
+					&SimdColorVec::mul_add(&x, &splat_color_vec(self.inv_white_point_square), &one),
+				),
+				&SimdColorVec::add(&x, &one),
+			),
+			TonemappingOperator::Aces =>
+			{
+				// Fitted curve by Krzysztof Narkowicz.
+				let numerator = SimdColorVec::mul(
+					&x,
+					&SimdColorVec::mul_add(&x, &splat_color_vec(2.51), &splat_color_vec(0.03)),
+				);
+				let denominator = SimdColorVec::mul_add(
+					&x,
+					&SimdColorVec::mul_add(&x, &splat_color_vec(2.43), &splat_color_vec(0.59)),
+					&splat_color_vec(0.14),
+				);
+				SimdColorVec::div(&numerator, &denominator)
+			},
+			TonemappingOperator::Uncharted2 =>
+			{
+				let x2 = SimdColorVec::scalar_mul(&x, 2.0);
+				SimdColorVec::scalar_mul(&uncharted2_curve_vec(&x2), self.uncharted2_inv_white)
+			},
+			TonemappingOperator::LinearClamp => x,
+		};
+
+		// Use "max" first in order to replace possible NaNs with zeros.
+		SimdColorVec::min(
+			&SimdColorVec::max(&SimdColorVec::scalar_mul(&result, 255.0), &SimdColorVec::zero()),
+			&splat_color_vec(255.0),
+		)
+	}
+
+	// Same as "do_it", but for two pixels (8 color components) at once.
+	#[cfg(target_arch = "x86_64")]
+	#[target_feature(enable = "avx2,fma")]
+	unsafe fn do_it_avx2(&self, c: __m256) -> __m256
+	{
+		let max_value = _mm256_set1_ps(255.0);
+		if self.operator == TonemappingOperator::Reinhard
+		{
+			// Use fast path for Reinhard formula.
+			let inv_255 = _mm256_set1_ps(INV_255);
+			let inv_scale = _mm256_set1_ps(self.inv_scale);
+			return _mm256_min_ps(_mm256_div_ps(c, _mm256_fmadd_ps(c, inv_255, inv_scale)), max_value);
+		}
+
+		let one = _mm256_set1_ps(1.0);
+		let x = _mm256_mul_ps(c, _mm256_set1_ps(self.exposure_scale));
+		let result = match self.operator
+		{
+			TonemappingOperator::Reinhard => _mm256_div_ps(x, _mm256_add_ps(x, one)),
+			TonemappingOperator::ExtendedReinhard => _mm256_div_ps(
+				_mm256_mul_ps(x, _mm256_fmadd_ps(x, _mm256_set1_ps(self.inv_white_point_square), one)),
+				_mm256_add_ps(x, one),
+			),
+			TonemappingOperator::Aces =>
+			{
+				let numerator = _mm256_mul_ps(x, _mm256_fmadd_ps(x, _mm256_set1_ps(2.51), _mm256_set1_ps(0.03)));
+				let denominator = _mm256_fmadd_ps(
+					x,
+					_mm256_fmadd_ps(x, _mm256_set1_ps(2.43), _mm256_set1_ps(0.59)),
+					_mm256_set1_ps(0.14),
+				);
+				_mm256_div_ps(numerator, denominator)
+			},
+			TonemappingOperator::Uncharted2 => _mm256_mul_ps(
+				uncharted2_curve_avx2(_mm256_add_ps(x, x)),
+				_mm256_set1_ps(self.uncharted2_inv_white),
+			),
+			TonemappingOperator::LinearClamp => x,
+		};
+
+		// Use "max" first in order to replace possible NaNs with zeros.
+		_mm256_min_ps(
+			_mm256_max_ps(_mm256_mul_ps(result, max_value), _mm256_setzero_ps()),
+			max_value,
+		)
+	}
+}
+
+const INV_255: f32 = 1.0 / 255.0;
+
+#[inline(always)]
+fn splat_color_vec<const SIMD_LEVEL: u32>(value: f32) -> SimdColorVec<SIMD_LEVEL>
+{
+	SimdColorVec::from_color_f32x3(&[value, value, value])
+}
+
+// Filmic curve by John Hable.
+const UNCHARTED2_A: f32 = 0.15;
+const UNCHARTED2_B: f32 = 0.50;
+const UNCHARTED2_C: f32 = 0.10;
+const UNCHARTED2_D: f32 = 0.20;
+const UNCHARTED2_E: f32 = 0.02;
+const UNCHARTED2_F: f32 = 0.30;
+
+fn uncharted2_curve(x: f32) -> f32
+{
+	const A: f32 = UNCHARTED2_A;
+	const B: f32 = UNCHARTED2_B;
+	const C: f32 = UNCHARTED2_C;
+	const D: f32 = UNCHARTED2_D;
+	const E: f32 = UNCHARTED2_E;
+	const F: f32 = UNCHARTED2_F;
+	((x * (A * x + C * B) + D * E) / (x * (A * x + B) + D * F)) - E / F
+}
+
+// Same as "uncharted2_curve", but for vector.
+#[inline(always)]
+fn uncharted2_curve_vec<const SIMD_LEVEL: u32>(x: &SimdColorVec<SIMD_LEVEL>) -> SimdColorVec<SIMD_LEVEL>
+{
+	const A: f32 = UNCHARTED2_A;
+	const B: f32 = UNCHARTED2_B;
+	const C: f32 = UNCHARTED2_C;
+	const D: f32 = UNCHARTED2_D;
+	const E: f32 = UNCHARTED2_E;
+	const F: f32 = UNCHARTED2_F;
+	let numerator = SimdColorVec::mul_add(
+		x,
+		&SimdColorVec::mul_add(x, &splat_color_vec(A), &splat_color_vec(C * B)),
+		&splat_color_vec(D * E),
+	);
+	let denominator = SimdColorVec::mul_add(
+		x,
+		&SimdColorVec::mul_add(x, &splat_color_vec(A), &splat_color_vec(B)),
+		&splat_color_vec(D * F),
+	);
+	SimdColorVec::add(&SimdColorVec::div(&numerator, &denominator), &splat_color_vec(-E / F))
+}
+
+// Same as "uncharted2_curve", but for 8 values at once.
+#[cfg(target_arch = "x86_64")]
+#[target_feature(enable = "avx2,fma")]
+unsafe fn uncharted2_curve_avx2(x: __m256) -> __m256
+{
+	const A: f32 = UNCHARTED2_A;
+	const B: f32 = UNCHARTED2_B;
+	const C: f32 = UNCHARTED2_C;
+	const D: f32 = UNCHARTED2_D;
+	const E: f32 = UNCHARTED2_E;
+	const F: f32 = UNCHARTED2_F;
+	let a = _mm256_set1_ps(A);
+	let numerator = _mm256_fmadd_ps(x, _mm256_fmadd_ps(x, a, _mm256_set1_ps(C * B)), _mm256_set1_ps(D * E));
+	let denominator = _mm256_fmadd_ps(x, _mm256_fmadd_ps(x, a, _mm256_set1_ps(B)), _mm256_set1_ps(D * F));
+	_mm256_add_ps(_mm256_div_ps(numerator, denominator), _mm256_set1_ps(-E / F))
+}
+
+struct LdrPostprocessingParams<'a>
+{
+	// Brightness, contrast and gamma correction for each color component.
+	components_table: [u8; 256],
+	use_components_table: bool,
+	// Color grading table, that includes also brightness, contrast and gamma correction.
+	color_grading_table: Option<&'a ColorGradingTable>,
+	// Scaled by 256.
+	saturation: i32,
+	vignette: f32,
+	vignette_center: [f32; 2],
+	// Tint color, premultiplied by alpha and scaled by 256.
+	tint_color: [i32; 3],
+	// Scaled by 256.
+	tint_inv_alpha: i32,
+}
+
+impl<'a> LdrPostprocessingParams<'a>
+{
+	fn new(
+		config: &PostprocessorConfig,
+		surface_info: &system_window::SurfaceInfo,
+		screen_tint: Option<&ScreenTint>,
+		color_grading_table: Option<&'a ColorGradingTable>,
+	) -> Self
+	{
+		let use_components_table = color_grading_table.is_none() &&
+			(config.brightness != 1.0 || config.contrast != 1.0 || config.gamma != 1.0);
+		let mut components_table = [0; 256];
+		if use_components_table
+		{
+			for (i, dst) in components_table.iter_mut().enumerate()
+			{
+				*dst = correct_color_component(i as f32, config).round() as u8;
+			}
+		}
+
+		let (tint_color, tint_inv_alpha) = if let Some(tint) = screen_tint
+		{
+			let alpha = tint.alpha.max(0.0).min(1.0);
+			(
+				[
+					(tint.color[0].max(0.0).min(255.0) * alpha * 256.0) as i32,
+					(tint.color[1].max(0.0).min(255.0) * alpha * 256.0) as i32,
+					(tint.color[2].max(0.0).min(255.0) * alpha * 256.0) as i32,
+				],
+				((1.0 - alpha) * 256.0) as i32,
+			)
+		}
+		else
+		{
+			([0, 0, 0], 256)
+		};
 
 		Self {
-			inv_scale_vec: ColorVec::from_color_f32x3(&[inv_scale, inv_scale, inv_scale]),
-			inv_255_vec: ColorVec::from_color_f32x3(&[inv_255, inv_255, inv_255]),
+			components_table,
+			use_components_table,
+			color_grading_table,
+			saturation: (config.saturation * 256.0) as i32,
+			vignette: config.vignette,
+			vignette_center: [(surface_info.width as f32) * 0.5, (surface_info.height as f32) * 0.5],
+			tint_color,
+			tint_inv_alpha,
 		}
 	}
 
-	fn do_it(&self, c: &ColorVec) -> ColorVec
+	fn is_identity(&self) -> bool
 	{
-		// Use Reinhard formula for tonemapping.
-		ColorVec::div(c, &ColorVec::mul_add(c, &self.inv_255_vec, &self.inv_scale_vec))
+		!self.use_components_table &&
+			self.color_grading_table.is_none() &&
+			self.saturation == 256 &&
+			self.vignette <= 0.0 &&
+			self.tint_inv_alpha == 256
+	}
+}
+
+// Apply brightness, contrast and gamma correction. Input and output are in range [0; 255].
+fn correct_color_component(c: f32, config: &PostprocessorConfig) -> f32
+{
+	let mut v = c / 255.0 * config.brightness;
+	v = ((v - 0.5) * config.contrast + 0.5).max(0.0).min(1.0);
+	v = v.powf(1.0 / config.gamma);
+	v * 255.0
+}
+
+fn perform_ldr_postprocessing_for_rows(
+	pixels: &mut [Color32],
+	surface_info: &system_window::SurfaceInfo,
+	y_start: usize,
+	y_end: usize,
+	params: &LdrPostprocessingParams,
+)
+{
+	let inv_center = [
+		1.0 / params.vignette_center[0].max(1.0),
+		1.0 / params.vignette_center[1].max(1.0),
+	];
+	for y in y_start .. y_end
+	{
+		let dy = ((y as f32) + 0.5 - params.vignette_center[1]) * inv_center[1];
+		let line = &mut pixels[y * surface_info.pitch .. y * surface_info.pitch + surface_info.width];
+		for (x, pixel) in line.iter_mut().enumerate()
+		{
+			let rgb = pixel.get_rgb();
+			let mut c = if let Some(color_grading_table) = params.color_grading_table
+			{
+				let rgb_graded = color_grading_table.apply(&rgb).into_color32().get_rgb();
+				[rgb_graded[0] as i32, rgb_graded[1] as i32, rgb_graded[2] as i32]
+			}
+			else if params.use_components_table
+			{
+				[
+					params.components_table[rgb[0] as usize] as i32,
+					params.components_table[rgb[1] as usize] as i32,
+					params.components_table[rgb[2] as usize] as i32,
+				]
+			}
+			else
+			{
+				[rgb[0] as i32, rgb[1] as i32, rgb[2] as i32]
+			};
+
+			if params.saturation != 256
+			{
+				let luminance = (c[0] * 54 + c[1] * 183 + c[2] * 19) >> 8;
+				for component in &mut c
+				{
+					*component = luminance + (((*component - luminance) * params.saturation) >> 8);
+				}
+			}
+
+			if params.vignette > 0.0
+			{
+				// Use square of distance to center, normalized to range [0; 1].
+				let dx = ((x as f32) + 0.5 - params.vignette_center[0]) * inv_center[0];
+				let distance_square = (dx * dx + dy * dy) * 0.5;
+				let factor = ((1.0 - params.vignette * distance_square * distance_square) * 256.0) as i32;
+				for component in &mut c
+				{
+					*component = (*component * factor) >> 8;
+				}
+			}
+
+			for i in 0 .. 3
+			{
+				c[i] = (c[i] * params.tint_inv_alpha + params.tint_color[i]) >> 8;
+			}
+
+			*pixel = Color32::from_rgb(
+				c[0].max(0).min(255) as u8,
+				c[1].max(0).min(255) as u8,
+				c[2].max(0).min(255) as u8,
+			);
+		}
 	}
 }
 
@@ -10,8 +10,28 @@ pub struct PostprocessorConfig
 	#[serde(default)]
 	pub use_multithreadig: bool,
 
+	// Speed of exposure adaptation, when scene becomes brighter.
 	#[serde(default = "default_one")]
-	pub exposure_update_speed: f32,
+	pub exposure_update_speed_to_light: f32,
+
+	// Speed of exposure adaptation, when scene becomes darker.
+	#[serde(default = "default_one")]
+	pub exposure_update_speed_to_dark: f32,
+
+	// Legacy single speed of exposure adaptation. If present, it is used for both directions.
+	#[serde(default, skip_serializing)]
+	pub exposure_update_speed: Option<f32>,
+
+	// 0 - average, 1 - center-weighted, 2 - spot, 3 - histogram (log-average within percentiles).
+	#[serde(default)]
+	pub exposure_metering_mode: u32,
+
+	// Percentiles of darkest and brightest parts of screen, excluded from histogram metering.
+	#[serde(default = "default_exposure_histogram_low_percentile")]
+	pub exposure_histogram_low_percentile: f32,
+
+	#[serde(default = "default_exposure_histogram_high_percentile")]
+	pub exposure_histogram_high_percentile: f32,
 
 	#[serde(default = "default_one")]
 	pub base_brightness: f32,
@@ -39,13 +59,80 @@ pub struct PostprocessorConfig
 
 	#[serde(default)]
 	pub linear_bloom_filter: bool,
+
+	// Blur image along screen-space motion, caused by camera movement.
+	// Renderer depth output is used if it is enabled, otherwise only camera rotation is taken into account.
+	#[serde(default)]
+	pub motion_blur: bool,
+
+	// Fraction of camera motion between frames used for blur (like shutter time).
+	#[serde(default = "default_motion_blur_scale")]
+	pub motion_blur_scale: f32,
+
+	#[serde(default = "default_motion_blur_samples")]
+	pub motion_blur_samples: u32,
+
+	// 0 - Reinhard, 1 - extended Reinhard, 2 - ACES (fitted), 3 - filmic (Uncharted 2), 4 - linear with clamping.
+	// May be overriden by map.
+	#[serde(default)]
+	pub tonemapping_operator: u32,
+
+	// Brightness (after exposure applying), mapped to white. Used for extended Reinhard and filmic operators.
+	#[serde(default = "default_tonemapping_white_point")]
+	pub tonemapping_white_point: f32,
+
+	// Apply color grading LUT, if it is specified for current map.
+	#[serde(default = "default_true")]
+	pub color_grading: bool,
+
+	// Params of final image correction, applied in both LDR and HDR modes. 1 - no changes.
+	#[serde(default = "default_one")]
+	pub brightness: f32,
+
+	#[serde(default = "default_one")]
+	pub gamma: f32,
+
+	#[serde(default = "default_one")]
+	pub contrast: f32,
+
+	#[serde(default = "default_one")]
+	pub saturation: f32,
+
+	// Darkening of screen corners. 0 - no vignette, 1 - corners are completely black.
+	#[serde(default)]
+	pub vignette: f32,
+
+	// 0 - none, 1 - FXAA, 2 - supersampling (rendering in doubled resolution).
+	#[serde(default)]
+	pub anti_aliasing: u32,
+
+	// Quantize final image to palette with up to 256 colors.
+	#[serde(default)]
+	pub palette_output: bool,
+
+	// Quake "palette.lmp" file or image, first 256 pixels of which are used.
+	#[serde(default = "default_palette_file")]
+	pub palette_file: String,
+
+	// Use ordered dithering for palette output.
+	#[serde(default = "default_true")]
+	pub palette_dithering: bool,
 }
 
 impl PostprocessorConfig
 {
 	pub fn from_app_config(app_config: &config::ConfigSharedPtr) -> Self
 	{
-		serde_json::from_value(app_config.lock().unwrap()["postprocessor"].clone()).unwrap_or_default()
+		let mut config: Self =
+			serde_json::from_value(app_config.lock().unwrap()["postprocessor"].clone()).unwrap_or_default();
+
+		if let Some(speed) = config.exposure_update_speed.take()
+		{
+			config.exposure_update_speed_to_light = speed;
+			config.exposure_update_speed_to_dark = speed;
+		}
+
+		config
 	}
 
 	pub fn update_app_config(&self, app_config: &config::ConfigSharedPtr)
@@ -57,11 +144,31 @@ impl PostprocessorConfig
 	}
 }
 
+fn default_true() -> bool
+{
+	true
+}
+
+fn default_palette_file() -> String
+{
+	"palette.lmp".to_string()
+}
+
 fn default_one() -> f32
 {
 	1.0
 }
 
+fn default_exposure_histogram_low_percentile() -> f32
+{
+	0.5
+}
+
+fn default_exposure_histogram_high_percentile() -> f32
+{
+	0.95
+}
+
 fn default_bloom_buffer_scale_log2() -> u32
 {
 	1
@@ -91,3 +198,18 @@ fn default_bloom_scale() -> f32
 {
 	0.25
 }
+
+fn default_motion_blur_scale() -> f32
+{
+	0.5
+}
+
+fn default_motion_blur_samples() -> u32
+{
+	8
+}
+
+fn default_tonemapping_white_point() -> f32
+{
+	4.0
+}
@@ -0,0 +1,383 @@
+use super::{abstract_color::*, textures::*};
+use crate::common::{color::*, image_hdr, material::ProceduralSkyParams, math_types::*};
+use rayon::prelude::*;
+use std::sync::Arc;
+
+// Sun parameters of procedural sky. Game code may change them in order to animate time of day.
+#[derive(Copy, Clone, PartialEq)]
+pub struct SkySun
+{
+	// Direction towards sun (in skybox space).
+	pub direction: Vec3f,
+	// Linear color, 1.0 - regular brightness.
+	pub color: [f32; 3],
+}
+
+impl SkySun
+{
+	pub fn from_sky_params(params: &ProceduralSkyParams) -> Self
+	{
+		Self {
+			direction: Vec3f::from(params.sun_direction),
+			color: params.sun_color,
+		}
+	}
+}
+
+// Generates skybox textures for procedural sky.
+// Only one instance exists for all views - textures are generated once and shared between all renderers.
+#[derive(Default)]
+pub struct ProceduralSky
+{
+	textures: Arc<ProceduralSkyTextures>,
+	// Parameters of last update. Used to avoid regeneration of textures if nothing changed.
+	last_update_key: Option<UpdateKey>,
+	last_update_time_s: f32,
+	// Temp buffers for sides pixels.
+	side_images: [image_hdr::ImageHDR; 6],
+}
+
+#[derive(Default)]
+pub struct ProceduralSkyTextures
+{
+	textures_32: SkyboxTextures<Color32>,
+	textures_64: SkyboxTextures<Color64>,
+}
+
+#[derive(Copy, Clone, PartialEq)]
+struct UpdateKey
+{
+	material_index: u32,
+	color_size: usize,
+	sun: SkySun,
+	time_s: f32,
+}
+
+// Regenerate sky with this frequency, if clouds are moving or sun is changed.
+const UPDATE_FREQUENCY: f32 = 15.0;
+
+impl ProceduralSky
+{
+	pub fn update<ColorT: AbstractColor>(
+		&mut self,
+		material_index: u32,
+		params: &ProceduralSkyParams,
+		sun: &SkySun,
+		time_s: f32,
+	)
+	{
+		let clouds_are_moving =
+			params.cloud_coverage > 0.0 && (params.cloud_speed[0] != 0.0 || params.cloud_speed[1] != 0.0);
+		let update_key = UpdateKey {
+			material_index,
+			color_size: std::mem::size_of::<ColorT>(),
+			sun: *sun,
+			time_s: if clouds_are_moving { time_s } else { 0.0 },
+		};
+		if let Some(last_update_key) = &self.last_update_key
+		{
+			if *last_update_key == update_key
+			{
+				return;
+			}
+			if last_update_key.material_index == update_key.material_index &&
+				last_update_key.color_size == update_key.color_size &&
+				(time_s - self.last_update_time_s).abs() < 1.0 / UPDATE_FREQUENCY
+			{
+				// Do not regenerate textures too often.
+				return;
+			}
+		}
+		self.last_update_key = Some(update_key);
+		self.last_update_time_s = time_s;
+
+		// Reuse memory of textures if renderers released them, else allocate new textures.
+		if Arc::get_mut(&mut self.textures).is_none()
+		{
+			self.textures = Arc::new(ProceduralSkyTextures::default());
+		}
+		let textures = Arc::get_mut(&mut self.textures).unwrap();
+
+		// Use an ugly hack to update proper textures.
+		// Tonemap sky in non-HDR mode in order to preserve sun disc and bright clouds.
+		if update_key.color_size == 4
+		{
+			generate_sky_textures(
+				&mut textures.textures_32,
+				&mut self.side_images,
+				params,
+				sun,
+				time_s,
+				true,
+			);
+		}
+		else if update_key.color_size == 8
+		{
+			generate_sky_textures(
+				&mut textures.textures_64,
+				&mut self.side_images,
+				params,
+				sun,
+				time_s,
+				false,
+			);
+		}
+		else
+		{
+			panic!("Unsupported type!");
+		}
+	}
+
+	pub fn get_textures(&self) -> &Arc<ProceduralSkyTextures>
+	{
+		&self.textures
+	}
+}
+
+impl ProceduralSkyTextures
+{
+	pub fn get_textures<ColorT: AbstractColor>(&self) -> &SkyboxTextures<ColorT>
+	{
+		// Use an ugly hack to return proper skybox texture.
+		let color_size = std::mem::size_of::<ColorT>();
+		if color_size == 4
+		{
+			unsafe { std::mem::transmute(&self.textures_32) }
+		}
+		else if color_size == 8
+		{
+			unsafe { std::mem::transmute(&self.textures_64) }
+		}
+		else
+		{
+			panic!("Unsupported type!");
+		}
+	}
+}
+
+fn generate_sky_textures<ColorT: AbstractColor>(
+	textures: &mut SkyboxTextures<ColorT>,
+	side_images: &mut [image_hdr::ImageHDR; 6],
+	params: &ProceduralSkyParams,
+	sun: &SkySun,
+	time_s: f32,
+	tonemap: bool,
+)
+{
+	// Sides in order -X, +X, -Y, +Y, -Z, +Z.
+	// Normal (directed inside box) and texture coordinates basis. Must match basis, used for skybox drawing.
+	let sides_basis = [
+		(Vec3f::unit_x(), Vec3f::unit_y(), -Vec3f::unit_z()),
+		(-Vec3f::unit_x(), -Vec3f::unit_y(), -Vec3f::unit_z()),
+		(Vec3f::unit_y(), -Vec3f::unit_x(), -Vec3f::unit_z()),
+		(-Vec3f::unit_y(), Vec3f::unit_x(), -Vec3f::unit_z()),
+		(Vec3f::unit_z(), Vec3f::unit_x(), -Vec3f::unit_y()),
+		(-Vec3f::unit_z(), Vec3f::unit_x(), Vec3f::unit_y()),
+	];
+
+	let size = params.texture_size.max(1).min(2048).next_power_of_two();
+	let sun_direction = sun.direction / sun.direction.magnitude().max(0.000001);
+	let sun_cos_radius = (params.sun_angular_size * 0.5).to_radians().cos();
+
+	let scale = 2.0 / (size as f32);
+	let process_row = |(normal, u_vec, v_vec): &(Vec3f, Vec3f, Vec3f), (y, row): (usize, &mut [[f32; 3]])| {
+		let v = ((y as f32) + 0.5) * scale - 1.0;
+		for (x, dst) in row.iter_mut().enumerate()
+		{
+			let u = ((x as f32) + 0.5) * scale - 1.0;
+			let dir = -*normal + *u_vec * u + *v_vec * v;
+			*dst = get_sky_color(
+				params,
+				&(dir / dir.magnitude()),
+				&sun_direction,
+				&sun.color,
+				sun_cos_radius,
+				time_s,
+			);
+		}
+	};
+
+	let process_side = |((basis, side_image), out_textures): (
+		(&(Vec3f, Vec3f, Vec3f), &mut image_hdr::ImageHDR),
+		&mut SkyboxSideTextureWithMips<ColorT>,
+	)| {
+		side_image.size = [size, size];
+		side_image.pixels.resize((size * size) as usize, [0.0; 3]);
+
+		if rayon::current_num_threads() == 1
+		{
+			side_image
+				.pixels
+				.chunks_mut(size as usize)
+				.enumerate()
+				.for_each(|row| process_row(basis, row));
+		}
+		else
+		{
+			side_image
+				.pixels
+				.par_chunks_mut(size as usize)
+				.enumerate()
+				.for_each(|row| process_row(basis, row));
+		}
+
+		let mip0 = make_skybox_side_texture_hdr(side_image, 1.0, tonemap);
+		*out_textures = make_skybox_side_texture_mips(mip0);
+	};
+
+	if rayon::current_num_threads() == 1
+	{
+		sides_basis
+			.iter()
+			.zip(side_images.iter_mut())
+			.zip(textures.iter_mut())
+			.for_each(process_side);
+	}
+	else
+	{
+		sides_basis
+			.par_iter()
+			.zip(side_images.par_iter_mut())
+			.zip(textures.par_iter_mut())
+			.for_each(process_side);
+	}
+}
+
+fn get_sky_color(
+	params: &ProceduralSkyParams,
+	dir: &Vec3f,
+	sun_direction: &Vec3f,
+	sun_color: &[f32; 3],
+	sun_cos_radius: f32,
+	time_s: f32,
+) -> [f32; 3]
+{
+	// Sky is bright at day and dark at night.
+	const NIGHT_BRIGHTNESS: f32 = 0.03;
+	let day_factor = smoothstep(-0.15, 0.25, sun_direction.z);
+	let sky_brightness = NIGHT_BRIGHTNESS + (1.0 - NIGHT_BRIGHTNESS) * day_factor;
+
+	// Horizon becomes reddish near sun, when sun is low.
+	let cos_to_sun = dir.dot(*sun_direction);
+	let sunset_factor = (1.0 - sun_direction.z.abs() * 4.0).max(0.0) * (cos_to_sun * 0.5 + 0.5).powi(2);
+	let horizon_color = mix(&params.horizon_color, &params.sunset_color, sunset_factor);
+
+	let mut color = if dir.z >= 0.0
+	{
+		mix(&horizon_color, &params.zenith_color, 1.0 - (1.0 - dir.z).powi(3))
+	}
+	else
+	{
+		mix(&horizon_color, &params.ground_color, smoothstep(0.0, 0.1, -dir.z))
+	};
+	for c in &mut color
+	{
+		*c *= sky_brightness;
+	}
+
+	// Approximate scattering around sun.
+	let glow = cos_to_sun.max(0.0).powi(32) * 0.5 * smoothstep(-0.1, 0.05, sun_direction.z);
+	for i in 0 .. 3
+	{
+		color[i] += sun_color[i] * glow;
+	}
+
+	// Clouds are placed on infinite plane above camera.
+	let mut cloud_density = 0.0;
+	if params.cloud_coverage > 0.0 && dir.z > 0.01
+	{
+		let plane_pos = [
+			dir.x / dir.z * params.cloud_scale + params.cloud_speed[0] * time_s,
+			dir.y / dir.z * params.cloud_scale + params.cloud_speed[1] * time_s,
+		];
+		let threshold = 1.0 - params.cloud_coverage.min(1.0);
+		cloud_density =
+			smoothstep(threshold, threshold + 0.3, fractal_noise(plane_pos)) * smoothstep(0.01, 0.15, dir.z);
+
+		let cloud_light = sky_brightness + glow;
+		let cloud_color = [
+			params.cloud_color[0] * cloud_light * mix_scalar(1.0, params.sunset_color[0], sunset_factor),
+			params.cloud_color[1] * cloud_light * mix_scalar(1.0, params.sunset_color[1], sunset_factor),
+			params.cloud_color[2] * cloud_light * mix_scalar(1.0, params.sunset_color[2], sunset_factor),
+		];
+		color = mix(&color, &cloud_color, cloud_density);
+	}
+
+	// Sun disc, partially hidden by clouds.
+	if cos_to_sun >= sun_cos_radius && dir.z > -0.02
+	{
+		let sun_visibility = (1.0 - cloud_density) * params.sun_disc_brightness;
+		for i in 0 .. 3
+		{
+			color[i] += sun_color[i] * sun_visibility;
+		}
+	}
+
+	color
+}
+
+// Result is in range [0; 1].
+fn fractal_noise(pos: [f32; 2]) -> f32
+{
+	const NUM_OCTAVES: u32 = 4;
+	let mut sum = 0.0;
+	let mut amplitude = 0.5;
+	let mut scale = 1.0;
+	for octave in 0 .. NUM_OCTAVES
+	{
+		sum += value_noise([pos[0] * scale, pos[1] * scale], octave) * amplitude;
+		amplitude *= 0.5;
+		scale *= 2.0;
+	}
+	sum / (1.0 - amplitude * 2.0)
+}
+
+// Result is in range [0; 1].
+fn value_noise(pos: [f32; 2], seed: u32) -> f32
+{
+	let x_floor = pos[0].floor();
+	let y_floor = pos[1].floor();
+	let x = x_floor as i32;
+	let y = y_floor as i32;
+	let dx = smoothstep(0.0, 1.0, pos[0] - x_floor);
+	let dy = smoothstep(0.0, 1.0, pos[1] - y_floor);
+
+	let v00 = hash(x, y, seed);
+	let v10 = hash(x + 1, y, seed);
+	let v01 = hash(x, y + 1, seed);
+	let v11 = hash(x + 1, y + 1, seed);
+
+	mix_scalar(mix_scalar(v00, v10, dx), mix_scalar(v01, v11, dx), dy)
+}
+
+// Result is in range [0; 1].
+fn hash(x: i32, y: i32, seed: u32) -> f32
+{
+	let mut h = (x as u32)
+		.wrapping_mul(374761393)
+		.wrapping_add((y as u32).wrapping_mul(668265263))
+		.wrapping_add(seed.wrapping_mul(2246822519));
+	h = (h ^ (h >> 13)).wrapping_mul(1274126177);
+	h ^= h >> 16;
+	(h & 0xFFFF) as f32 / 65535.0
+}
+
+fn smoothstep(edge0: f32, edge1: f32, x: f32) -> f32
+{
+	let t = ((x - edge0) / (edge1 - edge0)).max(0.0).min(1.0);
+	t * t * (3.0 - 2.0 * t)
+}
+
+fn mix_scalar(a: f32, b: f32, k: f32) -> f32
+{
+	a + (b - a) * k
+}
+
+fn mix(a: &[f32; 3], b: &[f32; 3], k: f32) -> [f32; 3]
+{
+	[
+		mix_scalar(a[0], b[0], k),
+		mix_scalar(a[1], b[1], k),
+		mix_scalar(a[2], b[2], k),
+	]
+}
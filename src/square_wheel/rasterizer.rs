@@ -1,5 +1,5 @@
-use super::{abstract_color::*, equations::*, fast_math::*};
-use crate::common::{fixed_math::*, system_window};
+use super::{abstract_color::*, cpu_features::*, equations::*, fast_math::*, fog::*, surfaces, textures};
+use crate::common::{color::*, fixed_math::*, math_types::*, system_window};
 
 #[derive(Copy, Clone, PartialEq, Eq)]
 pub enum TetureCoordinatesInterpolationMode
@@ -21,29 +21,87 @@ const BLENDING_MODE_ADDITIVE: usize = 2;
 const BLENDING_MODE_ALPHA_TEST: usize = 3;
 const BLENDING_MODE_ALPHA_BLEND: usize = 4;
 
+#[derive(Copy, Clone, PartialEq, Eq)]
+pub enum TextureFilteringMode
+{
+	Nearest,
+	// Add per-pixel offset to texture coordinates in 2x2 pattern. Looks similar to bilinear filtering, but is much cheaper.
+	Dithered,
+	Bilinear,
+}
+
+impl TextureFilteringMode
+{
+	pub fn from_config_value(value: u32) -> Self
+	{
+		match value
+		{
+			0 => TextureFilteringMode::Nearest,
+			1 => TextureFilteringMode::Dithered,
+			_ => TextureFilteringMode::Bilinear,
+		}
+	}
+}
+
+const TEXTURE_FILTERING_MODE_NEAREST: usize = 0;
+const TEXTURE_FILTERING_MODE_DITHERED: usize = 1;
+const TEXTURE_FILTERING_MODE_BILINEAR: usize = 2;
+
 pub struct Rasterizer<'a, ColorT: AbstractColor>
 {
 	color_buffer: &'a mut [ColorT],
 	row_size: i32,
+	// Inverse depth of opaque and alpha-tested pixels. Empty if depth output is not needed.
+	depth_buffer: &'a mut [f32],
+	depth_row_size: i32,
 	clip_rect: ClipRect,
+	texture_filtering_mode: TextureFilteringMode,
+	// Fog for triangles. Polygons surfaces contain fog already.
+	fog: ObjectFog,
 }
 
 impl<'a, ColorT: AbstractColor> Rasterizer<'a, ColorT>
 {
-	pub fn new(color_buffer: &'a mut [ColorT], surface_info: &system_window::SurfaceInfo, clip_rect: ClipRect) -> Self
+	pub fn new(
+		color_buffer: &'a mut [ColorT],
+		depth_buffer: &'a mut [f32],
+		surface_info: &system_window::SurfaceInfo,
+		clip_rect: ClipRect,
+		texture_filtering_mode: TextureFilteringMode,
+	) -> Self
 	{
 		Rasterizer {
 			color_buffer,
 			row_size: (surface_info.pitch) as i32,
+			depth_buffer,
+			depth_row_size: surface_info.width as i32,
 			clip_rect,
+			texture_filtering_mode,
+			fog: ObjectFog::default(),
 		}
 	}
 
+	// Set fog for following triangles.
+	pub fn set_fog(&mut self, fog: &ObjectFog)
+	{
+		self.fog = *fog;
+	}
+
+	// Depth is written only for opaque and alpha-tested pixels.
+	fn is_depth_write_needed<const BLENDING_MODE: usize>(&self) -> bool
+	{
+		(BLENDING_MODE == BLENDING_MODE_NONE || BLENDING_MODE == BLENDING_MODE_ALPHA_TEST) &&
+			!self.depth_buffer.is_empty()
+	}
+
 	// Fill convex clockwise polygon.
+	// "depth_equation" is used for texture coordinates interpolation and may be scaled.
+	// "depth_write_equation" is used for depth buffer writing.
 	pub fn fill_polygon(
 		&mut self,
 		vertices: &[PolygonPointProjected],
 		depth_equation: &DepthEquation,
+		depth_write_equation: &DepthEquation,
 		tex_coord_equation: &TexCoordEquation,
 		texture_info: &TextureInfo,
 		texture_data: &[ColorT],
@@ -58,6 +116,7 @@ impl<'a, ColorT: AbstractColor> Rasterizer<'a, ColorT>
 				self.fill_polygon_impl_1_static_params::<TEXTURE_COORDINATES_INTERPOLATION_MODE_FULL_PERSPECTIVE>(
 					vertices,
 					depth_equation,
+					depth_write_equation,
 					tex_coord_equation,
 					texture_info,
 					texture_data,
@@ -69,6 +128,7 @@ impl<'a, ColorT: AbstractColor> Rasterizer<'a, ColorT>
 				self.fill_polygon_impl_1_static_params::<TEXTURE_COORDINATES_INTERPOLATION_MODE_LINE_Z_CORRECTION>(
 					vertices,
 					depth_equation,
+					depth_write_equation,
 					tex_coord_equation,
 					texture_info,
 					texture_data,
@@ -79,6 +139,7 @@ impl<'a, ColorT: AbstractColor> Rasterizer<'a, ColorT>
 				.fill_polygon_impl_1_static_params::<TEXTURE_COORDINATES_INTERPOLATION_MODE_FULL_AFFINE>(
 					vertices,
 					depth_equation,
+					depth_write_equation,
 					tex_coord_equation,
 					texture_info,
 					texture_data,
@@ -91,6 +152,7 @@ impl<'a, ColorT: AbstractColor> Rasterizer<'a, ColorT>
 		&mut self,
 		vertices: &[PolygonPointProjected],
 		depth_equation: &DepthEquation,
+		depth_write_equation: &DepthEquation,
 		tex_coord_equation: &TexCoordEquation,
 		texture_info: &TextureInfo,
 		texture_data: &[ColorT],
@@ -103,6 +165,7 @@ impl<'a, ColorT: AbstractColor> Rasterizer<'a, ColorT>
 				.fill_polygon_impl_2_static_params::<TEXTURE_COORDINATES_INTERPOLATION_MODE, BLENDING_MODE_NONE>(
 					vertices,
 					depth_equation,
+					depth_write_equation,
 					tex_coord_equation,
 					texture_info,
 					texture_data,
@@ -111,6 +174,7 @@ impl<'a, ColorT: AbstractColor> Rasterizer<'a, ColorT>
 				.fill_polygon_impl_2_static_params::<TEXTURE_COORDINATES_INTERPOLATION_MODE, BLENDING_MODE_AVERAGE>(
 					vertices,
 					depth_equation,
+					depth_write_equation,
 					tex_coord_equation,
 					texture_info,
 					texture_data,
@@ -119,6 +183,7 @@ impl<'a, ColorT: AbstractColor> Rasterizer<'a, ColorT>
 				.fill_polygon_impl_2_static_params::<TEXTURE_COORDINATES_INTERPOLATION_MODE, BLENDING_MODE_ADDITIVE>(
 					vertices,
 					depth_equation,
+					depth_write_equation,
 					tex_coord_equation,
 					texture_info,
 					texture_data,
@@ -127,6 +192,7 @@ impl<'a, ColorT: AbstractColor> Rasterizer<'a, ColorT>
 				.fill_polygon_impl_2_static_params::<TEXTURE_COORDINATES_INTERPOLATION_MODE, BLENDING_MODE_ALPHA_TEST>(
 					vertices,
 					depth_equation,
+					depth_write_equation,
 					tex_coord_equation,
 					texture_info,
 					texture_data,
@@ -135,6 +201,7 @@ impl<'a, ColorT: AbstractColor> Rasterizer<'a, ColorT>
 				.fill_polygon_impl_2_static_params::<TEXTURE_COORDINATES_INTERPOLATION_MODE, BLENDING_MODE_ALPHA_BLEND>(
 					vertices,
 					depth_equation,
+					depth_write_equation,
 					tex_coord_equation,
 					texture_info,
 					texture_data,
@@ -149,6 +216,76 @@ impl<'a, ColorT: AbstractColor> Rasterizer<'a, ColorT>
 		&mut self,
 		vertices: &[PolygonPointProjected],
 		depth_equation: &DepthEquation,
+		depth_write_equation: &DepthEquation,
+		tex_coord_equation: &TexCoordEquation,
+		texture_info: &TextureInfo,
+		texture_data: &[ColorT],
+	)
+	{
+		match self.texture_filtering_mode
+		{
+			TextureFilteringMode::Nearest => self.fill_polygon_impl_3_static_params::<
+				TEXTURE_COORDINATES_INTERPOLATION_MODE,
+				BLENDING_MODE,
+				TEXTURE_FILTERING_MODE_NEAREST,
+			>(vertices, depth_equation, depth_write_equation, tex_coord_equation, texture_info, texture_data),
+			TextureFilteringMode::Dithered => self.fill_polygon_impl_3_static_params::<
+				TEXTURE_COORDINATES_INTERPOLATION_MODE,
+				BLENDING_MODE,
+				TEXTURE_FILTERING_MODE_DITHERED,
+			>(vertices, depth_equation, depth_write_equation, tex_coord_equation, texture_info, texture_data),
+			TextureFilteringMode::Bilinear => self.fill_polygon_impl_3_static_params::<
+				TEXTURE_COORDINATES_INTERPOLATION_MODE,
+				BLENDING_MODE,
+				TEXTURE_FILTERING_MODE_BILINEAR,
+			>(vertices, depth_equation, depth_write_equation, tex_coord_equation, texture_info, texture_data),
+		}
+	}
+
+	fn fill_polygon_impl_3_static_params<
+		const TEXTURE_COORDINATES_INTERPOLATION_MODE: usize,
+		const BLENDING_MODE: usize,
+		const TEXTURE_FILTERING_MODE: usize,
+	>(
+		&mut self,
+		vertices: &[PolygonPointProjected],
+		depth_equation: &DepthEquation,
+		depth_write_equation: &DepthEquation,
+		tex_coord_equation: &TexCoordEquation,
+		texture_info: &TextureInfo,
+		texture_data: &[ColorT],
+	)
+	{
+		call_with_simd_level!(
+			SIMD_LEVEL,
+			self.fill_polygon_impl_4::<
+				TEXTURE_COORDINATES_INTERPOLATION_MODE,
+				BLENDING_MODE,
+				TEXTURE_FILTERING_MODE,
+				SIMD_LEVEL,
+			>(
+				vertices,
+				depth_equation,
+				depth_write_equation,
+				tex_coord_equation,
+				texture_info,
+				texture_data,
+			)
+		)
+	}
+
+	// Should be inlined into SIMD level entry function in order to be compiled with its target features.
+	#[inline(always)]
+	fn fill_polygon_impl_4<
+		const TEXTURE_COORDINATES_INTERPOLATION_MODE: usize,
+		const BLENDING_MODE: usize,
+		const TEXTURE_FILTERING_MODE: usize,
+		const SIMD_LEVEL: u32,
+	>(
+		&mut self,
+		vertices: &[PolygonPointProjected],
+		depth_equation: &DepthEquation,
+		depth_write_equation: &DepthEquation,
 		tex_coord_equation: &TexCoordEquation,
 		texture_info: &TextureInfo,
 		texture_data: &[ColorT],
@@ -156,13 +293,19 @@ impl<'a, ColorT: AbstractColor> Rasterizer<'a, ColorT>
 	{
 		let draw_func = match TEXTURE_COORDINATES_INTERPOLATION_MODE
 		{
-			TEXTURE_COORDINATES_INTERPOLATION_MODE_FULL_PERSPECTIVE => Self::fill_polygon_part::<BLENDING_MODE>,
+			TEXTURE_COORDINATES_INTERPOLATION_MODE_FULL_PERSPECTIVE =>
+			{
+				Self::fill_polygon_part::<BLENDING_MODE, TEXTURE_FILTERING_MODE, SIMD_LEVEL>
+			},
 			TEXTURE_COORDINATES_INTERPOLATION_MODE_LINE_Z_CORRECTION =>
 			{
-				Self::fill_polygon_part_line_z_corrected::<BLENDING_MODE>
+				Self::fill_polygon_part_line_z_corrected::<BLENDING_MODE, TEXTURE_FILTERING_MODE, SIMD_LEVEL>
 			},
-			TEXTURE_COORDINATES_INTERPOLATION_MODE_FULL_AFFINE => Self::fill_polygon_part_affine::<BLENDING_MODE>,
-			_ => Self::fill_polygon_part::<BLENDING_MODE>,
+			TEXTURE_COORDINATES_INTERPOLATION_MODE_FULL_AFFINE =>
+			{
+				Self::fill_polygon_part_affine::<BLENDING_MODE, TEXTURE_FILTERING_MODE, SIMD_LEVEL>
+			},
+			_ => Self::fill_polygon_part::<BLENDING_MODE, TEXTURE_FILTERING_MODE, SIMD_LEVEL>,
 		};
 
 		// Search for start vertex (with min y).
@@ -220,6 +363,7 @@ impl<'a, ColorT: AbstractColor> Rasterizer<'a, ColorT>
 						dx_dy: dx_dy_right,
 					},
 					depth_equation,
+					depth_write_equation,
 					tex_coord_equation,
 					texture_info,
 					texture_data,
@@ -254,6 +398,7 @@ impl<'a, ColorT: AbstractColor> Rasterizer<'a, ColorT>
 							dx_dy: 0,
 						},
 						depth_equation,
+						depth_write_equation,
 						tex_coord_equation,
 						texture_info,
 						texture_data,
@@ -278,13 +423,15 @@ impl<'a, ColorT: AbstractColor> Rasterizer<'a, ColorT>
 		}
 	}
 
-	fn fill_polygon_part<const BLENDING_MODE: usize>(
+	#[inline(always)]
+	fn fill_polygon_part<const BLENDING_MODE: usize, const TEXTURE_FILTERING_MODE: usize, const SIMD_LEVEL: u32>(
 		&mut self,
 		y_start: Fixed16,
 		y_end: Fixed16,
 		left_side: PolygonSide,
 		right_side: PolygonSide,
 		depth_equation: &DepthEquation,
+		depth_write_equation: &DepthEquation,
 		tex_coord_equation: &TexCoordEquation,
 		texture_info: &TextureInfo,
 		texture_data: &[ColorT],
@@ -292,6 +439,8 @@ impl<'a, ColorT: AbstractColor> Rasterizer<'a, ColorT>
 	{
 		debug_assert!(texture_data.len() >= (texture_info.size[0] * texture_info.size[1]) as usize);
 
+		let write_depth = self.is_depth_write_needed::<BLENDING_MODE>();
+
 		let d_inv_z_dx = unchecked_to_int64(INV_Z_SCALE * depth_equation.d_inv_z_dx);
 		let d_inv_z_dy = unchecked_to_int64(INV_Z_SCALE * depth_equation.d_inv_z_dy);
 		// Add extra 0.5 to shift to pixel center.
@@ -429,6 +578,11 @@ impl<'a, ColorT: AbstractColor> Rasterizer<'a, ColorT>
 					}
 				}
 
+				let depth_line_offset = y_int * self.depth_row_size;
+				let mut pixel_inv_z =
+					depth_write_equation.sample_point(&Vec2f::new((x_start_int as f32) + 0.5, (y_int as f32) + 0.5));
+
+				let mut x_int = x_start_int;
 				for dst_pixel in line_dst
 				{
 					debug_assert!(span_tc[0] >= 0);
@@ -436,17 +590,46 @@ impl<'a, ColorT: AbstractColor> Rasterizer<'a, ColorT>
 					debug_assert!(span_inv_z >= (1 << INV_Z_PRE_SHIFT));
 
 					let z = unchecked_div(1 << Z_CALC_SHIFT, (span_inv_z as u32) >> INV_Z_PRE_SHIFT);
-					let pix_tc = [
-						(((z as u64) * (span_tc[0] as u64)) >> TC_FINAL_SHIFT) as u32,
-						(((z as u64) * (span_tc[1] as u64)) >> TC_FINAL_SHIFT) as u32,
-					];
-
-					debug_assert!(pix_tc[0] <= texture_size_minus_one[0] as u32);
-					debug_assert!(pix_tc[1] <= texture_size_minus_one[1] as u32);
-					let texel_address = (pix_tc[0] + pix_tc[1] * texture_width) as usize;
-					let texel = unchecked_texture_fetch(texture_data, texel_address);
-					write_into_framebuffer::<ColorT, BLENDING_MODE>(dst_pixel, texel);
+					let texel = if TEXTURE_FILTERING_MODE == TEXTURE_FILTERING_MODE_NEAREST
+					{
+						let pix_tc = [
+							(((z as u64) * (span_tc[0] as u64)) >> TC_FINAL_SHIFT) as u32,
+							(((z as u64) * (span_tc[1] as u64)) >> TC_FINAL_SHIFT) as u32,
+						];
+
+						debug_assert!(pix_tc[0] <= texture_size_minus_one[0] as u32);
+						debug_assert!(pix_tc[1] <= texture_size_minus_one[1] as u32);
+						let texel_address = (pix_tc[0] + pix_tc[1] * texture_width) as usize;
+						unchecked_texture_fetch(texture_data, texel_address)
+					}
+					else
+					{
+						// Preserve fractional part of texture coordinates for filtering.
+						let pix_tc = [
+							(((z as u64) * (span_tc[0] as u64)) >> (TC_FINAL_SHIFT - (FIXED16_BASE as i64))) as i32,
+							(((z as u64) * (span_tc[1] as u64)) >> (TC_FINAL_SHIFT - (FIXED16_BASE as i64))) as i32,
+						];
+						fetch_texel_filtered::<ColorT, TEXTURE_FILTERING_MODE, SIMD_LEVEL>(
+							texture_data,
+							&texture_info.size,
+							pix_tc,
+							x_int,
+							y_int,
+						)
+					};
+					write_into_framebuffer::<ColorT, BLENDING_MODE, SIMD_LEVEL>(dst_pixel, texel);
+					if write_depth
+					{
+						write_into_depth_buffer::<ColorT, BLENDING_MODE>(
+							self.depth_buffer,
+							(x_int + depth_line_offset) as usize,
+							pixel_inv_z,
+							texel,
+						);
+					}
 
+					x_int += 1;
+					pixel_inv_z += depth_write_equation.d_inv_z_dx;
 					span_inv_z += span_d_inv_z;
 					span_tc[0] += span_d_tc[0];
 					span_tc[1] += span_d_tc[1];
@@ -461,13 +644,19 @@ impl<'a, ColorT: AbstractColor> Rasterizer<'a, ColorT>
 		} // for lines
 	}
 
-	fn fill_polygon_part_line_z_corrected<const BLENDING_MODE: usize>(
+	#[inline(always)]
+	fn fill_polygon_part_line_z_corrected<
+		const BLENDING_MODE: usize,
+		const TEXTURE_FILTERING_MODE: usize,
+		const SIMD_LEVEL: u32,
+	>(
 		&mut self,
 		y_start: Fixed16,
 		y_end: Fixed16,
 		left_side: PolygonSide,
 		right_side: PolygonSide,
 		depth_equation: &DepthEquation,
+		depth_write_equation: &DepthEquation,
 		tex_coord_equation: &TexCoordEquation,
 		texture_info: &TextureInfo,
 		texture_data: &[ColorT],
@@ -475,6 +664,8 @@ impl<'a, ColorT: AbstractColor> Rasterizer<'a, ColorT>
 	{
 		debug_assert!(texture_data.len() >= (texture_info.size[0] * texture_info.size[1]) as usize);
 
+		let write_depth = self.is_depth_write_needed::<BLENDING_MODE>();
+
 		const LINE_TC_SHIFT: i64 = 16;
 
 		let d_inv_z_dx = unchecked_to_int64(INV_Z_SCALE * depth_equation.d_inv_z_dx);
@@ -563,18 +754,50 @@ impl<'a, ColorT: AbstractColor> Rasterizer<'a, ColorT>
 					}
 				}
 
+				let depth_line_offset = y_int * self.depth_row_size;
+				let mut pixel_inv_z =
+					depth_write_equation.sample_point(&Vec2f::new((x_start_int as f32) + 0.5, (y_int as f32) + 0.5));
+
+				let mut x_int = x_start_int;
 				for dst_pixel in line_dst
 				{
-					let tc_int = [span_tc[0] >> LINE_TC_SHIFT, span_tc[1] >> LINE_TC_SHIFT];
-					debug_assert!(tc_int[0] >= 0);
-					debug_assert!(tc_int[1] >= 0);
-					debug_assert!(tc_int[0] < texture_info.size[0] as i32);
-					debug_assert!(tc_int[1] < texture_info.size[1] as i32);
-
-					let texel_address = ((tc_int[0] as u32) + (tc_int[1] as u32) * texture_width) as usize;
-					let texel = unchecked_texture_fetch(texture_data, texel_address);
-					write_into_framebuffer::<ColorT, BLENDING_MODE>(dst_pixel, texel);
+					let texel = if TEXTURE_FILTERING_MODE == TEXTURE_FILTERING_MODE_NEAREST
+					{
+						let tc_int = [span_tc[0] >> LINE_TC_SHIFT, span_tc[1] >> LINE_TC_SHIFT];
+						debug_assert!(tc_int[0] >= 0);
+						debug_assert!(tc_int[1] >= 0);
+						debug_assert!(tc_int[0] < texture_info.size[0] as i32);
+						debug_assert!(tc_int[1] < texture_info.size[1] as i32);
+
+						let texel_address = ((tc_int[0] as u32) + (tc_int[1] as u32) * texture_width) as usize;
+						unchecked_texture_fetch(texture_data, texel_address)
+					}
+					else
+					{
+						fetch_texel_filtered::<ColorT, TEXTURE_FILTERING_MODE, SIMD_LEVEL>(
+							texture_data,
+							&texture_info.size,
+							[
+								span_tc[0] >> (LINE_TC_SHIFT - (FIXED16_BASE as i64)),
+								span_tc[1] >> (LINE_TC_SHIFT - (FIXED16_BASE as i64)),
+							],
+							x_int,
+							y_int,
+						)
+					};
+					write_into_framebuffer::<ColorT, BLENDING_MODE, SIMD_LEVEL>(dst_pixel, texel);
+					if write_depth
+					{
+						write_into_depth_buffer::<ColorT, BLENDING_MODE>(
+							self.depth_buffer,
+							(x_int + depth_line_offset) as usize,
+							pixel_inv_z,
+							texel,
+						);
+					}
 
+					x_int += 1;
+					pixel_inv_z += depth_write_equation.d_inv_z_dx;
 					span_tc[0] += span_d_tc[0];
 					span_tc[1] += span_d_tc[1];
 				} // for span pixels
@@ -588,13 +811,19 @@ impl<'a, ColorT: AbstractColor> Rasterizer<'a, ColorT>
 		} // for lines
 	}
 
-	fn fill_polygon_part_affine<const BLENDING_MODE: usize>(
+	#[inline(always)]
+	fn fill_polygon_part_affine<
+		const BLENDING_MODE: usize,
+		const TEXTURE_FILTERING_MODE: usize,
+		const SIMD_LEVEL: u32,
+	>(
 		&mut self,
 		y_start: Fixed16,
 		y_end: Fixed16,
 		left_side: PolygonSide,
 		right_side: PolygonSide,
 		depth_equation: &DepthEquation,
+		depth_write_equation: &DepthEquation,
 		tex_coord_equation: &TexCoordEquation,
 		texture_info: &TextureInfo,
 		texture_data: &[ColorT],
@@ -602,6 +831,8 @@ impl<'a, ColorT: AbstractColor> Rasterizer<'a, ColorT>
 	{
 		debug_assert!(texture_data.len() >= (texture_info.size[0] * texture_info.size[1]) as usize);
 
+		let write_depth = self.is_depth_write_needed::<BLENDING_MODE>();
+
 		let y_start_f32 = fixed16_to_f32(y_start);
 		let y_end_f32 = fixed16_to_f32(y_end);
 		let y_delta = y_end - y_start;
@@ -710,18 +941,47 @@ impl<'a, ColorT: AbstractColor> Rasterizer<'a, ColorT>
 					fixed16_div(tc_right[1] - tc_left[1], x_delta_for_tc_interpolation),
 				];
 
+				let depth_line_offset = y_int * self.depth_row_size;
+				let mut pixel_inv_z =
+					depth_write_equation.sample_point(&Vec2f::new((x_start_int as f32) + 0.5, (y_int as f32) + 0.5));
+
+				let mut x_int = x_start_int;
 				for dst_pixel in line_dst
 				{
-					// TODO - use unsigned shift for conversion to int?
-					let tc_int = [fixed16_floor_to_int(tc[0]), fixed16_floor_to_int(tc[1])];
-					debug_assert!(tc_int[0] >= 0);
-					debug_assert!(tc_int[1] >= 0);
-					debug_assert!(tc_int[0] < texture_info.size[0] as i32);
-					debug_assert!(tc_int[1] < texture_info.size[1] as i32);
-					let texel_address = (tc_int[0] + tc_int[1] * texture_info.size[0]) as usize;
-					let texel = unchecked_texture_fetch(texture_data, texel_address);
-					write_into_framebuffer::<ColorT, BLENDING_MODE>(dst_pixel, texel);
+					let texel = if TEXTURE_FILTERING_MODE == TEXTURE_FILTERING_MODE_NEAREST
+					{
+						// TODO - use unsigned shift for conversion to int?
+						let tc_int = [fixed16_floor_to_int(tc[0]), fixed16_floor_to_int(tc[1])];
+						debug_assert!(tc_int[0] >= 0);
+						debug_assert!(tc_int[1] >= 0);
+						debug_assert!(tc_int[0] < texture_info.size[0] as i32);
+						debug_assert!(tc_int[1] < texture_info.size[1] as i32);
+						let texel_address = (tc_int[0] + tc_int[1] * texture_info.size[0]) as usize;
+						unchecked_texture_fetch(texture_data, texel_address)
+					}
+					else
+					{
+						fetch_texel_filtered::<ColorT, TEXTURE_FILTERING_MODE, SIMD_LEVEL>(
+							texture_data,
+							&texture_info.size,
+							tc,
+							x_int,
+							y_int,
+						)
+					};
+					write_into_framebuffer::<ColorT, BLENDING_MODE, SIMD_LEVEL>(dst_pixel, texel);
+					if write_depth
+					{
+						write_into_depth_buffer::<ColorT, BLENDING_MODE>(
+							self.depth_buffer,
+							(x_int + depth_line_offset) as usize,
+							pixel_inv_z,
+							texel,
+						);
+					}
 
+					x_int += 1;
+					pixel_inv_z += depth_write_equation.d_inv_z_dx;
 					tc[0] += d_tc[0];
 					tc[1] += d_tc[1];
 				} // for span pixels
@@ -744,25 +1004,41 @@ impl<'a, ColorT: AbstractColor> Rasterizer<'a, ColorT>
 		blending_mode: BlendingMode,
 	)
 	{
+		// Apply fog scale to vertex light. Fog color is added later for each pixel.
+		let mut vertices_fogged = *vertices;
+		if self.fog.scale < 1.0
+		{
+			let scale = f32_to_fixed16(self.fog.scale);
+			for v in &mut vertices_fogged
+			{
+				for light_component in &mut v.light
+				{
+					*light_component = fixed16_mul(*light_component, scale);
+				}
+			}
+		}
+		let vertices = &vertices_fogged;
+
 		match blending_mode
 		{
 			BlendingMode::None =>
 			{
-				self.fill_triangle_impl::<TextureColorT, BLENDING_MODE_NONE>(vertices, texture_info, texture_data)
+				self.fill_triangle_impl_1::<TextureColorT, BLENDING_MODE_NONE>(vertices, texture_info, texture_data)
 			},
 			BlendingMode::Average =>
 			{
-				self.fill_triangle_impl::<TextureColorT, BLENDING_MODE_AVERAGE>(vertices, texture_info, texture_data)
+				self.fill_triangle_impl_1::<TextureColorT, BLENDING_MODE_AVERAGE>(vertices, texture_info, texture_data)
 			},
 			BlendingMode::Additive =>
 			{
-				self.fill_triangle_impl::<TextureColorT, BLENDING_MODE_ADDITIVE>(vertices, texture_info, texture_data)
+				self.fill_triangle_impl_1::<TextureColorT, BLENDING_MODE_ADDITIVE>(vertices, texture_info, texture_data)
 			},
-			BlendingMode::AlphaTest =>
-			{
-				self.fill_triangle_impl::<TextureColorT, BLENDING_MODE_ALPHA_TEST>(vertices, texture_info, texture_data)
-			},
-			BlendingMode::AlphaBlend => self.fill_triangle_impl::<TextureColorT, BLENDING_MODE_ALPHA_BLEND>(
+			BlendingMode::AlphaTest => self.fill_triangle_impl_1::<TextureColorT, BLENDING_MODE_ALPHA_TEST>(
+				vertices,
+				texture_info,
+				texture_data,
+			),
+			BlendingMode::AlphaBlend => self.fill_triangle_impl_1::<TextureColorT, BLENDING_MODE_ALPHA_BLEND>(
 				vertices,
 				texture_info,
 				texture_data,
@@ -770,7 +1046,65 @@ impl<'a, ColorT: AbstractColor> Rasterizer<'a, ColorT>
 		}
 	}
 
-	pub fn fill_triangle_impl<TextureColorT: AbstractColor, const BLENDING_MODE: usize>(
+	fn fill_triangle_impl_1<TextureColorT: AbstractColor, const BLENDING_MODE: usize>(
+		&mut self,
+		vertices: &[TrianglePointProjected; 3],
+		texture_info: &TextureInfo,
+		texture_data: &[TextureColorT],
+	)
+	{
+		match self.texture_filtering_mode
+		{
+			TextureFilteringMode::Nearest => self
+				.fill_triangle_impl::<TextureColorT, BLENDING_MODE, TEXTURE_FILTERING_MODE_NEAREST>(
+					vertices,
+					texture_info,
+					texture_data,
+				),
+			TextureFilteringMode::Dithered => self
+				.fill_triangle_impl::<TextureColorT, BLENDING_MODE, TEXTURE_FILTERING_MODE_DITHERED>(
+					vertices,
+					texture_info,
+					texture_data,
+				),
+			TextureFilteringMode::Bilinear => self
+				.fill_triangle_impl::<TextureColorT, BLENDING_MODE, TEXTURE_FILTERING_MODE_BILINEAR>(
+					vertices,
+					texture_info,
+					texture_data,
+				),
+		}
+	}
+
+	pub fn fill_triangle_impl<
+		TextureColorT: AbstractColor,
+		const BLENDING_MODE: usize,
+		const TEXTURE_FILTERING_MODE: usize,
+	>(
+		&mut self,
+		vertices: &[TrianglePointProjected; 3],
+		texture_info: &TextureInfo,
+		texture_data: &[TextureColorT],
+	)
+	{
+		call_with_simd_level!(
+			SIMD_LEVEL,
+			self.fill_triangle_impl_2::<TextureColorT, BLENDING_MODE, TEXTURE_FILTERING_MODE, SIMD_LEVEL>(
+				vertices,
+				texture_info,
+				texture_data,
+			)
+		)
+	}
+
+	// Should be inlined into SIMD level entry function in order to be compiled with its target features.
+	#[inline(always)]
+	fn fill_triangle_impl_2<
+		TextureColorT: AbstractColor,
+		const BLENDING_MODE: usize,
+		const TEXTURE_FILTERING_MODE: usize,
+		const SIMD_LEVEL: u32,
+	>(
 		&mut self,
 		vertices: &[TrianglePointProjected; 3],
 		texture_info: &TextureInfo,
@@ -802,6 +1136,8 @@ impl<'a, ColorT: AbstractColor> Rasterizer<'a, ColorT>
 		let lower_vertex = &vertices[lower_index];
 		let middle_vertex = &vertices[middle_index];
 
+		let depth_write_equation = get_triangle_depth_equation(vertices);
+
 		// Use hack with miminun dy to avoid division by zero and overflows.
 		let long_edge_dy = (upper_vertex.y - lower_vertex.y).max(FIXED16_HALF);
 		let lower_part_dy = (middle_vertex.y - lower_vertex.y).max(FIXED16_HALF);
@@ -865,7 +1201,7 @@ impl<'a, ColorT: AbstractColor> Rasterizer<'a, ColorT>
 				d_light_dx[i] = fixed16_div(long_edge_light_in_middle[i] - middle_vertex.light[i], middle_dx);
 			}
 
-			self.fill_triangle_part::<TextureColorT, BLENDING_MODE>(
+			self.fill_triangle_part::<TextureColorT, BLENDING_MODE, TEXTURE_FILTERING_MODE, SIMD_LEVEL>(
 				lower_vertex.y,
 				middle_vertex.y,
 				PolygonSide {
@@ -882,10 +1218,11 @@ impl<'a, ColorT: AbstractColor> Rasterizer<'a, ColorT>
 				lower_vertex.light,
 				d_light_dy_lower,
 				d_light_dx,
+				&depth_write_equation,
 				texture_info,
 				texture_data,
 			);
-			self.fill_triangle_part::<TextureColorT, BLENDING_MODE>(
+			self.fill_triangle_part::<TextureColorT, BLENDING_MODE, TEXTURE_FILTERING_MODE, SIMD_LEVEL>(
 				middle_vertex.y,
 				upper_vertex.y,
 				PolygonSide {
@@ -902,6 +1239,7 @@ impl<'a, ColorT: AbstractColor> Rasterizer<'a, ColorT>
 				middle_vertex.light,
 				d_light_dy_upper,
 				d_light_dx,
+				&depth_write_equation,
 				texture_info,
 				texture_data,
 			);
@@ -932,7 +1270,7 @@ impl<'a, ColorT: AbstractColor> Rasterizer<'a, ColorT>
 				d_light_dx[i] = fixed16_div(middle_vertex.light[i] - long_edge_light_in_middle[i], middle_dx);
 			}
 
-			self.fill_triangle_part::<TextureColorT, BLENDING_MODE>(
+			self.fill_triangle_part::<TextureColorT, BLENDING_MODE, TEXTURE_FILTERING_MODE, SIMD_LEVEL>(
 				lower_vertex.y,
 				middle_vertex.y,
 				PolygonSide {
@@ -949,10 +1287,11 @@ impl<'a, ColorT: AbstractColor> Rasterizer<'a, ColorT>
 				lower_vertex.light,
 				long_edge_d_light_dy,
 				d_light_dx,
+				&depth_write_equation,
 				texture_info,
 				texture_data,
 			);
-			self.fill_triangle_part::<TextureColorT, BLENDING_MODE>(
+			self.fill_triangle_part::<TextureColorT, BLENDING_MODE, TEXTURE_FILTERING_MODE, SIMD_LEVEL>(
 				middle_vertex.y,
 				upper_vertex.y,
 				PolygonSide {
@@ -969,6 +1308,7 @@ impl<'a, ColorT: AbstractColor> Rasterizer<'a, ColorT>
 				long_edge_light_in_middle,
 				long_edge_d_light_dy,
 				d_light_dx,
+				&depth_write_equation,
 				texture_info,
 				texture_data,
 			);
@@ -990,7 +1330,13 @@ impl<'a, ColorT: AbstractColor> Rasterizer<'a, ColorT>
 		}
 	}
 
-	fn fill_triangle_part<TextureColorT: AbstractColor, const BLENDING_MODE: usize>(
+	#[inline(always)]
+	fn fill_triangle_part<
+		TextureColorT: AbstractColor,
+		const BLENDING_MODE: usize,
+		const TEXTURE_FILTERING_MODE: usize,
+		const SIMD_LEVEL: u32,
+	>(
 		&mut self,
 		y_start: Fixed16,
 		y_end: Fixed16,
@@ -1002,6 +1348,7 @@ impl<'a, ColorT: AbstractColor> Rasterizer<'a, ColorT>
 		light_start_left: [Fixed16; 3],
 		mut d_light_left: [Fixed16; 3],
 		mut d_light_dx: [Fixed16; 3],
+		depth_write_equation: &DepthEquation,
 		texture_info: &TextureInfo,
 		texture_data: &[TextureColorT],
 	)
@@ -1200,7 +1547,20 @@ impl<'a, ColorT: AbstractColor> Rasterizer<'a, ColorT>
 			);
 		}
 
-		let d_light_dx_vec = ColorVecI::from_color_i32x3(&d_light_dx);
+		let d_light_dx_vec = SimdColorVecI::<SIMD_LEVEL>::from_color_i32x3(&d_light_dx);
+
+		let write_depth = self.is_depth_write_needed::<BLENDING_MODE>();
+
+		// Additive triangles are just darkened by fog.
+		// Zero alpha in order to preserve alpha of texels.
+		let fog_add = if BLENDING_MODE == BLENDING_MODE_ADDITIVE
+		{
+			SimdColorVecI::<SIMD_LEVEL>::zero()
+		}
+		else
+		{
+			SimdColorVecI::<SIMD_LEVEL>::from_color_f32x3(&self.fog.add)
+		};
 
 		for y_int in y_start_int .. y_end_int
 		{
@@ -1216,7 +1576,7 @@ impl<'a, ColorT: AbstractColor> Rasterizer<'a, ColorT>
 					tc_left[1] + fixed16_mul(x_start_delta, d_tc_dx[1]),
 				];
 
-				let mut line_light = ColorVecI::from_color_i32x3(&[
+				let mut line_light = SimdColorVecI::<SIMD_LEVEL>::from_color_i32x3(&[
 					light_left[0] + fixed16_mul(x_start_delta, d_light_dx[0]),
 					light_left[1] + fixed16_mul(x_start_delta, d_light_dx[1]),
 					light_left[2] + fixed16_mul(x_start_delta, d_light_dx[2]),
@@ -1229,41 +1589,62 @@ impl<'a, ColorT: AbstractColor> Rasterizer<'a, ColorT>
 					(x_end_int + line_buffer_offset) as usize,
 				);
 
+				let depth_line_offset = y_int * self.depth_row_size;
+				let mut pixel_inv_z =
+					depth_write_equation.sample_point(&Vec2f::new((x_start_int as f32) + 0.5, (y_int as f32) + 0.5));
+
+				let mut x_int = x_start_int;
 				for dst_pixel in line_dst
 				{
 					debug_assert!(line_light.extract::<0>() >= 0);
 					debug_assert!(line_light.extract::<1>() >= 0);
 					debug_assert!(line_light.extract::<2>() >= 0);
 
-					let u = fixed16_floor_to_int(line_tc[0]);
-					let v = fixed16_floor_to_int(line_tc[1]);
-					debug_assert!(u >= 0);
-					debug_assert!(u < texture_info.size[0]);
-					debug_assert!(v >= 0);
-					debug_assert!(v < texture_info.size[1]);
-
-					let texel_address = (u + v * texture_info.size[0]) as usize;
-					let texel = unchecked_texture_fetch(texture_data, texel_address);
-
-					let texel_vec = texel.into();
-					let texel_vec_lighted = ColorVecI::shift_right::<16>(&ColorVecI::mul(&texel_vec, &line_light));
+					let texel = if TEXTURE_FILTERING_MODE == TEXTURE_FILTERING_MODE_NEAREST
+					{
+						let u = fixed16_floor_to_int(line_tc[0]);
+						let v = fixed16_floor_to_int(line_tc[1]);
+						debug_assert!(u >= 0);
+						debug_assert!(u < texture_info.size[0]);
+						debug_assert!(v >= 0);
+						debug_assert!(v < texture_info.size[1]);
+
+						let texel_address = (u + v * texture_info.size[0]) as usize;
+						unchecked_texture_fetch(texture_data, texel_address)
+					}
+					else
+					{
+						fetch_texel_filtered::<TextureColorT, TEXTURE_FILTERING_MODE, SIMD_LEVEL>(
+							texture_data,
+							&texture_info.size,
+							line_tc,
+							x_int,
+							y_int,
+						)
+					};
+
+					let texel_vec = texel.into_color_vec_i::<SIMD_LEVEL>();
+					let texel_vec_lighted = SimdColorVecI::add(
+						&SimdColorVecI::shift_right::<16>(&SimdColorVecI::mul(&texel_vec, &line_light)),
+						&fog_add,
+					);
 
 					// TODO - fix this, remove unnecessary conversions of ColorVecI
 					if BLENDING_MODE == BLENDING_MODE_NONE
 					{
-						*dst_pixel = texel_vec_lighted.into();
+						*dst_pixel = ColorT::from_color_vec_i(texel_vec_lighted);
 					}
 					else if BLENDING_MODE == BLENDING_MODE_AVERAGE
 					{
-						*dst_pixel = ColorT::average(*dst_pixel, texel_vec_lighted.into());
+						*dst_pixel = ColorT::average(*dst_pixel, ColorT::from_color_vec_i(texel_vec_lighted));
 					}
 					else if BLENDING_MODE == BLENDING_MODE_ADDITIVE
 					{
-						*dst_pixel = ColorT::saturated_sum(*dst_pixel, texel_vec_lighted.into());
+						*dst_pixel = ColorT::saturated_sum(*dst_pixel, ColorT::from_color_vec_i(texel_vec_lighted));
 					}
 					else if BLENDING_MODE == BLENDING_MODE_ALPHA_TEST
 					{
-						let texel_converted: ColorT = texel_vec_lighted.into();
+						let texel_converted = ColorT::from_color_vec_i(texel_vec_lighted);
 						if texel_converted.test_alpha()
 						{
 							*dst_pixel = texel_converted;
@@ -1272,19 +1653,31 @@ impl<'a, ColorT: AbstractColor> Rasterizer<'a, ColorT>
 					else if BLENDING_MODE == BLENDING_MODE_ALPHA_BLEND
 					{
 						let alpha = texel.get_alpha();
-						let dst_vec: ColorVecI = (*dst_pixel).into();
-						let blend_result = ColorVecI::shift_right::<8>(&ColorVecI::add(
-							&ColorVecI::mul_scalar(&dst_vec, 255 - alpha),
-							&ColorVecI::mul_scalar(&texel_vec_lighted, alpha),
+						let dst_vec = (*dst_pixel).into_color_vec_i::<SIMD_LEVEL>();
+						let blend_result = SimdColorVecI::shift_right::<8>(&SimdColorVecI::add(
+							&SimdColorVecI::mul_scalar(&dst_vec, 255 - alpha),
+							&SimdColorVecI::mul_scalar(&texel_vec_lighted, alpha),
 						));
-						*dst_pixel = blend_result.into();
+						*dst_pixel = ColorT::from_color_vec_i(blend_result);
+					}
+
+					if write_depth
+					{
+						write_into_depth_buffer::<ColorT, BLENDING_MODE>(
+							self.depth_buffer,
+							(x_int + depth_line_offset) as usize,
+							pixel_inv_z,
+							ColorT::from_color_vec_i(texel_vec_lighted),
+						);
 					}
 
 					for i in 0 .. 2
 					{
 						line_tc[i] += d_tc_dx[i];
 					}
-					line_light = ColorVecI::add(&line_light, &d_light_dx_vec);
+					line_light = SimdColorVecI::add(&line_light, &d_light_dx_vec);
+					x_int += 1;
+					pixel_inv_z += depth_write_equation.d_inv_z_dx;
 				}
 			}
 			x_left += left_side.dx_dy;
@@ -1299,6 +1692,282 @@ impl<'a, ColorT: AbstractColor> Rasterizer<'a, ColorT>
 			}
 		}
 	}
+
+	pub fn fill_triangle_normal_mapped(
+		&mut self,
+		vertices: &[TrianglePointProjectedNormalMapped; 3],
+		texture_info: &TextureInfo,
+		texture: &textures::Texture,
+		blending_mode: BlendingMode,
+	)
+	{
+		match blending_mode
+		{
+			BlendingMode::None =>
+			{
+				self.fill_triangle_normal_mapped_impl_1::<BLENDING_MODE_NONE>(vertices, texture_info, texture)
+			},
+			BlendingMode::Average =>
+			{
+				self.fill_triangle_normal_mapped_impl_1::<BLENDING_MODE_AVERAGE>(vertices, texture_info, texture)
+			},
+			BlendingMode::Additive =>
+			{
+				self.fill_triangle_normal_mapped_impl_1::<BLENDING_MODE_ADDITIVE>(vertices, texture_info, texture)
+			},
+			BlendingMode::AlphaTest =>
+			{
+				self.fill_triangle_normal_mapped_impl_1::<BLENDING_MODE_ALPHA_TEST>(vertices, texture_info, texture)
+			},
+			BlendingMode::AlphaBlend =>
+			{
+				self.fill_triangle_normal_mapped_impl_1::<BLENDING_MODE_ALPHA_BLEND>(vertices, texture_info, texture)
+			},
+		}
+	}
+
+	fn fill_triangle_normal_mapped_impl_1<const BLENDING_MODE: usize>(
+		&mut self,
+		vertices: &[TrianglePointProjectedNormalMapped; 3],
+		texture_info: &TextureInfo,
+		texture: &textures::Texture,
+	)
+	{
+		if texture.has_normal_map
+		{
+			self.fill_triangle_normal_mapped_impl_2::<BLENDING_MODE, true>(vertices, texture_info, texture)
+		}
+		else
+		{
+			self.fill_triangle_normal_mapped_impl_2::<BLENDING_MODE, false>(vertices, texture_info, texture)
+		}
+	}
+
+	fn fill_triangle_normal_mapped_impl_2<const BLENDING_MODE: usize, const USE_NORMAL_MAP: bool>(
+		&mut self,
+		vertices: &[TrianglePointProjectedNormalMapped; 3],
+		texture_info: &TextureInfo,
+		texture: &textures::Texture,
+	)
+	{
+		if texture.has_non_one_roughness
+		{
+			if texture.is_metal
+			{
+				self.fill_triangle_normal_mapped_impl::<BLENDING_MODE, USE_NORMAL_MAP, { surfaces::SPECULAR_TYPE_METAL }>(
+					vertices,
+					texture_info,
+					&texture.pixels,
+					&texture.emissive,
+					texture.emissive_brightness,
+				)
+			}
+			else
+			{
+				self.fill_triangle_normal_mapped_impl::<
+					BLENDING_MODE,
+					USE_NORMAL_MAP,
+					{ surfaces::SPECULAR_TYPE_DIELECTRIC },
+				>(
+					vertices,
+					texture_info,
+					&texture.pixels,
+					&texture.emissive,
+					texture.emissive_brightness,
+				)
+			}
+		}
+		else
+		{
+			self.fill_triangle_normal_mapped_impl::<BLENDING_MODE, USE_NORMAL_MAP, { surfaces::SPECULAR_TYPE_NONE }>(
+				vertices,
+				texture_info,
+				&texture.pixels,
+				&texture.emissive,
+				texture.emissive_brightness,
+			)
+		}
+	}
+
+	fn fill_triangle_normal_mapped_impl<
+		const BLENDING_MODE: usize,
+		const USE_NORMAL_MAP: bool,
+		const SPECULAR_TYPE: u32,
+	>(
+		&mut self,
+		vertices: &[TrianglePointProjectedNormalMapped; 3],
+		texture_info: &TextureInfo,
+		texture_data: &[textures::TextureElement],
+		emissive_data: &[Color32],
+		emissive_brightness: f32,
+	)
+	{
+		call_with_simd_level!(
+			SIMD_LEVEL,
+			self.fill_triangle_normal_mapped_impl_3::<BLENDING_MODE, USE_NORMAL_MAP, SPECULAR_TYPE, SIMD_LEVEL>(
+				vertices,
+				texture_info,
+				texture_data,
+				emissive_data,
+				emissive_brightness,
+			)
+		)
+	}
+
+	// Triangle rasterization with per-pixel lighting.
+	// Use floating point attributes interpolation, since there are too many attributes for fixed point math.
+	// Should be inlined into SIMD level entry function in order to be compiled with its target features.
+	#[inline(always)]
+	fn fill_triangle_normal_mapped_impl_3<
+		const BLENDING_MODE: usize,
+		const USE_NORMAL_MAP: bool,
+		const SPECULAR_TYPE: u32,
+		const SIMD_LEVEL: u32,
+	>(
+		&mut self,
+		vertices: &[TrianglePointProjectedNormalMapped; 3],
+		texture_info: &TextureInfo,
+		texture_data: &[textures::TextureElement],
+		emissive_data: &[Color32],
+		emissive_brightness: f32,
+	)
+	{
+		let emissive_brightness_vec = SimdColorVec::<SIMD_LEVEL>::from_color_f32x3(&[emissive_brightness; 3]);
+
+		let write_depth = self.is_depth_write_needed::<BLENDING_MODE>();
+
+		// Apply fog after lighting. Alpha is preserved.
+		// Additive triangles are just darkened by fog.
+		let fog_scale_vec = SimdColorVec::<SIMD_LEVEL>::from_color_f32x3_with_one(&[self.fog.scale; 3]);
+		let fog_add_vec = if BLENDING_MODE == BLENDING_MODE_ADDITIVE
+		{
+			SimdColorVec::<SIMD_LEVEL>::zero()
+		}
+		else
+		{
+			SimdColorVec::<SIMD_LEVEL>::from_color_f32x3_with_zero(&self.fog.add)
+		};
+
+		let v0 = &vertices[0];
+		let dx1 = vertices[1].x - v0.x;
+		let dy1 = vertices[1].y - v0.y;
+		let dx2 = vertices[2].x - v0.x;
+		let dy2 = vertices[2].y - v0.y;
+		let det = dx1 * dy2 - dx2 * dy1;
+		if det.abs() < 1.0 / 256.0
+		{
+			// Degenerate triangle.
+			return;
+		}
+		let inv_det = 1.0 / det;
+
+		// Calculate attributes equations.
+		let attributes = vertices.map(|v| get_normal_mapped_triangle_point_attributes(&v));
+		let mut d_attributes_dx = [0.0; NORMAL_MAPPED_TRIANGLE_NUM_ATTRIBUTES];
+		let mut d_attributes_dy = [0.0; NORMAL_MAPPED_TRIANGLE_NUM_ATTRIBUTES];
+		for i in 0 .. NORMAL_MAPPED_TRIANGLE_NUM_ATTRIBUTES
+		{
+			let d1 = attributes[1][i] - attributes[0][i];
+			let d2 = attributes[2][i] - attributes[0][i];
+			d_attributes_dx[i] = (d1 * dy2 - d2 * dy1) * inv_det;
+			d_attributes_dy[i] = (d2 * dx1 - d1 * dx2) * inv_det;
+		}
+
+		// Sort triangle vertices.
+		let mut sorted_vertices = [&vertices[0], &vertices[1], &vertices[2]];
+		sorted_vertices.sort_by(|a, b| a.y.partial_cmp(&b.y).unwrap_or(std::cmp::Ordering::Equal));
+		let [lower_vertex, middle_vertex, upper_vertex] = sorted_vertices;
+
+		let y_start_int = ((lower_vertex.y + 0.5).floor() as i32).max(self.clip_rect.min_y);
+		let y_end_int = ((upper_vertex.y + 0.5).floor() as i32).min(self.clip_rect.max_y);
+
+		let max_tc = [texture_info.size[0] - 1, texture_info.size[1] - 1];
+
+		for y_int in y_start_int .. y_end_int
+		{
+			let y = (y_int as f32) + 0.5;
+			let x_long = get_edge_x(lower_vertex, upper_vertex, y);
+			let x_short = if y < middle_vertex.y
+			{
+				get_edge_x(lower_vertex, middle_vertex, y)
+			}
+			else
+			{
+				get_edge_x(middle_vertex, upper_vertex, y)
+			};
+
+			let x_start_int = ((x_long.min(x_short) + 0.5).floor() as i32).max(self.clip_rect.min_x);
+			let x_end_int = ((x_long.max(x_short) + 0.5).floor() as i32).min(self.clip_rect.max_x);
+			if x_start_int >= x_end_int
+			{
+				continue;
+			}
+
+			let x = (x_start_int as f32) + 0.5;
+			let mut line_attributes = [0.0; NORMAL_MAPPED_TRIANGLE_NUM_ATTRIBUTES];
+			for i in 0 .. NORMAL_MAPPED_TRIANGLE_NUM_ATTRIBUTES
+			{
+				line_attributes[i] =
+					attributes[0][i] + d_attributes_dx[i] * (x - v0.x) + d_attributes_dy[i] * (y - v0.y);
+			}
+
+			let line_buffer_offset = y_int * self.row_size;
+			let line_dst = unchecked_slice_range_mut(
+				&mut self.color_buffer,
+				(x_start_int + line_buffer_offset) as usize,
+				(x_end_int + line_buffer_offset) as usize,
+			);
+
+			let depth_line_offset = y_int * self.depth_row_size;
+
+			let mut x_int = x_start_int;
+			for dst_pixel in line_dst
+			{
+				let a = &line_attributes;
+				let u = (a[0] as i32).max(0).min(max_tc[0]);
+				let v = (a[1] as i32).max(0).min(max_tc[1]);
+
+				let texel_address = (u + v * texture_info.size[0]) as usize;
+				let texel = unchecked_texture_fetch(texture_data, texel_address);
+
+				let mut color =
+					surfaces::get_texel_color_with_directional_light::<USE_NORMAL_MAP, SPECULAR_TYPE, SIMD_LEVEL>(
+						texel,
+						&[a[2].max(0.0), a[3].max(0.0), a[4].max(0.0)],
+						&Vec3f::new(a[5], a[6], a[7]),
+						&[a[8].max(0.0), a[9].max(0.0), a[10].max(0.0)],
+						&Vec3f::new(a[11], a[12], a[13]),
+					);
+				if !emissive_data.is_empty()
+				{
+					// Add emissive light after lighting. Alpha is preserved since alpha of brightness vector is zero.
+					let emissive = unchecked_texture_fetch(emissive_data, texel_address);
+					color =
+						SimdColorVec::mul_add(&SimdColorVec::from_color32(emissive), &emissive_brightness_vec, &color);
+				}
+
+				color = SimdColorVec::mul_add(&color, &fog_scale_vec, &fog_add_vec);
+
+				write_into_framebuffer::<ColorT, BLENDING_MODE, SIMD_LEVEL>(dst_pixel, ColorT::from_color_vec(color));
+
+				if write_depth
+				{
+					write_into_depth_buffer::<ColorT, BLENDING_MODE>(
+						self.depth_buffer,
+						(x_int + depth_line_offset) as usize,
+						a[14],
+						ColorT::from_color_vec(color),
+					);
+				}
+
+				for i in 0 .. NORMAL_MAPPED_TRIANGLE_NUM_ATTRIBUTES
+				{
+					line_attributes[i] += d_attributes_dx[i];
+				}
+				x_int += 1;
+			}
+		}
+	}
 }
 
 pub struct DepthRasterizer<'a>
@@ -1493,6 +2162,20 @@ pub struct TrianglePointProjected
 	pub y: Fixed16,
 	pub tc: [Fixed16; 2],
 	pub light: [Fixed16; 3],
+	pub inv_z: f32,
+}
+
+#[derive(Copy, Clone)]
+pub struct TrianglePointProjectedNormalMapped
+{
+	pub x: f32,
+	pub y: f32,
+	pub tc: [f32; 2],
+	pub light: [f32; 3],
+	pub light_vector_scaled: [f32; 3],
+	pub light_color: [f32; 3],
+	pub vec_to_camera: [f32; 3],
+	pub inv_z: f32,
 }
 
 pub struct TextureInfo
@@ -1506,7 +2189,78 @@ struct PolygonSide
 	dx_dy: Fixed16,
 }
 
-fn write_into_framebuffer<ColorT: AbstractColor, const BLENDING_MODE: usize>(dst_pixel: &mut ColorT, texel: ColorT)
+const NORMAL_MAPPED_TRIANGLE_NUM_ATTRIBUTES: usize = 15;
+
+fn get_normal_mapped_triangle_point_attributes(
+	v: &TrianglePointProjectedNormalMapped,
+) -> [f32; NORMAL_MAPPED_TRIANGLE_NUM_ATTRIBUTES]
+{
+	[
+		v.tc[0],
+		v.tc[1],
+		v.light[0],
+		v.light[1],
+		v.light[2],
+		v.light_vector_scaled[0],
+		v.light_vector_scaled[1],
+		v.light_vector_scaled[2],
+		v.light_color[0],
+		v.light_color[1],
+		v.light_color[2],
+		v.vec_to_camera[0],
+		v.vec_to_camera[1],
+		v.vec_to_camera[2],
+		v.inv_z,
+	]
+}
+
+// Calculate screen-space inverse depth equation of triangle plane.
+fn get_triangle_depth_equation(vertices: &[TrianglePointProjected; 3]) -> DepthEquation
+{
+	let v0 = &vertices[0];
+	let x0 = fixed16_to_f32(v0.x);
+	let y0 = fixed16_to_f32(v0.y);
+	let dx1 = fixed16_to_f32(vertices[1].x) - x0;
+	let dy1 = fixed16_to_f32(vertices[1].y) - y0;
+	let dx2 = fixed16_to_f32(vertices[2].x) - x0;
+	let dy2 = fixed16_to_f32(vertices[2].y) - y0;
+	let det = dx1 * dy2 - dx2 * dy1;
+	if det.abs() < 1.0 / 256.0
+	{
+		// Degenerate triangle - use constant depth.
+		return DepthEquation {
+			d_inv_z_dx: 0.0,
+			d_inv_z_dy: 0.0,
+			k: v0.inv_z,
+		};
+	}
+	let inv_det = 1.0 / det;
+
+	let d1 = vertices[1].inv_z - v0.inv_z;
+	let d2 = vertices[2].inv_z - v0.inv_z;
+	let d_inv_z_dx = (d1 * dy2 - d2 * dy1) * inv_det;
+	let d_inv_z_dy = (d2 * dx1 - d1 * dx2) * inv_det;
+	DepthEquation {
+		d_inv_z_dx,
+		d_inv_z_dy,
+		k: v0.inv_z - d_inv_z_dx * x0 - d_inv_z_dy * y0,
+	}
+}
+
+fn get_edge_x(v0: &TrianglePointProjectedNormalMapped, v1: &TrianglePointProjectedNormalMapped, y: f32) -> f32
+{
+	let dy = v1.y - v0.y;
+	if dy.abs() < 1.0 / 65536.0
+	{
+		return v0.x;
+	}
+	v0.x + (y - v0.y) * (v1.x - v0.x) / dy
+}
+
+fn write_into_framebuffer<ColorT: AbstractColor, const BLENDING_MODE: usize, const SIMD_LEVEL: u32>(
+	dst_pixel: &mut ColorT,
+	texel: ColorT,
+)
 {
 	if BLENDING_MODE == BLENDING_MODE_NONE
 	{
@@ -1529,7 +2283,28 @@ fn write_into_framebuffer<ColorT: AbstractColor, const BLENDING_MODE: usize>(dst
 	}
 	else if BLENDING_MODE == BLENDING_MODE_ALPHA_BLEND
 	{
-		*dst_pixel = ColorT::alpha_blend(*dst_pixel, texel);
+		*dst_pixel = ColorT::alpha_blend::<SIMD_LEVEL>(*dst_pixel, texel);
+	}
+}
+
+fn write_into_depth_buffer<ColorT: AbstractColor, const BLENDING_MODE: usize>(
+	depth_buffer: &mut [f32],
+	address: usize,
+	inv_z: f32,
+	texel: ColorT,
+)
+{
+	if BLENDING_MODE == BLENDING_MODE_NONE || (BLENDING_MODE == BLENDING_MODE_ALPHA_TEST && texel.test_alpha())
+	{
+		// Depth buffer has same size as color buffer, so, address is always in range.
+		#[cfg(debug_assertions)]
+		{
+			depth_buffer[address] = inv_z;
+		}
+		#[cfg(not(debug_assertions))]
+		unsafe {
+			*depth_buffer.get_unchecked_mut(address) = inv_z;
+		}
 	}
 }
 
@@ -1553,6 +2328,77 @@ fn unchecked_to_int64(x: f32) -> i64
 	unsafe { x.to_int_unchecked::<i64>() }
 }
 
+// Fetch texel using Fixed16 texture coordinates in range [0; size - 1] and given filtering mode.
+// Screen coordinates are used for dithering.
+#[inline(always)]
+fn fetch_texel_filtered<ColorT: AbstractColor, const TEXTURE_FILTERING_MODE: usize, const SIMD_LEVEL: u32>(
+	texture_data: &[ColorT],
+	texture_size: &[i32; 2],
+	tc: [Fixed16; 2],
+	x: i32,
+	y: i32,
+) -> ColorT
+{
+	debug_assert!(tc[0] >= 0);
+	debug_assert!(tc[1] >= 0);
+	let size_minus_one = [texture_size[0] - 1, texture_size[1] - 1];
+
+	if TEXTURE_FILTERING_MODE == TEXTURE_FILTERING_MODE_DITHERED
+	{
+		let offset = &TEXTURE_DITHER_KERNEL[((x & 1) | ((y & 1) << 1)) as usize];
+		let u = ((tc[0] + offset[0]) >> FIXED16_BASE).min(size_minus_one[0]);
+		let v = ((tc[1] + offset[1]) >> FIXED16_BASE).min(size_minus_one[1]);
+		unchecked_texture_fetch(texture_data, (u + v * texture_size[0]) as usize)
+	}
+	else if TEXTURE_FILTERING_MODE == TEXTURE_FILTERING_MODE_BILINEAR
+	{
+		// Shift coordinates to texel center.
+		let tc_shifted = [(tc[0] - FIXED16_HALF).max(0), (tc[1] - FIXED16_HALF).max(0)];
+		let u0 = (tc_shifted[0] >> FIXED16_BASE).min(size_minus_one[0]);
+		let v0 = (tc_shifted[1] >> FIXED16_BASE).min(size_minus_one[1]);
+		let u1 = (u0 + 1).min(size_minus_one[0]);
+		let v1 = (v0 + 1).min(size_minus_one[1]);
+
+		// Use 8 bits for interpolation factors.
+		let k_u = (tc_shifted[0] >> (FIXED16_BASE - 8)) & 255;
+		let k_v = (tc_shifted[1] >> (FIXED16_BASE - 8)) & 255;
+
+		let row0 = v0 * texture_size[0];
+		let row1 = v1 * texture_size[0];
+		let c00 = unchecked_texture_fetch(texture_data, (u0 + row0) as usize).into_color_vec_i::<SIMD_LEVEL>();
+		let c10 = unchecked_texture_fetch(texture_data, (u1 + row0) as usize).into_color_vec_i::<SIMD_LEVEL>();
+		let c01 = unchecked_texture_fetch(texture_data, (u0 + row1) as usize).into_color_vec_i::<SIMD_LEVEL>();
+		let c11 = unchecked_texture_fetch(texture_data, (u1 + row1) as usize).into_color_vec_i::<SIMD_LEVEL>();
+
+		let c0 = SimdColorVecI::shift_right::<8>(&SimdColorVecI::add(
+			&SimdColorVecI::mul_scalar(&c00, 256 - k_u),
+			&SimdColorVecI::mul_scalar(&c10, k_u),
+		));
+		let c1 = SimdColorVecI::shift_right::<8>(&SimdColorVecI::add(
+			&SimdColorVecI::mul_scalar(&c01, 256 - k_u),
+			&SimdColorVecI::mul_scalar(&c11, k_u),
+		));
+		ColorT::from_color_vec_i(SimdColorVecI::shift_right::<8>(&SimdColorVecI::add(
+			&SimdColorVecI::mul_scalar(&c0, 256 - k_v),
+			&SimdColorVecI::mul_scalar(&c1, k_v),
+		)))
+	}
+	else
+	{
+		let u = tc[0] >> FIXED16_BASE;
+		let v = tc[1] >> FIXED16_BASE;
+		unchecked_texture_fetch(texture_data, (u + v * texture_size[0]) as usize)
+	}
+}
+
+// Texture coordinates offsets for 2x2 pixel block, used for dithered filtering.
+const TEXTURE_DITHER_KERNEL: [[Fixed16; 2]; 4] = [
+	[FIXED16_ONE / 4, 0],
+	[FIXED16_ONE / 2, FIXED16_ONE * 3 / 4],
+	[FIXED16_ONE * 3 / 4, FIXED16_ONE / 2],
+	[0, FIXED16_ONE / 4],
+];
+
 fn unchecked_texture_fetch<ColorT: Copy>(texture_data: &[ColorT], texel_address: usize) -> ColorT
 {
 	// operator [] checks bounds and calls panic! handler in case if index is out of bounds.
@@ -1,26 +1,37 @@
 use super::{
-	abstract_color::*, config, debug_stats_printer::*, depth_renderer::*, draw_ordering, dynamic_models_index::*,
-	equations::*, fast_math::*, frame_info::*, frame_number::*, inline_models_index::*, map_materials_processor::*,
-	map_visibility_calculator::*, performance_counter::*, rasterizer::*, rect_splitting, renderer_config::*,
-	resources_manager::*, shadow_map::*, surfaces::*, textures::*, triangle_model::*, triangle_models_rendering::*,
+	abstract_color::*, config, cpu_features::*, debug_stats_printer::*, depth_renderer::*, draw_ordering,
+	dynamic_models_index::*, equations::*, fast_math::*, fog::*, frame_info::*, frame_number::*,
+	inline_models_index::*, light::*, map_materials_processor::*, map_visibility_calculator::*, performance_counter::*,
+	picking::*, procedural_sky::*, rasterizer::*, rect_splitting, renderer_config::*, resources_manager::*,
+	shadow_map::*, sprites_rendering::*, surfaces::*, textures::*, triangle_model::*, triangle_models_rendering::*,
+	underwater_effects::*,
 };
 use crate::common::{
-	bsp_map_compact, clipping::*, clipping_polygon::*, fixed_math::*, lightmap, material, math_types::*, matrix::*,
-	plane::*, shared_mut_slice::*, system_window,
+	bbox::*, bsp_map_compact, clipping::*, clipping_polygon::*, color::*, fixed_math::*, image, lightmap, material,
+	math_types::*, matrix::*, plane::*, shared_mut_slice::*, system_window,
 };
 use rayon::prelude::*;
 use std::sync::{Arc, Mutex};
 
 pub struct Renderer
 {
+	resources_manager: ResourcesManagerSharedPtr,
 	app_config: config::ConfigSharedPtr,
 	config: RendererConfig,
 	config_is_durty: bool,
 
+	// Nesting level of this view. 0 - main view, 1 - view inside mirror or portal, 2 - view inside mirror inside mirror, etc.
+	view_depth: u32,
+	// Camera matrices of current view. For views inside mirrors and portals these are transformed matrices.
+	camera_matrices: CameraMatrices,
+
 	current_frame: FrameNumber,
 	map: Arc<bsp_map_compact::BSPMap>,
 	visibility_calculator: MapVisibilityCalculator,
 	shadows_maps_renderer: DepthRenderer,
+	// Shadow maps of dynamic lights of current frame. Mapped 1 to 1 to frame lights.
+	// Built only by main view and shared with nested views. Nested views release them after drawing.
+	dynamic_lights_shadow_maps: Option<Arc<Vec<CubeShadowMap>>>,
 	polygons_data: Vec<DrawPolygonData>,
 	vertices_transformed: Vec<Vec3f>,
 	// Store surfaces pixels as raw array.
@@ -34,7 +45,12 @@ pub struct Renderer
 	submodels_info: Vec<VisibleSubmodelInfo>,
 	// Material index and clipping polygon of current frame sky.
 	current_sky: Option<(u32, ClippingPolygon)>,
-	materials_processor: MapMaterialsProcessor,
+	// Generator of procedural sky. Used only by main renderer and shared with nested renderers.
+	procedural_sky: ProceduralSky,
+	// Textures of procedural sky for current frame.
+	procedural_sky_textures: Option<Arc<ProceduralSkyTextures>>,
+	// Materials are updated only by main view and shared with nested views. Nested views release them after drawing.
+	materials_processor: Option<Arc<MapMaterialsProcessor>>,
 	performance_counters: Arc<Mutex<RendererPerformanceCounters>>,
 	// TODO - maybe extract dynamic models-related stuff into separate class?
 	dynamic_models_index: DynamicModelsIndex,
@@ -45,14 +61,44 @@ pub struct Renderer
 	dynamic_model_to_dynamic_meshes_index: Vec<DynamicModelInfo>,
 	dynamic_meshes_vertices: Vec<ModelVertex3d>,
 	dynamic_meshes_triangles: Vec<Triangle>,
+	sprites_index: DynamicModelsIndex,
+	// Mapped 1 to 1 to frame sprites.
+	visible_sprites_info: Vec<Option<VisibleSpriteInfo>>,
+	visible_mirrors: Vec<VisibleMirrorInfo>,
+	// Renderers of views inside mirrors. Mapped 1 to 1 to visible mirrors, created lazily.
+	mirror_renderers: Vec<Renderer>,
+	portals_index: DynamicModelsIndex,
+	// World space vertices of portals of current frame. Mapped 1 to 1 to frame portals.
+	portals_vertices: Vec<Vec<Vec3f>>,
+	visible_portals: Vec<VisiblePortalInfo>,
+	// Renderers of views inside portals. Mapped 1 to 1 to visible portals, created lazily.
+	portal_renderers: Vec<Renderer>,
+	// Global fog, defined in map.
+	map_fog: Option<FogParams>,
+	map_fog_volumes: Vec<FogVolume>,
+	// Global fog (if present) and all fog volumes for current frame.
+	current_fog_volumes: Vec<FogVolume>,
+	// Renderers of camera views. Mapped 1 to 1 to frame camera views, created lazily. Used only by main view.
+	camera_view_renderers: Vec<Renderer>,
+	// Images of camera views. Mapped 1 to 1 to frame camera views. Used only by main view.
+	camera_view_images: Vec<CameraViewImage>,
+	// Pixels of view inside mirror or portal. Used only by nested renderers.
+	view_pixels: Vec<u8>,
+	view_size: [u32; 2],
+	// Inverse depth of main view pixels (0 - infinitely far). Filled only if depth output is enabled.
+	depth_buffer: Vec<f32>,
+	depth_buffer_size: [u32; 2],
 }
 
 struct RendererPerformanceCounters
 {
+	camera_views: PerformanceCounter,
 	materials_update: PerformanceCounter,
 	visible_leafs_search: PerformanceCounter,
 	triangle_models_preparation: PerformanceCounter,
 	surfaces_preparation: PerformanceCounter,
+	procedural_sky_preparation: PerformanceCounter,
+	nested_views_preparation: PerformanceCounter,
 	background_fill: PerformanceCounter,
 	rasterization: PerformanceCounter,
 }
@@ -63,10 +109,13 @@ impl RendererPerformanceCounters
 	{
 		let window_size = 100;
 		Self {
+			camera_views: PerformanceCounter::new(window_size),
 			materials_update: PerformanceCounter::new(window_size),
 			visible_leafs_search: PerformanceCounter::new(window_size),
 			triangle_models_preparation: PerformanceCounter::new(window_size),
 			surfaces_preparation: PerformanceCounter::new(window_size),
+			procedural_sky_preparation: PerformanceCounter::new(window_size),
+			nested_views_preparation: PerformanceCounter::new(window_size),
 			background_fill: PerformanceCounter::new(window_size),
 			rasterization: PerformanceCounter::new(window_size),
 		}
@@ -96,6 +145,10 @@ struct DrawPolygonData
 	surface_size: [u32; 2],
 	mip: u32,
 	surface_tc_min: [i32; 2],
+	// If some - use this frame of animated texture instead of current frame.
+	texture_frame: Option<u32>,
+	// If some - draw view inside mirror with given index.
+	mirror_index: Option<u32>,
 }
 
 #[derive(Default, Copy, Clone)]
@@ -103,6 +156,7 @@ struct VisibleSubmodelInfo
 {
 	// Calculate matrices once for frame and use them during polygons reparation, sorting and polygons ordering.
 	camera_matrices: Option<CameraMatrices>,
+	texture_frame: Option<u32>,
 }
 
 struct VisibleDynamicMeshInfo
@@ -116,7 +170,57 @@ struct VisibleDynamicMeshInfo
 	clipping_polygon: ClippingPolygon,
 	model_matrix: Mat4f,
 	camera_matrices: CameraMatrices,
+	fog: ObjectFog,
+}
+
+struct VisibleSpriteInfo
+{
+	vertices: [ModelVertex3d; 4],
+	bbox_vertices_transformed: [Vec3f; 8],
+	clipping_polygon: ClippingPolygon,
 	mip: u32,
+	fog: ObjectFog,
+}
+
+#[derive(Copy, Clone)]
+struct VisibleMirrorInfo
+{
+	plane: Plane,
+	// Leaf of first visible polygon of this mirror. Visibility search inside mirror starts from it.
+	start_leaf: u32,
+	// Screen-space bounds of all visible polygons of this mirror.
+	bounds: ClippingPolygon,
+}
+
+#[derive(Copy, Clone)]
+struct VisiblePortalInfo
+{
+	// Index in frame portals list.
+	portal_index: u32,
+	camera_matrices: CameraMatrices,
+	visibility: NestedViewVisibility,
+}
+
+// Parameters of visibility calculation for view inside mirror or portal.
+#[derive(Copy, Clone)]
+struct NestedViewVisibility
+{
+	// Screen-space bounds of nested view.
+	bounds: ClippingPolygon,
+	// Visibility search starts from this leaf.
+	start_leaf: u32,
+	// Map portals behind this plane are ignored.
+	clip_plane: Plane,
+}
+
+// Item of leaf, that is sorted together with other items of this leaf.
+#[derive(Copy, Clone)]
+enum LeafDrawItem
+{
+	Submodel(u32),
+	// Index in visible dynamic meshes list.
+	DynamicMesh(u32),
+	Sprite(u32),
 }
 
 #[derive(Default, Copy, Clone)]
@@ -126,6 +230,41 @@ struct DynamicModelInfo
 	num_visible_meshes: u32,
 }
 
+// Data of current frame, shared between main view and all nested views.
+struct FrameSharedData<'a>
+{
+	// Mapped 1 to 1 to frame lights.
+	dynamic_lights_shadow_maps: &'a Arc<Vec<CubeShadowMap>>,
+	// Total number of mirrors in all views of current frame.
+	num_visible_mirrors: u32,
+	// Total number of portals in all views of current frame.
+	num_visible_portals: u32,
+	// Procedural sky is generated once for all views.
+	procedural_sky: &'a mut ProceduralSky,
+	// Materials are updated once for all views.
+	materials_processor: Arc<MapMaterialsProcessor>,
+}
+
+// Result of camera view rendering.
+#[derive(Default)]
+struct CameraViewImage
+{
+	name: String,
+	// Empty for invalid camera views.
+	image: image::Image,
+}
+
+// Data needed for dynamic lights calculation for decals on specific polygon.
+struct DecalDynamicLightsData<'a>
+{
+	lights: &'a [PointLight],
+	// Mapped 1 to 1 to lights.
+	shadow_maps: &'a [CubeShadowMap],
+	view_to_world_matrix: Mat4f,
+	// World space normal of polygon.
+	normal: Vec3f,
+}
+
 impl Renderer
 {
 	pub fn new(
@@ -133,16 +272,43 @@ impl Renderer
 		app_config: config::ConfigSharedPtr,
 		map: Arc<bsp_map_compact::BSPMap>,
 	) -> Self
+	{
+		Self::new_with_view_depth(resources_manager, app_config, map, 0)
+	}
+
+	fn new_with_view_depth(
+		resources_manager: ResourcesManagerSharedPtr,
+		app_config: config::ConfigSharedPtr,
+		map: Arc<bsp_map_compact::BSPMap>,
+		view_depth: u32,
+	) -> Self
 	{
 		let config_parsed = RendererConfig::from_app_config(&app_config);
 		config_parsed.update_app_config(&app_config); // Update JSON with struct fields.
 
-		let materials_processor = MapMaterialsProcessor::new(resources_manager, &*map);
+		// Nested views use materials processor of main view.
+		let materials_processor = if view_depth == 0
+		{
+			Some(Arc::new(MapMaterialsProcessor::new(resources_manager.clone(), &*map)))
+		}
+		else
+		{
+			None
+		};
+		let map_fog = get_map_fog_params(&map);
+		let map_fog_volumes = get_map_fog_volumes(&map);
 
 		Renderer {
+			resources_manager,
 			app_config,
 			config: config_parsed,
 			config_is_durty: false,
+			view_depth,
+			camera_matrices: CameraMatrices {
+				position: Vec3f::zero(),
+				view_matrix: Mat4f::identity(),
+				planes_matrix: Mat4f::identity(),
+			},
 			current_frame: FrameNumber::default(),
 			polygons_data: vec![DrawPolygonData::default(); map.polygons.len()],
 			vertices_transformed: vec![Vec3f::new(0.0, 0.0, 0.0); map.vertices.len()],
@@ -153,17 +319,37 @@ impl Renderer
 			inline_models_index: InlineModelsIndex::new(map.clone()),
 			submodels_info: vec![VisibleSubmodelInfo::default(); map.submodels.len()],
 			current_sky: None,
+			procedural_sky: ProceduralSky::default(),
+			procedural_sky_textures: None,
 			visibility_calculator: MapVisibilityCalculator::new(map.clone()),
 			shadows_maps_renderer: DepthRenderer::new(map.clone()),
+			dynamic_lights_shadow_maps: None,
 			map: map.clone(),
 			materials_processor,
 			performance_counters: Arc::new(Mutex::new(RendererPerformanceCounters::new())),
 			dynamic_models_index: DynamicModelsIndex::new(map.clone()),
-			decals_index: DynamicModelsIndex::new(map),
+			decals_index: DynamicModelsIndex::new(map.clone()),
 			visible_dynamic_meshes_list: Vec::new(),
 			dynamic_model_to_dynamic_meshes_index: Vec::new(),
 			dynamic_meshes_vertices: Vec::new(),
 			dynamic_meshes_triangles: Vec::new(),
+			sprites_index: DynamicModelsIndex::new(map.clone()),
+			visible_sprites_info: Vec::new(),
+			visible_mirrors: Vec::new(),
+			mirror_renderers: Vec::new(),
+			portals_index: DynamicModelsIndex::new(map),
+			portals_vertices: Vec::new(),
+			visible_portals: Vec::new(),
+			portal_renderers: Vec::new(),
+			map_fog,
+			map_fog_volumes,
+			current_fog_volumes: Vec::new(),
+			camera_view_renderers: Vec::new(),
+			camera_view_images: Vec::new(),
+			view_pixels: Vec::new(),
+			view_size: [0, 0],
+			depth_buffer: Vec::new(),
+			depth_buffer_size: [0, 0],
 		}
 	}
 
@@ -173,43 +359,103 @@ impl Renderer
 		frame_info: &FrameInfo,
 	)
 	{
+		// Reuse memory of shadow maps of previous frame, if nested views released them.
+		let mut dynamic_lights_shadow_maps = self
+			.dynamic_lights_shadow_maps
+			.take()
+			.and_then(|s| Arc::try_unwrap(s).ok())
+			.unwrap_or_default();
+		// Build shadow maps only once and reuse them for all nested views.
+		self.build_dynamic_lights_shadow_maps(&frame_info.lights, &mut dynamic_lights_shadow_maps);
+		let dynamic_lights_shadow_maps = Arc::new(dynamic_lights_shadow_maps);
+
+		// Reuse materials processor of previous frame, if nested views released it.
+		let mut materials_processor = self
+			.materials_processor
+			.take()
+			.and_then(|m| Arc::try_unwrap(m).ok())
+			.unwrap_or_else(|| MapMaterialsProcessor::new(self.resources_manager.clone(), &self.map));
+
 		let performance_counters_ptr = self.performance_counters.clone();
-		let mut performance_counters = performance_counters_ptr.lock().unwrap();
 
-		self.synchronize_config();
-		self.update_mip_bias();
+		// Update materials only once and reuse them for all nested views.
+		// Camera view images of previous frame are still set, they are used by camera views.
+		run_with_measure(
+			|| materials_processor.update(frame_info.game_time_s, &frame_info.materials_texture_frames),
+			&mut performance_counters_ptr.lock().unwrap().materials_update,
+		);
 
-		self.current_frame.next();
+		// Release procedural sky textures of previous frame in order to reuse their memory.
+		self.procedural_sky_textures = None;
+		// Take procedural sky in order to avoid borrowing problems.
+		let mut procedural_sky = std::mem::take(&mut self.procedural_sky);
+
+		let mut shared_data = FrameSharedData {
+			dynamic_lights_shadow_maps: &dynamic_lights_shadow_maps,
+			num_visible_mirrors: 0,
+			num_visible_portals: 0,
+			procedural_sky: &mut procedural_sky,
+			materials_processor: Arc::new(materials_processor),
+		};
 
+		// Take images in order to avoid borrowing problems. Reuse memory of images of previous frame.
+		let mut camera_view_images = std::mem::take(&mut self.camera_view_images);
 		run_with_measure(
-			|| self.materials_processor.update(frame_info.game_time_s),
-			&mut performance_counters.materials_update,
+			|| self.prepare_camera_views(frame_info, &mut camera_view_images, &mut shared_data),
+			&mut performance_counters_ptr.lock().unwrap().camera_views,
 		);
 
-		let depth_map_size = 256;
-		let mut test_lights_shadow_maps = Vec::with_capacity(frame_info.lights.len());
-		// TODO - perform parallel shadowmaps build.
-		for light in &frame_info.lights
+		// Camera views are already drawn and released materials processor, so, it can be modified.
+		// Use camera view images of current frame for main view and its nested views.
+		if let Some(materials_processor) = Arc::get_mut(&mut shared_data.materials_processor)
 		{
-			let mut cube_shadow_map = CubeShadowMap {
-				size: depth_map_size,
-				sides: [Vec::new(), Vec::new(), Vec::new(), Vec::new(), Vec::new(), Vec::new()],
-			};
-			for side in 0 .. 6
-			{
-				let depth_matrices = calculate_cube_shadow_map_side_matrices(
-					light.pos,
-					depth_map_size as f32,
-					int_to_cubemap_side(side).unwrap(),
-				);
+			Self::set_camera_view_images(materials_processor, &camera_view_images);
+		}
 
-				let mut depth_data = vec![0.0; (depth_map_size * depth_map_size) as usize];
-				self.shadows_maps_renderer
-					.draw_map(&mut depth_data, depth_map_size, depth_map_size, &depth_matrices);
+		self.prepare_frame_impl::<ColorT>(
+			surface_info,
+			frame_info,
+			&frame_info.camera_matrices,
+			None,
+			&mut shared_data,
+		);
 
-				cube_shadow_map.sides[side as usize] = depth_data;
-			}
-			test_lights_shadow_maps.push(cube_shadow_map);
+		self.camera_view_images = camera_view_images;
+		self.procedural_sky = procedural_sky;
+	}
+
+	// If nested view is some - prepare view inside mirror or portal.
+	fn prepare_frame_impl<ColorT: AbstractColor>(
+		&mut self,
+		surface_info: &system_window::SurfaceInfo,
+		frame_info: &FrameInfo,
+		camera_matrices: &CameraMatrices,
+		nested_view: Option<&NestedViewVisibility>,
+		shared_data: &mut FrameSharedData,
+	)
+	{
+		self.camera_matrices = *camera_matrices;
+		// Keep shadow maps for decals drawing.
+		self.dynamic_lights_shadow_maps = Some(shared_data.dynamic_lights_shadow_maps.clone());
+		// Keep materials for surfaces building and rasterization.
+		self.materials_processor = Some(shared_data.materials_processor.clone());
+
+		let performance_counters_ptr = self.performance_counters.clone();
+		let mut performance_counters = performance_counters_ptr.lock().unwrap();
+
+		self.synchronize_config();
+		self.update_mip_bias();
+
+		self.current_frame.next();
+
+		// TODO - avoid allocation.
+		let mut lights_with_shadow_maps = Vec::with_capacity(frame_info.lights.len());
+		for (light, shadow_map) in frame_info
+			.lights
+			.iter()
+			.zip(shared_data.dynamic_lights_shadow_maps.iter())
+		{
+			lights_with_shadow_maps.push((light, shadow_map));
 		}
 
 		run_with_measure(
@@ -217,41 +463,64 @@ impl Renderer
 				// TODO - before preparing frame try to shift camera a little bit away from all planes of BSP nodes before current leaf.
 				// This is needed to fix possible z_near clipping of current leaf portals.
 
-				let frame_bounds =
-					ClippingPolygon::from_box(0.0, 0.0, surface_info.width as f32, surface_info.height as f32);
-				self.visibility_calculator
-					.update_visibility(&frame_info.camera_matrices, &frame_bounds);
+				if let Some(nested_view) = nested_view
+				{
+					self.visibility_calculator.update_visibility_from_leaf(
+						camera_matrices,
+						&nested_view.bounds,
+						nested_view.start_leaf,
+						&nested_view.clip_plane,
+					);
+				}
+				else
+				{
+					let frame_bounds =
+						ClippingPolygon::from_box(0.0, 0.0, surface_info.width as f32, surface_info.height as f32);
+					self.visibility_calculator
+						.update_visibility(camera_matrices, &frame_bounds);
+				}
 			},
 			&mut performance_counters.visible_leafs_search,
 		);
 
-		self.prepare_submodels(&frame_info.camera_matrices, &frame_info.submodel_entities);
+		self.prepare_submodels(camera_matrices, &frame_info.submodel_entities);
+
+		self.prepare_fog(frame_info);
 
 		run_with_measure(
 			|| {
-				self.prepare_dynamic_models(&frame_info.camera_matrices, &frame_info.model_entities);
-				self.build_dynamic_models_buffers(&frame_info.model_entities);
+				self.prepare_dynamic_models(camera_matrices, &frame_info.model_entities);
+				self.build_dynamic_models_buffers(&frame_info.model_entities, &lights_with_shadow_maps);
 			},
 			&mut performance_counters.triangle_models_preparation,
 		);
 
 		self.decals_index.position_decals(&frame_info.decals);
 
+		self.prepare_sprites(camera_matrices, &frame_info.sprites);
+
+		self.prepare_portals(&frame_info.portals, &mut shared_data.num_visible_portals);
+
 		run_with_measure(
 			|| {
-				self.prepare_polygons_surfaces(&frame_info.camera_matrices);
+				self.prepare_polygons_surfaces(camera_matrices, &mut shared_data.num_visible_mirrors);
 				self.allocate_surfaces_pixels::<ColorT>();
-
-				// TODO - avoid allocation.
-				let mut lights_with_shadow_maps = Vec::new();
-				for (light, shadow_map) in frame_info.lights.iter().zip(test_lights_shadow_maps.iter())
-				{
-					lights_with_shadow_maps.push((light, shadow_map));
-				}
-				self.build_polygons_surfaces::<ColorT>(&frame_info.camera_matrices, &lights_with_shadow_maps);
+				self.build_polygons_surfaces::<ColorT>(camera_matrices, &lights_with_shadow_maps);
 			},
 			&mut performance_counters.surfaces_preparation,
 		);
+
+		run_with_measure(
+			|| self.prepare_procedural_sky::<ColorT>(frame_info, shared_data.procedural_sky),
+			&mut performance_counters.procedural_sky_preparation,
+		);
+
+		drop(lights_with_shadow_maps);
+
+		run_with_measure(
+			|| self.prepare_nested_views::<ColorT>(surface_info, frame_info, shared_data),
+			&mut performance_counters.nested_views_preparation,
+		);
 	}
 
 	pub fn draw_frame<ColorT: AbstractColor>(
@@ -262,6 +531,26 @@ impl Renderer
 		debug_stats_printer: &mut DebugStatsPrinter,
 	)
 	{
+		self.draw_frame_impl(pixels, surface_info, frame_info);
+
+		if debug_stats_printer.show_debug_stats()
+		{
+			let performance_counters_ptr = self.performance_counters.clone();
+			let performance_counters = performance_counters_ptr.lock().unwrap();
+			self.print_debug_stats(frame_info, debug_stats_printer, &performance_counters);
+		}
+	}
+
+	fn draw_frame_impl<ColorT: AbstractColor>(
+		&mut self,
+		pixels: &mut [ColorT],
+		surface_info: &system_window::SurfaceInfo,
+		frame_info: &FrameInfo,
+	)
+	{
+		// Draw views inside mirrors and portals first, since they are used for mirror and portal polygons rasterization.
+		self.draw_nested_views::<ColorT>(surface_info, frame_info);
+
 		let performance_counters_ptr = self.performance_counters.clone();
 		let mut performance_counters = performance_counters_ptr.lock().unwrap();
 
@@ -278,14 +567,413 @@ impl Renderer
 			&mut performance_counters.background_fill,
 		);
 
+		// Depth is written during rasterization (if needed). Sky and background have zero inverse depth.
+		let mut depth_buffer = std::mem::take(&mut self.depth_buffer);
+		self.depth_buffer_size = if self.view_depth == 0 && self.config.depth_output
+		{
+			[surface_info.width as u32, surface_info.height as u32]
+		}
+		else
+		{
+			[0, 0]
+		};
+		let depth_buffer_required_size = (self.depth_buffer_size[0] * self.depth_buffer_size[1]) as usize;
+		if depth_buffer.len() < depth_buffer_required_size
+		{
+			depth_buffer.resize(depth_buffer_required_size, 0.0);
+		}
+		let depth_buffer_used = &mut depth_buffer[.. depth_buffer_required_size];
+		depth_buffer_used.fill(0.0);
+
 		run_with_measure(
-			|| self.perform_rasterization(pixels, surface_info, frame_info),
+			|| self.perform_rasterization(pixels, depth_buffer_used, surface_info, frame_info),
 			&mut performance_counters.rasterization,
 		);
 
-		if debug_stats_printer.show_debug_stats()
+		self.depth_buffer = depth_buffer;
+
+		// Views inside mirrors and portals are composited into main view, so, apply effects only for main view.
+		if self.view_depth == 0 && self.config.underwater_effects
 		{
-			self.print_debug_stats(frame_info, debug_stats_printer, &performance_counters);
+			let contents = frame_info
+				.view_contents
+				.unwrap_or_else(|| bsp_map_compact::get_point_contents(&self.map, &self.camera_matrices.position));
+			if bsp_map_compact::is_liquid_contents(contents)
+			{
+				apply_underwater_effects(pixels, surface_info, contents, frame_info.game_time_s);
+			}
+		}
+	}
+
+	// Get inverse depth of pixels of last frame (0 - infinitely far) and its size.
+	// Returns None if depth output is disabled.
+	pub fn get_depth_buffer(&self) -> Option<(&[f32], [u32; 2])>
+	{
+		let size = self.depth_buffer_size;
+		if size[0] == 0 || size[1] == 0
+		{
+			return None;
+		}
+		Some((&self.depth_buffer[.. (size[0] * size[1]) as usize], size))
+	}
+
+	// Reconstruct world position of given pixel of last frame using depth buffer.
+	// Returns None if depth output is disabled or if there is no geometry at given pixel.
+	pub fn get_world_position_for_pixel(&self, x: u32, y: u32) -> Option<Vec3f>
+	{
+		let (depth_buffer, size) = self.get_depth_buffer()?;
+		if x >= size[0] || y >= size[1]
+		{
+			return None;
+		}
+		let inv_z = depth_buffer[(x + y * size[0]) as usize];
+		if inv_z <= 0.0
+		{
+			return None;
+		}
+		get_screen_point_world_position(&self.camera_matrices, (x as f32) + 0.5, (y as f32) + 0.5, 1.0 / inv_z)
+	}
+
+	// Find nearest object under given screen pixel.
+	// Submodels positions of last prepared frame are used. Polygons with non-drawable materials are skipped.
+	pub fn pick(&self, camera_matrices: &CameraMatrices, x: u32, y: u32, frame_info: &FrameInfo) -> Option<PickResult>
+	{
+		pick(
+			&self.map,
+			&self.inline_models_index,
+			camera_matrices,
+			(x as f32) + 0.5,
+			(y as f32) + 0.5,
+			&frame_info.model_entities,
+			&|polygon| self.get_materials_processor().get_material(polygon.texture).draw,
+		)
+	}
+
+	// Get material by index in map textures list (as returned by picking).
+	pub fn get_material(&self, material_index: u32) -> &material::Material
+	{
+		self.get_materials_processor().get_material(material_index)
+	}
+
+	fn prepare_nested_views<ColorT: AbstractColor>(
+		&mut self,
+		surface_info: &system_window::SurfaceInfo,
+		frame_info: &FrameInfo,
+		shared_data: &mut FrameSharedData,
+	)
+	{
+		let viewport_width = surface_info.width as f32;
+		let viewport_height = surface_info.height as f32;
+		let nested_view_surface_info = get_nested_view_surface_info(surface_info);
+
+		Self::create_nested_renderers(
+			&mut self.mirror_renderers,
+			self.visible_mirrors.len(),
+			&self.resources_manager,
+			&self.app_config,
+			&self.map,
+			self.view_depth + 1,
+		);
+		for (mirror, mirror_renderer) in self.visible_mirrors.iter().zip(self.mirror_renderers.iter_mut())
+		{
+			let mirror_camera_matrices =
+				build_reflected_camera_matrices(&self.camera_matrices, &mirror.plane, viewport_width, viewport_height);
+
+			// View inside mirror is flipped horizontally, so, flip also mirror bounds.
+			let mut bounds = mirror.bounds;
+			bounds.flip_x(viewport_width);
+
+			mirror_renderer.prepare_frame_impl::<ColorT>(
+				&nested_view_surface_info,
+				frame_info,
+				&mirror_camera_matrices,
+				Some(&NestedViewVisibility {
+					bounds,
+					start_leaf: mirror.start_leaf,
+					clip_plane: mirror.plane,
+				}),
+				shared_data,
+			);
+		}
+
+		Self::create_nested_renderers(
+			&mut self.portal_renderers,
+			self.visible_portals.len(),
+			&self.resources_manager,
+			&self.app_config,
+			&self.map,
+			self.view_depth + 1,
+		);
+		for (portal, portal_renderer) in self.visible_portals.iter().zip(self.portal_renderers.iter_mut())
+		{
+			portal_renderer.prepare_frame_impl::<ColorT>(
+				&nested_view_surface_info,
+				frame_info,
+				&portal.camera_matrices,
+				Some(&portal.visibility),
+				shared_data,
+			);
+		}
+	}
+
+	fn prepare_procedural_sky<ColorT: AbstractColor>(
+		&mut self,
+		frame_info: &FrameInfo,
+		procedural_sky: &mut ProceduralSky,
+	)
+	{
+		self.procedural_sky_textures = None;
+
+		let material_index = if let Some((material_index, _)) = &self.current_sky
+		{
+			*material_index
+		}
+		else
+		{
+			return;
+		};
+
+		let material = self.get_materials_processor().get_material(material_index);
+		if let Some(params) = material.skybox.as_ref().and_then(|s| s.procedural.as_ref())
+		{
+			let sun = frame_info.sky_sun.unwrap_or_else(|| SkySun::from_sky_params(params));
+			// Textures are regenerated only if needed, so, multiple views with same sky share same textures.
+			procedural_sky.update::<ColorT>(material_index, params, &sun, frame_info.game_time_s);
+			self.procedural_sky_textures = Some(procedural_sky.get_textures().clone());
+		}
+	}
+
+	fn prepare_fog(&mut self, frame_info: &FrameInfo)
+	{
+		self.current_fog_volumes.clear();
+		if !self.config.use_fog
+		{
+			return;
+		}
+
+		if let Some(params) = frame_info.fog.or(self.map_fog)
+		{
+			// Represent global fog as fog volume with very large box.
+			let inf = 1.0e20;
+			self.current_fog_volumes.push(FogVolume {
+				bbox: BBox::from_min_max(Vec3f::new(-inf, -inf, -inf), Vec3f::new(inf, inf, inf)),
+				params,
+			});
+		}
+
+		self.current_fog_volumes.extend_from_slice(&self.map_fog_volumes);
+	}
+
+	// Render camera views and make images from them.
+	// "camera_view_images" contains images of previous frame, they are replaced with new images.
+	fn prepare_camera_views(
+		&mut self,
+		frame_info: &FrameInfo,
+		camera_view_images: &mut Vec<CameraViewImage>,
+		shared_data: &mut FrameSharedData,
+	)
+	{
+		Self::create_nested_renderers(
+			&mut self.camera_view_renderers,
+			frame_info.camera_views.len(),
+			&self.resources_manager,
+			&self.app_config,
+			&self.map,
+			self.view_depth + 1,
+		);
+
+		for (camera_view, camera_view_renderer) in frame_info
+			.camera_views
+			.iter()
+			.zip(self.camera_view_renderers.iter_mut())
+		{
+			// Reset size in order to mark view as invalid.
+			camera_view_renderer.view_size = [0, 0];
+			if camera_view.size[0] == 0 || camera_view.size[1] == 0
+			{
+				continue;
+			}
+
+			// Render view with size of texture which is replaced by this view, in order to preserve texture coordinates.
+			// Rescale camera matrices, which are built for view size.
+			let size = shared_data
+				.materials_processor
+				.get_camera_view_texture_size(&camera_view.name)
+				.unwrap_or(camera_view.size);
+			if size[0] == 0 || size[1] == 0
+			{
+				continue;
+			}
+			let camera_matrices = build_scaled_viewport_camera_matrices(
+				&camera_view.camera_matrices,
+				(size[0] as f32) / (camera_view.size[0] as f32),
+				(size[1] as f32) / (camera_view.size[1] as f32),
+			);
+
+			let surface_info = system_window::SurfaceInfo {
+				width: size[0] as usize,
+				height: size[1] as usize,
+				pitch: size[0] as usize,
+			};
+
+			// Camera views inside camera views use images of previous frame.
+			camera_view_renderer.prepare_frame_impl::<Color32>(
+				&surface_info,
+				frame_info,
+				&camera_matrices,
+				None,
+				shared_data,
+			);
+			camera_view_renderer.draw_nested_view::<Color32>(&surface_info, frame_info);
+		}
+
+		// Update images only after rendering of all views. Reuse memory of images of previous frame.
+		camera_view_images.resize_with(frame_info.camera_views.len(), CameraViewImage::default);
+
+		let mut resources_manager = self.resources_manager.lock().unwrap();
+		// Remove textures of camera views which are not present anymore.
+		resources_manager.retain_camera_view_textures(|name| frame_info.camera_views.iter().any(|c| c.name == name));
+
+		for ((camera_view, camera_view_renderer), camera_view_image) in frame_info
+			.camera_views
+			.iter()
+			.zip(self.camera_view_renderers.iter())
+			.zip(camera_view_images.iter_mut())
+		{
+			let view_size = camera_view_renderer.view_size;
+			camera_view_image.name.clear();
+			camera_view_image.name.push_str(&camera_view.name);
+			camera_view_image.image.size = view_size;
+			camera_view_image.image.pixels.clear();
+			if view_size[0] == 0 || view_size[1] == 0
+			{
+				continue;
+			}
+
+			camera_view_image
+				.image
+				.pixels
+				.extend_from_slice(camera_view_renderer.get_view_pixels::<Color32>());
+
+			// Make result available for game code.
+			resources_manager.set_camera_view_texture(&camera_view.name, &camera_view_image.image);
+		}
+	}
+
+	fn set_camera_view_images(materials_processor: &mut MapMaterialsProcessor, camera_view_images: &[CameraViewImage])
+	{
+		// Previous camera view images are reset here.
+		materials_processor.reset_camera_view_images();
+		for camera_view_image in camera_view_images
+		{
+			if !camera_view_image.image.pixels.is_empty()
+			{
+				materials_processor.set_camera_view_image(&camera_view_image.name, &camera_view_image.image);
+			}
+		}
+	}
+
+	fn get_materials_processor(&self) -> &MapMaterialsProcessor
+	{
+		// Nested views receive materials processor from main view during frame preparation.
+		self.materials_processor.as_ref().unwrap()
+	}
+
+	fn create_nested_renderers(
+		renderers: &mut Vec<Renderer>,
+		num_renderers: usize,
+		resources_manager: &ResourcesManagerSharedPtr,
+		app_config: &config::ConfigSharedPtr,
+		map: &Arc<bsp_map_compact::BSPMap>,
+		view_depth: u32,
+	)
+	{
+		while renderers.len() < num_renderers
+		{
+			renderers.push(Renderer::new_with_view_depth(
+				resources_manager.clone(),
+				app_config.clone(),
+				map.clone(),
+				view_depth,
+			));
+		}
+	}
+
+	fn draw_nested_views<ColorT: AbstractColor>(
+		&mut self,
+		surface_info: &system_window::SurfaceInfo,
+		frame_info: &FrameInfo,
+	)
+	{
+		let nested_view_surface_info = get_nested_view_surface_info(surface_info);
+
+		for renderer in self.mirror_renderers[.. self.visible_mirrors.len()]
+			.iter_mut()
+			.chain(self.portal_renderers[.. self.visible_portals.len()].iter_mut())
+		{
+			renderer.draw_nested_view::<ColorT>(&nested_view_surface_info, frame_info);
+		}
+	}
+
+	// Draw view inside mirror or portal into own pixels buffer.
+	fn draw_nested_view<ColorT: AbstractColor>(
+		&mut self,
+		surface_info: &system_window::SurfaceInfo,
+		frame_info: &FrameInfo,
+	)
+	{
+		// Take pixels in order to avoid borrowing problems.
+		let mut view_pixels = std::mem::take(&mut self.view_pixels);
+		view_pixels.resize(
+			surface_info.width * surface_info.height * std::mem::size_of::<ColorT>(),
+			0,
+		);
+
+		self.draw_frame_impl(
+			unsafe { view_pixels.align_to_mut::<ColorT>().1 },
+			surface_info,
+			frame_info,
+		);
+
+		self.view_pixels = view_pixels;
+		self.view_size = [surface_info.width as u32, surface_info.height as u32];
+
+		// Release shadow maps, sky textures and materials in order to allow main view to reuse them in next frame.
+		self.dynamic_lights_shadow_maps = None;
+		self.procedural_sky_textures = None;
+		self.materials_processor = None;
+	}
+
+	fn build_dynamic_lights_shadow_maps(&mut self, lights: &[PointLight], shadow_maps: &mut Vec<CubeShadowMap>)
+	{
+		let depth_map_size = 256;
+		// Reuse shadow maps memory of previous frame.
+		shadow_maps.truncate(lights.len());
+		while shadow_maps.len() < lights.len()
+		{
+			shadow_maps.push(CubeShadowMap {
+				size: depth_map_size,
+				sides: [Vec::new(), Vec::new(), Vec::new(), Vec::new(), Vec::new(), Vec::new()],
+			});
+		}
+
+		// TODO - perform parallel shadowmaps build.
+		for (light, cube_shadow_map) in lights.iter().zip(shadow_maps.iter_mut())
+		{
+			for side in 0 .. 6
+			{
+				let depth_matrices = calculate_cube_shadow_map_side_matrices(
+					light.pos,
+					depth_map_size as f32,
+					int_to_cubemap_side(side).unwrap(),
+				);
+
+				let depth_data = &mut cube_shadow_map.sides[side as usize];
+				depth_data.clear();
+				depth_data.resize((depth_map_size * depth_map_size) as usize, 0.0);
+				self.shadows_maps_renderer
+					.draw_map(depth_data, depth_map_size, depth_map_size, &depth_matrices);
+			}
 		}
 	}
 
@@ -350,6 +1038,10 @@ impl Renderer
 			}
 		}
 
+		debug_stats_printer.add_line(format!(
+			"camera views: {:04.2}ms",
+			performance_counters.camera_views.get_average_value() * 1000.0
+		));
 		debug_stats_printer.add_line(format!(
 			"materials update: {:04.2}ms",
 			performance_counters.materials_update.get_average_value() * 1000.0
@@ -366,6 +1058,14 @@ impl Renderer
 			"surfaces preparation: {:04.2}ms",
 			performance_counters.surfaces_preparation.get_average_value() * 1000.0
 		));
+		debug_stats_printer.add_line(format!(
+			"procedural sky preparation: {:04.2}ms",
+			performance_counters.procedural_sky_preparation.get_average_value() * 1000.0
+		));
+		debug_stats_printer.add_line(format!(
+			"nested views preparation: {:04.2}ms",
+			performance_counters.nested_views_preparation.get_average_value() * 1000.0
+		));
 		debug_stats_printer.add_line(format!(
 			"background fill: {:04.2}ms",
 			performance_counters.background_fill.get_average_value() * 1000.0
@@ -374,9 +1074,15 @@ impl Renderer
 			"rasterization: {:04.2}ms",
 			performance_counters.rasterization.get_average_value() * 1000.0
 		));
+		debug_stats_printer.add_line(format!("SIMD: {}", get_simd_level().get_name()));
 		debug_stats_printer.add_line(format!("leafs: {}/{}", num_visible_leafs, self.map.leafs.len()));
 		debug_stats_printer.add_line(format!("submodels parts: {}", num_visible_submodels_parts));
 		debug_stats_printer.add_line(format!("polygons: {}", self.current_frame_visible_polygons.len()));
+		debug_stats_printer.add_line(format!(
+			"mirrors: {}, portals: {}",
+			self.visible_mirrors.len(),
+			self.visible_portals.len()
+		));
 		debug_stats_printer.add_line(format!(
 			"dynamic meshes : {}, parts: {}, triangles: {}, vertices: {}",
 			self.visible_dynamic_meshes_list.len(),
@@ -385,6 +1091,11 @@ impl Renderer
 			triangle_vertices
 		));
 		debug_stats_printer.add_line(format!("decals: {}, (parsts in leafs: {})", decals, decals_leafs_parts));
+		debug_stats_printer.add_line(format!(
+			"sprites: {}/{}",
+			self.visible_sprites_info.iter().filter(|s| s.is_some()).count(),
+			frame_info.sprites.len()
+		));
 		debug_stats_printer.add_line(format!(
 			"surfaces pixels: {}k",
 			(self.num_visible_surfaces_pixels + 1023) / 1024
@@ -412,6 +1123,7 @@ impl Renderer
 			{
 				None
 			};
+			submodel_info.texture_frame = submodels[index].and_then(|s| s.texture_frame);
 		}
 	}
 
@@ -479,10 +1191,16 @@ impl Renderer
 
 			dynamic_model_info.first_visible_mesh = self.visible_dynamic_meshes_list.len() as u32;
 
+			let fog = get_object_fog(
+				&self.current_fog_volumes,
+				&camera_matrices.position,
+				&(model_matrix * bbox.get_center().extend(1.0)).truncate(),
+			);
+
 			let model_camera_matrices = CameraMatrices {
 				view_matrix: model_view_matrix,
 				planes_matrix: camera_matrices.planes_matrix * model_matrix.transpose().invert().unwrap(),
-				position: Vec3f::zero(),
+				position: camera_matrices.position,
 			};
 
 			for (mesh_index, mesh) in model.model.meshes.iter().enumerate()
@@ -497,7 +1215,7 @@ impl Renderer
 					clipping_polygon,
 					model_matrix,
 					camera_matrices: model_camera_matrices,
-					mip: 0, // Set later.
+					fog,
 				});
 
 				let num_vertices = match &mesh.vertex_data
@@ -522,6 +1240,7 @@ impl Renderer
 					pos: Vec3f::zero(),
 					tc: Vec2f::zero(),
 					light: [0.0; 3],
+					tangent_space_light: ModelVertexTangentSpaceLight::zero(),
 				},
 			);
 		}
@@ -531,27 +1250,28 @@ impl Renderer
 		}
 	}
 
-	fn build_dynamic_models_buffers(&mut self, models: &[ModelEntity])
+	fn build_dynamic_models_buffers(&mut self, models: &[ModelEntity], dynamic_lights: &[LightWithShadowMap])
 	{
 		// It is safe to share vertices and triangle buffers since each mesh uses its own region.
 		let dst_vertices_shared = SharedMutSlice::new(&mut self.dynamic_meshes_vertices);
 		let dst_triangles_shared = SharedMutSlice::new(&mut self.dynamic_meshes_triangles);
 
 		let map = &self.map;
-		let mip_bias = self.mip_bias;
+		let use_shadow_maps = self.config.dynamic_lights_shadows_for_models_and_decals;
 
 		let func = |visible_dynamic_mesh: &mut VisibleDynamicMeshInfo| {
 			let model = &models[visible_dynamic_mesh.entity_index as usize];
 			let animation = &model.animation;
 
-			visible_dynamic_mesh.mip = calculate_triangle_model_texture_mip(
-				&visible_dynamic_mesh.camera_matrices.view_matrix,
-				&get_current_triangle_model_bbox(&model.model, animation),
-				model.texture[0].size,
-				mip_bias,
-			);
+			let texture_size = if let Some(material) = &model.material
+			{
+				material[0].size
+			}
+			else
+			{
+				model.texture[0].size
+			};
 
-			let texture = &model.texture[visible_dynamic_mesh.mip as usize];
 			let mesh = &model.model.meshes[visible_dynamic_mesh.mesh_index as usize];
 
 			// Perform vertices transformation.
@@ -562,9 +1282,14 @@ impl Renderer
 				mesh,
 				animation,
 				&get_model_light(map, &model),
+				dynamic_lights,
+				use_shadow_maps,
+				model.material.is_some(),
+				&visible_dynamic_mesh.camera_matrices.position,
 				&visible_dynamic_mesh.model_matrix,
 				&visible_dynamic_mesh.camera_matrices.view_matrix,
-				&Vec2f::new(texture.size[0] as f32, texture.size[1] as f32),
+				// Use texture coordinates of first mip. Mip is selected later for each triangle.
+				&Vec2f::new(texture_size[0] as f32, texture_size[1] as f32),
 				&model.model.tc_shift,
 				dst_mesh_vertices,
 			);
@@ -591,9 +1316,79 @@ impl Renderer
 		}
 	}
 
+	fn prepare_sprites(&mut self, camera_matrices: &CameraMatrices, sprites: &[Sprite])
+	{
+		self.sprites_index.position_sprites(sprites);
+
+		self.visible_sprites_info.clear();
+		for (sprite_index, sprite) in sprites.iter().enumerate()
+		{
+			let visible_sprite_info = self.prepare_sprite(camera_matrices, sprite_index, sprite);
+			self.visible_sprites_info.push(visible_sprite_info);
+		}
+	}
+
+	fn prepare_sprite(
+		&self,
+		camera_matrices: &CameraMatrices,
+		sprite_index: usize,
+		sprite: &Sprite,
+	) -> Option<VisibleSpriteInfo>
+	{
+		let bbox_vertices_transformed = get_sprite_bbox(sprite).get_corners_vertices().map(|pos| {
+			let pos_transformed = camera_matrices.view_matrix * pos.extend(1.0);
+			Vec3f::new(pos_transformed.x, pos_transformed.y, pos_transformed.w)
+		});
+
+		// Sprite is behind camera plane.
+		let clipping_polygon = calculate_triangle_model_screen_polygon(&bbox_vertices_transformed)?;
+
+		let mut visible = false;
+		for leaf_index in self.sprites_index.get_model_leafs(sprite_index)
+		{
+			if let Some(mut leaf_clipping_polygon) =
+				self.visibility_calculator.get_current_frame_leaf_bounds(*leaf_index)
+			{
+				leaf_clipping_polygon.intersect(&clipping_polygon);
+				if leaf_clipping_polygon.is_valid_and_non_empty()
+				{
+					visible = true;
+					break;
+				}
+			}
+		}
+
+		if !visible
+		{
+			return None;
+		}
+
+		let mip = calculate_triangle_model_texture_mip(
+			&camera_matrices.view_matrix,
+			&get_sprite_bbox_for_mip_calculation(sprite),
+			sprite.texture[0].size,
+			self.mip_bias,
+		);
+
+		Some(VisibleSpriteInfo {
+			vertices: build_sprite_vertices(
+				sprite,
+				camera_matrices,
+				sprite.texture[mip as usize].size,
+				get_sprite_light(&self.map, sprite),
+			),
+			bbox_vertices_transformed,
+			clipping_polygon,
+			mip,
+			fog: get_object_fog(&self.current_fog_volumes, &camera_matrices.position, &sprite.position),
+		})
+	}
+
+	// "depth_buffer" - empty if depth output is not needed.
 	fn perform_rasterization<ColorT: AbstractColor>(
 		&self,
 		pixels: &mut [ColorT],
+		depth_buffer: &mut [f32],
 		surface_info: &system_window::SurfaceInfo,
 		frame_info: &FrameInfo,
 	)
@@ -608,6 +1403,7 @@ impl Renderer
 		{
 			let mut rasterizer = Rasterizer::new(
 				pixels,
+				depth_buffer,
 				&surface_info,
 				ClipRect {
 					min_x: 0,
@@ -615,6 +1411,7 @@ impl Renderer
 					max_x: surface_info.width as i32,
 					max_y: surface_info.height as i32,
 				},
+				TextureFilteringMode::from_config_value(self.config.texture_filtering),
 			);
 
 			let viewport_clipping_polygon = ClippingPolygon::from_box(
@@ -629,6 +1426,7 @@ impl Renderer
 		else
 		{
 			let pixels_shared = SharedMutSlice::new(pixels);
+			let depth_buffer_shared = SharedMutSlice::new(depth_buffer);
 
 			// Split viewport rect into several rects for each thread.
 			// Use tricky splitting method that avoid creation of thin rects.
@@ -638,11 +1436,13 @@ impl Renderer
 
 			rects[.. num_threads].par_iter().for_each(|rect| {
 				let pixels_cur = unsafe { pixels_shared.get() };
+				let depth_buffer_cur = unsafe { depth_buffer_shared.get() };
 
 				// Create rasterizer with custom clip rect in order to perform pixel-perfect clipping.
 				// TODO - change this. Just create rasterizer with shifted raster and shift vertex coordinates instead.
 				let mut rasterizer = Rasterizer::new(
 					pixels_cur,
+					depth_buffer_cur,
 					&surface_info,
 					ClipRect {
 						min_x: rect.min.x as i32,
@@ -650,6 +1450,7 @@ impl Renderer
 						max_x: rect.max.x as i32,
 						max_y: rect.max.y as i32,
 					},
+					TextureFilteringMode::from_config_value(self.config.texture_filtering),
 				);
 
 				// Extend it just a bit to fix possible gaps.
@@ -681,7 +1482,7 @@ impl Renderer
 		{
 			self.draw_skybox(
 				rasterizer,
-				&frame_info.camera_matrices,
+				&self.camera_matrices,
 				&frame_info.skybox_rotation,
 				&viewport_clipping_polygon,
 			);
@@ -694,18 +1495,24 @@ impl Renderer
 		{
 			self.draw_skybox(
 				rasterizer,
-				&frame_info.camera_matrices,
+				&self.camera_matrices,
 				&frame_info.skybox_rotation,
 				&viewport_clipping_polygon,
 			);
 		}
 
-		self.draw_view_models(rasterizer, &viewport_clipping_polygon, &frame_info.model_entities);
+		if self.view_depth == 0
+		{
+			self.draw_view_models(rasterizer, &viewport_clipping_polygon, &frame_info.model_entities);
+		}
 	}
 
-	fn prepare_polygons_surfaces(&mut self, camera_matrices: &CameraMatrices)
+	// "num_visible_mirrors" - total number of mirrors in current frame, used for mirrors limit.
+	fn prepare_polygons_surfaces(&mut self, camera_matrices: &CameraMatrices, num_visible_mirrors: &mut u32)
 	{
 		self.current_frame_visible_polygons.clear();
+		self.visible_mirrors.clear();
+		let use_mirrors = self.view_depth < self.config.mirrors_max_depth;
 
 		self.current_sky = None;
 
@@ -726,62 +1533,310 @@ impl Renderer
 						&clip_planes,
 						&mut surfaces_pixels_accumulated_offset,
 						polygon_index as usize,
+						None,
 					);
+
+					if use_mirrors &&
+						self.polygons_data[polygon_index as usize].visible_frame == self.current_frame &&
+						self.get_materials_processor()
+							.get_material(self.map.polygons[polygon_index as usize].texture)
+							.mirror
+							.is_some()
+					{
+						self.add_visible_mirror_polygon(
+							&clip_planes,
+							i as u32,
+							polygon_index as usize,
+							num_visible_mirrors,
+						);
+					}
+				}
+			}
+		}
+
+		// Prepare surfaces for submodels.
+		// Do this only for sumbodels located in visible leafs.
+		for index in 0 .. self.map.submodels.len()
+		{
+			let submodel_camera_matrices = if let Some(m) = self.submodels_info[index].camera_matrices
+			{
+				m
+			}
+			else
+			{
+				continue;
+			};
+			let texture_frame = self.submodels_info[index].texture_frame;
+
+			let mut bounds: Option<ClippingPolygon> = None;
+			for &leaf_index in self.inline_models_index.get_model_leafs(index as u32)
+			{
+				if let Some(leaf_bounds) = self.visibility_calculator.get_current_frame_leaf_bounds(leaf_index)
+				{
+					if let Some(bounds) = &mut bounds
+					{
+						bounds.extend(&leaf_bounds);
+					}
+					else
+					{
+						bounds = Some(leaf_bounds);
+					}
 				}
 			}
-		}
 
-		// Prepare surfaces for submodels.
-		// Do this only for sumbodels located in visible leafs.
-		for index in 0 .. self.map.submodels.len()
-		{
-			let submodel_camera_matrices = if let Some(m) = self.submodels_info[index].camera_matrices
+			let clip_planes = if let Some(b) = bounds
+			{
+				b.get_clip_planes()
+			}
+			else
+			{
+				continue;
+			};
+
+			let submodel = &self.map.submodels[index];
+			for polygon_index in submodel.first_polygon .. (submodel.first_polygon + submodel.num_polygons)
+			{
+				self.prepare_polygon_surface(
+					&submodel_camera_matrices,
+					&clip_planes,
+					&mut surfaces_pixels_accumulated_offset,
+					polygon_index as usize,
+					texture_frame,
+				);
+			}
+		}
+
+		self.num_visible_surfaces_pixels = surfaces_pixels_accumulated_offset;
+	}
+
+	// "num_visible_portals" - total number of portals in current frame, used for portals limit.
+	fn prepare_portals(&mut self, portals: &[ViewPortal], num_visible_portals: &mut u32)
+	{
+		self.visible_portals.clear();
+		if self.view_depth >= self.config.portals_max_depth
+		{
+			self.portals_vertices.clear();
+			self.portals_index.position_portals(&[]);
+			return;
+		}
+
+		self.prepare_portals_vertices(portals);
+		self.portals_index.position_portals(&self.portals_vertices);
+
+		for (portal_index, portal) in portals.iter().enumerate()
+		{
+			if *num_visible_portals >= self.config.max_portals_per_frame
+			{
+				break;
+			}
+			let portal_vertices = &self.portals_vertices[portal_index];
+			if portal_vertices.len() < 3
+			{
+				continue;
+			}
+
+			// Clip portal by bounds of visible leafs where it is located.
+			// Thus view inside portal never exceeds bounds of current view.
+			// Skip portals in invisible leafs.
+			let mut leafs_bounds: Option<ClippingPolygon> = None;
+			for &leaf_index in self.portals_index.get_model_leafs(portal_index)
+			{
+				if let Some(leaf_bounds) = self.visibility_calculator.get_current_frame_leaf_bounds(leaf_index)
+				{
+					if let Some(leafs_bounds) = &mut leafs_bounds
+					{
+						leafs_bounds.extend(&leaf_bounds);
+					}
+					else
+					{
+						leafs_bounds = Some(leaf_bounds);
+					}
+				}
+			}
+			let clip_planes = if let Some(b) = leafs_bounds
+			{
+				b.get_clip_planes()
+			}
+			else
+			{
+				continue;
+			};
+
+			// Both portal sides are visible, so, orient plane towards camera.
+			let mut plane = get_portal_plane(portal_vertices);
+			let camera_dist = plane.vec.dot(self.camera_matrices.position) - plane.dist;
+			if camera_dist == 0.0
+			{
+				continue;
+			}
+			if camera_dist < 0.0
+			{
+				plane = plane.get_inverted();
+			}
+
+			let mut vertices_transformed = [Vec3f::zero(); MAX_VERTICES]; // TODO - use uninitialized memory
+			let vertex_count =
+				transform_portal_vertices(portal_vertices, &self.camera_matrices, &mut vertices_transformed);
+
+			let mut vertices_2d = [Vec2f::zero(); MAX_VERTICES]; // TODO - use uninitialized memory
+			let vertex_count = project_and_clip_polygon(
+				&clip_planes,
+				&vertices_transformed[.. vertex_count],
+				&mut vertices_2d[..],
+			);
+			if vertex_count < 3
 			{
-				m
+				continue;
 			}
-			else
+
+			let mut bounds = ClippingPolygon::from_point(&vertices_2d[0]);
+			for p in &vertices_2d[1 .. vertex_count]
 			{
-				continue;
+				bounds.extend_with_point(p);
+			}
+
+			let transform_matrix = get_object_matrix(portal.destination_position, portal.destination_rotation) *
+				get_object_matrix(portal.source_position, portal.source_rotation)
+					.invert()
+					.unwrap_or_else(Mat4f::identity);
+
+			// Transform portal plane and invert it, in order to see only space behind destination polygon.
+			let plane_transformed =
+				transform_matrix.invert().unwrap_or_else(Mat4f::identity).transpose() * plane.vec.extend(-plane.dist);
+			let clip_plane = Plane {
+				vec: -plane_transformed.truncate(),
+				dist: plane_transformed.w,
 			};
 
-			let mut bounds: Option<ClippingPolygon> = None;
-			for &leaf_index in self.inline_models_index.get_model_leafs(index as u32)
+			// Start visibility search from leaf just behind destination polygon.
+			let mut center = Vec3f::zero();
+			for v in portal_vertices
 			{
-				if let Some(leaf_bounds) = self.visibility_calculator.get_current_frame_leaf_bounds(leaf_index)
+				center += *v;
+			}
+			center /= portal_vertices.len() as f32;
+			let start_point = (transform_matrix * center.extend(1.0)).truncate() +
+				clip_plane.vec * (0.5 / clip_plane.vec.magnitude().max(0.00000001));
+
+			self.visible_portals.push(VisiblePortalInfo {
+				portal_index: portal_index as u32,
+				camera_matrices: build_transformed_camera_matrices(&self.camera_matrices, &transform_matrix),
+				visibility: NestedViewVisibility {
+					bounds,
+					start_leaf: bsp_map_compact::get_leaf_for_point(&self.map, &start_point),
+					clip_plane,
+				},
+			});
+			*num_visible_portals += 1;
+		}
+	}
+
+	// Calculate world space vertices of portals (with submodels transformations applied).
+	// Empty list is produced for invalid portals.
+	fn prepare_portals_vertices(&mut self, portals: &[ViewPortal])
+	{
+		self.portals_vertices.resize(portals.len(), Vec::new());
+		for (portal, vertices) in portals.iter().zip(self.portals_vertices.iter_mut())
+		{
+			vertices.clear();
+			match &portal.geometry
+			{
+				ViewPortalGeometry::Polygon(polygon_vertices) =>
 				{
-					if let Some(bounds) = &mut bounds
+					vertices.extend_from_slice(polygon_vertices);
+				},
+				ViewPortalGeometry::SubmodelPolygon {
+					submodel_index,
+					polygon_index,
+				} =>
+				{
+					let submodel = if let Some(s) = self.map.submodels.get(*submodel_index as usize)
 					{
-						bounds.extend(&leaf_bounds);
+						s
 					}
 					else
 					{
-						bounds = Some(leaf_bounds);
+						continue;
+					};
+					if *polygon_index >= submodel.num_polygons
+					{
+						continue;
 					}
-				}
-			}
+					// Matrix is none for submodels which are not present in current frame.
+					let model_matrix = if let Some(m) = self.inline_models_index.get_model_matrix(*submodel_index)
+					{
+						m
+					}
+					else
+					{
+						continue;
+					};
 
-			let clip_planes = if let Some(b) = bounds
-			{
-				b.get_clip_planes()
+					let polygon = &self.map.polygons[(submodel.first_polygon + polygon_index) as usize];
+					vertices.extend(
+						self.map.vertices[(polygon.first_vertex as usize) ..
+							((polygon.first_vertex + polygon.num_vertices) as usize)]
+							.iter()
+							.map(|v| (model_matrix * v.extend(1.0)).truncate()),
+					);
+				},
 			}
-			else
-			{
-				continue;
-			};
+		}
+	}
 
-			let submodel = &self.map.submodels[index];
-			for polygon_index in submodel.first_polygon .. (submodel.first_polygon + submodel.num_polygons)
+	// Polygons of single mirror are combined together, if they have same plane.
+	fn add_visible_mirror_polygon(
+		&mut self,
+		clip_planes: &ClippingPolygonPlanes,
+		leaf_index: u32,
+		polygon_index: usize,
+		num_visible_mirrors: &mut u32,
+	)
+	{
+		let polygon = &self.map.polygons[polygon_index];
+
+		let mut vertices_2d = [Vec2f::zero(); MAX_VERTICES]; // TODO - use uninitialized memory
+		let vertex_count = project_and_clip_polygon(
+			clip_planes,
+			&self.vertices_transformed
+				[(polygon.first_vertex as usize) .. ((polygon.first_vertex + polygon.num_vertices) as usize)],
+			&mut vertices_2d[..],
+		);
+		if vertex_count < 3
+		{
+			return;
+		}
+
+		let mut polygon_bounds = ClippingPolygon::from_point(&vertices_2d[0]);
+		for p in &vertices_2d[1 .. vertex_count]
+		{
+			polygon_bounds.extend_with_point(p);
+		}
+
+		for (mirror_index, mirror) in self.visible_mirrors.iter_mut().enumerate()
+		{
+			if (mirror.plane.vec - polygon.plane.vec).magnitude2() <= 0.0001 * mirror.plane.vec.magnitude2() &&
+				(mirror.plane.dist - polygon.plane.dist).abs() <= 0.01 * mirror.plane.vec.magnitude()
 			{
-				self.prepare_polygon_surface(
-					&submodel_camera_matrices,
-					&clip_planes,
-					&mut surfaces_pixels_accumulated_offset,
-					polygon_index as usize,
-				);
+				mirror.bounds.extend(&polygon_bounds);
+				self.polygons_data[polygon_index].mirror_index = Some(mirror_index as u32);
+				return;
 			}
 		}
 
-		self.num_visible_surfaces_pixels = surfaces_pixels_accumulated_offset;
+		if *num_visible_mirrors >= self.config.max_mirrors_per_frame
+		{
+			// Mirrors limit reached - draw this polygon as regular polygon.
+			return;
+		}
+		*num_visible_mirrors += 1;
+
+		self.polygons_data[polygon_index].mirror_index = Some(self.visible_mirrors.len() as u32);
+		self.visible_mirrors.push(VisibleMirrorInfo {
+			plane: polygon.plane,
+			start_leaf: leaf_index,
+			bounds: polygon_bounds,
+		});
 	}
 
 	fn allocate_surfaces_pixels<ColorT>(&mut self)
@@ -800,6 +1855,7 @@ impl Renderer
 		clip_planes: &ClippingPolygonPlanes,
 		surfaces_pixels_accumulated_offset: &mut usize,
 		polygon_index: usize,
+		texture_frame: Option<u32>,
 	)
 	{
 		let polygon_data = &mut self.polygons_data[polygon_index];
@@ -813,7 +1869,8 @@ impl Renderer
 			return;
 		}
 
-		let material = self.materials_processor.get_material(polygon.texture);
+		// Access field directly in order to avoid borrowing whole "self".
+		let material = self.materials_processor.as_ref().unwrap().get_material(polygon.texture);
 
 		if !material.draw
 		{
@@ -957,6 +2014,8 @@ impl Renderer
 		polygon_data.surface_size = [surface_size[0] as u32, surface_size[1] as u32];
 		polygon_data.mip = mip;
 		polygon_data.surface_tc_min = surface_tc_min;
+		polygon_data.texture_frame = texture_frame;
+		polygon_data.mirror_index = None;
 
 		// Correct texture coordinates equation to compensate shift to surface rect.
 		for i in 0 .. 2
@@ -983,8 +2042,10 @@ impl Renderer
 		let lightmaps_data = &self.map.lightmaps_data;
 		let directional_lightmaps_data = &self.map.directional_lightmaps_data;
 		let polygons = &self.map.polygons;
+		let vertices = &self.map.vertices;
 		let polygons_data = &self.polygons_data;
-		let materials_processor = &self.materials_processor;
+		let materials_processor = self.materials_processor.as_ref().unwrap();
+		let fog_volumes = &self.current_fog_volumes;
 
 		let use_directional_lightmap = self.config.use_directional_lightmaps && !directional_lightmaps_data.is_empty();
 
@@ -996,7 +2057,14 @@ impl Renderer
 			let polygon_data = &polygons_data[polygon_index as usize];
 			let surface_size = polygon_data.surface_size;
 
-			let texture = &materials_processor.get_texture(polygon.texture)[polygon_data.mip as usize];
+			let texture = if let Some(frame) = polygon_data.texture_frame
+			{
+				&materials_processor.get_texture_with_frame(polygon.texture, frame)[polygon_data.mip as usize]
+			}
+			else
+			{
+				&materials_processor.get_texture(polygon.texture)[polygon_data.mip as usize]
+			};
 			let surface_data = unsafe {
 				&mut surfaces_pixels_shared.get()[polygon_data.surface_pixels_offset ..
 					polygon_data.surface_pixels_offset + (surface_size[0] * surface_size[1]) as usize]
@@ -1079,6 +2147,49 @@ impl Renderer
 					surface_data,
 				);
 			}
+
+			if !fog_volumes.is_empty()
+			{
+				// Select fog volumes, which may affect segments between camera and polygon points.
+				let mut bbox = BBox::from_point(&camera_matrices.position);
+				for v in &vertices
+					[(polygon.first_vertex as usize) .. ((polygon.first_vertex + polygon.num_vertices) as usize)]
+				{
+					bbox.extend_with_point(v);
+				}
+
+				let mut polygon_fog_volumes = [FogVolume {
+					bbox: BBox::zero(),
+					params: FogParams {
+						color: [0.0; 3],
+						density: 0.0,
+						start: 0.0,
+						end: 0.0,
+					},
+				}; MAX_SURFACE_FOG_VOLUMES];
+				let mut num_polygon_fog_volumes = 0;
+				for fog_volume in fog_volumes
+				{
+					if num_polygon_fog_volumes < MAX_SURFACE_FOG_VOLUMES && fog_volume.bbox.intersects(&bbox)
+					{
+						polygon_fog_volumes[num_polygon_fog_volumes] = *fog_volume;
+						num_polygon_fog_volumes += 1;
+					}
+				}
+
+				if num_polygon_fog_volumes > 0
+				{
+					apply_surface_fog(
+						&polygon.plane,
+						&tex_coord_equation_scaled,
+						surface_size,
+						polygon_data.surface_tc_min,
+						&polygon_fog_volumes[.. num_polygon_fog_volumes],
+						&camera_matrices.position,
+						surface_data,
+					);
+				}
+			}
 		};
 
 		if rayon::current_num_threads() == 1
@@ -1143,7 +2254,26 @@ impl Renderer
 			return;
 		}
 
-		let skybox_textures = if let Some(t) = self.materials_processor.get_skybox_textures(material_index)
+		let is_procedural = self
+			.get_materials_processor()
+			.get_material(material_index)
+			.skybox
+			.as_ref()
+			.map(|s| s.procedural.is_some())
+			.unwrap_or(false);
+
+		let skybox_textures = if is_procedural
+		{
+			if let Some(t) = &self.procedural_sky_textures
+			{
+				t.get_textures::<ColorT>()
+			}
+			else
+			{
+				return;
+			}
+		}
+		else if let Some(t) = self.get_materials_processor().get_skybox_textures(material_index)
 		{
 			t
 		}
@@ -1245,11 +2375,13 @@ impl Renderer
 
 			let side_texture = &side_textures[mip as usize];
 
+			// Sky is infinitely far, so, write zero inverse depth.
 			draw_polygon(
 				rasterizer,
 				&clip_planes,
 				&vertices_transformed,
 				&depth_equation,
+				&DepthEquation::default(),
 				&tc_equation_scaled,
 				&[side_texture.size, side_texture.size],
 				&side_texture.pixels,
@@ -1281,7 +2413,7 @@ impl Renderer
 		else
 		{
 			let node = &self.map.nodes[current_index as usize];
-			let plane_transformed = frame_info.camera_matrices.planes_matrix * node.plane.vec.extend(-node.plane.dist);
+			let plane_transformed = self.camera_matrices.planes_matrix * node.plane.vec.extend(-node.plane.dist);
 			let mut mask = if plane_transformed.w >= 0.0 { 1 } else { 0 };
 			if self.config.invert_polygons_order
 			{
@@ -1322,16 +2454,39 @@ impl Renderer
 				continue;
 			}
 
+			let material = self.get_materials_processor().get_material(polygon.texture);
+			let polygon_vertices = &self.vertices_transformed
+				[(polygon.first_vertex as usize) .. ((polygon.first_vertex + polygon.num_vertices) as usize)];
+
+			if let Some(mirror_index) = polygon_data.mirror_index
+			{
+				let mirror_renderer = &self.mirror_renderers[mirror_index as usize];
+				draw_polygon_with_nested_view(
+					rasterizer,
+					&clip_planes,
+					polygon_vertices,
+					&polygon_data.depth_equation,
+					mirror_renderer.get_view_pixels(),
+					&mirror_renderer.view_size,
+					true,
+				);
+
+				if !material.mirror.map(|m| m.draw_texture).unwrap_or(false)
+				{
+					continue;
+				}
+			}
+
 			draw_polygon(
 				rasterizer,
 				&clip_planes,
-				&self.vertices_transformed
-					[(polygon.first_vertex as usize) .. ((polygon.first_vertex + polygon.num_vertices) as usize)],
+				polygon_vertices,
+				&polygon_data.depth_equation,
 				&polygon_data.depth_equation,
 				&polygon_data.tex_coord_equation,
 				&polygon_data.surface_size,
 				self.get_polygon_surface_data(polygon_data),
-				self.materials_processor.get_material(polygon.texture).blending_mode,
+				material.blending_mode,
 			);
 		}
 
@@ -1341,22 +2496,22 @@ impl Renderer
 		{
 			for polygon_index in leaf.first_polygon .. (leaf.first_polygon + leaf.num_polygons)
 			{
-				self.draw_polygon_decals(
-					rasterizer,
-					&frame_info.camera_matrices,
-					&clip_planes,
-					polygon_index,
-					&frame_info.decals,
-					leaf_decals,
-				);
+				self.draw_polygon_decals(rasterizer, frame_info, &clip_planes, polygon_index, leaf_decals);
 			}
 		}
 
-		// Draw contents of leaf - submodels and triangle models.
+		// Draw portals after all leaf polygons and decals.
+		for &portal_index in self.portals_index.get_leaf_models(leaf_index)
+		{
+			self.draw_portal(rasterizer, &clip_planes, portal_index);
+		}
+
+		// Draw contents of leaf - submodels, triangle models and sprites.
 
 		let leaf_submodels = self.inline_models_index.get_leaf_models(leaf_index);
 		let leaf_dynamic_models = self.dynamic_models_index.get_leaf_models(leaf_index);
-		if leaf_submodels.is_empty() && leaf_dynamic_models.is_empty()
+		let leaf_sprites = self.sprites_index.get_leaf_models(leaf_index);
+		if leaf_submodels.is_empty() && leaf_dynamic_models.is_empty() && leaf_sprites.is_empty()
 		{
 			return;
 		}
@@ -1435,7 +2590,7 @@ impl Renderer
 		// Also it's faster to transform only unique planes.
 		for plane in used_leaf_clip_planes.iter_mut()
 		{
-			let plane_transformed_vec4 = frame_info.camera_matrices.planes_matrix * plane.vec.extend(-plane.dist);
+			let plane_transformed_vec4 = self.camera_matrices.planes_matrix * plane.vec.extend(-plane.dist);
 			*plane = Plane {
 				vec: plane_transformed_vec4.truncate(),
 				dist: -plane_transformed_vec4.w,
@@ -1443,7 +2598,7 @@ impl Renderer
 		}
 
 		// Fast path for cases with single model, to avoid expensive sorting structures preparations.
-		if leaf_submodels.len() == 1 && leaf_dynamic_models.len() == 0
+		if leaf_submodels.len() == 1 && leaf_dynamic_models.len() == 0 && leaf_sprites.len() == 0
 		{
 			self.draw_submodel_in_leaf(
 				rasterizer,
@@ -1455,7 +2610,7 @@ impl Renderer
 			);
 			return;
 		}
-		if leaf_submodels.len() == 0 && leaf_dynamic_models.len() == 1
+		if leaf_submodels.len() == 0 && leaf_dynamic_models.len() == 1 && leaf_sprites.len() == 0
 		{
 			let entry = self.dynamic_model_to_dynamic_meshes_index[leaf_dynamic_models[0] as usize];
 			for visible_mesh_index in entry.first_visible_mesh .. entry.first_visible_mesh + entry.num_visible_meshes
@@ -1475,14 +2630,15 @@ impl Renderer
 
 		// TODO - use uninitialized memory and increase this value.
 		const MAX_SUBMODELS_IN_LEAF: usize = 12;
-		let mut models_for_sorting = [draw_ordering::BBoxForDrawOrdering::default(); MAX_SUBMODELS_IN_LEAF];
+		let mut models_for_sorting =
+			[(LeafDrawItem::Submodel(0), draw_ordering::ProjectedBBox::default()); MAX_SUBMODELS_IN_LEAF];
 
 		for (&model_index, model_for_sorting) in leaf_submodels.iter().zip(models_for_sorting.iter_mut())
 		{
 			if let Some(submodel_camera_matrices) = &self.submodels_info[model_index as usize].camera_matrices
 			{
 				*model_for_sorting = (
-					model_index,
+					LeafDrawItem::Submodel(model_index),
 					draw_ordering::project_bbox(
 						&self.inline_models_index.get_model_bbox_for_ordering(model_index),
 						&submodel_camera_matrices,
@@ -1492,7 +2648,6 @@ impl Renderer
 		}
 		let mut num_models = std::cmp::min(leaf_submodels.len(), MAX_SUBMODELS_IN_LEAF);
 
-		const DYNAMIC_MESH_INDEX_ADD: u32 = 65536;
 		for dynamic_model_index in leaf_dynamic_models
 		{
 			if num_models == MAX_SUBMODELS_IN_LEAF
@@ -1520,50 +2675,95 @@ impl Renderer
 
 				let mesh = &self.visible_dynamic_meshes_list[visible_mesh_index as usize];
 				models_for_sorting[num_models] = (
-					visible_mesh_index + DYNAMIC_MESH_INDEX_ADD,
+					LeafDrawItem::DynamicMesh(visible_mesh_index),
 					draw_ordering::project_bbox(&bbox, &mesh.camera_matrices),
 				);
 				num_models += 1;
 			}
 		}
 
-		draw_ordering::order_bboxes(&mut models_for_sorting[.. num_models]);
-
-		// Draw dynamic models and submodels, located in this leaf, after leaf polygons.
-		for (submodel_index, _bbox) in &models_for_sorting[.. num_models]
+		let mut num_sorted_sprites = 0;
+		for sprite_index in leaf_sprites
 		{
-			if *submodel_index >= DYNAMIC_MESH_INDEX_ADD
+			if num_models == MAX_SUBMODELS_IN_LEAF
 			{
-				let visible_mesh_index = *submodel_index - DYNAMIC_MESH_INDEX_ADD;
-				self.draw_mesh(
-					rasterizer,
-					bounds,
-					used_leaf_clip_planes,
-					&frame_info.model_entities,
-					&self.visible_dynamic_meshes_list[visible_mesh_index as usize],
-				);
+				break;
 			}
-			else
+
+			if self.visible_sprites_info[*sprite_index as usize].is_some()
 			{
-				self.draw_submodel_in_leaf(
-					rasterizer,
-					frame_info,
-					&clip_planes,
-					used_leaf_clip_planes,
-					leaf_decals,
-					*submodel_index,
+				models_for_sorting[num_models] = (
+					LeafDrawItem::Sprite(*sprite_index),
+					draw_ordering::project_bbox(
+						&get_sprite_bbox(&frame_info.sprites[*sprite_index as usize]),
+						&self.camera_matrices,
+					),
 				);
+				num_models += 1;
+			}
+			num_sorted_sprites += 1;
+		}
+
+		draw_ordering::order_bboxes(&mut models_for_sorting[.. num_models]);
+
+		// Draw dynamic models, sprites and submodels, located in this leaf, after leaf polygons.
+		for (item, _bbox) in &models_for_sorting[.. num_models]
+		{
+			match *item
+			{
+				LeafDrawItem::Submodel(submodel_index) =>
+				{
+					self.draw_submodel_in_leaf(
+						rasterizer,
+						frame_info,
+						&clip_planes,
+						used_leaf_clip_planes,
+						leaf_decals,
+						submodel_index,
+					);
+				},
+				LeafDrawItem::DynamicMesh(visible_mesh_index) =>
+				{
+					self.draw_mesh(
+						rasterizer,
+						bounds,
+						used_leaf_clip_planes,
+						&frame_info.model_entities,
+						&self.visible_dynamic_meshes_list[visible_mesh_index as usize],
+					);
+				},
+				LeafDrawItem::Sprite(sprite_index) =>
+				{
+					self.draw_sprite(
+						rasterizer,
+						bounds,
+						used_leaf_clip_planes,
+						&frame_info.sprites,
+						sprite_index,
+					);
+				},
 			}
 		}
+
+		// Draw sprites that do not fit into sorting buffer without sorting.
+		for sprite_index in &leaf_sprites[num_sorted_sprites ..]
+		{
+			self.draw_sprite(
+				rasterizer,
+				bounds,
+				used_leaf_clip_planes,
+				&frame_info.sprites,
+				*sprite_index,
+			);
+		}
 	}
 
 	fn draw_polygon_decals<'a, ColorT: AbstractColor>(
 		&self,
 		rasterizer: &mut Rasterizer<'a, ColorT>,
-		camera_matrices: &CameraMatrices,
+		frame_info: &FrameInfo,
 		clip_planes: &ClippingPolygonPlanes,
 		polygon_index: u32,
-		decals: &[Decal],
 		current_decals: &[ModelId],
 	)
 	{
@@ -1575,7 +2775,7 @@ impl Renderer
 
 		let polygon = &self.map.polygons[polygon_index as usize];
 
-		if !self.materials_processor.get_material(polygon.texture).decals
+		if !self.get_materials_processor().get_material(polygon.texture).decals
 		{
 			return;
 		}
@@ -1592,6 +2792,10 @@ impl Renderer
 		// TODO - maybe use different basis?
 		const DECAL_TEXTURE_BASIS: [[f32; 4]; 2] = [[0.0, -0.5, 0.0, 0.5], [0.0, 0.0, -0.5, 0.5]];
 
+		let camera_matrices = &self.camera_matrices;
+
+		let dynamic_lights_data = self.get_decals_dynamic_lights_data(frame_info, polygon);
+
 		// TODO - use uninitialized memory.
 		let mut vertices_clipped0 = unsafe { std::mem::zeroed::<[Vec3f; MAX_VERTICES]>() };
 		let mut vertices_clipped1 = unsafe { std::mem::zeroed::<[Vec3f; MAX_VERTICES]>() };
@@ -1599,7 +2803,7 @@ impl Renderer
 
 		'decals_loop: for &decal_index in current_decals
 		{
-			let decal = &decals[decal_index as usize];
+			let decal = &frame_info.decals[decal_index as usize];
 			let decal_matrix = get_object_matrix(decal.position, decal.rotation) *
 				Mat4f::from_nonuniform_scale(decal.scale.x, decal.scale.y, decal.scale.z);
 
@@ -1675,11 +2879,18 @@ impl Renderer
 			];
 			let polygon_lightmap_eqution = polygon_data.tex_coord_equation * polygon_lightmap_coord_scale;
 
+			rasterizer.set_fog(&get_object_fog(
+				&self.current_fog_volumes,
+				&camera_matrices.position,
+				&decal.position,
+			));
+
 			for t in 0 .. num_vertices - 2
 			{
 				self.subdivide_and_draw_decal_triangle(
 					rasterizer,
 					decal,
+					&dynamic_lights_data,
 					polygon,
 					depth_equation,
 					&tc_equation_scaled,
@@ -1701,6 +2912,7 @@ impl Renderer
 		&self,
 		rasterizer: &mut Rasterizer<'a, ColorT>,
 		decal: &Decal,
+		dynamic_lights_data: &DecalDynamicLightsData,
 		polygon: &bsp_map_compact::Polygon,
 		depth_equation: &DepthEquation,
 		tc_equation: &TexCoordEquation,
@@ -1750,7 +2962,8 @@ impl Renderer
 			let mut vertices_fixed = unsafe { std::mem::zeroed::<[TrianglePointProjected; 3]>() };
 			for (src, dst) in points.iter().zip(vertices_fixed.iter_mut())
 			{
-				let z = 1.0 / depth_equation.sample_point(src);
+				let inv_z = depth_equation.sample_point(src);
+				let z = 1.0 / inv_z;
 
 				let mut light = decal.light_add;
 				if decal.lightmap_light_scale > 0.0
@@ -1770,6 +2983,35 @@ impl Renderer
 					{
 						light[i] += lightmap_light[i] * decal.lightmap_light_scale;
 					}
+
+					if !dynamic_lights_data.lights.is_empty()
+					{
+						let pos = (dynamic_lights_data.view_to_world_matrix * Vec4f::new(src.x * z, src.y * z, 1.0, z))
+							.truncate();
+						for (dynamic_light, shadow_map) in dynamic_lights_data
+							.lights
+							.iter()
+							.zip(dynamic_lights_data.shadow_maps.iter())
+						{
+							let dynamic_light_value = get_dynamic_light_vertex_light(
+								dynamic_light,
+								if self.config.dynamic_lights_shadows_for_models_and_decals
+								{
+									Some(shadow_map)
+								}
+								else
+								{
+									None
+								},
+								&pos,
+								&dynamic_lights_data.normal,
+							);
+							for i in 0 .. 3
+							{
+								light[i] += dynamic_light_value[i] * decal.lightmap_light_scale;
+							}
+						}
+					}
 				}
 
 				*dst = TrianglePointProjected {
@@ -1792,6 +3034,7 @@ impl Renderer
 						f32_to_fixed16(light[1]),
 						f32_to_fixed16(light[2]),
 					],
+					inv_z,
 				};
 			}
 
@@ -1820,6 +3063,7 @@ impl Renderer
 				self.subdivide_and_draw_decal_triangle(
 					rasterizer,
 					decal,
+					dynamic_lights_data,
 					polygon,
 					depth_equation,
 					&tc_equation,
@@ -1833,6 +3077,63 @@ impl Renderer
 		}
 	}
 
+	fn get_decals_dynamic_lights_data<'a>(
+		&'a self,
+		frame_info: &'a FrameInfo,
+		polygon: &bsp_map_compact::Polygon,
+	) -> DecalDynamicLightsData<'a>
+	{
+		let shadow_maps = if let Some(s) = &self.dynamic_lights_shadow_maps
+		{
+			s.as_slice()
+		}
+		else
+		{
+			&[]
+		};
+
+		if frame_info.lights.is_empty() || shadow_maps.is_empty() || polygon.num_vertices < 3
+		{
+			return DecalDynamicLightsData {
+				lights: &[],
+				shadow_maps: &[],
+				view_to_world_matrix: Mat4f::identity(),
+				normal: Vec3f::zero(),
+			};
+		}
+
+		// Reconstruct world space position using screen position and depth.
+		// Use matrices of current view, since this view may be view inside mirror or portal.
+		let view_to_world_matrix = self
+			.camera_matrices
+			.view_matrix
+			.invert()
+			.unwrap_or_else(Mat4f::identity);
+
+		// Calculate world space normal based on transformed polygon vertices.
+		// We can't just use polygon plane, since submodels polygons may be rotated.
+		let v = &self.vertices_transformed[polygon.first_vertex as usize .. (polygon.first_vertex + 3) as usize];
+		let v_world = [v[0], v[1], v[2]].map(|v| (view_to_world_matrix * Vec4f::new(v.x, v.y, 1.0, v.z)).truncate());
+		let mut normal = (v_world[1] - v_world[0]).cross(v_world[2] - v_world[1]);
+		let normal_len2 = normal.magnitude2();
+		if normal_len2 > 0.0
+		{
+			normal /= normal_len2.sqrt();
+		}
+		// Polygon is visible, so, its normal must be directed towards camera.
+		if normal.dot(self.camera_matrices.position - v_world[0]) < 0.0
+		{
+			normal = -normal;
+		}
+
+		DecalDynamicLightsData {
+			lights: &frame_info.lights,
+			shadow_maps,
+			view_to_world_matrix,
+			normal,
+		}
+	}
+
 	fn draw_submodel_in_leaf<'a, ColorT: AbstractColor>(
 		&self,
 		rasterizer: &mut Rasterizer<'a, ColorT>,
@@ -1901,14 +3202,7 @@ impl Renderer
 
 			if !leaf_decals.is_empty()
 			{
-				self.draw_polygon_decals(
-					rasterizer,
-					&frame_info.camera_matrices,
-					clip_planes,
-					polygon_index,
-					&frame_info.decals,
-					leaf_decals,
-				);
+				self.draw_polygon_decals(rasterizer, frame_info, clip_planes, polygon_index, leaf_decals);
 			}
 		}
 
@@ -1981,10 +3275,13 @@ impl Renderer
 			&clip_planes,
 			&vertices_clipped[.. vertex_count],
 			&polygon_data.depth_equation,
+			&polygon_data.depth_equation,
 			&polygon_data.tex_coord_equation,
 			&polygon_data.surface_size,
 			self.get_polygon_surface_data(polygon_data),
-			self.materials_processor.get_material(polygon.texture).blending_mode,
+			self.get_materials_processor()
+				.get_material(polygon.texture)
+				.blending_mode,
 		);
 	}
 
@@ -2018,169 +3315,193 @@ impl Renderer
 			vec: Vec3f::zero(),
 			dist: 0.0,
 		}; MAX_LEAF_CLIP_PLANES];
-		let mut num_clip_planes_3d = 0;
-
-		let near_z_plane = Plane {
-			vec: Vec3f::unit_z(),
-			dist: Z_NEAR,
+		let mut clip_planes_2d = [Vec3f::zero(); 4];
+		let (num_clip_planes_3d, num_clip_planes_2d) = if let Some(n) = select_object_clip_planes(
+			&visible_dynamic_mesh.bbox_vertices_transformed,
+			&visible_dynamic_mesh.clipping_polygon,
+			clipping_polygon,
+			leaf_clip_planes,
+			&mut clip_planes_3d,
+			&mut clip_planes_2d,
+		)
+		{
+			n
+		}
+		else
+		{
+			// Model is fully clipped.
+			return;
 		};
-		for clip_plane in [near_z_plane].iter().chain(leaf_clip_planes.iter())
+
+		let clip_planes_3d = &clip_planes_3d[.. num_clip_planes_3d];
+		let clip_planes_2d = &clip_planes_2d[.. num_clip_planes_2d];
+
+		let vertices = &self.dynamic_meshes_vertices[visible_dynamic_mesh.vertices_offset ..];
+		let triangles = &self.dynamic_meshes_triangles[visible_dynamic_mesh.triangles_offset ..
+			visible_dynamic_mesh.triangles_offset + visible_dynamic_mesh.num_visible_triangles];
+
+		let mip_bias = self.mip_bias;
+		let tc_shift = &model.model.tc_shift;
+		// Blending of two mips is possible only for opaque meshes - perform drawing of second mip with averaging.
+		let mip_blending = self.config.triangle_models_mip_blending && blending_mode == material::BlendingMode::None;
+
+		rasterizer.set_fog(&visible_dynamic_mesh.fog);
+
+		if let Some(material) = &model.material
+		{
+			clip_and_project_triangles(vertices, triangles, clip_planes_3d, clip_planes_2d, |polygon| {
+				let mip_f = calculate_triangle_model_polygon_texture_mip(polygon, mip_bias);
+				let (mips, num_mips) = get_triangle_model_polygon_mips(mip_f, mip_blending, blending_mode);
+				for &(mip, mip_blending_mode) in &mips[.. num_mips]
+				{
+					fill_model_polygon_normal_mapped(
+						rasterizer,
+						polygon,
+						tc_shift,
+						mip,
+						&material[mip as usize],
+						mip_blending_mode,
+					);
+				}
+			});
+		}
+		else
+		{
+			// TODO - use individual texture for each mesh.
+			clip_and_project_triangles(vertices, triangles, clip_planes_3d, clip_planes_2d, |polygon| {
+				let mip_f = calculate_triangle_model_polygon_texture_mip(polygon, mip_bias);
+				let (mips, num_mips) = get_triangle_model_polygon_mips(mip_f, mip_blending, blending_mode);
+				for &(mip, mip_blending_mode) in &mips[.. num_mips]
+				{
+					fill_model_polygon(
+						rasterizer,
+						polygon,
+						tc_shift,
+						mip,
+						&model.texture[mip as usize],
+						mip_blending_mode,
+					);
+				}
+			});
+		}
+	}
+
+	fn draw_sprite<'a, ColorT: AbstractColor>(
+		&self,
+		rasterizer: &mut Rasterizer<'a, ColorT>,
+		clipping_polygon: &ClippingPolygon,
+		leaf_clip_planes: &[Plane],
+		sprites: &[Sprite],
+		sprite_index: u32,
+	)
+	{
+		let visible_sprite = if let Some(s) = &self.visible_sprites_info[sprite_index as usize]
+		{
+			s
+		}
+		else
 		{
-			let mut vertices_front = 0;
-			for v in visible_dynamic_mesh.bbox_vertices_transformed
-			{
-				if clip_plane.vec.dot(v) >= clip_plane.dist
-				{
-					vertices_front += 1;
-				}
-			}
+			return;
+		};
 
-			if vertices_front == visible_dynamic_mesh.bbox_vertices_transformed.len()
-			{
-				// This clip plane is useless.
-			}
-			else if vertices_front == 0
+		{
+			let mut sprite_clipping_polygon = visible_sprite.clipping_polygon;
+			sprite_clipping_polygon.intersect(clipping_polygon);
+			if sprite_clipping_polygon.is_empty_or_invalid()
 			{
-				// Model is fully clipped.
+				// This sprite is not visble in this leaf or for this screeen rect.
 				return;
 			}
-			else
-			{
-				clip_planes_3d[num_clip_planes_3d] = *clip_plane;
-				num_clip_planes_3d += 1;
-			}
 		}
 
-		// Find 2d clip planes that affect this model.
-		// Use only box clip planes to reduce number of checks.
 		// TODO - use uninitialized memory.
+		let mut clip_planes_3d = [Plane {
+			vec: Vec3f::zero(),
+			dist: 0.0,
+		}; MAX_LEAF_CLIP_PLANES];
 		let mut clip_planes_2d = [Vec3f::zero(); 4];
-		let mut num_clip_planes_2d = 0;
-		for (mesh_plane, cur_plane) in visible_dynamic_mesh
-			.clipping_polygon
-			.get_box_clip_planes()
-			.iter()
-			.zip(clipping_polygon.get_box_clip_planes().iter())
+		let (num_clip_planes_3d, num_clip_planes_2d) = if let Some(n) = select_object_clip_planes(
+			&visible_sprite.bbox_vertices_transformed,
+			&visible_sprite.clipping_polygon,
+			clipping_polygon,
+			leaf_clip_planes,
+			&mut clip_planes_3d,
+			&mut clip_planes_2d,
+		)
 		{
-			if cur_plane.z > mesh_plane.z
-			{
-				clip_planes_2d[num_clip_planes_2d] = *cur_plane;
-				num_clip_planes_2d += 1;
-			}
+			n
 		}
-
-		let texture = &model.texture[visible_dynamic_mesh.mip as usize];
-
-		// TODO - use individual texture for each mesh.
-		let texture_info = TextureInfo {
-			size: [texture.size[0] as i32, texture.size[1] as i32],
+		else
+		{
+			// Sprite is fully clipped.
+			return;
 		};
 
-		let texture_data = &texture.pixels;
+		let sprite = &sprites[sprite_index as usize];
+		let texture = &sprite.texture[visible_sprite.mip as usize];
 
-		let vertices_combined = &self.dynamic_meshes_vertices[visible_dynamic_mesh.vertices_offset ..];
-		let triangles = &self.dynamic_meshes_triangles[visible_dynamic_mesh.triangles_offset ..
-			visible_dynamic_mesh.triangles_offset + visible_dynamic_mesh.num_visible_triangles];
+		rasterizer.set_fog(&visible_sprite.fog);
 
-		if num_clip_planes_3d == 0 && num_clip_planes_2d == 0
-		{
-			// Special case - perform no clipping at all, just draw source triangles.
-			for triangle in triangles
-			{
-				let vertices_projected = triangle.map(|index| {
-					let v = triangle_vertex_debug_checked_fetch(vertices_combined, index);
-					let point = v.pos.truncate() / v.pos.z;
-					TrianglePointProjected {
-						x: f32_to_fixed16(point.x),
-						y: f32_to_fixed16(point.y),
-						tc: [f32_to_fixed16(v.tc.x), f32_to_fixed16(v.tc.y)],
-						light: [
-							f32_to_fixed16(v.light[0]),
-							f32_to_fixed16(v.light[1]),
-							f32_to_fixed16(v.light[2]),
-						],
-					}
-				});
+		clip_and_project_triangles(
+			&visible_sprite.vertices,
+			&SPRITE_TRIANGLES,
+			&clip_planes_3d[.. num_clip_planes_3d],
+			&clip_planes_2d[.. num_clip_planes_2d],
+			|polygon| {
+				// Sprite vertices texture coordinates are already calculated for selected mip.
+				fill_model_polygon(rasterizer, polygon, &Vec2f::zero(), 0, texture, sprite.blending_mode);
+			},
+		);
+	}
 
-				rasterizer.fill_triangle(&vertices_projected, &texture_info, texture_data, blending_mode);
-			}
+	fn draw_portal<'a, ColorT: AbstractColor>(
+		&self,
+		rasterizer: &mut Rasterizer<'a, ColorT>,
+		clip_planes: &ClippingPolygonPlanes,
+		portal_index: u32,
+	)
+	{
+		let portal_renderer = if let Some(visible_portal_index) =
+			self.visible_portals.iter().position(|p| p.portal_index == portal_index)
+		{
+			&self.portal_renderers[visible_portal_index]
 		}
 		else
 		{
-			let mut vertices_clipped0 = unsafe { std::mem::zeroed::<[ModelVertex3d; MAX_VERTICES]>() };
-			let mut vertices_clipped1 = unsafe { std::mem::zeroed::<[ModelVertex3d; MAX_VERTICES]>() };
-			let mut vertices_projected0 = unsafe { std::mem::zeroed::<[ModelVertex2d; MAX_VERTICES]>() };
-			let mut vertices_projected1 = unsafe { std::mem::zeroed::<[ModelVertex2d; MAX_VERTICES]>() };
-			let mut vertices_fixed = unsafe { std::mem::zeroed::<[TrianglePointProjected; MAX_VERTICES]>() };
-
-			'triangles_loop: for triangle in triangles
-			{
-				let mut vc_src = &mut vertices_clipped0;
-				let mut vc_dst = &mut vertices_clipped1;
-
-				for (&index, dst_vertex) in triangle.iter().zip(vc_src.iter_mut())
-				{
-					*dst_vertex = triangle_vertex_debug_checked_fetch(vertices_combined, index);
-				}
-
-				let mut num_vertices = 3;
-				for clip_plane in &clip_planes_3d[.. num_clip_planes_3d]
-				{
-					num_vertices = clip_3d_model_polygon_by_plane(&vc_src[.. num_vertices], clip_plane, vc_dst);
-					if num_vertices < 3
-					{
-						continue 'triangles_loop;
-					}
-					std::mem::swap(&mut vc_src, &mut vc_dst);
-				}
+			return;
+		};
 
-				let mut vp_src = &mut vertices_projected0;
-				let mut vp_dst = &mut vertices_projected1;
+		let mut vertices_transformed = [Vec3f::zero(); MAX_VERTICES]; // TODO - use uninitialized memory
+		let vertex_count = transform_portal_vertices(
+			&self.portals_vertices[portal_index as usize],
+			&self.camera_matrices,
+			&mut vertices_transformed,
+		);
 
-				for (src, dst) in vc_src[.. num_vertices].iter().zip(vp_src.iter_mut())
-				{
-					*dst = ModelVertex2d {
-						pos: src.pos.truncate() / src.pos.z,
-						tc: src.tc,
-						light: src.light,
-					};
-				}
+		// Portal may be visible from both sides, but rasterizer requires clockwise vertices order.
+		if !is_projected_polygon_clockwise(&vertices_transformed[.. vertex_count])
+		{
+			vertices_transformed[.. vertex_count].reverse();
+		}
 
-				for clip_plane in &clip_planes_2d[.. num_clip_planes_2d]
-				{
-					num_vertices = clip_2d_model_polygon(&vp_src[.. num_vertices], clip_plane, vp_dst);
-					if num_vertices < 3
-					{
-						continue 'triangles_loop;
-					}
-					std::mem::swap(&mut vp_src, &mut vp_dst);
-				}
+		let portal_plane = get_portal_plane(&vertices_transformed[.. vertex_count]);
+		let depth_equation =
+			DepthEquation::from_transformed_plane_equation(&portal_plane.vec.extend(-portal_plane.dist));
 
-				for (src, dst) in vp_src[.. num_vertices].iter().zip(vertices_fixed.iter_mut())
-				{
-					*dst = TrianglePointProjected {
-						x: f32_to_fixed16(src.pos.x),
-						y: f32_to_fixed16(src.pos.y),
-						tc: [f32_to_fixed16(src.tc.x), f32_to_fixed16(src.tc.y)],
-						light: [
-							f32_to_fixed16(src.light[0]),
-							f32_to_fixed16(src.light[1]),
-							f32_to_fixed16(src.light[2]),
-						],
-					};
-				}
+		draw_polygon_with_nested_view(
+			rasterizer,
+			clip_planes,
+			&vertices_transformed[.. vertex_count],
+			&depth_equation,
+			portal_renderer.get_view_pixels(),
+			&portal_renderer.view_size,
+			false,
+		);
+	}
 
-				for t in 0 .. num_vertices - 2
-				{
-					rasterizer.fill_triangle(
-						&[vertices_fixed[0], vertices_fixed[t + 1], vertices_fixed[t + 2]],
-						&texture_info,
-						texture_data,
-						blending_mode,
-					);
-				} // for subtriangles
-			} // For triangles
-		}
+	fn get_view_pixels<ColorT>(&self) -> &[ColorT]
+	{
+		unsafe { self.view_pixels.align_to::<ColorT>().1 }
 	}
 
 	fn get_polygon_surface_data<ColorT>(&self, polygon_data: &DrawPolygonData) -> &[ColorT]
@@ -2307,6 +3628,278 @@ fn get_polygon_lightap_light(
 	total_light
 }
 
+// Find clip planes that affect object with given bbox and screen clipping polygon.
+// Returns number of 3d and 2d clip planes or None if object is fully clipped.
+fn select_object_clip_planes(
+	bbox_vertices_transformed: &[Vec3f; 8],
+	object_clipping_polygon: &ClippingPolygon,
+	clipping_polygon: &ClippingPolygon,
+	leaf_clip_planes: &[Plane],
+	out_clip_planes_3d: &mut [Plane; MAX_LEAF_CLIP_PLANES],
+	out_clip_planes_2d: &mut [Vec3f; 4],
+) -> Option<(usize, usize)>
+{
+	let mut num_clip_planes_3d = 0;
+
+	let near_z_plane = Plane {
+		vec: Vec3f::unit_z(),
+		dist: Z_NEAR,
+	};
+	for clip_plane in [near_z_plane].iter().chain(leaf_clip_planes.iter())
+	{
+		let mut vertices_front = 0;
+		for v in bbox_vertices_transformed
+		{
+			if clip_plane.vec.dot(*v) >= clip_plane.dist
+			{
+				vertices_front += 1;
+			}
+		}
+
+		if vertices_front == bbox_vertices_transformed.len()
+		{
+			// This clip plane is useless.
+		}
+		else if vertices_front == 0
+		{
+			// Object is fully clipped.
+			return None;
+		}
+		else
+		{
+			out_clip_planes_3d[num_clip_planes_3d] = *clip_plane;
+			num_clip_planes_3d += 1;
+		}
+	}
+
+	// Find 2d clip planes that affect this object.
+	// Use only box clip planes to reduce number of checks.
+	let mut num_clip_planes_2d = 0;
+	for (object_plane, cur_plane) in object_clipping_polygon
+		.get_box_clip_planes()
+		.iter()
+		.zip(clipping_polygon.get_box_clip_planes().iter())
+	{
+		if cur_plane.z > object_plane.z
+		{
+			out_clip_planes_2d[num_clip_planes_2d] = *cur_plane;
+			num_clip_planes_2d += 1;
+		}
+	}
+
+	Some((num_clip_planes_3d, num_clip_planes_2d))
+}
+
+// Returns list of mips (up to two) and blending modes for drawing of model polygon with given mip level.
+fn get_triangle_model_polygon_mips(
+	mip_f: f32,
+	mip_blending: bool,
+	blending_mode: material::BlendingMode,
+) -> ([(u32, material::BlendingMode); 2], usize)
+{
+	let mip = (mip_f as u32).min(MAX_MIP as u32);
+	let mip_fract = mip_f - (mip as f32);
+
+	let mut result = [(mip, blending_mode), (mip, blending_mode)];
+	let mut num_mips = 1;
+	if mip_blending && mip < MAX_MIP as u32
+	{
+		// Use simple 3-step blending - lower mip, average of two mips, upper mip.
+		if mip_fract >= 0.75
+		{
+			result[0].0 = mip + 1;
+		}
+		else if mip_fract >= 0.25
+		{
+			result[1] = (mip + 1, material::BlendingMode::Average);
+			num_mips = 2;
+		}
+	}
+
+	(result, num_mips)
+}
+
+// Model polygon texture coordinates are in texels of first mip. Convert them into coordinates of given mip.
+fn get_model_polygon_mip_tc(tc: &Vec2f, tc_shift: &Vec2f, mip: u32) -> Vec2f
+{
+	(tc - tc_shift) * (1.0 / ((1 << mip) as f32)) + tc_shift
+}
+
+fn fill_model_polygon<'a, ColorT: AbstractColor>(
+	rasterizer: &mut Rasterizer<'a, ColorT>,
+	polygon: &[ModelVertex2d],
+	tc_shift: &Vec2f,
+	mip: u32,
+	texture: &TextureLite,
+	blending_mode: material::BlendingMode,
+)
+{
+	let texture_info = TextureInfo {
+		size: [texture.size[0] as i32, texture.size[1] as i32],
+	};
+
+	let mut vertices_fixed = unsafe { std::mem::zeroed::<[TrianglePointProjected; MAX_VERTICES]>() };
+	for (src, dst) in polygon.iter().zip(vertices_fixed.iter_mut())
+	{
+		let tc = get_model_polygon_mip_tc(&src.tc, tc_shift, mip);
+		*dst = TrianglePointProjected {
+			x: f32_to_fixed16(src.pos.x),
+			y: f32_to_fixed16(src.pos.y),
+			tc: [f32_to_fixed16(tc.x), f32_to_fixed16(tc.y)],
+			light: [
+				f32_to_fixed16(src.light[0]),
+				f32_to_fixed16(src.light[1]),
+				f32_to_fixed16(src.light[2]),
+			],
+			inv_z: src.inv_z,
+		};
+	}
+
+	for t in 0 .. polygon.len() - 2
+	{
+		rasterizer.fill_triangle(
+			&[vertices_fixed[0], vertices_fixed[t + 1], vertices_fixed[t + 2]],
+			&texture_info,
+			&texture.pixels,
+			blending_mode,
+		);
+	}
+}
+
+fn fill_model_polygon_normal_mapped<'a, ColorT: AbstractColor>(
+	rasterizer: &mut Rasterizer<'a, ColorT>,
+	polygon: &[ModelVertex2d],
+	tc_shift: &Vec2f,
+	mip: u32,
+	texture: &Texture,
+	blending_mode: material::BlendingMode,
+)
+{
+	let texture_info = TextureInfo {
+		size: [texture.size[0] as i32, texture.size[1] as i32],
+	};
+
+	let mut vertices_projected = unsafe { std::mem::zeroed::<[TrianglePointProjectedNormalMapped; MAX_VERTICES]>() };
+	for (src, dst) in polygon.iter().zip(vertices_projected.iter_mut())
+	{
+		let tc = get_model_polygon_mip_tc(&src.tc, tc_shift, mip);
+		let l = &src.tangent_space_light;
+		*dst = TrianglePointProjectedNormalMapped {
+			x: src.pos.x,
+			y: src.pos.y,
+			tc: [tc.x, tc.y],
+			light: src.light,
+			light_vector_scaled: [
+				l.light_vector_scaled.x,
+				l.light_vector_scaled.y,
+				l.light_vector_scaled.z,
+			],
+			light_color: l.light_color,
+			vec_to_camera: [l.vec_to_camera.x, l.vec_to_camera.y, l.vec_to_camera.z],
+			inv_z: src.inv_z,
+		};
+	}
+
+	for t in 0 .. polygon.len() - 2
+	{
+		rasterizer.fill_triangle_normal_mapped(
+			&[
+				vertices_projected[0],
+				vertices_projected[t + 1],
+				vertices_projected[t + 2],
+			],
+			&texture_info,
+			texture,
+			blending_mode,
+		);
+	}
+}
+
+// Clip given triangles and call given function for each projected polygon.
+fn clip_and_project_triangles<PolygonFunc: FnMut(&[ModelVertex2d])>(
+	vertices_combined: &[ModelVertex3d],
+	triangles: &[Triangle],
+	clip_planes_3d: &[Plane],
+	clip_planes_2d: &[Vec3f],
+	mut polygon_func: PolygonFunc,
+)
+{
+	if clip_planes_3d.is_empty() && clip_planes_2d.is_empty()
+	{
+		// Special case - perform no clipping at all, just draw source triangles.
+		for triangle in triangles
+		{
+			let vertices_projected = triangle.map(|index| {
+				let v = triangle_vertex_debug_checked_fetch(vertices_combined, index);
+				ModelVertex2d {
+					pos: v.pos.truncate() / v.pos.z,
+					inv_z: 1.0 / v.pos.z,
+					tc: v.tc,
+					light: v.light,
+					tangent_space_light: v.tangent_space_light,
+				}
+			});
+
+			polygon_func(&vertices_projected);
+		}
+	}
+	else
+	{
+		let mut vertices_clipped0 = unsafe { std::mem::zeroed::<[ModelVertex3d; MAX_VERTICES]>() };
+		let mut vertices_clipped1 = unsafe { std::mem::zeroed::<[ModelVertex3d; MAX_VERTICES]>() };
+		let mut vertices_projected0 = unsafe { std::mem::zeroed::<[ModelVertex2d; MAX_VERTICES]>() };
+		let mut vertices_projected1 = unsafe { std::mem::zeroed::<[ModelVertex2d; MAX_VERTICES]>() };
+
+		'triangles_loop: for triangle in triangles
+		{
+			let mut vc_src = &mut vertices_clipped0;
+			let mut vc_dst = &mut vertices_clipped1;
+
+			for (&index, dst_vertex) in triangle.iter().zip(vc_src.iter_mut())
+			{
+				*dst_vertex = triangle_vertex_debug_checked_fetch(vertices_combined, index);
+			}
+
+			let mut num_vertices = 3;
+			for clip_plane in clip_planes_3d
+			{
+				num_vertices = clip_3d_model_polygon_by_plane(&vc_src[.. num_vertices], clip_plane, vc_dst);
+				if num_vertices < 3
+				{
+					continue 'triangles_loop;
+				}
+				std::mem::swap(&mut vc_src, &mut vc_dst);
+			}
+
+			let mut vp_src = &mut vertices_projected0;
+			let mut vp_dst = &mut vertices_projected1;
+
+			for (src, dst) in vc_src[.. num_vertices].iter().zip(vp_src.iter_mut())
+			{
+				*dst = ModelVertex2d {
+					pos: src.pos.truncate() / src.pos.z,
+					inv_z: 1.0 / src.pos.z,
+					tc: src.tc,
+					light: src.light,
+					tangent_space_light: src.tangent_space_light,
+				};
+			}
+
+			for clip_plane in clip_planes_2d
+			{
+				num_vertices = clip_2d_model_polygon(&vp_src[.. num_vertices], clip_plane, vp_dst);
+				if num_vertices < 3
+				{
+					continue 'triangles_loop;
+				}
+				std::mem::swap(&mut vp_src, &mut vp_dst);
+			}
+
+			polygon_func(&vp_src[.. num_vertices]);
+		} // For triangles
+	}
+}
+
 fn draw_background<ColorT: Copy + Send + Sync>(pixels: &mut [ColorT], color: ColorT)
 {
 	let num_threads = rayon::current_num_threads();
@@ -2331,11 +3924,13 @@ fn draw_background_impl<ColorT: Copy>(pixels: &mut [ColorT], color: ColorT)
 	}
 }
 
+// "depth_write_equation" - equation for depth buffer writing, usually same as "depth_equation".
 fn draw_polygon<'a, ColorT: AbstractColor>(
 	rasterizer: &mut Rasterizer<'a, ColorT>,
 	clip_planes: &ClippingPolygonPlanes,
 	vertices_transformed: &[Vec3f],
 	depth_equation: &DepthEquation,
+	depth_write_equation: &DepthEquation,
 	tex_coord_equation: &TexCoordEquation,
 	texture_size: &[u32; 2],
 	texture_data: &[ColorT],
@@ -2414,6 +4009,7 @@ fn draw_polygon<'a, ColorT: AbstractColor>(
 		rasterizer.fill_polygon(
 			&vertices_for_rasterizer[0 .. vertex_count],
 			&depth_equation,
+			depth_write_equation,
 			&tex_coord_equation,
 			&texture_info,
 			texture_data,
@@ -2442,6 +4038,7 @@ fn draw_polygon<'a, ColorT: AbstractColor>(
 			rasterizer.fill_polygon(
 				&vertices_for_rasterizer[0 .. vertex_count],
 				&depth_equation_scaled,
+				depth_write_equation,
 				&tex_coord_equation_scaled,
 				&texture_info,
 				texture_data,
@@ -2454,6 +4051,7 @@ fn draw_polygon<'a, ColorT: AbstractColor>(
 			rasterizer.fill_polygon(
 				&vertices_for_rasterizer[0 .. vertex_count],
 				&depth_equation_scaled,
+				depth_write_equation,
 				&tex_coord_equation_scaled,
 				&texture_info,
 				texture_data,
@@ -2464,6 +4062,138 @@ fn draw_polygon<'a, ColorT: AbstractColor>(
 	}
 }
 
+fn get_nested_view_surface_info(surface_info: &system_window::SurfaceInfo) -> system_window::SurfaceInfo
+{
+	system_window::SurfaceInfo {
+		width: surface_info.width,
+		height: surface_info.height,
+		pitch: surface_info.width,
+	}
+}
+
+fn get_portal_plane(vertices: &[Vec3f]) -> Plane
+{
+	// Use Newell's method in order to obtain robust normal even for polygons with collinear vertices.
+	let mut normal = Vec3f::zero();
+	let mut center = Vec3f::zero();
+	for (i, v) in vertices.iter().enumerate()
+	{
+		let v_next = vertices[(i + 1) % vertices.len()];
+		normal += v.cross(v_next);
+		center += *v;
+	}
+	center /= vertices.len() as f32;
+
+	Plane {
+		vec: normal,
+		dist: normal.dot(center),
+	}
+}
+
+// Returns number of vertices.
+fn transform_portal_vertices(
+	vertices: &[Vec3f],
+	camera_matrices: &CameraMatrices,
+	out_vertices: &mut [Vec3f; MAX_VERTICES],
+) -> usize
+{
+	let mut vertex_count = 0;
+	for (src, dst) in vertices.iter().zip(out_vertices.iter_mut())
+	{
+		let v = camera_matrices.view_matrix * src.extend(1.0);
+		*dst = Vec3f::new(v.x, v.y, v.w);
+		vertex_count += 1;
+	}
+	vertex_count
+}
+
+// Check order of vertices after projection. Vertices are in view space (x * d, y * d, d).
+fn is_projected_polygon_clockwise(vertices_transformed: &[Vec3f]) -> bool
+{
+	// Use triple product in order to avoid division by z.
+	// Z is directed forward and Y is directed downwards, so, positive sign means clockwise order on screen.
+	let mut sum = 0.0;
+	for i in 1 .. vertices_transformed.len().saturating_sub(1)
+	{
+		sum += vertices_transformed[0].dot(vertices_transformed[i].cross(vertices_transformed[i + 1]));
+	}
+	sum >= 0.0
+}
+
+// Draw polygon using view inside mirror or portal as screen-space texture.
+// View inside mirror is flipped horizontally.
+fn draw_polygon_with_nested_view<'a, ColorT: AbstractColor>(
+	rasterizer: &mut Rasterizer<'a, ColorT>,
+	clip_planes: &ClippingPolygonPlanes,
+	vertices_transformed: &[Vec3f],
+	depth_equation: &DepthEquation,
+	view_pixels: &[ColorT],
+	view_size: &[u32; 2],
+	flip_x: bool,
+)
+{
+	if vertices_transformed.len() < 3 || view_pixels.len() < (view_size[0] * view_size[1]) as usize
+	{
+		return;
+	}
+
+	let mut vertices_2d = [Vec2f::zero(); MAX_VERTICES]; // TODO - use uninitialized memory
+	let vertex_count = project_and_clip_polygon(clip_planes, vertices_transformed, &mut vertices_2d[..]);
+	if vertex_count < 3
+	{
+		return;
+	}
+
+	let mut vertices_for_rasterizer = [PolygonPointProjected { x: 0, y: 0 }; MAX_VERTICES]; // TODO - use uninitialized memory
+	for (vertex_2d, vertex_for_rasterizer) in vertices_2d
+		.iter()
+		.take(vertex_count)
+		.zip(vertices_for_rasterizer.iter_mut())
+	{
+		*vertex_for_rasterizer = PolygonPointProjected {
+			x: unsafe { f32_to_fixed16_unchecked(vertex_2d.x) },
+			y: unsafe { f32_to_fixed16_unchecked(vertex_2d.y) },
+		};
+	}
+
+	// Use constant depth and texture coordinates equal to flipped screen coordinates.
+	// Actual polygon depth is used only for depth buffer writing.
+	let constant_depth_equation = DepthEquation {
+		d_inv_z_dx: 0.0,
+		d_inv_z_dy: 0.0,
+		k: 1.0,
+	};
+	let tex_coord_equation = if flip_x
+	{
+		TexCoordEquation {
+			d_tc_dx: [-1.0, 0.0],
+			d_tc_dy: [0.0, 1.0],
+			k: [view_size[0] as f32, 0.0],
+		}
+	}
+	else
+	{
+		TexCoordEquation {
+			d_tc_dx: [1.0, 0.0],
+			d_tc_dy: [0.0, 1.0],
+			k: [0.0, 0.0],
+		}
+	};
+
+	rasterizer.fill_polygon(
+		&vertices_for_rasterizer[0 .. vertex_count],
+		&constant_depth_equation,
+		depth_equation,
+		&tex_coord_equation,
+		&TextureInfo {
+			size: [view_size[0] as i32, view_size[1] as i32],
+		},
+		view_pixels,
+		TetureCoordinatesInterpolationMode::Affine,
+		material::BlendingMode::None,
+	);
+}
+
 fn affine_texture_coordinates_interpolation_may_be_used(
 	depth_equation: &DepthEquation,
 	tex_coord_equation: &TexCoordEquation,
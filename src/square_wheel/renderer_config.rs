@@ -19,8 +19,46 @@ pub struct RendererConfig
 	#[serde(default)]
 	pub dynamic_mip_bias: bool,
 
+	// 0 - nearest, 1 - dithered, 2 - bilinear.
+	#[serde(default)]
+	pub texture_filtering: u32,
+
+	// Smooth transition between mips of triangle models. Makes rendering of some model triangles twice slower.
+	#[serde(default)]
+	pub triangle_models_mip_blending: bool,
+
 	#[serde(default = "default_true")]
 	pub use_directional_lightmaps: bool,
+
+	#[serde(default = "default_true")]
+	pub dynamic_lights_shadows_for_models_and_decals: bool,
+
+	#[serde(default = "default_true")]
+	pub use_fog: bool,
+
+	// Apply screen warp and color tint if camera is inside liquid.
+	#[serde(default = "default_true")]
+	pub underwater_effects: bool,
+
+	// Write depth buffer during rasterization. Needed for depth-aware postprocessing effects and picking.
+	#[serde(default)]
+	pub depth_output: bool,
+
+	// Maximum number of nested mirror views. 0 - disable mirrors.
+	#[serde(default = "default_mirrors_max_depth")]
+	pub mirrors_max_depth: u32,
+
+	// Maximum number of mirror views, rendered in whole frame (including mirrors inside other views).
+	#[serde(default = "default_max_mirrors_per_frame")]
+	pub max_mirrors_per_frame: u32,
+
+	// Maximum number of nested portal views. 0 - disable portals.
+	#[serde(default = "default_portals_max_depth")]
+	pub portals_max_depth: u32,
+
+	// Maximum number of portal views, rendered in whole frame (including portals inside other views).
+	#[serde(default = "default_max_portals_per_frame")]
+	pub max_portals_per_frame: u32,
 }
 
 impl RendererConfig
@@ -43,3 +81,23 @@ fn default_true() -> bool
 {
 	true
 }
+
+fn default_mirrors_max_depth() -> u32
+{
+	1
+}
+
+fn default_max_mirrors_per_frame() -> u32
+{
+	2
+}
+
+fn default_portals_max_depth() -> u32
+{
+	1
+}
+
+fn default_max_portals_per_frame() -> u32
+{
+	2
+}
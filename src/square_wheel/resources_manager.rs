@@ -2,7 +2,9 @@ use super::{
 	abstract_color::*, config, console::*, resources_manager_config::*, textures::*, triangle_model,
 	triangle_model_iqm, triangle_model_md3,
 };
-use crate::common::{bbox::*, bsp_map_compact::*, bsp_map_save_load::*, color::*, image, material::*, math_types::*};
+use crate::common::{
+	bbox::*, bsp_map_compact::*, bsp_map_save_load::*, color::*, image, image_hdr, material::*, math_types::*,
+};
 use std::{
 	collections::HashMap,
 	path::PathBuf,
@@ -26,9 +28,12 @@ pub struct ResourcesManager
 	images: ResourcesMap<image::Image>,
 	stub_image: SharedResourcePtr<image::Image>,
 	material_textures: ResourcesMap<TextureWithMips>,
+	material_animation_frames: ResourcesMap<Vec<SharedResourcePtr<TextureWithMips>>>,
 	lite_textures: ResourcesMap<TextureLiteWithMips>,
 	skybox_textures_32: ResourcesMap<SkyboxTextures<Color32>>,
 	skybox_textures_64: ResourcesMap<SkyboxTextures<Color64>>,
+	// Results of camera views rendering, updated by renderer each frame.
+	camera_view_textures: ResourcesMap<TextureLiteWithMips>,
 }
 
 pub type ResourcesManagerSharedPtr = Arc<Mutex<ResourcesManager>>;
@@ -59,9 +64,11 @@ impl ResourcesManager
 			images: ResourcesMap::new(),
 			stub_image: SharedResourcePtr::new(image::make_stub()),
 			material_textures: ResourcesMap::new(),
+			material_animation_frames: ResourcesMap::new(),
 			lite_textures: ResourcesMap::new(),
 			skybox_textures_32: ResourcesMap::new(),
 			skybox_textures_64: ResourcesMap::new(),
+			camera_view_textures: ResourcesMap::new(),
 		}))
 	}
 
@@ -191,6 +198,49 @@ impl ResourcesManager
 		ptr
 	}
 
+	// Returns empty list for non-animated materials.
+	pub fn get_material_animation_frames(
+		&mut self,
+		key: &ResourceKey,
+	) -> SharedResourcePtr<Vec<SharedResourcePtr<TextureWithMips>>>
+	{
+		if let Some(p) = self.material_animation_frames.get(key)
+		{
+			return p.clone();
+		}
+
+		// All frames should have size of base texture, since texture coordinates are calculated for it.
+		let base_size = self.get_material_texture(key)[0].size;
+
+		let material = self.materials.get(key).unwrap_or_else(|| {
+			self.console
+				.lock()
+				.unwrap()
+				.add_text(format!("Failed to find material {:?}", key));
+			&self.default_material
+		});
+
+		let frames = load_animation_frames(material, &self.config.textures_path, base_size, &self.console);
+
+		let ptr = SharedResourcePtr::new(frames);
+		self.material_animation_frames.insert(key.clone(), ptr.clone());
+
+		ptr
+	}
+
+	// Get texture of given frame of flipbook animation of material (for models with animated materials).
+	// Returns regular material texture for non-animated materials.
+	pub fn get_material_animation_frame(&mut self, key: &ResourceKey, frame: u32)
+		-> SharedResourcePtr<TextureWithMips>
+	{
+		let frames = self.get_material_animation_frames(key);
+		if frames.is_empty()
+		{
+			return self.get_material_texture(key);
+		}
+		frames[(frame as usize) % frames.len()].clone()
+	}
+
 	pub fn get_texture_lite(&mut self, key: &ResourceKey) -> SharedResourcePtr<TextureLiteWithMips>
 	{
 		if let Some(p) = self.lite_textures.get(key)
@@ -220,7 +270,7 @@ impl ResourcesManager
 			&self.default_material
 		});
 
-		let skybox_texture = load_skybox_texture(material, &self.config.textures_path);
+		let skybox_texture = load_skybox_texture(material, &self.config.textures_path, true, &self.console);
 
 		let ptr = SharedResourcePtr::new(skybox_texture);
 		self.skybox_textures_32.insert(key.clone(), ptr.clone());
@@ -243,7 +293,7 @@ impl ResourcesManager
 			&self.default_material
 		});
 
-		let skybox_texture = load_skybox_texture(material, &self.config.textures_path);
+		let skybox_texture = load_skybox_texture(material, &self.config.textures_path, false, &self.console);
 
 		let ptr = SharedResourcePtr::new(skybox_texture);
 		self.skybox_textures_64.insert(key.clone(), ptr.clone());
@@ -251,12 +301,47 @@ impl ResourcesManager
 		ptr
 	}
 
+	// Get result of camera view rendering of previous frame.
+	pub fn get_camera_view_texture(&mut self, name: &str) -> Option<SharedResourcePtr<TextureLiteWithMips>>
+	{
+		self.camera_view_textures.get(name).cloned()
+	}
+
+	// Memory of texture of previous frame is reused, if this texture is not used anymore.
+	pub fn set_camera_view_texture(&mut self, name: &str, image: &image::Image)
+	{
+		if let Some(texture) = self
+			.camera_view_textures
+			.get_mut(name)
+			.and_then(SharedResourcePtr::get_mut)
+		{
+			if texture[0].size == image.size
+			{
+				texture[0].pixels.copy_from_slice(&image.pixels);
+				update_texture_lite_mips(texture);
+				return;
+			}
+		}
+
+		self.camera_view_textures.insert(
+			name.to_string(),
+			SharedResourcePtr::new(make_texture_lite_mips(image.clone())),
+		);
+	}
+
+	// Remove textures of camera views for which given predicate returns false.
+	pub fn retain_camera_view_textures<F: FnMut(&str) -> bool>(&mut self, mut f: F)
+	{
+		self.camera_view_textures.retain(|name, _| f(name));
+	}
+
 	pub fn clear_cache(&mut self)
 	{
 		// Remove all resources that are stored only inside cache.
 		remove_unused_resource_map_entries(&mut self.models);
 		remove_unused_resource_map_entries(&mut self.images);
 		remove_unused_resource_map_entries(&mut self.material_textures);
+		remove_unused_resource_map_entries(&mut self.material_animation_frames);
 		remove_unused_resource_map_entries(&mut self.lite_textures);
 		remove_unused_resource_map_entries(&mut self.skybox_textures_32);
 		remove_unused_resource_map_entries(&mut self.skybox_textures_64);
@@ -280,6 +365,7 @@ fn make_stub_model() -> triangle_model::TriangleModel
 			triangles: Vec::new(),
 			num_frames: 1,
 			vertex_data: triangle_model::VertexData::SkeletonAnimated(Vec::new()),
+			tangent_space: Vec::new(),
 		}],
 		bones: Vec::new(),
 		tc_shift: Vec2f::zero(),
@@ -300,6 +386,85 @@ fn load_texture(material: &Material, textures_path: &str) -> TextureWithMips
 		image::make_stub()
 	};
 
+	load_texture_with_diffuse(material, diffuse, textures_path)
+}
+
+fn load_animation_frames(
+	material: &Material,
+	textures_path: &str,
+	base_size: [u32; 2],
+	console: &ConsoleSharedPtr,
+) -> Vec<SharedResourcePtr<TextureWithMips>>
+{
+	let animation = if let Some(a) = &material.animation
+	{
+		a
+	}
+	else
+	{
+		return Vec::new();
+	};
+
+	let mut diffuse_images = Vec::new();
+	if !animation.frames.is_empty()
+	{
+		for frame in &animation.frames
+		{
+			diffuse_images.push(load_image(frame, textures_path).unwrap_or_else(image::make_stub));
+		}
+	}
+	else if let Some(frame_strip) = &animation.frame_strip
+	{
+		if let Some(strip_image) = load_image(&frame_strip.image, textures_path)
+		{
+			// Frames are placed vertically, so each frame is just a continuous range of rows.
+			let num_frames = frame_strip.num_frames.max(1);
+			let frame_height = strip_image.size[1] / num_frames;
+			if frame_height == 0
+			{
+				console
+					.lock()
+					.unwrap()
+					.add_text(format!("Invalid frame strip {:?}", frame_strip.image));
+			}
+			else
+			{
+				let frame_pixels = (strip_image.size[0] * frame_height) as usize;
+				for frame_index in 0 .. num_frames as usize
+				{
+					diffuse_images.push(image::Image {
+						size: [strip_image.size[0], frame_height],
+						pixels: strip_image.pixels[frame_index * frame_pixels .. (frame_index + 1) * frame_pixels]
+							.to_vec(),
+					});
+				}
+			}
+		}
+	}
+
+	diffuse_images
+		.into_iter()
+		.enumerate()
+		.map(|(frame_index, diffuse)| {
+			let diffuse = if diffuse.size != base_size
+			{
+				console.lock().unwrap().add_text(format!(
+					"Animation frame {} has size {:?}, different from texture size {:?}, resampling it",
+					frame_index, diffuse.size, base_size
+				));
+				resize_image(&diffuse, base_size)
+			}
+			else
+			{
+				diffuse
+			};
+			SharedResourcePtr::new(load_texture_with_diffuse(material, diffuse, textures_path))
+		})
+		.collect()
+}
+
+fn load_texture_with_diffuse(material: &Material, diffuse: image::Image, textures_path: &str) -> TextureWithMips
+{
 	let normals = if let Some(normal_map_texture) = &material.normal_map
 	{
 		load_image(&normal_map_texture.clone(), textures_path)
@@ -318,12 +483,35 @@ fn load_texture(material: &Material, textures_path: &str) -> TextureWithMips
 		None
 	};
 
-	let mip0 = make_texture(diffuse, normals, material.roughness, roughness_map, material.is_metal);
+	let emissive = if let Some(emissive_texture) = &material.emissive_texture
+	{
+		load_image(&emissive_texture.clone(), textures_path)
+	}
+	else
+	{
+		None
+	};
+
+	let mip0 = make_texture(
+		diffuse,
+		normals,
+		material.roughness,
+		roughness_map,
+		material.is_metal,
+		emissive,
+		material.emissive_texture_brightness,
+	);
 
 	build_texture_mips(mip0)
 }
 
-fn load_skybox_texture<ColorT: AbstractColor>(material: &Material, textures_path: &str) -> SkyboxTextures<ColorT>
+// If "tonemap" is true - compress HDR images into regular color range.
+fn load_skybox_texture<ColorT: AbstractColor>(
+	material: &Material,
+	textures_path: &str,
+	tonemap: bool,
+	console: &ConsoleSharedPtr,
+) -> SkyboxTextures<ColorT>
 {
 	// TODO - avoid "unwrap".
 	let skybox = material.skybox.as_ref().unwrap();
@@ -331,7 +519,7 @@ fn load_skybox_texture<ColorT: AbstractColor>(material: &Material, textures_path
 	let mut result = SkyboxTextures::default();
 	for (side_image, out_side) in skybox.side_images.iter().zip(result.iter_mut())
 	{
-		*out_side = load_skybox_texture_side(textures_path, &side_image, skybox.brightness);
+		*out_side = load_skybox_texture_side(textures_path, &side_image, skybox.brightness, tonemap, console);
 	}
 	result
 }
@@ -340,6 +528,8 @@ fn load_skybox_texture_side<ColorT: AbstractColor>(
 	textures_path: &str,
 	texture_image_name: &str,
 	brightness: f32,
+	tonemap: bool,
+	console: &ConsoleSharedPtr,
 ) -> SkyboxSideTextureWithMips<ColorT>
 {
 	if texture_image_name.is_empty()
@@ -347,6 +537,21 @@ fn load_skybox_texture_side<ColorT: AbstractColor>(
 		return SkyboxSideTextureWithMips::default();
 	}
 
+	if image_hdr::is_hdr_file_name(texture_image_name)
+	{
+		let mut path = PathBuf::from(textures_path);
+		path.push(texture_image_name);
+		match image_hdr::load(&path)
+		{
+			Ok(image) =>
+			{
+				let mip0 = make_skybox_side_texture_hdr(&image, brightness, tonemap);
+				return make_skybox_side_texture_mips(mip0);
+			},
+			Err(e) => console.lock().unwrap().add_text(e),
+		}
+	}
+
 	let image = load_image(texture_image_name, textures_path).unwrap_or_else(image::make_stub);
 	let mip0 = make_skybox_side_texture(&image, brightness);
 	make_skybox_side_texture_mips(mip0)
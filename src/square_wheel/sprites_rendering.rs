@@ -0,0 +1,128 @@
+use super::{frame_info::*, triangle_model::*, triangle_models_rendering::*};
+use crate::common::{bbox::*, bsp_map_compact, clipping::*, math_types::*, matrix::*};
+
+// Sprite quad consists of two triangles.
+pub const SPRITE_TRIANGLES: [Triangle; 2] = [[0, 1, 2], [0, 2, 3]];
+
+// Get bbox, containing sprite with any orientation and rotation angle.
+pub fn get_sprite_bbox(sprite: &Sprite) -> BBox
+{
+	let half_diagonal = 0.5 * sprite.size.magnitude();
+	let half_size = Vec3f::new(half_diagonal, half_diagonal, half_diagonal);
+	BBox::from_min_max(sprite.position - half_size, sprite.position + half_size)
+}
+
+// Get bbox used for sprite mip calculation.
+pub fn get_sprite_bbox_for_mip_calculation(sprite: &Sprite) -> BBox
+{
+	let half_size_scalar = 0.5 * sprite.size.x.max(sprite.size.y);
+	let half_size = Vec3f::new(half_size_scalar, half_size_scalar, half_size_scalar);
+	BBox::from_min_max(sprite.position - half_size, sprite.position + half_size)
+}
+
+// Sprites have no normal, so, use average light from all directions.
+pub fn get_sprite_light(map: &bsp_map_compact::BSPMap, sprite: &Sprite) -> [f32; 3]
+{
+	let light = get_entity_light(map, &sprite.lighting, &sprite.position);
+
+	let directional_light_scale = 0.5 * light.light_direction_vector_scaled.magnitude();
+
+	let mut result = [0.0; 3];
+	for i in 0 .. 3
+	{
+		for side in &light.light_cube
+		{
+			result[i] += side[i];
+		}
+		result[i] = result[i] / 6.0 + light.directional_light_color[i] * directional_light_scale;
+	}
+
+	result
+}
+
+// Build transformed vertices of sprite quad.
+// Texture coordinates are in texels of given texture mip.
+pub fn build_sprite_vertices(
+	sprite: &Sprite,
+	camera_matrices: &CameraMatrices,
+	texture_size: [u32; 2],
+	light: [f32; 3],
+) -> [ModelVertex3d; 4]
+{
+	let (right, up) = get_sprite_basis(sprite, camera_matrices);
+
+	let half_width_vec = right * (0.5 * sprite.size.x);
+	let half_height_vec = up * (0.5 * sprite.size.y);
+	let texture_size_f = Vec2f::new(texture_size[0] as f32, texture_size[1] as f32);
+
+	let corners = [
+		(sprite.position - half_width_vec + half_height_vec, Vec2f::new(0.0, 0.0)),
+		(
+			sprite.position + half_width_vec + half_height_vec,
+			Vec2f::new(texture_size_f.x, 0.0),
+		),
+		(sprite.position + half_width_vec - half_height_vec, texture_size_f),
+		(
+			sprite.position - half_width_vec - half_height_vec,
+			Vec2f::new(0.0, texture_size_f.y),
+		),
+	];
+
+	corners.map(|(pos, tc)| {
+		let pos_transformed = camera_matrices.view_matrix * pos.extend(1.0);
+		ModelVertex3d {
+			pos: Vec3f::new(pos_transformed.x, pos_transformed.y, pos_transformed.w),
+			tc,
+			light,
+			tangent_space_light: ModelVertexTangentSpaceLight::zero(),
+		}
+	})
+}
+
+// Returns normalized world-space vectors for sprite width and height axes.
+fn get_sprite_basis(sprite: &Sprite, camera_matrices: &CameraMatrices) -> (Vec3f, Vec3f)
+{
+	let (right, up) = match sprite.orientation
+	{
+		SpriteOrientation::FacingTowardsCamera => get_camera_plane_basis(camera_matrices),
+		SpriteOrientation::AlignedToZAxis =>
+		{
+			let vec_to_camera = camera_matrices.position - sprite.position;
+			let right = Vec3f::new(-vec_to_camera.y, vec_to_camera.x, 0.0);
+			let right_length = right.magnitude();
+			if right_length > 0.0
+			{
+				(right / right_length, Vec3f::unit_z())
+			}
+			else
+			{
+				// Camera is exactly above or below sprite.
+				get_camera_plane_basis(camera_matrices)
+			}
+		},
+		SpriteOrientation::Fixed(rotation) => (rotation * Vec3f::unit_x(), rotation * Vec3f::unit_z()),
+	};
+
+	if sprite.angle == 0.0
+	{
+		return (right, up);
+	}
+
+	let (sin, cos) = sprite.angle.sin_cos();
+	(right * cos + up * sin, up * cos - right * sin)
+}
+
+// Calculate world-space vectors for screen right and screen up directions.
+fn get_camera_plane_basis(camera_matrices: &CameraMatrices) -> (Vec3f, Vec3f)
+{
+	// Use inverse view matrix to obtain vectors, changing only screen x or only screen y.
+	let view_matrix_inverse = camera_matrices.view_matrix.invert().unwrap_or_else(Mat4f::identity);
+	let right = (view_matrix_inverse * Vec4f::unit_x()).truncate();
+	// Screen y axis is directed downwards.
+	let up = -(view_matrix_inverse * Vec4f::unit_y()).truncate();
+
+	(
+		right / right.magnitude().max(0.00000001),
+		up / up.magnitude().max(0.00000001),
+	)
+}
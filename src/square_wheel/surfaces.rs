@@ -1,4 +1,4 @@
-use super::{abstract_color::*, fast_math::*, light::*, shadow_map::*, textures};
+use super::{abstract_color::*, cpu_features::*, fast_math::*, fog::*, light::*, shadow_map::*, textures};
 use crate::common::{bsp_map_compact, lightmap, math_types::*, plane::*};
 
 pub type LightWithShadowMap<'a, 'b> = (&'a PointLight, &'b CubeShadowMap);
@@ -182,6 +182,48 @@ fn build_surface_impl_2_static_params<ColorT: AbstractColor, LightmapElementOpsT
 	{
 		panic!("Wrong lightmap_scale_log2, expected value in range [1; 4]!");
 	}
+
+	if !texture.emissive.is_empty()
+	{
+		add_surface_emissive(surface_size, surface_tc_min, texture, out_surface_data);
+	}
+}
+
+// Add emissive light after lighting in order to make emissive texels bright even in darkness.
+fn add_surface_emissive<ColorT: AbstractColor>(
+	surface_size: [u32; 2],
+	surface_tc_min: [i32; 2],
+	texture: &textures::Texture,
+	out_surface_data: &mut [ColorT],
+)
+{
+	const BRIGHTNESS_SHIFT: i32 = 8;
+	let brightness_i = (texture.emissive_brightness * ((1 << BRIGHTNESS_SHIFT) as f32)) as i32;
+	// Alpha of brightness vector is zero in order to preserve alpha of destination texel.
+	let brightness_vec = ColorVecI::from_color_i32x3(&[brightness_i, brightness_i, brightness_i]);
+	for dst_v in 0 .. surface_size[1]
+	{
+		let dst_line_start = (dst_v * surface_size[0]) as usize;
+		let dst_line = &mut out_surface_data[dst_line_start .. dst_line_start + (surface_size[0] as usize)];
+
+		let src_v = (surface_tc_min[1] + (dst_v as i32)).rem_euclid(texture.size[1] as i32);
+		let src_line_start = ((src_v as u32) * texture.size[0]) as usize;
+		let src_line = &texture.emissive[src_line_start .. src_line_start + (texture.size[0] as usize)];
+		let mut src_u = surface_tc_min[0].rem_euclid(texture.size[0] as i32);
+		for dst_texel in dst_line.iter_mut()
+		{
+			let emissive = unsafe { debug_only_checked_fetch(src_line, src_u as usize) };
+			let emissive_vec =
+				ColorVecI::shift_right::<BRIGHTNESS_SHIFT>(&ColorVecI::mul(&emissive.into(), &brightness_vec));
+			*dst_texel = ColorVecI::add(&(*dst_texel).into(), &emissive_vec).into();
+
+			src_u += 1;
+			if src_u == (texture.size[0] as i32)
+			{
+				src_u = 0;
+			}
+		}
+	}
 }
 
 fn build_surface_impl_3_static_params<
@@ -412,33 +454,72 @@ fn build_surface_impl_6_static_params<
 	out_surface_data: &mut [ColorT],
 )
 {
-	// Calculate inverse matrix for tex_coord equation and plane equation in order to calculate world position for UV.
-	// TODO - project tc equation to surface plane?
-	let tex_coord_basis = Mat4f::from_cols(
-		tex_coord_equation[0].vec.extend(tex_coord_equation[0].dist),
-		tex_coord_equation[1].vec.extend(tex_coord_equation[1].dist),
-		plane.vec.extend(-plane.dist),
-		Vec4f::new(0.0, 0.0, 0.0, 1.0),
-	);
-	let tex_coord_basis_inverted_opt = tex_coord_basis.transpose().invert();
-	if tex_coord_basis_inverted_opt.is_none()
+	call_with_simd_level!(
+		SIMD_LEVEL,
+		build_surface_impl_7::<
+			ColorT,
+			LightmapElementOpsT,
+			LIGHTAP_SCALE_LOG2,
+			USE_DYNAMIC_LIGHTS,
+			USE_NORMAL_MAP,
+			SPECULAR_TYPE,
+			SIMD_LEVEL,
+		>(
+			plane,
+			tex_coord_equation,
+			surface_size,
+			surface_tc_min,
+			texture,
+			lightmap_size,
+			lightmap_tc_shift,
+			lightmap_data,
+			dynamic_lights,
+			cam_pos,
+			out_surface_data,
+		)
+	)
+}
+
+// Should be inlined into SIMD level entry function in order to be compiled with its target features.
+#[inline(always)]
+fn build_surface_impl_7<
+	ColorT: AbstractColor,
+	LightmapElementOpsT: LightmapElementOps,
+	const LIGHTAP_SCALE_LOG2: u32,
+	const USE_DYNAMIC_LIGHTS: bool,
+	const USE_NORMAL_MAP: bool,
+	const SPECULAR_TYPE: u32,
+	const SIMD_LEVEL: u32,
+>(
+	plane: &Plane,
+	tex_coord_equation: &[Plane; 2],
+	surface_size: [u32; 2],
+	surface_tc_min: [i32; 2],
+	texture: &textures::Texture,
+	lightmap_size: [u32; 2],
+	lightmap_tc_shift: [u32; 2],
+	lightmap_data: &[LightmapElementOpsT::LightmapElement],
+	dynamic_lights: &[LightWithShadowMap],
+	cam_pos: &Vec3f,
+	out_surface_data: &mut [ColorT],
+)
+{
+	let (start_pos, u_vec, v_vec) = if let Some(basis) =
+		get_surface_texels_positions_basis(plane, tex_coord_equation, surface_tc_min)
 	{
-		return;
+		basis
 	}
-	let tex_coord_basis_inverted = tex_coord_basis_inverted_opt.unwrap();
-
-	let u_vec = tex_coord_basis_inverted.x.truncate();
-	let v_vec = tex_coord_basis_inverted.y.truncate();
-	let start_pos = tex_coord_basis_inverted.w.truncate() +
-		u_vec * ((surface_tc_min[0]) as f32 + 0.5) +
-		v_vec * ((surface_tc_min[1]) as f32 + 0.5);
+	else
+	{
+		return;
+	};
 
-	let plane_normal_normalized = plane.vec * inv_sqrt_fast(vec3_len2(&plane.vec));
+	let plane_normal_normalized = plane.vec * inv_sqrt_fast(vec3_len2::<SIMD_LEVEL>(&plane.vec));
 
 	// Use texture basis vectors as basis for normal transformation.
 	// This may be inaccurate if texture is non-uniformly stretched or shifted, but it is still fine for most cases.
-	let u_vec_normalized = u_vec * inv_sqrt_fast(vec3_len2(&u_vec).max(MIN_POSITIVE_VALUE));
-	let v_vec_normalized = v_vec * inv_sqrt_fast(vec3_len2(&v_vec).max(MIN_POSITIVE_VALUE));
+	let u_vec_normalized = u_vec * inv_sqrt_fast(vec3_len2::<SIMD_LEVEL>(&u_vec).max(MIN_POSITIVE_VALUE));
+	let v_vec_normalized = v_vec * inv_sqrt_fast(vec3_len2::<SIMD_LEVEL>(&v_vec).max(MIN_POSITIVE_VALUE));
 
 	// TODO - use uninitialized memory instead.
 	let mut line_lightmap = unsafe {
@@ -459,7 +540,7 @@ fn build_surface_impl_6_static_params<
 			let lightmap_v = lightmap_base_v >> LIGHTAP_SCALE_LOG2;
 			let lightmap_v_plus_one = lightmap_v + 1;
 			debug_assert!(lightmap_v_plus_one < lightmap_size[1]);
-			let k = f32_mul_add(
+			let k = f32_mul_add::<SIMD_LEVEL>(
 				(lightmap_base_v & lightmap_fetch_mask) as f32,
 				inv_lightmap_scale_f,
 				k_shift,
@@ -476,7 +557,7 @@ fn build_surface_impl_6_static_params<
 						(base_lightmap_address + 2 * lightmap_size[0]) as usize],
 				)
 			{
-				*dst = LightmapElementOpsT::mix(&l1, &l0, k);
+				*dst = LightmapElementOpsT::mix::<SIMD_LEVEL>(&l1, &l0, k);
 			}
 		}
 
@@ -488,10 +569,10 @@ fn build_surface_impl_6_static_params<
 		let src_line = &texture.pixels[src_line_start .. src_line_start + (texture.size[0] as usize)];
 		let mut src_u = surface_tc_min[0].rem_euclid(texture.size[0] as i32);
 		let mut dst_u = 0;
-		let start_pos_v = vec3_scalar_mul_add(&v_vec, dst_v as f32, &start_pos);
+		let start_pos_v = vec3_scalar_mul_add::<SIMD_LEVEL>(&v_vec, dst_v as f32, &start_pos);
 		for dst_texel in dst_line.iter_mut()
 		{
-			let pos = vec3_scalar_mul_add(&u_vec, dst_u as f32, &start_pos_v);
+			let pos = vec3_scalar_mul_add::<SIMD_LEVEL>(&u_vec, dst_u as f32, &start_pos_v);
 
 			let texel_value = unsafe { debug_only_checked_fetch(src_line, src_u as usize) };
 			let (texel_normal, texel_roughness) = if USE_NORMAL_MAP
@@ -510,8 +591,8 @@ fn build_surface_impl_6_static_params<
 				}
 			};
 
-			let mut total_light_albedo_modulated = ColorVec::zero();
-			let mut total_light_direct = ColorVec::zero();
+			let mut total_light_albedo_modulated = SimdColorVec::<SIMD_LEVEL>::zero();
+			let mut total_light_direct = SimdColorVec::<SIMD_LEVEL>::zero();
 			if LIGHTAP_SCALE_LOG2 != NO_LIGHTMAP_SCALE
 			{
 				let lightmap_base_u = dst_u + lightmap_tc_shift[0];
@@ -520,30 +601,31 @@ fn build_surface_impl_6_static_params<
 				debug_assert!(lightmap_u_plus_one < lightmap_size[0]);
 				let l0 = unsafe { debug_only_checked_fetch(&line_lightmap, lightmap_u as usize) };
 				let l1 = unsafe { debug_only_checked_fetch(&line_lightmap, lightmap_u_plus_one as usize) };
-				let k = f32_mul_add(
+				let k = f32_mul_add::<SIMD_LEVEL>(
 					(lightmap_base_u & lightmap_fetch_mask) as f32,
 					inv_lightmap_scale_f,
 					k_shift,
 				);
-				let l_mixed = LightmapElementOpsT::mix(&l1, &l0, k);
+				let l_mixed = LightmapElementOpsT::mix::<SIMD_LEVEL>(&l1, &l0, k);
 
 				if SPECULAR_TYPE == SPECULAR_TYPE_NONE
 				{
-					total_light_albedo_modulated =
-						ColorVec::from_color_f32x3_with_one(&LightmapElementOpsT::get_constant_component(&l_mixed));
+					total_light_albedo_modulated = SimdColorVec::<SIMD_LEVEL>::from_color_f32x3_with_one(
+						&LightmapElementOpsT::get_constant_component(&l_mixed),
+					);
 					if let Some(directional_component) = LightmapElementOpsT::get_directional_component(&l_mixed)
 					{
 						let dot = if USE_NORMAL_MAP
 						{
-							vec3_dot(&directional_component.vector_scaled, &texel_normal).max(0.0)
+							vec3_dot::<SIMD_LEVEL>(&directional_component.vector_scaled, &texel_normal).max(0.0)
 						}
 						else
 						{
 							directional_component.vector_scaled.z
 						};
 
-						total_light_albedo_modulated = ColorVec::mul_scalar_add(
-							&ColorVec::from_color_f32x3(&directional_component.color),
+						total_light_albedo_modulated = SimdColorVec::mul_scalar_add(
+							&SimdColorVec::<SIMD_LEVEL>::from_color_f32x3(&directional_component.color),
 							dot,
 							&total_light_albedo_modulated,
 						);
@@ -562,15 +644,15 @@ fn build_surface_impl_6_static_params<
 
 					let vec_to_camera = cam_pos - pos;
 					let vec_to_camera_texture_space = Vec3f::new(
-						vec3_dot(&vec_to_camera, &u_vec_normalized),
-						vec3_dot(&vec_to_camera, &v_vec_normalized),
-						vec3_dot(&vec_to_camera, &plane_normal_normalized),
+						vec3_dot::<SIMD_LEVEL>(&vec_to_camera, &u_vec_normalized),
+						vec3_dot::<SIMD_LEVEL>(&vec_to_camera, &v_vec_normalized),
+						vec3_dot::<SIMD_LEVEL>(&vec_to_camera, &plane_normal_normalized),
 					);
 
-					let vec_to_camera_normal_dot = vec3_dot(&vec_to_camera_texture_space, &normal);
+					let vec_to_camera_normal_dot = vec3_dot::<SIMD_LEVEL>(&vec_to_camera_texture_space, &normal);
 					let vec_to_camera_reflected =
 						normal * (2.0 * vec_to_camera_normal_dot) - vec_to_camera_texture_space;
-					let vec_to_camera_len2 = vec3_len2(&vec_to_camera_reflected).max(MIN_POSITIVE_VALUE);
+					let vec_to_camera_len2 = vec3_len2::<SIMD_LEVEL>(&vec_to_camera_reflected).max(MIN_POSITIVE_VALUE);
 
 					let vec_to_camera_normal_angle_cos =
 						(vec_to_camera_normal_dot * inv_sqrt_fast(vec_to_camera_len2)).max(0.0);
@@ -578,11 +660,11 @@ fn build_surface_impl_6_static_params<
 
 					let specular_k = if SPECULAR_TYPE == SPECULAR_TYPE_DIELECTRIC
 					{
-						get_specular_k_dielectric(fresnel_factor_base, texel_roughness)
+						get_specular_k_dielectric::<SIMD_LEVEL>(fresnel_factor_base, texel_roughness)
 					}
 					else if SPECULAR_TYPE == SPECULAR_TYPE_METAL
 					{
-						get_specular_k_metal(fresnel_factor_base, texel_roughness)
+						get_specular_k_metal::<SIMD_LEVEL>(fresnel_factor_base, texel_roughness)
 					}
 					else
 					{
@@ -590,46 +672,50 @@ fn build_surface_impl_6_static_params<
 					};
 					let one_minus_specular_k = 1.0 - specular_k;
 
-					let constant_component =
-						ColorVec::from_color_f32x3(&LightmapElementOpsT::get_constant_component(&l_mixed));
+					let constant_component = SimdColorVec::<SIMD_LEVEL>::from_color_f32x3(
+						&LightmapElementOpsT::get_constant_component(&l_mixed),
+					);
 
-					total_light_albedo_modulated = ColorVec::scalar_mul(&constant_component, one_minus_specular_k);
-					total_light_direct = ColorVec::scalar_mul(&constant_component, specular_k);
+					total_light_albedo_modulated = SimdColorVec::scalar_mul(&constant_component, one_minus_specular_k);
+					total_light_direct = SimdColorVec::scalar_mul(&constant_component, specular_k);
 
 					// Set alpha component to one to preserve alpha.
 					total_light_albedo_modulated.insert::<3>(1.0);
 
 					if let Some(directional_component) = LightmapElementOpsT::get_directional_component(&l_mixed)
 					{
-						let direction_vec_len2 = vec3_len2(&directional_component.vector_scaled);
+						let direction_vec_len2 = vec3_len2::<SIMD_LEVEL>(&directional_component.vector_scaled);
 						let direction_vec_len = direction_vec_len2 * inv_sqrt_fast(direction_vec_len2);
 
 						let vec_to_camera_reflected_light_angle_cos =
-							vec3_dot(&vec_to_camera_reflected, &directional_component.vector_scaled) *
+							vec3_dot::<SIMD_LEVEL>(&vec_to_camera_reflected, &directional_component.vector_scaled) *
 								inv_sqrt_fast(vec_to_camera_len2 * direction_vec_len2);
 
 						// Make roughness greater  for light with large deviation.
 						let inv_roughness_corrected =
 							inv_fast(texel_roughness + directional_component.deviation).max(0.75);
 
-						let specular_intensity =
-							get_specular_intensity(vec_to_camera_reflected_light_angle_cos, inv_roughness_corrected);
+						let specular_intensity = get_specular_intensity::<SIMD_LEVEL>(
+							vec_to_camera_reflected_light_angle_cos,
+							inv_roughness_corrected,
+						);
 
-						let directional_component_color = ColorVec::from_color_f32x3(&directional_component.color);
+						let directional_component_color =
+							SimdColorVec::<SIMD_LEVEL>::from_color_f32x3(&directional_component.color);
 						if SPECULAR_TYPE == SPECULAR_TYPE_DIELECTRIC
 						{
 							let diffuse_intensity =
-								vec3_dot(&directional_component.vector_scaled, &texel_normal).max(0.0);
+								vec3_dot::<SIMD_LEVEL>(&directional_component.vector_scaled, &texel_normal).max(0.0);
 
 							let light_intensity_diffuse = diffuse_intensity * one_minus_specular_k;
-							total_light_albedo_modulated = ColorVec::mul_scalar_add(
+							total_light_albedo_modulated = SimdColorVec::mul_scalar_add(
 								&directional_component_color,
 								light_intensity_diffuse,
 								&total_light_albedo_modulated,
 							);
 
 							let light_intensity_specular = specular_intensity * specular_k * direction_vec_len;
-							total_light_direct = ColorVec::mul_scalar_add(
+							total_light_direct = SimdColorVec::mul_scalar_add(
 								&directional_component_color,
 								light_intensity_specular,
 								&total_light_direct,
@@ -640,14 +726,14 @@ fn build_surface_impl_6_static_params<
 							let specular_intensity_scale_factor = specular_intensity * direction_vec_len;
 
 							let light_intensity_modulated = one_minus_specular_k * specular_intensity_scale_factor;
-							total_light_albedo_modulated = ColorVec::mul_scalar_add(
+							total_light_albedo_modulated = SimdColorVec::mul_scalar_add(
 								&directional_component_color,
 								light_intensity_modulated,
 								&total_light_albedo_modulated,
 							);
 
 							let light_intensity_direct = specular_k * specular_intensity_scale_factor;
-							total_light_direct = ColorVec::mul_scalar_add(
+							total_light_direct = SimdColorVec::mul_scalar_add(
 								&directional_component_color,
 								light_intensity_direct,
 								&total_light_direct,
@@ -663,28 +749,28 @@ fn build_surface_impl_6_static_params<
 				{
 					// Normal transformed to world space.
 					Vec3f::new(
-						f32_mul_add(
+						f32_mul_add::<SIMD_LEVEL>(
 							texel_normal.x,
 							u_vec_normalized.x,
-							f32_mul_add(
+							f32_mul_add::<SIMD_LEVEL>(
 								texel_normal.y,
 								v_vec_normalized.x,
 								texel_normal.z * plane_normal_normalized.x,
 							),
 						),
-						f32_mul_add(
+						f32_mul_add::<SIMD_LEVEL>(
 							texel_normal.x,
 							u_vec_normalized.y,
-							f32_mul_add(
+							f32_mul_add::<SIMD_LEVEL>(
 								texel_normal.y,
 								v_vec_normalized.y,
 								texel_normal.z * plane_normal_normalized.y,
 							),
 						),
-						f32_mul_add(
+						f32_mul_add::<SIMD_LEVEL>(
 							texel_normal.x,
 							u_vec_normalized.z,
-							f32_mul_add(
+							f32_mul_add::<SIMD_LEVEL>(
 								texel_normal.y,
 								v_vec_normalized.z,
 								texel_normal.z * plane_normal_normalized.z,
@@ -707,9 +793,9 @@ fn build_surface_impl_6_static_params<
 					// Calculate reflected view angle and fresnel factor based on it.
 					// Use these data later for calculation o specular light for all dynamic lights.
 					let vec_to_camera = cam_pos - pos;
-					let vec_to_camera_normal_dot = vec3_dot(&vec_to_camera, &normal);
+					let vec_to_camera_normal_dot = vec3_dot::<SIMD_LEVEL>(&vec_to_camera, &normal);
 					vec_to_camera_reflected = normal * (2.0 * vec_to_camera_normal_dot) - vec_to_camera;
-					vec_to_camera_len2 = vec3_len2(&vec_to_camera_reflected).max(MIN_POSITIVE_VALUE);
+					vec_to_camera_len2 = vec3_len2::<SIMD_LEVEL>(&vec_to_camera_reflected).max(MIN_POSITIVE_VALUE);
 
 					let vec_to_camera_normal_angle_cos =
 						(vec_to_camera_normal_dot * inv_sqrt_fast(vec_to_camera_len2)).max(0.0);
@@ -717,11 +803,11 @@ fn build_surface_impl_6_static_params<
 					let fresnel_factor_base = get_fresnel_factor_base(vec_to_camera_normal_angle_cos);
 					specular_k = if SPECULAR_TYPE == SPECULAR_TYPE_DIELECTRIC
 					{
-						get_specular_k_dielectric(fresnel_factor_base, texel_roughness)
+						get_specular_k_dielectric::<SIMD_LEVEL>(fresnel_factor_base, texel_roughness)
 					}
 					else if SPECULAR_TYPE == SPECULAR_TYPE_METAL
 					{
-						get_specular_k_metal(fresnel_factor_base, texel_roughness)
+						get_specular_k_metal::<SIMD_LEVEL>(fresnel_factor_base, texel_roughness)
 					}
 					else
 					{
@@ -742,8 +828,8 @@ fn build_surface_impl_6_static_params<
 				{
 					let vec_to_light = light.pos - pos;
 
-					let shadow_factor = cube_shadow_map_fetch(shadow_cube_map, &vec_to_light);
-					let vec_to_light_len2 = vec3_len2(&vec_to_light).max(MIN_POSITIVE_VALUE);
+					let shadow_factor = cube_shadow_map_fetch::<SIMD_LEVEL>(shadow_cube_map, &vec_to_light);
+					let vec_to_light_len2 = vec3_len2::<SIMD_LEVEL>(&vec_to_light).max(MIN_POSITIVE_VALUE);
 					let shadow_distance_factor = shadow_factor * inv_fast(vec_to_light_len2);
 
 					let diffuse_intensity = if SPECULAR_TYPE == SPECULAR_TYPE_METAL
@@ -753,7 +839,7 @@ fn build_surface_impl_6_static_params<
 					}
 					else
 					{
-						(vec3_dot(&normal, &vec_to_light) * inv_sqrt_fast(vec_to_light_len2)).max(0.0)
+						(vec3_dot::<SIMD_LEVEL>(&normal, &vec_to_light) * inv_sqrt_fast(vec_to_light_len2)).max(0.0)
 					};
 
 					let specular_intensity = if SPECULAR_TYPE == SPECULAR_TYPE_NONE
@@ -763,18 +849,19 @@ fn build_surface_impl_6_static_params<
 					}
 					else
 					{
-						let vec_to_camera_reflected_light_angle_cos = vec3_dot(&vec_to_camera_reflected, &vec_to_light) *
-							inv_sqrt_fast(vec_to_camera_len2 * vec_to_light_len2);
+						let vec_to_camera_reflected_light_angle_cos =
+							vec3_dot::<SIMD_LEVEL>(&vec_to_camera_reflected, &vec_to_light) *
+								inv_sqrt_fast(vec_to_camera_len2 * vec_to_light_len2);
 
-						get_specular_intensity(vec_to_camera_reflected_light_angle_cos, inv_roughness)
+						get_specular_intensity::<SIMD_LEVEL>(vec_to_camera_reflected_light_angle_cos, inv_roughness)
 					};
 
-					let light_color = ColorVec::from_color_f32x3(&light.color);
+					let light_color = SimdColorVec::<SIMD_LEVEL>::from_color_f32x3(&light.color);
 					match SPECULAR_TYPE
 					{
 						SPECULAR_TYPE_NONE =>
 						{
-							total_light_albedo_modulated = ColorVec::mul_scalar_add(
+							total_light_albedo_modulated = SimdColorVec::mul_scalar_add(
 								&light_color,
 								diffuse_intensity * shadow_distance_factor,
 								&total_light_albedo_modulated,
@@ -784,15 +871,18 @@ fn build_surface_impl_6_static_params<
 						{
 							let light_intensity_diffuse =
 								diffuse_intensity * (1.0 - specular_k) * shadow_distance_factor;
-							total_light_albedo_modulated = ColorVec::mul_scalar_add(
+							total_light_albedo_modulated = SimdColorVec::mul_scalar_add(
 								&light_color,
 								light_intensity_diffuse,
 								&total_light_albedo_modulated,
 							);
 
 							let light_intensity_specular = specular_intensity * specular_k * shadow_distance_factor;
-							total_light_direct =
-								ColorVec::mul_scalar_add(&light_color, light_intensity_specular, &total_light_direct);
+							total_light_direct = SimdColorVec::mul_scalar_add(
+								&light_color,
+								light_intensity_specular,
+								&total_light_direct,
+							);
 						},
 						SPECULAR_TYPE_METAL =>
 						{
@@ -800,7 +890,7 @@ fn build_surface_impl_6_static_params<
 
 							let light_intensity_modulated =
 								(1.0 - specular_k) * specular_intensity_shadow_distance_factor;
-							total_light_albedo_modulated = ColorVec::mul_scalar_add(
+							total_light_albedo_modulated = SimdColorVec::mul_scalar_add(
 								&light_color,
 								light_intensity_modulated,
 								&total_light_albedo_modulated,
@@ -808,7 +898,7 @@ fn build_surface_impl_6_static_params<
 
 							let light_intensity_direct = specular_k * specular_intensity_shadow_distance_factor;
 							total_light_direct =
-								ColorVec::mul_scalar_add(&light_color, light_intensity_direct, &total_light_direct);
+								SimdColorVec::mul_scalar_add(&light_color, light_intensity_direct, &total_light_direct);
 						},
 						_ =>
 						{
@@ -818,16 +908,16 @@ fn build_surface_impl_6_static_params<
 				} // For dynamic lights.
 			} // If use dynmic lights.
 
-			let mut result_color = ColorVec::mul(
-				&ColorVec::from_color32(texel_value.diffuse),
+			let mut result_color = SimdColorVec::mul(
+				&SimdColorVec::<SIMD_LEVEL>::from_color32(texel_value.diffuse),
 				&total_light_albedo_modulated,
 			);
 			if SPECULAR_TYPE != SPECULAR_TYPE_NONE
 			{
-				result_color = ColorVec::mul_scalar_add(&total_light_direct, 255.0, &result_color);
+				result_color = SimdColorVec::mul_scalar_add(&total_light_direct, 255.0, &result_color);
 			}
 
-			*dst_texel = result_color.into();
+			*dst_texel = ColorT::from_color_vec(result_color);
 			src_u += 1;
 			if src_u == (texture.size[0] as i32)
 			{
@@ -843,7 +933,11 @@ trait LightmapElementOps
 {
 	type LightmapElement: Copy;
 
-	fn mix(a: &Self::LightmapElement, b: &Self::LightmapElement, ratio: f32) -> Self::LightmapElement;
+	fn mix<const SIMD_LEVEL: u32>(
+		a: &Self::LightmapElement,
+		b: &Self::LightmapElement,
+		ratio: f32,
+	) -> Self::LightmapElement;
 
 	fn get_constant_component(el: &Self::LightmapElement) -> [f32; 3];
 
@@ -862,13 +956,18 @@ impl LightmapElementOps for LightmapElementOpsSimple
 {
 	type LightmapElement = [f32; 3];
 
-	fn mix(a: &Self::LightmapElement, b: &Self::LightmapElement, ratio: f32) -> Self::LightmapElement
+	#[inline(always)]
+	fn mix<const SIMD_LEVEL: u32>(
+		a: &Self::LightmapElement,
+		b: &Self::LightmapElement,
+		ratio: f32,
+	) -> Self::LightmapElement
 	{
 		let one_minus_ratio = 1.0 - ratio;
 		[
-			f32_mul_add(a[0], ratio, b[0] * one_minus_ratio),
-			f32_mul_add(a[1], ratio, b[1] * one_minus_ratio),
-			f32_mul_add(a[2], ratio, b[2] * one_minus_ratio),
+			f32_mul_add::<SIMD_LEVEL>(a[0], ratio, b[0] * one_minus_ratio),
+			f32_mul_add::<SIMD_LEVEL>(a[1], ratio, b[1] * one_minus_ratio),
+			f32_mul_add::<SIMD_LEVEL>(a[2], ratio, b[2] * one_minus_ratio),
 		]
 	}
 
@@ -888,49 +987,54 @@ impl LightmapElementOps for LightmapElementOpsDirectional
 {
 	type LightmapElement = bsp_map_compact::DirectionalLightmapElement;
 
-	fn mix(a: &Self::LightmapElement, b: &Self::LightmapElement, ratio: f32) -> Self::LightmapElement
+	#[inline(always)]
+	fn mix<const SIMD_LEVEL: u32>(
+		a: &Self::LightmapElement,
+		b: &Self::LightmapElement,
+		ratio: f32,
+	) -> Self::LightmapElement
 	{
 		let one_minus_ratio = 1.0 - ratio;
 		Self::LightmapElement {
 			ambient_light: [
-				f32_mul_add(a.ambient_light[0], ratio, b.ambient_light[0] * one_minus_ratio),
-				f32_mul_add(a.ambient_light[1], ratio, b.ambient_light[1] * one_minus_ratio),
-				f32_mul_add(a.ambient_light[2], ratio, b.ambient_light[2] * one_minus_ratio),
+				f32_mul_add::<SIMD_LEVEL>(a.ambient_light[0], ratio, b.ambient_light[0] * one_minus_ratio),
+				f32_mul_add::<SIMD_LEVEL>(a.ambient_light[1], ratio, b.ambient_light[1] * one_minus_ratio),
+				f32_mul_add::<SIMD_LEVEL>(a.ambient_light[2], ratio, b.ambient_light[2] * one_minus_ratio),
 			],
 			light_direction_vector_scaled: Vec3f::new(
-				f32_mul_add(
+				f32_mul_add::<SIMD_LEVEL>(
 					a.light_direction_vector_scaled.x,
 					ratio,
 					b.light_direction_vector_scaled.x * one_minus_ratio,
 				),
-				f32_mul_add(
+				f32_mul_add::<SIMD_LEVEL>(
 					a.light_direction_vector_scaled.y,
 					ratio,
 					b.light_direction_vector_scaled.y * one_minus_ratio,
 				),
-				f32_mul_add(
+				f32_mul_add::<SIMD_LEVEL>(
 					a.light_direction_vector_scaled.z,
 					ratio,
 					b.light_direction_vector_scaled.z * one_minus_ratio,
 				),
 			),
-			directional_light_deviation: f32_mul_add(
+			directional_light_deviation: f32_mul_add::<SIMD_LEVEL>(
 				a.directional_light_deviation,
 				ratio,
 				b.directional_light_deviation * one_minus_ratio,
 			),
 			directional_light_color: [
-				f32_mul_add(
+				f32_mul_add::<SIMD_LEVEL>(
 					a.directional_light_color[0],
 					ratio,
 					b.directional_light_color[0] * one_minus_ratio,
 				),
-				f32_mul_add(
+				f32_mul_add::<SIMD_LEVEL>(
 					a.directional_light_color[1],
 					ratio,
 					b.directional_light_color[1] * one_minus_ratio,
 				),
-				f32_mul_add(
+				f32_mul_add::<SIMD_LEVEL>(
 					a.directional_light_color[2],
 					ratio,
 					b.directional_light_color[2] * one_minus_ratio,
@@ -954,55 +1058,280 @@ impl LightmapElementOps for LightmapElementOpsDirectional
 	}
 }
 
+pub const MAX_SURFACE_FOG_VOLUMES: usize = 4;
+
+// Blend surface texels with fog color.
+// Fog amount depends on length of part of segment between camera and texel inside each fog volume.
+pub fn apply_surface_fog<ColorT: AbstractColor>(
+	plane: &Plane,
+	tex_coord_equation: &[Plane; 2],
+	surface_size: [u32; 2],
+	surface_tc_min: [i32; 2],
+	fog_volumes: &[FogVolume],
+	cam_pos: &Vec3f,
+	out_surface_data: &mut [ColorT],
+)
+{
+	let (start_pos, u_vec, v_vec) = if let Some(basis) =
+		get_surface_texels_positions_basis(plane, tex_coord_equation, surface_tc_min)
+	{
+		basis
+	}
+	else
+	{
+		return;
+	};
+
+	// Zero alpha in order to preserve alpha of texels.
+	let rgb_mask = ColorVecI::from_color_i32x3(&[1, 1, 1]);
+
+	let mut fog_colors = [ColorVecI::zero(); MAX_SURFACE_FOG_VOLUMES];
+	for (fog_volume, fog_color) in fog_volumes.iter().zip(fog_colors.iter_mut())
+	{
+		*fog_color = ColorVecI::from_color_f32x3(&fog_volume.params.color);
+	}
+
+	for dst_v in 0 .. surface_size[1]
+	{
+		let dst_line_start = (dst_v * surface_size[0]) as usize;
+		let dst_line = &mut out_surface_data[dst_line_start .. dst_line_start + (surface_size[0] as usize)];
+		let start_pos_v = vec3_scalar_mul_add::<SIMD_LEVEL_SCALAR>(&v_vec, dst_v as f32, &start_pos);
+		for (dst_u, dst_texel) in dst_line.iter_mut().enumerate()
+		{
+			let pos = vec3_scalar_mul_add::<SIMD_LEVEL_SCALAR>(&u_vec, dst_u as f32, &start_pos_v);
+			let distance = (pos - cam_pos).magnitude();
+
+			let mut texel_value: ColorVecI = (*dst_texel).into();
+			for (fog_volume, fog_color) in fog_volumes.iter().zip(fog_colors.iter())
+			{
+				let fraction = get_segment_bbox_intersection_fraction(cam_pos, &pos, &fog_volume.bbox);
+				if fraction <= 0.0
+				{
+					continue;
+				}
+
+				let fog_amount = get_fog_amount(&fog_volume.params, distance * fraction);
+				let fog_amount_i = (fog_amount * 256.0) as i32;
+
+				// texel + (fog - texel) * k
+				let delta = ColorVecI::add(
+					fog_color,
+					&ColorVecI::mul_scalar(&ColorVecI::mul(&texel_value, &rgb_mask), -1),
+				);
+				texel_value = ColorVecI::add(
+					&texel_value,
+					&ColorVecI::shift_right::<8>(&ColorVecI::mul_scalar(&delta, fog_amount_i)),
+				);
+			}
+
+			*dst_texel = texel_value.into();
+		}
+	}
+}
+
+// Returns world position of first texel center and world-space vectors for u and v texture axes.
+fn get_surface_texels_positions_basis(
+	plane: &Plane,
+	tex_coord_equation: &[Plane; 2],
+	surface_tc_min: [i32; 2],
+) -> Option<(Vec3f, Vec3f, Vec3f)>
+{
+	// Calculate inverse matrix for tex_coord equation and plane equation in order to calculate world position for UV.
+	// TODO - project tc equation to surface plane?
+	let tex_coord_basis = Mat4f::from_cols(
+		tex_coord_equation[0].vec.extend(tex_coord_equation[0].dist),
+		tex_coord_equation[1].vec.extend(tex_coord_equation[1].dist),
+		plane.vec.extend(-plane.dist),
+		Vec4f::new(0.0, 0.0, 0.0, 1.0),
+	);
+	let tex_coord_basis_inverted = tex_coord_basis.transpose().invert()?;
+
+	let u_vec = tex_coord_basis_inverted.x.truncate();
+	let v_vec = tex_coord_basis_inverted.y.truncate();
+	let start_pos = tex_coord_basis_inverted.w.truncate() +
+		u_vec * ((surface_tc_min[0]) as f32 + 0.5) +
+		v_vec * ((surface_tc_min[1]) as f32 + 0.5);
+
+	Some((start_pos, u_vec, v_vec))
+}
+
+// Calculate color of texel lighted by ambient light and single directional light.
+// All vectors are in tangent space.
+// Used for triangle models with normal maps.
+#[inline(always)]
+pub fn get_texel_color_with_directional_light<
+	const USE_NORMAL_MAP: bool,
+	const SPECULAR_TYPE: u32,
+	const SIMD_LEVEL: u32,
+>(
+	texel_value: textures::TextureElement,
+	ambient_light: &[f32; 3],
+	light_vector_scaled: &Vec3f,
+	light_color: &[f32; 3],
+	vec_to_camera: &Vec3f,
+) -> SimdColorVec<SIMD_LEVEL>
+{
+	let (texel_normal, texel_roughness) = if USE_NORMAL_MAP
+	{
+		texel_value.packed_normal_roughness.unpack()
+	}
+	else
+	{
+		if SPECULAR_TYPE == SPECULAR_TYPE_NONE
+		{
+			(Vec3f::unit_z(), 0.0)
+		}
+		else
+		{
+			(Vec3f::unit_z(), texel_value.packed_normal_roughness.unpack_roughness())
+		}
+	};
+
+	let light_color_vec = SimdColorVec::<SIMD_LEVEL>::from_color_f32x3(light_color);
+
+	if SPECULAR_TYPE == SPECULAR_TYPE_NONE
+	{
+		let total_light = SimdColorVec::mul_scalar_add(
+			&light_color_vec,
+			vec3_dot::<SIMD_LEVEL>(light_vector_scaled, &texel_normal).max(0.0),
+			&SimdColorVec::<SIMD_LEVEL>::from_color_f32x3_with_one(ambient_light),
+		);
+		return SimdColorVec::mul(
+			&SimdColorVec::<SIMD_LEVEL>::from_color32(texel_value.diffuse),
+			&total_light,
+		);
+	}
+
+	let vec_to_camera_normal_dot = vec3_dot::<SIMD_LEVEL>(vec_to_camera, &texel_normal);
+	let vec_to_camera_reflected = texel_normal * (2.0 * vec_to_camera_normal_dot) - vec_to_camera;
+	let vec_to_camera_len2 = vec3_len2::<SIMD_LEVEL>(&vec_to_camera_reflected).max(MIN_POSITIVE_VALUE);
+
+	let vec_to_camera_normal_angle_cos = (vec_to_camera_normal_dot * inv_sqrt_fast(vec_to_camera_len2)).max(0.0);
+	let fresnel_factor_base = get_fresnel_factor_base(vec_to_camera_normal_angle_cos);
+
+	let specular_k = if SPECULAR_TYPE == SPECULAR_TYPE_DIELECTRIC
+	{
+		get_specular_k_dielectric::<SIMD_LEVEL>(fresnel_factor_base, texel_roughness)
+	}
+	else
+	{
+		get_specular_k_metal::<SIMD_LEVEL>(fresnel_factor_base, texel_roughness)
+	};
+	let one_minus_specular_k = 1.0 - specular_k;
+
+	let ambient_light_vec = SimdColorVec::<SIMD_LEVEL>::from_color_f32x3(ambient_light);
+	let mut total_light_albedo_modulated = SimdColorVec::scalar_mul(&ambient_light_vec, one_minus_specular_k);
+	let mut total_light_direct = SimdColorVec::scalar_mul(&ambient_light_vec, specular_k);
+
+	// Set alpha component to one to preserve alpha.
+	total_light_albedo_modulated.insert::<3>(1.0);
+
+	let direction_vec_len2 = vec3_len2::<SIMD_LEVEL>(light_vector_scaled).max(MIN_POSITIVE_VALUE);
+	let direction_vec_len = direction_vec_len2 * inv_sqrt_fast(direction_vec_len2);
+
+	let vec_to_camera_reflected_light_angle_cos = vec3_dot::<SIMD_LEVEL>(&vec_to_camera_reflected, light_vector_scaled) *
+		inv_sqrt_fast(vec_to_camera_len2 * direction_vec_len2);
+
+	let specular_intensity = get_specular_intensity::<SIMD_LEVEL>(
+		vec_to_camera_reflected_light_angle_cos,
+		inv_fast(texel_roughness).max(0.75),
+	);
+
+	if SPECULAR_TYPE == SPECULAR_TYPE_DIELECTRIC
+	{
+		let diffuse_intensity = vec3_dot::<SIMD_LEVEL>(light_vector_scaled, &texel_normal).max(0.0);
+
+		total_light_albedo_modulated = SimdColorVec::mul_scalar_add(
+			&light_color_vec,
+			diffuse_intensity * one_minus_specular_k,
+			&total_light_albedo_modulated,
+		);
+		total_light_direct = SimdColorVec::mul_scalar_add(
+			&light_color_vec,
+			specular_intensity * specular_k * direction_vec_len,
+			&total_light_direct,
+		);
+	}
+	else if SPECULAR_TYPE == SPECULAR_TYPE_METAL
+	{
+		let specular_intensity_scale_factor = specular_intensity * direction_vec_len;
+
+		total_light_albedo_modulated = SimdColorVec::mul_scalar_add(
+			&light_color_vec,
+			one_minus_specular_k * specular_intensity_scale_factor,
+			&total_light_albedo_modulated,
+		);
+		total_light_direct = SimdColorVec::mul_scalar_add(
+			&light_color_vec,
+			specular_k * specular_intensity_scale_factor,
+			&total_light_direct,
+		);
+	}
+
+	let result_color = SimdColorVec::mul(
+		&SimdColorVec::<SIMD_LEVEL>::from_color32(texel_value.diffuse),
+		&total_light_albedo_modulated,
+	);
+	SimdColorVec::mul_scalar_add(&total_light_direct, 255.0, &result_color)
+}
+
 // Returns 1 if in light, 0 if in shadow.
-fn cube_shadow_map_fetch(cube_shadow_map: &CubeShadowMap, vec: &Vec3f) -> f32
+#[inline(always)]
+pub fn cube_shadow_map_fetch<const SIMD_LEVEL: u32>(cube_shadow_map: &CubeShadowMap, vec: &Vec3f) -> f32
 {
 	let vec_abs = Vec3f::new(vec.x.abs(), vec.y.abs(), vec.z.abs());
 	if vec_abs.x >= vec_abs.y && vec_abs.x >= vec_abs.z
 	{
 		if vec.x >= 0.0
 		{
-			cube_shadow_map_side_fetch(cube_shadow_map, &Vec3f::new(-vec.y, vec.z, vec_abs.x), 1)
+			cube_shadow_map_side_fetch::<SIMD_LEVEL>(cube_shadow_map, &Vec3f::new(-vec.y, vec.z, vec_abs.x), 1)
 		}
 		else
 		{
-			cube_shadow_map_side_fetch(cube_shadow_map, &Vec3f::new(vec.y, vec.z, vec_abs.x), 0)
+			cube_shadow_map_side_fetch::<SIMD_LEVEL>(cube_shadow_map, &Vec3f::new(vec.y, vec.z, vec_abs.x), 0)
 		}
 	}
 	else if vec_abs.y >= vec_abs.x && vec_abs.y >= vec_abs.z
 	{
 		if vec.y >= 0.0
 		{
-			cube_shadow_map_side_fetch(cube_shadow_map, &Vec3f::new(vec.x, vec.z, vec_abs.y), 3)
+			cube_shadow_map_side_fetch::<SIMD_LEVEL>(cube_shadow_map, &Vec3f::new(vec.x, vec.z, vec_abs.y), 3)
 		}
 		else
 		{
-			cube_shadow_map_side_fetch(cube_shadow_map, &Vec3f::new(-vec.x, vec.z, vec_abs.y), 2)
+			cube_shadow_map_side_fetch::<SIMD_LEVEL>(cube_shadow_map, &Vec3f::new(-vec.x, vec.z, vec_abs.y), 2)
 		}
 	}
 	else
 	{
 		if vec.z >= 0.0
 		{
-			cube_shadow_map_side_fetch(cube_shadow_map, &Vec3f::new(-vec.x, vec.y, vec_abs.z), 5)
+			cube_shadow_map_side_fetch::<SIMD_LEVEL>(cube_shadow_map, &Vec3f::new(-vec.x, vec.y, vec_abs.z), 5)
 		}
 		else
 		{
-			cube_shadow_map_side_fetch(cube_shadow_map, &Vec3f::new(-vec.x, -vec.y, vec_abs.z), 4)
+			cube_shadow_map_side_fetch::<SIMD_LEVEL>(cube_shadow_map, &Vec3f::new(-vec.x, -vec.y, vec_abs.z), 4)
 		}
 	}
 }
 
 // Returns 1 if in light, 0 if in shadow.
-fn cube_shadow_map_side_fetch(cube_shadow_map: &CubeShadowMap, vec: &Vec3f, side: u32) -> f32
+#[inline(always)]
+fn cube_shadow_map_side_fetch<const SIMD_LEVEL: u32>(cube_shadow_map: &CubeShadowMap, vec: &Vec3f, side: u32) -> f32
 {
 	const ONE_MINUS_EPS: f32 = 1.0 - 1.0 / 65536.0;
 	let cubemap_size_f = cube_shadow_map.size as f32;
 
 	let depth = inv_fast(vec.z.max(MIN_POSITIVE_VALUE));
 	let half_depth = 0.5 * depth;
-	let u_f = f32_mul_add(vec.x, half_depth, 0.5).max(0.0).min(ONE_MINUS_EPS) * cubemap_size_f;
-	let v_f = f32_mul_add(vec.y, half_depth, 0.5).max(0.0).min(ONE_MINUS_EPS) * cubemap_size_f;
+	let u_f = f32_mul_add::<SIMD_LEVEL>(vec.x, half_depth, 0.5)
+		.max(0.0)
+		.min(ONE_MINUS_EPS) *
+		cubemap_size_f;
+	let v_f = f32_mul_add::<SIMD_LEVEL>(vec.y, half_depth, 0.5)
+		.max(0.0)
+		.min(ONE_MINUS_EPS) *
+		cubemap_size_f;
 	// It is safe to use "unsafe" f32 to int conversion, since NaN and Inf is not possible here.
 	let u = unsafe { u_f.to_int_unchecked::<u32>() };
 	let v = unsafe { v_f.to_int_unchecked::<u32>() };
@@ -1020,7 +1349,11 @@ fn cube_shadow_map_side_fetch(cube_shadow_map: &CubeShadowMap, vec: &Vec3f, side
 	}
 }
 
-fn get_specular_intensity(vec_to_camera_reflected_light_angle_cos: f32, inv_roughness: f32) -> f32
+#[inline(always)]
+fn get_specular_intensity<const SIMD_LEVEL: u32>(
+	vec_to_camera_reflected_light_angle_cos: f32,
+	inv_roughness: f32,
+) -> f32
 {
 	if false
 	{
@@ -1032,7 +1365,7 @@ fn get_specular_intensity(vec_to_camera_reflected_light_angle_cos: f32, inv_roug
 		let a = 0.1875;
 		let b = 0.75;
 		let c = 0.75;
-		f32_mul_add(x, f32_mul_add(x, a, b), c) * inv_roughness
+		f32_mul_add::<SIMD_LEVEL>(x, f32_mul_add::<SIMD_LEVEL>(x, a, b), c) * inv_roughness
 	}
 	else
 	{
@@ -1044,7 +1377,7 @@ fn get_specular_intensity(vec_to_camera_reflected_light_angle_cos: f32, inv_roug
 		const A: f32 = 2.0 * std::f32::consts::PI / SQRT_7;
 		const B: f32 = std::f32::consts::PI * SQRT_7 / 2.0;
 
-		inv_fast(f32_mul_add(x * x, B, A)) * inv_roughness
+		inv_fast(f32_mul_add::<SIMD_LEVEL>(x * x, B, A)) * inv_roughness
 	}
 }
 
@@ -1057,9 +1390,10 @@ fn get_fresnel_factor_base(vec_to_camera_normal_angle_cos: f32) -> f32
 	one_minus_angle_cos2 * one_minus_angle_cos2 * one_minus_angle_cos
 }
 
-fn get_specular_k_dielectric(fresnel_factor_base: f32, roughness: f32) -> f32
+#[inline(always)]
+fn get_specular_k_dielectric<const SIMD_LEVEL: u32>(fresnel_factor_base: f32, roughness: f32) -> f32
 {
-	let fresnel_factor = f32_mul_add(
+	let fresnel_factor = f32_mul_add::<SIMD_LEVEL>(
 		fresnel_factor_base,
 		1.0 - DIELECTRIC_ZERO_REFLECTIVITY,
 		DIELECTRIC_ZERO_REFLECTIVITY,
@@ -1068,16 +1402,17 @@ fn get_specular_k_dielectric(fresnel_factor_base: f32, roughness: f32) -> f32
 	// For glossy surface we can just use Fresnel factor for diffuse/specular mixing.
 	// But for rough srufaces we can't. Normally we should use some sort of integral of Schlick's approximation.
 	// But it's too expensive. So, just make mix of Fresnel factor depending on view angle with constant factor for absolutely rough surface.
-	f32_mul_add(
+	f32_mul_add::<SIMD_LEVEL>(
 		fresnel_factor,
 		1.0 - roughness,
 		DIELECTRIC_AVERAGE_REFLECTIVITY * roughness,
 	)
 }
 
-fn get_specular_k_metal(fresnel_factor_base: f32, roughness: f32) -> f32
+#[inline(always)]
+fn get_specular_k_metal<const SIMD_LEVEL: u32>(fresnel_factor_base: f32, roughness: f32) -> f32
 {
-	f32_mul_add(
+	f32_mul_add::<SIMD_LEVEL>(
 		fresnel_factor_base,
 		1.0 - roughness,
 		METAL_AVERAGE_SCHLICK_FACTOR * roughness,
@@ -1091,24 +1426,27 @@ const DIELECTRIC_AVERAGE_REFLECTIVITY: f32 = DIELECTRIC_ZERO_REFLECTIVITY * 3.0;
 const METAL_AVERAGE_SCHLICK_FACTOR: f32 = 0.5;
 
 // Faster version of dot product, because it uses "mul_add".
-fn vec3_dot(a: &Vec3f, b: &Vec3f) -> f32
+#[inline(always)]
+fn vec3_dot<const SIMD_LEVEL: u32>(a: &Vec3f, b: &Vec3f) -> f32
 {
-	f32_mul_add(a.x, b.x, f32_mul_add(a.y, b.y, a.z * b.z))
+	f32_mul_add::<SIMD_LEVEL>(a.x, b.x, f32_mul_add::<SIMD_LEVEL>(a.y, b.y, a.z * b.z))
 }
 
 // Faster than naive vec = a * scalar + b, because of "mul_add".
-fn vec3_scalar_mul_add(a: &Vec3f, scalar: f32, b: &Vec3f) -> Vec3f
+#[inline(always)]
+fn vec3_scalar_mul_add<const SIMD_LEVEL: u32>(a: &Vec3f, scalar: f32, b: &Vec3f) -> Vec3f
 {
 	Vec3f::new(
-		f32_mul_add(a.x, scalar, b.x),
-		f32_mul_add(a.y, scalar, b.y),
-		f32_mul_add(a.z, scalar, b.z),
+		f32_mul_add::<SIMD_LEVEL>(a.x, scalar, b.x),
+		f32_mul_add::<SIMD_LEVEL>(a.y, scalar, b.y),
+		f32_mul_add::<SIMD_LEVEL>(a.z, scalar, b.z),
 	)
 }
 
-fn vec3_len2(v: &Vec3f) -> f32
+#[inline(always)]
+fn vec3_len2<const SIMD_LEVEL: u32>(v: &Vec3f) -> f32
 {
-	vec3_dot(v, v)
+	vec3_dot::<SIMD_LEVEL>(v, v)
 }
 
 unsafe fn debug_only_checked_fetch<T: Copy>(data: &[T], address: usize) -> T
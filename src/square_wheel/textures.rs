@@ -1,5 +1,5 @@
 use super::{abstract_color::*, fast_math::*};
-use crate::common::{color::*, image, math_types::*};
+use crate::common::{color::*, image, image_hdr, math_types::*};
 
 // MAX_MIP must be not greater, than LIGHTMAP_SCALE_LOG2
 pub const MAX_MIP: usize = 3;
@@ -14,6 +14,9 @@ pub struct Texture
 	pub has_normal_map: bool, // If false, normals data is trivial.
 	pub has_non_one_roughness: bool,
 	pub is_metal: bool,
+	// Emissive color of each texel. Empty if texture has no emissive.
+	pub emissive: Vec<Color32>,
+	pub emissive_brightness: f32,
 }
 
 #[derive(Copy, Clone)]
@@ -95,6 +98,8 @@ pub fn make_texture(
 	roughness: f32,
 	mut roughness_map: Option<image::Image>,
 	is_metal: bool,
+	emissive: Option<image::Image>,
+	emissive_brightness: f32,
 ) -> Texture
 {
 	let mut result = Texture {
@@ -103,8 +108,22 @@ pub fn make_texture(
 		has_normal_map: normals.is_some(),
 		has_non_one_roughness: roughness < 1.0 || roughness_map.is_some(),
 		is_metal,
+		emissive: Vec::new(),
+		emissive_brightness,
 	};
 
+	if let Some(e) = emissive
+	{
+		result.emissive = if e.size != diffuse.size
+		{
+			resize_image(&e, diffuse.size).pixels
+		}
+		else
+		{
+			e.pixels
+		};
+	}
+
 	if let Some(n) = &mut normals
 	{
 		if n.size != diffuse.size
@@ -160,7 +179,7 @@ pub fn make_texture(
 }
 
 // Resize with simple nearset filter.
-fn resize_image(image: &image::Image, target_size: [u32; 2]) -> image::Image
+pub fn resize_image(image: &image::Image, target_size: [u32; 2]) -> image::Image
 {
 	let mut result = image::Image {
 		size: target_size,
@@ -206,6 +225,60 @@ pub fn make_skybox_side_texture<ColorT: AbstractColor>(
 	SkyboxSideTexture { size, pixels }
 }
 
+// Make skybox side texture from HDR image.
+// If "tonemap" is true - compress values into range of regular textures, else - preserve full range.
+pub fn make_skybox_side_texture_hdr<ColorT: AbstractColor>(
+	image: &image_hdr::ImageHDR,
+	brightness: f32,
+	tonemap: bool,
+) -> SkyboxSideTexture<ColorT>
+{
+	// HDR images store values in range [0; 1] for regular brightness, but textures use range [0; 255].
+	const SCALE: f32 = 255.0;
+	// Maximum value of 16-bit color component.
+	const MAX_VALUE: f32 = 65535.0;
+
+	let size = image.size[0].min(image.size[1]);
+
+	let mut pixels = vec![ColorT::default(); (size * size) as usize];
+	for y in 0 .. size
+	{
+		for x in 0 .. size
+		{
+			let src = &image.pixels[(x + image.size[0] * y) as usize];
+			let mut c = [0.0; 3];
+			for i in 0 .. 3
+			{
+				let value = src[i] * brightness;
+				c[i] = if tonemap
+				{
+					tonemap_hdr_value(value) * SCALE
+				}
+				else
+				{
+					(value * SCALE).min(MAX_VALUE)
+				};
+			}
+			pixels[(x + y * size) as usize] = ColorVec::from_color_f32x3(&c).into();
+		}
+	}
+
+	SkyboxSideTexture { size, pixels }
+}
+
+// Keep low values unchanged, smoothly compress values above knee into range [knee; 1].
+fn tonemap_hdr_value(value: f32) -> f32
+{
+	const KNEE: f32 = 0.5;
+	if value <= KNEE
+	{
+		return value.max(0.0);
+	}
+
+	let excess = value - KNEE;
+	KNEE + (1.0 - KNEE) * excess / (excess + (1.0 - KNEE))
+}
+
 pub fn make_skybox_side_texture_mips<ColorT: AbstractColor>(
 	mip0: SkyboxSideTexture<ColorT>,
 ) -> SkyboxSideTextureWithMips<ColorT>
@@ -278,9 +351,15 @@ pub fn build_texture_mips(mip0: Texture) -> TextureWithMips
 			has_normal_map: prev_mip.has_normal_map,
 			has_non_one_roughness: prev_mip.has_non_one_roughness,
 			is_metal: prev_mip.is_metal,
+			emissive: Vec::new(),
+			emissive_brightness: prev_mip.emissive_brightness,
 		};
 
 		mip.pixels = vec![TextureElement::default(); (mip.size[0] * mip.size[1]) as usize];
+		if !prev_mip.emissive.is_empty()
+		{
+			mip.emissive = vec![Color32::black(); (mip.size[0] * mip.size[1]) as usize];
+		}
 
 		let prev_mip_width = prev_mip.size[0] as usize;
 		let mip_width = mip.size[0] as usize;
@@ -323,6 +402,22 @@ pub fn build_texture_mips(mip0: Texture) -> TextureWithMips
 
 				dst.packed_normal_roughness = PackedNormalRoughness::pack(&dst_normal, dst_roughness);
 			}
+
+			if !prev_mip.emissive.is_empty()
+			{
+				for (dst, x) in mip.emissive[y * mip_width .. (y + 1) * mip_width]
+					.iter_mut()
+					.zip(0 .. mip_width)
+				{
+					let src_x = x * 2;
+					*dst = Color32::get_average_4([
+						prev_mip.emissive[src_x + src_offset0],
+						prev_mip.emissive[src_x + src_offset1],
+						prev_mip.emissive[src_x + 1 + src_offset0],
+						prev_mip.emissive[src_x + 1 + src_offset1],
+					]);
+				}
+			}
 		}
 		result[i] = mip;
 	}
@@ -330,6 +425,38 @@ pub fn build_texture_mips(mip0: Texture) -> TextureWithMips
 	result
 }
 
+// Recalculate diffuse color of mips using first mip. Normals and roughness are not changed.
+// All mips should have proper size.
+pub fn update_texture_diffuse_mips(mips: &mut TextureWithMips)
+{
+	for i in 1 .. NUM_MIPS
+	{
+		let (prev_mips, next_mips) = mips.split_at_mut(i);
+		let prev_mip = &prev_mips[i - 1];
+		let mip = &mut next_mips[0];
+
+		let prev_mip_width = prev_mip.size[0] as usize;
+		let mip_width = mip.size[0] as usize;
+		for y in 0 .. mip.size[1] as usize
+		{
+			let src_offset0 = (y * 2) * prev_mip_width;
+			let src_offset1 = (y * 2 + 1) * prev_mip_width;
+			for (dst, x) in mip.pixels[y * mip_width .. (y + 1) * mip_width]
+				.iter_mut()
+				.zip(0 .. mip_width)
+			{
+				let src_x = x * 2;
+				dst.diffuse = Color32::get_average_4([
+					prev_mip.pixels[src_x + src_offset0].diffuse,
+					prev_mip.pixels[src_x + src_offset1].diffuse,
+					prev_mip.pixels[src_x + 1 + src_offset0].diffuse,
+					prev_mip.pixels[src_x + 1 + src_offset1].diffuse,
+				]);
+			}
+		}
+	}
+}
+
 pub fn make_texture_lite_mips(mip0: TextureLite) -> TextureLiteWithMips
 {
 	let mut result = [
@@ -338,21 +465,28 @@ pub fn make_texture_lite_mips(mip0: TextureLite) -> TextureLiteWithMips
 		TextureLite::default(),
 		TextureLite::default(),
 	];
+	update_texture_lite_mips(&mut result);
+	result
+}
 
+// Recalculate mips using first mip. Memory of previous mips is reused.
+pub fn update_texture_lite_mips(mips: &mut TextureLiteWithMips)
+{
 	for i in 1 .. NUM_MIPS
 	{
-		let prev_mip = &mut result[i - 1];
-		let mut mip = TextureLite {
-			size: [prev_mip.size[0] >> 1, prev_mip.size[1] >> 1],
-			pixels: Vec::new(),
-		};
+		let (prev_mips, next_mips) = mips.split_at_mut(i);
+		let prev_mip = &prev_mips[i - 1];
+		let mip = &mut next_mips[0];
 
-		if mip.size[0] * mip.size[1] == 0
+		let size = [prev_mip.size[0] >> 1, prev_mip.size[1] >> 1];
+		if size[0] * size[1] == 0
 		{
+			*mip = TextureLite::default();
 			continue;
 		}
 
-		mip.pixels = vec![Color32::black(); (mip.size[0] * mip.size[1]) as usize];
+		mip.size = size;
+		mip.pixels.resize((size[0] * size[1]) as usize, Color32::black());
 
 		let prev_mip_width = prev_mip.size[0] as usize;
 		let mip_width = mip.size[0] as usize;
@@ -373,10 +507,7 @@ pub fn make_texture_lite_mips(mip0: TextureLite) -> TextureLiteWithMips
 				*dst = Color32::get_average_4([p00, p01, p10, p11]);
 			}
 		}
-		result[i] = mip;
 	}
-
-	result
 }
 
 // Do not allow absolte zero roughness. Limit this value to integer 1 in compressed format.
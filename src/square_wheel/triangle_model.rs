@@ -46,6 +46,8 @@ pub struct TriangleModelMesh
 	pub num_frames: u32,
 
 	pub vertex_data: VertexData,
+	// Per-vertex tangent space vectors (for first frame or bind pose). Used for normal mapping.
+	pub tangent_space: Vec<VertexTangentSpace>,
 }
 
 // TODO - maybe use 8-byte alignment for triangle structure?
@@ -95,6 +97,15 @@ pub struct SkeletonAnimatedVertex
 	pub bones_description: [VertexBoneDescription; 4],
 }
 
+#[derive(Copy, Clone)]
+pub struct VertexTangentSpace
+{
+	// Direction of "u" texture coordinate growth.
+	pub tangent: Vec3f,
+	// Direction of "v" texture coordinate growth.
+	pub binormal: Vec3f,
+}
+
 #[derive(Debug, Copy, Clone)]
 pub struct VertexBoneDescription
 {
@@ -86,7 +86,7 @@ pub fn load_model_iqm(file_path: &std::path::Path) -> Result<Option<TriangleMode
 		frames_info = vec![TriangleModelFrameInfo { bbox }];
 	}
 
-	let triangles_transformed = triangles
+	let triangles_transformed: Vec<Triangle> = triangles
 		.iter()
 		.map(|t| {
 			[
@@ -110,12 +110,15 @@ pub fn load_model_iqm(file_path: &std::path::Path) -> Result<Option<TriangleMode
 
 	// TODO - export all meshes separately.
 	// Now just load single mesh.
+	let vertex_data = VertexData::SkeletonAnimated(vertices);
+	let tangent_space = calculate_tangent_space(&triangles_transformed, &vertex_data);
 	let single_mesh = TriangleModelMesh {
 		name: get_text_str(&texts, meshes[0].name).to_string(),
 		material_name: get_text_str(&texts, meshes[0].material).to_string(),
 		triangles: triangles_transformed,
-		vertex_data: VertexData::SkeletonAnimated(vertices),
+		vertex_data,
 		num_frames: header.num_frames,
+		tangent_space,
 	};
 
 	// Add extra shift because we use texture coordinates floor, instead of linear OpenGL interpolation as Quake III does.
@@ -1,3 +1,5 @@
+use super::triangle_model::*;
+use crate::common::math_types::*;
 use std::io::{Read, Seek};
 
 pub fn read_chunk<T: Copy>(file: &mut std::fs::File, offset: u64, dst: &mut [T]) -> Result<(), std::io::Error>
@@ -28,3 +30,73 @@ pub fn read_vector<T: Copy>(file: &mut std::fs::File, offset: u64, num_elements:
 
 	Ok(result)
 }
+
+// Calculate per-vertex tangent space vectors, using positions of first frame (or bind pose) and texture coordinates.
+pub fn calculate_tangent_space(triangles: &[Triangle], vertex_data: &VertexData) -> Vec<VertexTangentSpace>
+{
+	let (positions, tex_coords): (Vec<Vec3f>, Vec<[f32; 2]>) = match vertex_data
+	{
+		VertexData::NonAnimated(v) => (
+			v.iter().map(|v| v.position).collect(),
+			v.iter().map(|v| v.tex_coord).collect(),
+		),
+		VertexData::VertexAnimated { constant, variable } => (
+			variable[.. constant.len()].iter().map(|v| v.position).collect(),
+			constant.iter().map(|v| v.tex_coord).collect(),
+		),
+		VertexData::SkeletonAnimated(v) => (
+			v.iter().map(|v| v.position).collect(),
+			v.iter().map(|v| v.tex_coord).collect(),
+		),
+	};
+
+	let mut result = vec![
+		VertexTangentSpace {
+			tangent: Vec3f::zero(),
+			binormal: Vec3f::zero(),
+		};
+		positions.len()
+	];
+
+	for triangle in triangles
+	{
+		let p = triangle.map(|index| positions[index as usize]);
+		let tc = triangle.map(|index| Vec2f::from(tex_coords[index as usize]));
+
+		let edge1 = p[1] - p[0];
+		let edge2 = p[2] - p[0];
+		let d_tc1 = tc[1] - tc[0];
+		let d_tc2 = tc[2] - tc[0];
+
+		let det = d_tc1.x * d_tc2.y - d_tc2.x * d_tc1.y;
+		if det.abs() < 0.0000001
+		{
+			// Degenerate texture mapping.
+			continue;
+		}
+		let inv_det = 1.0 / det;
+
+		let tangent = (edge1 * d_tc2.y - edge2 * d_tc1.y) * inv_det;
+		let binormal = (edge2 * d_tc1.x - edge1 * d_tc2.x) * inv_det;
+		for &index in triangle
+		{
+			let dst = &mut result[index as usize];
+			dst.tangent += tangent;
+			dst.binormal += binormal;
+		}
+	}
+
+	for v in &mut result
+	{
+		if v.tangent.magnitude2() > 0.0
+		{
+			v.tangent = v.tangent.normalize();
+		}
+		if v.binormal.magnitude2() > 0.0
+		{
+			v.binormal = v.binormal.normalize();
+		}
+	}
+
+	result
+}
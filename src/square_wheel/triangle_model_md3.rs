@@ -142,7 +142,7 @@ fn load_md3_mesh(
 	)?;
 	let shaders_src = read_vector::<Md3Shader>(file, src_mesh.lump_shaders as u64 + mesh_offset, src_mesh.num_shaders)?;
 
-	let triangles = triangles_src
+	let triangles: Vec<Triangle> = triangles_src
 		.iter()
 		.map(|x| [x[0] as VertexIndex, x[1] as VertexIndex, x[2] as VertexIndex])
 		.collect();
@@ -192,12 +192,15 @@ fn load_md3_mesh(
 		}
 	};
 
+	let tangent_space = calculate_tangent_space(&triangles, &vertex_data);
+
 	Ok(Some(TriangleModelMesh {
 		name: get_str(&src_mesh.name).to_string(),
 		material_name,
 		triangles,
 		num_frames: src_mesh.num_frames,
 		vertex_data,
+		tangent_space,
 	}))
 }
 
@@ -1,4 +1,6 @@
-use super::{fast_math::*, frame_info::*, textures::*, triangle_model::*};
+use super::{
+	cpu_features::*, fast_math::*, frame_info::*, light::*, shadow_map::*, surfaces::*, textures::*, triangle_model::*,
+};
 use crate::common::{bbox::*, bsp_map_compact, clipping::*, clipping_polygon::*, math_types::*, plane::*};
 
 pub fn animate_and_transform_triangle_mesh_vertices(
@@ -6,6 +8,10 @@ pub fn animate_and_transform_triangle_mesh_vertices(
 	mesh: &TriangleModelMesh,
 	animation: &AnimationPoint,
 	light: &bsp_map_compact::LightGridElement,
+	dynamic_lights: &[LightWithShadowMap],
+	use_shadow_maps: bool,
+	calculate_tangent_space_light: bool,
+	camera_position: &Vec3f,
 	model_matrix: &Mat4f,
 	model_view_matrix: &Mat4f,
 	tc_scale: &Vec2f,
@@ -15,6 +21,26 @@ pub fn animate_and_transform_triangle_mesh_vertices(
 {
 	let normals_matrix = get_normals_matrix(model_matrix);
 
+	// Dynamic lights and tangent space light are calculated in world space.
+	// Use this matrix to reconstruct world position from transformed vertex position.
+	let view_to_world_matrix = if dynamic_lights.is_empty() && !calculate_tangent_space_light
+	{
+		Mat4f::identity()
+	}
+	else
+	{
+		model_matrix * model_view_matrix.invert().unwrap_or_else(Mat4f::identity)
+	};
+
+	let lighting_params = VertexLightingParams {
+		light,
+		dynamic_lights,
+		use_shadow_maps,
+		calculate_tangent_space_light,
+		camera_position: *camera_position,
+		view_to_world_matrix,
+	};
+
 	let frame0 = animation.frames[0] as usize;
 	let frame1 = animation.frames[1] as usize;
 	let lerp0 = animation.lerp.max(0.0).min(1.0);
@@ -26,40 +52,50 @@ pub fn animate_and_transform_triangle_mesh_vertices(
 	{
 		VertexData::NonAnimated(v) =>
 		{
-			for (v, dst_v) in v.iter().zip(dst_vertices.iter_mut())
+			// Each vertex must have tangent space, otherwise some vertices will be skipped.
+			debug_assert_eq!(mesh.tangent_space.len(), v.len());
+			for ((v, tangent_space), dst_v) in v.iter().zip(mesh.tangent_space.iter()).zip(dst_vertices.iter_mut())
 			{
 				let pos_transformed = model_view_matrix * v.position.extend(1.0);
 				let normal_transformed = normals_matrix * v.normal;
-				*dst_v = ModelVertex3d {
-					pos: Vec3f::new(pos_transformed.x, pos_transformed.y, pos_transformed.w),
-					tc: Vec2f::from(v.tex_coord).mul_element_wise(*tc_scale) + tc_shift,
-					light: get_vertex_light(light, &normal_transformed),
-				};
+				*dst_v = make_vertex(
+					&lighting_params,
+					&pos_transformed,
+					&normal_transformed,
+					&normals_matrix,
+					tangent_space,
+					Vec2f::from(v.tex_coord).mul_element_wise(*tc_scale) + tc_shift,
+				);
 			}
 		},
 		VertexData::VertexAnimated { constant, variable } =>
 		{
+			debug_assert_eq!(mesh.tangent_space.len(), constant.len());
 			let frame_vertex_data0 = &variable[frame0 * constant.len() .. (frame0 + 1) * constant.len()];
 			let frame_vertex_data1 = &variable[frame1 * constant.len() .. (frame1 + 1) * constant.len()];
 
 			if perform_lerp
 			{
 				// Perform smooth interpolation.
-				for (((v_v0, v_v1), v_c), dst_v) in frame_vertex_data0
+				for ((((v_v0, v_v1), v_c), tangent_space), dst_v) in frame_vertex_data0
 					.iter()
 					.zip(frame_vertex_data1)
 					.zip(constant.iter())
+					.zip(mesh.tangent_space.iter())
 					.zip(dst_vertices.iter_mut())
 				{
 					let position_lerped = v_v0.position * lerp0 + v_v1.position * lerp1;
 					let pos_transformed = model_view_matrix * position_lerped.extend(1.0);
 					let normal_lerped = v_v0.normal * lerp0 + v_v1.normal * lerp1;
 					let normal_transformed = normals_matrix * normal_lerped;
-					*dst_v = ModelVertex3d {
-						pos: Vec3f::new(pos_transformed.x, pos_transformed.y, pos_transformed.w),
-						tc: Vec2f::from(v_c.tex_coord).mul_element_wise(*tc_scale) + tc_shift,
-						light: get_vertex_light(light, &normal_transformed),
-					};
+					*dst_v = make_vertex(
+						&lighting_params,
+						&pos_transformed,
+						&normal_transformed,
+						&normals_matrix,
+						tangent_space,
+						Vec2f::from(v_c.tex_coord).mul_element_wise(*tc_scale) + tc_shift,
+					);
 				}
 			}
 			else
@@ -73,35 +109,43 @@ pub fn animate_and_transform_triangle_mesh_vertices(
 				{
 					frame_vertex_data1
 				};
-				for ((v_v, v_c), dst_v) in frame_vertex_data
+				for (((v_v, v_c), tangent_space), dst_v) in frame_vertex_data
 					.iter()
 					.zip(constant.iter())
+					.zip(mesh.tangent_space.iter())
 					.zip(dst_vertices.iter_mut())
 				{
 					let pos_transformed = model_view_matrix * v_v.position.extend(1.0);
 					let normal_transformed = normals_matrix * v_v.normal;
-					*dst_v = ModelVertex3d {
-						pos: Vec3f::new(pos_transformed.x, pos_transformed.y, pos_transformed.w),
-						tc: Vec2f::from(v_c.tex_coord).mul_element_wise(*tc_scale) + tc_shift,
-						light: get_vertex_light(light, &normal_transformed),
-					};
+					*dst_v = make_vertex(
+						&lighting_params,
+						&pos_transformed,
+						&normal_transformed,
+						&normals_matrix,
+						tangent_space,
+						Vec2f::from(v_c.tex_coord).mul_element_wise(*tc_scale) + tc_shift,
+					);
 				}
 			}
 		},
 		VertexData::SkeletonAnimated(v) =>
 		{
+			debug_assert_eq!(mesh.tangent_space.len(), v.len());
 			if model.frame_bones.is_empty()
 			{
 				// No animation - just use source vertces.
-				for (v, dst_v) in v.iter().zip(dst_vertices.iter_mut())
+				for ((v, tangent_space), dst_v) in v.iter().zip(mesh.tangent_space.iter()).zip(dst_vertices.iter_mut())
 				{
 					let pos_transformed = model_view_matrix * v.position.extend(1.0);
 					let normal_transformed = normals_matrix * v.normal;
-					*dst_v = ModelVertex3d {
-						pos: Vec3f::new(pos_transformed.x, pos_transformed.y, pos_transformed.w),
-						tc: Vec2f::from(v.tex_coord).mul_element_wise(*tc_scale) + tc_shift,
-						light: get_vertex_light(light, &normal_transformed),
-					};
+					*dst_v = make_vertex(
+						&lighting_params,
+						&pos_transformed,
+						&normal_transformed,
+						&normals_matrix,
+						tangent_space,
+						Vec2f::from(v.tex_coord).mul_element_wise(*tc_scale) + tc_shift,
+					);
 				}
 			}
 			else
@@ -163,7 +207,7 @@ pub fn animate_and_transform_triangle_mesh_vertices(
 					*bone_matrix = model_view_matrix_weight_scaled * *bone_matrix;
 				}
 
-				for (v, dst_v) in v.iter().zip(dst_vertices.iter_mut())
+				for ((v, tangent_space), dst_v) in v.iter().zip(mesh.tangent_space.iter()).zip(dst_vertices.iter_mut())
 				{
 					let i0 = v.bones_description[0].bone_index as usize;
 					let w0 = v.bones_description[0].weight as f32;
@@ -183,11 +227,14 @@ pub fn animate_and_transform_triangle_mesh_vertices(
 
 					let pos_transformed = mat * v.position.extend(1.0);
 					let normal_transformed = normal_mat * v.normal;
-					*dst_v = ModelVertex3d {
-						pos: Vec3f::new(pos_transformed.x, pos_transformed.y, pos_transformed.w),
-						tc: Vec2f::from(v.tex_coord).mul_element_wise(*tc_scale) + tc_shift,
-						light: get_vertex_light(light, &normal_transformed),
-					};
+					*dst_v = make_vertex(
+						&lighting_params,
+						&pos_transformed,
+						&normal_transformed,
+						&normal_mat,
+						tangent_space,
+						Vec2f::from(v.tex_coord).mul_element_wise(*tc_scale) + tc_shift,
+					);
 				}
 			}
 		},
@@ -320,6 +367,56 @@ pub fn calculate_triangle_model_texture_mip(
 	((approximate_texels_per_pixel.log2() + mip_bias).floor().max(0.0) as u32).min(MAX_MIP as u32)
 }
 
+// Calculate mip level (with fractional part) for projected model polygon.
+// Texture coordinates of polygon vertices should be in texels of first mip.
+// Screen-space texture coordinates derivatives are calculated using fan triangle with largest projected area.
+pub fn calculate_triangle_model_polygon_texture_mip(polygon: &[ModelVertex2d], mip_bias: f32) -> f32
+{
+	let v0 = &polygon[0];
+	let mut max_area = 0.0;
+	let mut derivatives_triangle = None;
+	for t in 0 .. polygon.len() - 2
+	{
+		let e1 = polygon[t + 1].pos - v0.pos;
+		let e2 = polygon[t + 2].pos - v0.pos;
+		let area_doubled = e1.x * e2.y - e1.y * e2.x;
+		if area_doubled.abs() > max_area
+		{
+			max_area = area_doubled.abs();
+			derivatives_triangle = Some((t, e1, e2, area_doubled));
+		}
+	}
+
+	let (t, e1, e2, area_doubled) = if let Some(d) = derivatives_triangle
+	{
+		d
+	}
+	else
+	{
+		// Degenerate polygon.
+		return MAX_MIP as f32;
+	};
+	if max_area < MIN_POLYGON_AREA_FOR_MIP_CALCULATION
+	{
+		return MAX_MIP as f32;
+	}
+
+	let tc1 = polygon[t + 1].tc - v0.tc;
+	let tc2 = polygon[t + 2].tc - v0.tc;
+	let inv_area_doubled = 1.0 / area_doubled;
+	let d_tc_dx = (tc1 * e2.y - tc2 * e1.y) * inv_area_doubled;
+	let d_tc_dy = (tc2 * e1.x - tc1 * e2.x) * inv_area_doubled;
+
+	// Use maximum derivative of texture coordinate components.
+	let d_tc_2 = (d_tc_dx.x * d_tc_dx.x + d_tc_dy.x * d_tc_dy.x).max(d_tc_dx.y * d_tc_dx.y + d_tc_dy.y * d_tc_dy.y);
+	let mip_f = d_tc_2.max(MIN_POSITIVE_VALUE).log2() * 0.5 + mip_bias; // log(sqrt(x)) = log(x) * 0.5
+
+	mip_f.max(0.0).min(MAX_MIP as f32)
+}
+
+const MIN_POLYGON_AREA_FOR_MIP_CALCULATION: f32 = 1.0 / 256.0;
+const MIN_POSITIVE_VALUE: f32 = 1.0 / ((1 << 30) as f32);
+
 pub fn reject_triangle_model_back_faces(
 	transformed_vertices: &[ModelVertex3d],
 	triangles: &[Triangle],
@@ -386,9 +483,18 @@ pub fn triangle_vertex_debug_checked_fetch<VertexT: Copy>(vertices: &[VertexT],
 
 pub fn get_model_light(map: &bsp_map_compact::BSPMap, model: &ModelEntity) -> bsp_map_compact::LightGridElement
 {
-	match model.lighting
+	get_entity_light(map, &model.lighting, &model.position)
+}
+
+pub fn get_entity_light(
+	map: &bsp_map_compact::BSPMap,
+	lighting: &ModelLighting,
+	entity_position: &Vec3f,
+) -> bsp_map_compact::LightGridElement
+{
+	match *lighting
 	{
-		ModelLighting::Default => fetch_light_from_grid(map, &model.position),
+		ModelLighting::Default => fetch_light_from_grid(map, entity_position),
 		ModelLighting::ConstantLight(l) => bsp_map_compact::LightGridElement {
 			light_cube: [l; 6],
 			light_direction_vector_scaled: Vec3f::zero(),
@@ -545,13 +651,190 @@ fn fetch_light_from_grid(map: &bsp_map_compact::BSPMap, pos: &Vec3f) -> bsp_map_
 	total_light
 }
 
-fn get_vertex_light(light: &bsp_map_compact::LightGridElement, normal_tranformed: &Vec3f) -> [f32; 3]
+struct VertexLightingParams<'a>
+{
+	light: &'a bsp_map_compact::LightGridElement,
+	dynamic_lights: &'a [LightWithShadowMap<'a, 'a>],
+	use_shadow_maps: bool,
+	// Calculate tangent space light for normal mapping instead of simple vertex light.
+	calculate_tangent_space_light: bool,
+	camera_position: Vec3f,
+	view_to_world_matrix: Mat4f,
+}
+
+fn make_vertex(
+	params: &VertexLightingParams,
+	pos_transformed: &Vec4f,
+	normal_transformed: &Vec3f,
+	normals_matrix: &Mat3f,
+	tangent_space: &VertexTangentSpace,
+	tc: Vec2f,
+) -> ModelVertex3d
+{
+	let pos = Vec3f::new(pos_transformed.x, pos_transformed.y, pos_transformed.w);
+	if params.calculate_tangent_space_light
+	{
+		// Transform tangent space vectors like normal. It is not correct for non-uniform scale, but it is fine for now.
+		let (light, tangent_space_light) = get_vertex_tangent_space_light(
+			params,
+			pos_transformed,
+			normal_transformed,
+			&(normals_matrix * tangent_space.tangent),
+			&(normals_matrix * tangent_space.binormal),
+		);
+		ModelVertex3d {
+			pos,
+			tc,
+			light,
+			tangent_space_light,
+		}
+	}
+	else
+	{
+		ModelVertex3d {
+			pos,
+			tc,
+			light: get_vertex_light(params, pos_transformed, normal_transformed),
+			tangent_space_light: ModelVertexTangentSpaceLight::zero(),
+		}
+	}
+}
+
+fn get_vertex_light(params: &VertexLightingParams, pos_transformed: &Vec4f, normal_tranformed: &Vec3f) -> [f32; 3]
 {
 	// After transformation normal may be unnormalized. Renormalize it.
 	let normal_normalized = normal_tranformed * inv_sqrt_fast(normal_tranformed.magnitude2().max(0.00000001));
 
+	let light = params.light;
+	let mut total_light = get_light_cube_light(light, &normal_normalized);
+
+	// Use directional component.
+	let light_dir_dot = normal_normalized.dot(light.light_direction_vector_scaled).max(0.0);
+	for i in 0 .. 3
+	{
+		total_light[i] += light.directional_light_color[i] * light_dir_dot;
+	}
+
+	if !params.dynamic_lights.is_empty()
+	{
+		let pos = (params.view_to_world_matrix * pos_transformed).truncate();
+		for (dynamic_light, shadow_map) in params.dynamic_lights
+		{
+			let dynamic_light_value = get_dynamic_light_vertex_light(
+				dynamic_light,
+				if params.use_shadow_maps { Some(shadow_map) } else { None },
+				&pos,
+				&normal_normalized,
+			);
+			for i in 0 .. 3
+			{
+				total_light[i] += dynamic_light_value[i];
+			}
+		}
+	}
+
+	total_light
+}
+
+// Calculate light for normal-mapped models.
+// Result is ambient light (from light cube) and single directional light in tangent space.
+// Light grid directional light and all dynamic lights are combined into this directional light.
+fn get_vertex_tangent_space_light(
+	params: &VertexLightingParams,
+	pos_transformed: &Vec4f,
+	normal_tranformed: &Vec3f,
+	tangent_transformed: &Vec3f,
+	binormal_transformed: &Vec3f,
+) -> ([f32; 3], ModelVertexTangentSpaceLight)
+{
+	let normal_normalized = normal_tranformed * inv_sqrt_fast(normal_tranformed.magnitude2().max(0.00000001));
+
+	// Make tangent space vectors orthogonal to normal.
+	let tangent = tangent_transformed - normal_normalized * normal_normalized.dot(*tangent_transformed);
+	let tangent_normalized = tangent * inv_sqrt_fast(tangent.magnitude2().max(0.00000001));
+	let binormal = binormal_transformed - normal_normalized * normal_normalized.dot(*binormal_transformed);
+	let binormal_normalized = binormal * inv_sqrt_fast(binormal.magnitude2().max(0.00000001));
+
+	let to_tangent_space = |v: Vec3f| {
+		Vec3f::new(
+			v.dot(tangent_normalized),
+			v.dot(binormal_normalized),
+			v.dot(normal_normalized),
+		)
+	};
+
+	let light = params.light;
+	let ambient_light = get_light_cube_light(light, &normal_normalized);
+
+	// Sum light vectors weighted by intensity.
+	let mut light_vector_scaled_sum = Vec3f::zero();
+	let mut light_color_sum = [0.0, 0.0, 0.0];
+	let mut light_intensity_sum = 0.0;
+	let mut add_light = |light_vector_scaled: Vec3f, color: [f32; 3]| {
+		let intensity = (color[0] + color[1] + color[2]) * (1.0 / 3.0);
+		if intensity <= 0.0
+		{
+			return;
+		}
+		light_vector_scaled_sum += light_vector_scaled * intensity;
+		for i in 0 .. 3
+		{
+			light_color_sum[i] += color[i];
+		}
+		light_intensity_sum += intensity;
+	};
+
+	add_light(light.light_direction_vector_scaled, light.directional_light_color);
+
+	let pos = (params.view_to_world_matrix * pos_transformed).truncate();
+	for (dynamic_light, shadow_map) in params.dynamic_lights
+	{
+		let vec_to_light = dynamic_light.pos - pos;
+		if vec_to_light.dot(normal_normalized) <= 0.0
+		{
+			// Ignore lights behind surface - they can't affect combined light direction.
+			continue;
+		}
+
+		let vec_to_light_len2 = vec_to_light.magnitude2().max(0.00000001);
+		let shadow_factor = if params.use_shadow_maps
+		{
+			cube_shadow_map_fetch::<SIMD_LEVEL_SCALAR>(shadow_map, &vec_to_light)
+		}
+		else
+		{
+			1.0
+		};
+
+		let scale = shadow_factor / vec_to_light_len2;
+		add_light(
+			vec_to_light * inv_sqrt_fast(vec_to_light_len2),
+			dynamic_light.color.map(|c| c * scale),
+		);
+	}
+
+	let light_vector_scaled = if light_intensity_sum > 0.0
+	{
+		light_vector_scaled_sum / light_intensity_sum
+	}
+	else
+	{
+		Vec3f::zero()
+	};
+
+	(
+		ambient_light,
+		ModelVertexTangentSpaceLight {
+			light_vector_scaled: to_tangent_space(light_vector_scaled),
+			light_color: light_color_sum,
+			vec_to_camera: to_tangent_space(params.camera_position - pos),
+		},
+	)
+}
+
+fn get_light_cube_light(light: &bsp_map_compact::LightGridElement, normal_normalized: &Vec3f) -> [f32; 3]
+{
 	let mut total_light = [0.0, 0.0, 0.0];
-	// Fetch light from cube.
 	if normal_normalized.x <= 0.0
 	{
 		for i in 0 .. 3
@@ -595,14 +878,38 @@ fn get_vertex_light(light: &bsp_map_compact::LightGridElement, normal_tranformed
 		}
 	}
 
-	// Use directional component.
-	let light_dir_dot = normal_normalized.dot(light.light_direction_vector_scaled).max(0.0);
-	for i in 0 .. 3
+	total_light
+}
+
+// Calculate light of dynamic light for given world space position and normalized normal.
+// Shadow map is optional - light is not shadowed if it is not specified.
+pub fn get_dynamic_light_vertex_light(
+	light: &PointLight,
+	shadow_map: Option<&CubeShadowMap>,
+	pos: &Vec3f,
+	normal_normalized: &Vec3f,
+) -> [f32; 3]
+{
+	let vec_to_light = light.pos - pos;
+	let vec_to_light_len2 = vec_to_light.magnitude2().max(0.00000001);
+
+	let angle_cos = normal_normalized.dot(vec_to_light) * inv_sqrt_fast(vec_to_light_len2);
+	if angle_cos <= 0.0
 	{
-		total_light[i] += light.directional_light_color[i] * light_dir_dot;
+		return [0.0; 3];
 	}
 
-	total_light
+	let shadow_factor = if let Some(shadow_map) = shadow_map
+	{
+		cube_shadow_map_fetch::<SIMD_LEVEL_SCALAR>(shadow_map, &vec_to_light)
+	}
+	else
+	{
+		1.0
+	};
+
+	let scale = angle_cos * shadow_factor / vec_to_light_len2;
+	light.color.map(|c| c * scale)
 }
 
 fn get_normals_matrix(model_matrix: &Mat4f) -> Mat3f
@@ -0,0 +1,79 @@
+use super::{abstract_color::*, fast_math::*};
+use crate::common::{bsp_map_compact, system_window};
+use rayon::prelude::*;
+
+// Apply screen warp and color tint if camera is inside liquid with given contents.
+pub fn apply_underwater_effects<ColorT: AbstractColor>(
+	pixels: &mut [ColorT],
+	surface_info: &system_window::SurfaceInfo,
+	contents: u32,
+	time_s: f32,
+)
+{
+	let tint = if let Some(t) = get_liquid_tint(contents)
+	{
+		t
+	}
+	else
+	{
+		return;
+	};
+
+	let width = surface_info.width;
+	let height = surface_info.height;
+	if width == 0 || height == 0
+	{
+		return;
+	}
+
+	// Shift rows horizontally using sine wave, moving over time.
+	let amplitude = (height as f32) * (1.0 / 160.0);
+	let wave_frequency = std::f32::consts::TAU / (height as f32) * 3.0;
+	let time_frequency = 2.5;
+
+	let tint_vec = ColorVecI::from_color_i32x3(&tint);
+
+	let process_row = |row_buffer: &mut Vec<ColorT>, (y, row): (usize, &mut [ColorT])| {
+		let row = &mut row[.. width];
+		row_buffer.clear();
+		row_buffer.extend_from_slice(row);
+
+		let shift = (amplitude * ((y as f32) * wave_frequency + time_s * time_frequency).sin()).round() as i32;
+		for (x, dst) in row.iter_mut().enumerate()
+		{
+			let src_x = (x as i32 + shift).max(0).min(width as i32 - 1) as usize;
+			let src: ColorVecI = row_buffer[src_x].into();
+			*dst = ColorVecI::shift_right::<8>(&ColorVecI::mul(&src, &tint_vec)).into();
+		}
+	};
+
+	if rayon::current_num_threads() == 1
+	{
+		let mut row_buffer = Vec::with_capacity(width);
+		pixels
+			.chunks_mut(surface_info.pitch)
+			.take(height)
+			.enumerate()
+			.for_each(|row| process_row(&mut row_buffer, row));
+	}
+	else
+	{
+		pixels
+			.par_chunks_mut(surface_info.pitch)
+			.take(height)
+			.enumerate()
+			.for_each_init(|| Vec::with_capacity(width), process_row);
+	}
+}
+
+// Result is scaled by 256.
+fn get_liquid_tint(contents: u32) -> Option<[i32; 3]>
+{
+	match contents
+	{
+		bsp_map_compact::CONTENTS_WATER => Some([144, 192, 240]),
+		bsp_map_compact::CONTENTS_SLIME => Some([112, 224, 80]),
+		bsp_map_compact::CONTENTS_LAVA => Some([256, 112, 48]),
+		_ => None,
+	}
+}
@@ -0,0 +1,233 @@
+use super::fast_math::*;
+use crate::common::{color::*, system_window};
+use rayon::prelude::*;
+
+// Upscales image, rendered in low resolution, to window resolution.
+#[derive(Default)]
+pub struct Upscaler
+{
+	buffer_size: [usize; 2],
+	buffer: Vec<Color32>,
+	sharpened_buffer: Vec<Color32>,
+}
+
+#[derive(Copy, Clone, PartialEq)]
+pub enum UpscaleMode
+{
+	Nearest,
+	Bilinear,
+	// Sharpen low resolution image and than use bilinear filter.
+	BilinearSharpened,
+}
+
+impl UpscaleMode
+{
+	pub fn from_config_value(value: u32) -> Self
+	{
+		match value
+		{
+			0 => UpscaleMode::Nearest,
+			1 => UpscaleMode::Bilinear,
+			_ => UpscaleMode::BilinearSharpened,
+		}
+	}
+}
+
+// Get surface info with size scaled by given factor.
+pub fn get_scaled_surface_info(surface_info: &system_window::SurfaceInfo, scale: f32) -> system_window::SurfaceInfo
+{
+	let width = ((surface_info.width as f32) * scale).round().max(1.0) as usize;
+	let height = ((surface_info.height as f32) * scale).round().max(1.0) as usize;
+	system_window::SurfaceInfo {
+		width: width.min(surface_info.width),
+		height: height.min(surface_info.height),
+		pitch: width.min(surface_info.width),
+	}
+}
+
+impl Upscaler
+{
+	// Get buffer for low resolution image.
+	pub fn get_buffer(&mut self, size: [usize; 2]) -> &mut [Color32]
+	{
+		let required_size = size[0] * size[1];
+		if self.buffer.len() < required_size
+		{
+			self.buffer.resize(required_size, Color32::black());
+		}
+		self.buffer_size = size;
+
+		&mut self.buffer[.. required_size]
+	}
+
+	// Upscale contents of buffer into given pixels.
+	pub fn perform_upscale(
+		&mut self,
+		pixels: &mut [Color32],
+		surface_info: &system_window::SurfaceInfo,
+		mode: UpscaleMode,
+	)
+	{
+		let src_size = self.buffer_size;
+		if src_size[0] == 0 || src_size[1] == 0 || surface_info.width == 0 || surface_info.height == 0
+		{
+			return;
+		}
+
+		let num_src_pixels = src_size[0] * src_size[1];
+
+		let src = match mode
+		{
+			UpscaleMode::Nearest | UpscaleMode::Bilinear => &self.buffer[.. num_src_pixels],
+			UpscaleMode::BilinearSharpened =>
+			{
+				if self.sharpened_buffer.len() < num_src_pixels
+				{
+					self.sharpened_buffer.resize(num_src_pixels, Color32::black());
+				}
+				sharpen(
+					&self.buffer[.. num_src_pixels],
+					&mut self.sharpened_buffer[.. num_src_pixels],
+					src_size,
+				);
+				&self.sharpened_buffer[.. num_src_pixels]
+			},
+		};
+
+		let dst_size = [surface_info.width, surface_info.height];
+		let func = |(y, dst_line): (usize, &mut [Color32])| {
+			let dst_line = &mut dst_line[.. dst_size[0]];
+			if mode == UpscaleMode::Nearest
+			{
+				upscale_line_nearest(src, src_size, dst_line, dst_size, y);
+			}
+			else
+			{
+				upscale_line_bilinear(src, src_size, dst_line, dst_size, y);
+			}
+		};
+
+		if rayon::current_num_threads() == 1
+		{
+			pixels
+				.chunks_mut(surface_info.pitch)
+				.take(dst_size[1])
+				.enumerate()
+				.for_each(func);
+		}
+		else
+		{
+			pixels
+				.par_chunks_mut(surface_info.pitch)
+				.take(dst_size[1])
+				.enumerate()
+				.for_each(func);
+		}
+	}
+}
+
+fn upscale_line_nearest(src: &[Color32], src_size: [usize; 2], dst_line: &mut [Color32], dst_size: [usize; 2], y: usize)
+{
+	let src_y = (y * src_size[1] / dst_size[1]).min(src_size[1] - 1);
+	let src_line = &src[src_y * src_size[0] .. (src_y + 1) * src_size[0]];
+	for (x, dst) in dst_line.iter_mut().enumerate()
+	{
+		let src_x = (x * src_size[0] / dst_size[0]).min(src_size[0] - 1);
+		*dst = src_line[src_x];
+	}
+}
+
+fn upscale_line_bilinear(
+	src: &[Color32],
+	src_size: [usize; 2],
+	dst_line: &mut [Color32],
+	dst_size: [usize; 2],
+	y: usize,
+)
+{
+	const SHIFT: i32 = 8;
+	const ONE: i32 = 1 << SHIFT;
+
+	let (src_y0, src_y1, frac_y) = get_bilinear_coord(y, src_size[1], dst_size[1]);
+	let src_line0 = &src[src_y0 * src_size[0] .. (src_y0 + 1) * src_size[0]];
+	let src_line1 = &src[src_y1 * src_size[0] .. (src_y1 + 1) * src_size[0]];
+
+	for (x, dst) in dst_line.iter_mut().enumerate()
+	{
+		let (src_x0, src_x1, frac_x) = get_bilinear_coord(x, src_size[0], dst_size[0]);
+
+		let c00 = ColorVecI::from_color32(src_line0[src_x0]);
+		let c01 = ColorVecI::from_color32(src_line0[src_x1]);
+		let c10 = ColorVecI::from_color32(src_line1[src_x0]);
+		let c11 = ColorVecI::from_color32(src_line1[src_x1]);
+
+		let c0 =
+			ColorVecI::shift_right::<SHIFT>(&ColorVecI::add(&c00.mul_scalar(ONE - frac_x), &c01.mul_scalar(frac_x)));
+		let c1 =
+			ColorVecI::shift_right::<SHIFT>(&ColorVecI::add(&c10.mul_scalar(ONE - frac_x), &c11.mul_scalar(frac_x)));
+		*dst = ColorVecI::shift_right::<SHIFT>(&ColorVecI::add(&c0.mul_scalar(ONE - frac_y), &c1.mul_scalar(frac_y)))
+			.into();
+	}
+}
+
+// Returns two source coordinates and interpolation factor (in range [0; 256)) for given destination coordinate.
+fn get_bilinear_coord(dst_coord: usize, src_size: usize, dst_size: usize) -> (usize, usize, i32)
+{
+	// Use coordinates of pixel centers.
+	let src_coord_scaled = ((2 * dst_coord + 1) * src_size * 256 / (2 * dst_size)) as i64 - 128;
+	let src_coord_scaled = src_coord_scaled.max(0) as usize;
+	let coord0 = (src_coord_scaled >> 8).min(src_size - 1);
+	let coord1 = (coord0 + 1).min(src_size - 1);
+	(coord0, coord1, (src_coord_scaled & 255) as i32)
+}
+
+fn sharpen(src: &[Color32], dst: &mut [Color32], size: [usize; 2])
+{
+	let func = |(y, dst_line): (usize, &mut [Color32])| sharpen_line(src, size, dst_line, y);
+
+	if rayon::current_num_threads() == 1
+	{
+		dst.chunks_mut(size[0]).take(size[1]).enumerate().for_each(func);
+	}
+	else
+	{
+		dst.par_chunks_mut(size[0]).take(size[1]).enumerate().for_each(func);
+	}
+}
+
+fn sharpen_line(src: &[Color32], size: [usize; 2], dst_line: &mut [Color32], y: usize)
+{
+	// Amount of sharpening, scaled by 256.
+	const AMOUNT: i32 = 48;
+
+	let y_prev = y.max(1) - 1;
+	let y_next = (y + 1).min(size[1] - 1);
+	for (x, dst) in dst_line.iter_mut().enumerate()
+	{
+		let x_prev = x.max(1) - 1;
+		let x_next = (x + 1).min(size[0] - 1);
+
+		let center = src[x + y * size[0]].get_raw();
+		let neighbors = [
+			src[x_prev + y * size[0]].get_raw(),
+			src[x_next + y * size[0]].get_raw(),
+			src[x + y_prev * size[0]].get_raw(),
+			src[x + y_next * size[0]].get_raw(),
+		];
+
+		// Process each component separately in order to clamp result properly.
+		let mut result = center & 0xFF000000;
+		for shift in [0, 8, 16]
+		{
+			let c = ((center >> shift) & 0xFF) as i32;
+			let mut neighbors_sum = 0;
+			for n in neighbors
+			{
+				neighbors_sum += ((n >> shift) & 0xFF) as i32;
+			}
+			let value = (c * (256 + 4 * AMOUNT) - neighbors_sum * AMOUNT) >> 8;
+			result |= (value.max(0).min(255) as u32) << shift;
+		}
+		*dst = Color32::from_raw(result);
+	}
+}
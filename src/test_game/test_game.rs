@@ -1,12 +1,12 @@
 use super::{
-	commands_processor, commands_queue, console, frame_info::*, game_interface::*, light::*, resources_manager::*,
-	test_game_physics,
+	commands_processor, commands_queue, console, fog::*, frame_info::*, game_interface::*, light::*, picking::*,
+	procedural_sky::*, resources_manager::*, test_game_physics,
 };
 use square_wheel_lib::common::{
 	bsp_map_compact, camera_controller::*, camera_rotation_controller::*, color::*, material, math_types::*, matrix::*,
 	system_window,
 };
-use std::sync::Arc;
+use std::{collections::HashMap, sync::Arc};
 
 pub struct Game
 {
@@ -22,7 +22,20 @@ pub struct Game
 	test_lights: Vec<PointLight>,
 	test_models: Vec<PhysicsTestModel>,
 	test_decals: Vec<Decal>,
+	test_sprites: Vec<Sprite>,
+	test_portals: Vec<ViewPortal>,
+	// Position and rotation for destination of next test portal.
+	test_portal_destination: Option<(Vec3f, QuaternionF)>,
+	test_camera_views: Vec<CameraView>,
+	test_fog: Option<FogParams>,
+	test_sky_sun: Option<SkySun>,
+	test_view_contents: Option<u32>,
+	test_exposure_override: Option<ExposureOverride>,
+	// Color, start time and duration of screen flash.
+	screen_flash: Option<([f32; 3], f32, f32)>,
 	view_model: Option<ModelEntity>,
+	// Pick object under crosshair on next frame.
+	pick_requested: bool,
 	game_time: f32,
 }
 
@@ -46,9 +59,27 @@ impl Game
 			("reset_test_models", Game::command_reset_test_models),
 			("add_test_decal", Game::command_add_test_decal),
 			("reset_test_decals", Game::command_reset_test_decals),
+			("add_test_sprite", Game::command_add_test_sprite),
+			("reset_test_sprites", Game::command_reset_test_sprites),
+			("set_test_portal_destination", Game::command_set_test_portal_destination),
+			("add_test_portal", Game::command_add_test_portal),
+			("add_test_submodel_portal", Game::command_add_test_submodel_portal),
+			("reset_test_portals", Game::command_reset_test_portals),
+			("add_test_camera_view", Game::command_add_test_camera_view),
+			("reset_test_camera_views", Game::command_reset_test_camera_views),
+			("set_test_fog", Game::command_set_test_fog),
+			("reset_test_fog", Game::command_reset_test_fog),
+			("set_test_sky_sun", Game::command_set_test_sky_sun),
+			("reset_test_sky_sun", Game::command_reset_test_sky_sun),
+			("set_test_view_contents", Game::command_set_test_view_contents),
+			("reset_test_view_contents", Game::command_reset_test_view_contents),
+			("set_test_exposure", Game::command_set_test_exposure),
+			("reset_test_exposure", Game::command_reset_test_exposure),
+			("screen_flash", Game::command_screen_flash),
 			("set_view_model", Game::command_set_view_model),
 			("reset_view_model", Game::command_reset_view_model),
 			("noclip", Game::command_noclip),
+			("pick", Game::command_pick),
 		]);
 
 		let commands_queue_dyn = commands_queue.clone() as commands_queue::CommandsQueueDynPtr;
@@ -72,7 +103,17 @@ impl Game
 			test_lights: Vec::new(),
 			test_models: Vec::new(),
 			test_decals: Vec::new(),
+			test_sprites: Vec::new(),
+			test_portals: Vec::new(),
+			test_portal_destination: None,
+			test_camera_views: Vec::new(),
+			test_fog: None,
+			test_sky_sun: None,
+			test_view_contents: None,
+			test_exposure_override: None,
+			screen_flash: None,
 			view_model: None,
+			pick_requested: false,
 			game_time: 0.0,
 		}
 	}
@@ -248,6 +289,8 @@ impl Game
 		let mut r = self.resources_manager.lock().unwrap();
 		let model = r.get_model(&args[0]);
 		let texture = r.get_texture_lite(&args[1]);
+		// Optional third argument - material name.
+		let material = args.get(2).map(|m| r.get_material_texture(m));
 
 		let (pos, rotation) = self.get_camera_location();
 		let bbox = model.frames_info[0].bbox;
@@ -263,6 +306,7 @@ impl Game
 				},
 				model,
 				texture,
+				material,
 				blending_mode: material::BlendingMode::None,
 				lighting: ModelLighting::Default,
 				is_view_model: false,
@@ -326,6 +370,354 @@ impl Game
 		self.test_decals.clear();
 	}
 
+	fn command_add_test_sprite(&mut self, args: commands_queue::CommandArgs)
+	{
+		if args.len() < 1
+		{
+			self.console
+				.lock()
+				.unwrap()
+				.add_text("Expected at least 1 arg".to_string());
+			return;
+		}
+
+		let texture = self.resources_manager.lock().unwrap().get_texture_lite(&args[0]);
+		let scale = if args.len() >= 2
+		{
+			args[1].parse::<f32>().unwrap_or(1.0)
+		}
+		else
+		{
+			1.0
+		};
+
+		let orientation = match args.get(2).map(|s| s.as_str())
+		{
+			Some("z_axis") => SpriteOrientation::AlignedToZAxis,
+			Some("fixed") => SpriteOrientation::Fixed(self.get_camera_location().1),
+			_ => SpriteOrientation::FacingTowardsCamera,
+		};
+
+		let texture_mip0 = &texture[0];
+		let size = Vec2f::new(texture_mip0.size[0] as f32, texture_mip0.size[1] as f32) * scale;
+
+		self.test_sprites.push(Sprite {
+			position: self.get_camera_location().0,
+			angle: 0.0,
+			size,
+			orientation,
+			texture,
+			blending_mode: material::BlendingMode::AlphaTest,
+			lighting: ModelLighting::Default,
+		});
+	}
+
+	fn command_reset_test_sprites(&mut self, _args: commands_queue::CommandArgs)
+	{
+		self.test_sprites.clear();
+	}
+
+	fn command_set_test_portal_destination(&mut self, _args: commands_queue::CommandArgs)
+	{
+		self.test_portal_destination = Some(self.get_camera_location());
+	}
+
+	fn command_add_test_portal(&mut self, args: commands_queue::CommandArgs)
+	{
+		let (destination_position, destination_rotation) = if let Some(d) = self.test_portal_destination
+		{
+			d
+		}
+		else
+		{
+			self.console
+				.lock()
+				.unwrap()
+				.add_text("Set portal destination first".to_string());
+			return;
+		};
+
+		let size = if args.len() >= 1
+		{
+			args[0].parse::<f32>().unwrap_or(64.0)
+		}
+		else
+		{
+			64.0
+		};
+
+		// Create square portal, facing towards camera.
+		let (position, rotation) = self.get_camera_location();
+		let half_width_vec = rotation * Vec3f::new(0.0, 0.5 * size, 0.0);
+		let half_height_vec = rotation * Vec3f::new(0.0, 0.0, 0.5 * size);
+
+		self.test_portals.push(ViewPortal {
+			geometry: ViewPortalGeometry::Polygon(vec![
+				position - half_width_vec + half_height_vec,
+				position + half_width_vec + half_height_vec,
+				position + half_width_vec - half_height_vec,
+				position - half_width_vec - half_height_vec,
+			]),
+			source_position: position,
+			source_rotation: rotation,
+			destination_position,
+			destination_rotation,
+		});
+	}
+
+	fn command_add_test_submodel_portal(&mut self, args: commands_queue::CommandArgs)
+	{
+		let (destination_position, destination_rotation) = if let Some(d) = self.test_portal_destination
+		{
+			d
+		}
+		else
+		{
+			self.console
+				.lock()
+				.unwrap()
+				.add_text("Set portal destination first".to_string());
+			return;
+		};
+
+		if args.len() < 2
+		{
+			self.console
+				.lock()
+				.unwrap()
+				.add_text("Expected submodel index and polygon index".to_string());
+			return;
+		}
+
+		let (submodel_index, polygon_index) = match (args[0].parse::<u32>(), args[1].parse::<u32>())
+		{
+			(Ok(s), Ok(p)) => (s, p),
+			_ =>
+			{
+				self.console
+					.lock()
+					.unwrap()
+					.add_text("Failed to parse indices".to_string());
+				return;
+			},
+		};
+
+		// Use current camera location as portal source space.
+		let (source_position, source_rotation) = self.get_camera_location();
+
+		self.test_portals.push(ViewPortal {
+			geometry: ViewPortalGeometry::SubmodelPolygon {
+				submodel_index,
+				polygon_index,
+			},
+			source_position,
+			source_rotation,
+			destination_position,
+			destination_rotation,
+		});
+	}
+
+	fn command_reset_test_portals(&mut self, _args: commands_queue::CommandArgs)
+	{
+		self.test_portals.clear();
+	}
+
+	fn command_add_test_camera_view(&mut self, args: commands_queue::CommandArgs)
+	{
+		if args.len() < 1
+		{
+			self.console
+				.lock()
+				.unwrap()
+				.add_text("Expected at least 1 arg".to_string());
+			return;
+		}
+
+		let size = if args.len() >= 3
+		{
+			[
+				args[1].parse::<u32>().unwrap_or(256),
+				args[2].parse::<u32>().unwrap_or(256),
+			]
+		}
+		else
+		{
+			[256, 256]
+		};
+
+		let (pos, rotation) = self.get_camera_location();
+		let fov = std::f32::consts::PI * 0.5;
+		let camera_matrices = build_view_matrix_with_full_rotation(pos, rotation, fov, size[0] as f32, size[1] as f32);
+
+		self.test_camera_views.push(CameraView {
+			name: args[0].clone(),
+			camera_matrices,
+			size,
+		});
+	}
+
+	fn command_reset_test_camera_views(&mut self, _args: commands_queue::CommandArgs)
+	{
+		self.test_camera_views.clear();
+	}
+
+	fn command_set_test_fog(&mut self, args: commands_queue::CommandArgs)
+	{
+		if args.len() < 4
+		{
+			self.console.lock().unwrap().add_text("Expected 4 args".to_string());
+			return;
+		}
+
+		let density = args[0].parse::<f32>().unwrap_or(0.0);
+		let color = [
+			args[1].parse::<f32>().unwrap_or(0.0),
+			args[2].parse::<f32>().unwrap_or(0.0),
+			args[3].parse::<f32>().unwrap_or(0.0),
+		];
+		let start = args.get(4).and_then(|s| s.parse::<f32>().ok()).unwrap_or(0.0);
+		let end = args.get(5).and_then(|s| s.parse::<f32>().ok()).unwrap_or(0.0);
+
+		self.test_fog = Some(FogParams {
+			color,
+			density,
+			start,
+			end,
+		});
+	}
+
+	fn command_reset_test_fog(&mut self, _args: commands_queue::CommandArgs)
+	{
+		self.test_fog = None;
+	}
+
+	fn command_set_test_sky_sun(&mut self, args: commands_queue::CommandArgs)
+	{
+		if args.len() < 1
+		{
+			self.console
+				.lock()
+				.unwrap()
+				.add_text("Expected at least 1 arg".to_string());
+			return;
+		}
+
+		// Angles are in degrees.
+		let elevation = args[0].parse::<f32>().unwrap_or(0.0).to_radians();
+		let azimuth = args
+			.get(1)
+			.and_then(|s| s.parse::<f32>().ok())
+			.unwrap_or(0.0)
+			.to_radians();
+		let brightness = args.get(2).and_then(|s| s.parse::<f32>().ok()).unwrap_or(1.0);
+
+		// Make sun reddish near horizon.
+		let elevation_sin = elevation.sin();
+		let redness = (1.0 - elevation_sin.abs() * 3.0).max(0.0);
+
+		self.test_sky_sun = Some(SkySun {
+			direction: Vec3f::new(
+				elevation.cos() * azimuth.cos(),
+				elevation.cos() * azimuth.sin(),
+				elevation_sin,
+			),
+			color: [
+				brightness,
+				brightness * (1.0 - 0.4 * redness),
+				brightness * (1.0 - 0.7 * redness),
+			],
+		});
+	}
+
+	fn command_reset_test_sky_sun(&mut self, _args: commands_queue::CommandArgs)
+	{
+		self.test_sky_sun = None;
+	}
+
+	fn command_set_test_view_contents(&mut self, args: commands_queue::CommandArgs)
+	{
+		if args.len() < 1
+		{
+			self.console.lock().unwrap().add_text("Expected 1 arg".to_string());
+			return;
+		}
+
+		self.test_view_contents = match args[0].as_str()
+		{
+			"empty" => Some(bsp_map_compact::CONTENTS_EMPTY),
+			"water" => Some(bsp_map_compact::CONTENTS_WATER),
+			"slime" => Some(bsp_map_compact::CONTENTS_SLIME),
+			"lava" => Some(bsp_map_compact::CONTENTS_LAVA),
+			_ =>
+			{
+				self.console
+					.lock()
+					.unwrap()
+					.add_text("Expected one of: empty, water, slime, lava".to_string());
+				return;
+			},
+		};
+	}
+
+	fn command_reset_test_view_contents(&mut self, _args: commands_queue::CommandArgs)
+	{
+		self.test_view_contents = None;
+	}
+
+	fn command_set_test_exposure(&mut self, args: commands_queue::CommandArgs)
+	{
+		if args.is_empty()
+		{
+			self.console
+				.lock()
+				.unwrap()
+				.add_text("Expected exposure value or \"lock\"".to_string());
+			return;
+		}
+
+		self.test_exposure_override = if args[0] == "lock"
+		{
+			Some(ExposureOverride::Lock)
+		}
+		else if let Ok(exposure) = args[0].parse::<f32>()
+		{
+			Some(ExposureOverride::Fixed(exposure))
+		}
+		else
+		{
+			self.console
+				.lock()
+				.unwrap()
+				.add_text("Failed to parse exposure".to_string());
+			return;
+		};
+	}
+
+	fn command_reset_test_exposure(&mut self, _args: commands_queue::CommandArgs)
+	{
+		self.test_exposure_override = None;
+	}
+
+	fn command_screen_flash(&mut self, args: commands_queue::CommandArgs)
+	{
+		if args.len() < 3
+		{
+			self.console
+				.lock()
+				.unwrap()
+				.add_text("Expected at least 3 args".to_string());
+			return;
+		}
+
+		let color = [
+			args[0].parse::<f32>().unwrap_or(0.0),
+			args[1].parse::<f32>().unwrap_or(0.0),
+			args[2].parse::<f32>().unwrap_or(0.0),
+		];
+		let duration = args.get(3).and_then(|s| s.parse::<f32>().ok()).unwrap_or(0.5);
+		self.screen_flash = Some((color, self.game_time, duration.max(0.01)));
+	}
+
 	fn command_set_view_model(&mut self, args: commands_queue::CommandArgs)
 	{
 		self.view_model = None;
@@ -348,6 +740,7 @@ impl Game
 			},
 			model,
 			texture,
+			material: None,
 			blending_mode: material::BlendingMode::Average,
 			lighting: ModelLighting::Default,
 			is_view_model: true,
@@ -390,6 +783,45 @@ impl Game
 			self.console.lock().unwrap().add_text("Noclip OFF".to_string());
 		}
 	}
+
+	fn command_pick(&mut self, _args: commands_queue::CommandArgs)
+	{
+		self.pick_requested = true;
+	}
+
+	fn get_pick_target_description(&self, target: &PickTarget, queries: &dyn FrameQueries) -> String
+	{
+		let get_material_description = |material_index: u32| {
+			format!(
+				"material \"{}\" ({:?})",
+				bsp_map_compact::get_texture_string(&self.map.textures[material_index as usize]),
+				queries.get_material(material_index).blending_mode
+			)
+		};
+
+		match target
+		{
+			PickTarget::Polygon {
+				polygon_index,
+				material_index,
+			} => format!(
+				"polygon {}, {}",
+				polygon_index,
+				get_material_description(*material_index)
+			),
+			PickTarget::SubmodelPolygon {
+				submodel_index,
+				polygon_index,
+				material_index,
+			} => format!(
+				"submodel {} polygon {}, {}",
+				submodel_index,
+				polygon_index,
+				get_material_description(*material_index)
+			),
+			PickTarget::ModelEntity(index) => format!("model entity {}", index),
+		}
+	}
 }
 
 impl GameInterface for Game
@@ -501,6 +933,7 @@ impl GameInterface for Game
 					draw_entity: SubmodelEntity {
 						rotation,
 						position: Vec3f::zero(),
+						texture_frame: None,
 					},
 				});
 			}
@@ -592,11 +1025,70 @@ impl GameInterface for Game
 			camera_matrices,
 			submodel_entities,
 			skybox_rotation: QuaternionF::zero(),
+			sky_sun: self.test_sky_sun,
+			screen_tint: self.screen_flash.and_then(|(color, start_time, duration)| {
+				let fraction = (self.game_time - start_time) / duration;
+				if fraction < 1.0
+				{
+					// Fade flash over time.
+					Some(ScreenTint {
+						color,
+						alpha: 0.5 * (1.0 - fraction),
+					})
+				}
+				else
+				{
+					None
+				}
+			}),
+			fog: self.test_fog,
+			materials_texture_frames: HashMap::new(),
+			exposure_override: self.test_exposure_override,
+			view_contents: self.test_view_contents,
 			game_time_s: self.game_time,
 			lights: self.test_lights.clone(),
 			model_entities,
 			decals: self.test_decals.clone(),
+			sprites: self.test_sprites.clone(),
+			portals: self.test_portals.clone(),
+			camera_views: self.test_camera_views.clone(),
+		}
+	}
+
+	fn process_frame_queries(&mut self, queries: &dyn FrameQueries, surface_info: &system_window::SurfaceInfo)
+	{
+		if !self.pick_requested
+		{
+			return;
 		}
+		self.pick_requested = false;
+
+		// Use crosshair position.
+		let x = (surface_info.width / 2) as u32;
+		let y = (surface_info.height / 2) as u32;
+
+		let pick_text = match queries.pick(x, y)
+		{
+			Some(pick_result) => format!(
+				"Picked {} at {} {} {}, distance {}",
+				self.get_pick_target_description(&pick_result.target, queries),
+				pick_result.pos.x,
+				pick_result.pos.y,
+				pick_result.pos.z,
+				pick_result.distance
+			),
+			None => "Nothing picked".to_string(),
+		};
+
+		let depth_text = match queries.get_world_position_for_pixel(x, y)
+		{
+			Some(pos) => format!("Depth buffer position {} {} {}", pos.x, pos.y, pos.z),
+			None => "No depth buffer position".to_string(),
+		};
+
+		let mut console = self.console.lock().unwrap();
+		console.add_text(pick_text);
+		console.add_text(depth_text);
 	}
 
 	fn draw_frame_overlay(&self, pixels: &mut [Color32], surface_info: &system_window::SurfaceInfo)